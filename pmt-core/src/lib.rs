@@ -0,0 +1,24 @@
+//! The template engine of pmt: parses `## Title` sections into templates,
+//! their bodies into placeholders, and renders them with field values.
+//!
+//! ```
+//! use pmt_core::{OptionPools, collect_fields, parse_templates, parse_tokens, render_template};
+//!
+//! let templates = parse_templates("## 问候\n你好，{name}！\n");
+//! let tokens = parse_tokens(&templates[0].body, &OptionPools::new());
+//! let mut fields = collect_fields(&tokens);
+//! fields[0].value = "世界".to_string();
+//! assert_eq!(render_template(&tokens, &fields), "你好，世界！");
+//! ```
+//!
+//! Messages are in Chinese unless [`locale::set_locale`] picks English.
+
+pub mod locale;
+pub mod models;
+pub mod parser;
+
+pub use models::{Field, FieldKind, OptionPools, Template, Token};
+pub use parser::{
+    collect_fields, parse_nested_templates, parse_templates, parse_tokens, render_template,
+    render_template_at,
+};
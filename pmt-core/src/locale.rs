@@ -0,0 +1,93 @@
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::Deserialize;
+
+/// Language of the TUI and CLI messages. Every message is written at its
+/// call site in both languages, with `tr!` or `pick`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en-US")]
+    EnUs,
+}
+
+static CURRENT: AtomicU8 = AtomicU8::new(0);
+
+impl Locale {
+    /// `language` from `config.toml`, else the first of `LC_ALL`,
+    /// `LC_MESSAGES` and `LANG` that is set.
+    pub fn resolve(configured: Option<Locale>) -> Self {
+        if let Some(locale) = configured {
+            return locale;
+        }
+        let name = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        Self::from_env(&name)
+    }
+
+    /// The locale of a POSIX locale name such as `zh_CN.UTF-8`. Chinese,
+    /// `C`, `POSIX` or no locale at all mean zh-CN; any other language
+    /// means en-US.
+    fn from_env(name: &str) -> Self {
+        let language = name.split(['_', '.', '-']).next().unwrap_or_default();
+        match language {
+            "" | "C" | "POSIX" | "zh" => Self::ZhCn,
+            _ => Self::EnUs,
+        }
+    }
+}
+
+pub fn set_locale(locale: Locale) {
+    CURRENT.store(locale as u8, Ordering::Relaxed);
+}
+
+pub fn locale() -> Locale {
+    match CURRENT.load(Ordering::Relaxed) {
+        1 => Locale::EnUs,
+        _ => Locale::ZhCn,
+    }
+}
+
+/// The text of the current locale, for fixed strings such as titles.
+pub fn pick(zh: &'static str, en: &'static str) -> &'static str {
+    match locale() {
+        Locale::ZhCn => zh,
+        Locale::EnUs => en,
+    }
+}
+
+/// `format!` with a zh-CN and an en-US template; both see the same
+/// arguments.
+#[macro_export]
+macro_rules! tr {
+    ($zh:literal, $en:literal $(, $($arg:tt)*)?) => {
+        match $crate::locale::locale() {
+            $crate::locale::Locale::ZhCn => format!($zh $(, $($arg)*)?),
+            $crate::locale::Locale::EnUs => format!($en $(, $($arg)*)?),
+        }
+    };
+}
+
+pub use crate::tr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn posix_locale_names_choose_the_language() {
+        for name in ["", "C", "C.UTF-8", "POSIX", "zh_CN.UTF-8", "zh_TW"] {
+            assert_eq!(Locale::from_env(name), Locale::ZhCn, "{name}");
+        }
+        for name in ["en_US.UTF-8", "de_DE", "fr"] {
+            assert_eq!(Locale::from_env(name), Locale::EnUs, "{name}");
+        }
+        assert_eq!(Locale::resolve(Some(Locale::EnUs)), Locale::EnUs);
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::locale::{pick, tr};
+
+/// Named option lists referenced as `{random|@name}` or `{select|x|@name}`.
+pub type OptionPools = BTreeMap<String, Vec<String>>;
+
+#[derive(Clone, Debug)]
+pub struct Template {
+    pub name: String,
+    pub body: String,
+    /// File the template was read from; empty when parsed from a string.
+    pub source: PathBuf,
+    /// Zero-based line of the heading within `source`.
+    pub line: usize,
+    /// Number of `{{include}}` hops from the main prompts file.
+    pub include_depth: usize,
+    /// Fields of the frontmatter block under the heading, if any.
+    pub metadata: Metadata,
+    /// Why the frontmatter could not be read; `metadata` is empty then.
+    pub metadata_error: Option<String>,
+    /// Folders in front of the heading, with a trailing `/`: the directory
+    /// of `source` below the prompts directory, then the titles of the
+    /// headings it is nested under. Empty when there are none.
+    pub prefix: String,
+    /// Read with nested headings: every `#` to `######` heading ends the
+    /// section, not just `##`.
+    pub nested: bool,
+    /// `source` has no `##` heading; all of it is this template, named
+    /// after the file.
+    pub whole_file: bool,
+    /// URL the template was fetched from. Such templates are read-only;
+    /// `source` is the cached copy.
+    pub remote: Option<String>,
+    pub layer: Layer,
+    /// Layer of the template with the same name this one hides.
+    pub overrides: Option<Layer>,
+    /// The name as written when an earlier template of the same layer has
+    /// it too; `name` then carries a ` (2)`, ` (3)`… suffix.
+    pub duplicate_of: Option<String>,
+}
+
+/// Where a template comes from, lowest precedence first. A template hides
+/// those with the same name in the layers below it.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Layer {
+    /// `/etc/pmt`, shared by every user of the machine.
+    System,
+    /// `[[remote]]` sources.
+    Remote,
+    /// `[prompts] team` files and directories.
+    Team,
+    /// The prompts file and directory.
+    #[default]
+    Personal,
+}
+
+impl Layer {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::System => pick("系统", "system"),
+            Self::Remote => pick("远程", "remote"),
+            Self::Team => pick("团队", "team"),
+            Self::Personal => pick("个人", "personal"),
+        }
+    }
+}
+
+impl Template {
+    /// The name in the template's heading or file name, without the suffix
+    /// of a duplicate.
+    pub fn written_name(&self) -> &str {
+        self.duplicate_of.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Whether the frontmatter lists `tag`, ignoring case.
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.metadata
+            .tags
+            .iter()
+            .any(|own| own.to_lowercase() == tag.to_lowercase())
+    }
+}
+
+/// Frontmatter of a template: YAML between `---` lines or TOML between
+/// `+++` lines, right under the heading. Unknown keys are ignored.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Model the prompt is written for, e.g. `gpt-4o`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Field values to start with, by placeholder name; they take
+    /// precedence over `globals.toml`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeItem {
+    pub label: String,
+    pub depth: usize,
+    pub template_index: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub enum FieldKind {
+    Var,
+    /// Multi-line input rendered as one list item per non-empty line.
+    List,
+    Select {
+        options: Vec<String>,
+    },
+    Random {
+        token_index: usize,
+        pinned: bool,
+    },
+    /// Read-only: shows where a `{file|...}` token was read from.
+    File {
+        token_index: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: String,
+    pub label: String,
+    pub value: String,
+    pub kind: FieldKind,
+    /// Marked `{name!}`: copying waits until it has a value.
+    pub required: bool,
+    pub rule: Option<Rule>,
+}
+
+impl Field {
+    /// Named fields (free text, list and select) are filled by the user and
+    /// looked up by name when rendering; random and file fields are not.
+    pub fn is_named(&self) -> bool {
+        !matches!(self.kind, FieldKind::Random { .. } | FieldKind::File { .. })
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.required && self.value.trim().is_empty()
+    }
+
+    /// Why the value breaks the field's rule; each item of a list field is
+    /// checked on its own.
+    pub fn invalid(&self) -> Option<String> {
+        let rule = self.rule.as_ref()?;
+        match self.kind {
+            FieldKind::List => self.value.lines().find_map(|line| rule.check(line)),
+            _ => rule.check(&self.value),
+        }
+    }
+}
+
+/// What a field value must look like before the prompt is copied, given as
+/// the last part of a placeholder: `{count|数量|int}`, `{mail||regex:@}`.
+#[derive(Clone, Debug)]
+pub enum Rule {
+    Int,
+    Number,
+    Regex(Regex),
+}
+
+impl Rule {
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.trim() {
+            "int" => Some(Self::Int),
+            "number" => Some(Self::Number),
+            spec => Regex::new(spec.strip_prefix("regex:")?)
+                .ok()
+                .map(Self::Regex),
+        }
+    }
+
+    /// Why `value` breaks the rule. Empty values pass; `required` is the
+    /// check for those.
+    fn check(&self, value: &str) -> Option<String> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        match self {
+            Self::Int if value.parse::<i64>().is_err() => Some(tr!("需要整数", "integer required")),
+            Self::Number if value.parse::<f64>().is_err() => {
+                Some(tr!("需要数字", "number required"))
+            }
+            Self::Regex(regex) if !regex.is_match(value) => {
+                Some(tr!("不符合格式 {}", "does not match {}", regex.as_str()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Transform applied to a value where one placeholder is rendered, so one
+/// field can be written differently in several places: `{name||upper}`,
+/// `{code||trim,indent:4}`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    /// Spaces put in front of every non-empty line.
+    Indent(usize),
+    /// Wraps the value in double quotes, escaping the quotes inside.
+    Quote,
+    /// Escapes the value for use inside a JSON string.
+    JsonEscape,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (name, arg) = match spec.trim().split_once(':') {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (spec.trim(), None),
+        };
+        match (name, arg) {
+            ("upper", None) => Some(Self::Upper),
+            ("lower", None) => Some(Self::Lower),
+            ("trim", None) => Some(Self::Trim),
+            ("indent", None) => Some(Self::Indent(4)),
+            ("indent", Some(width)) => width.parse().ok().map(Self::Indent),
+            ("quote", None) => Some(Self::Quote),
+            ("json-escape", None) => Some(Self::JsonEscape),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Self::Upper => value.to_uppercase(),
+            Self::Lower => value.to_lowercase(),
+            Self::Trim => value.trim().to_string(),
+            Self::Indent(width) => value
+                .split('\n')
+                .map(|line| {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{}{line}", " ".repeat(*width))
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Self::Quote => format!("\"{}\"", value.replace('"', "\\\"")),
+            Self::JsonEscape => {
+                let quoted = serde_json::to_string(value).expect("strings are serializable");
+                quoted[1..quoted.len() - 1].to_string()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upper => f.write_str("upper"),
+            Self::Lower => f.write_str("lower"),
+            Self::Trim => f.write_str("trim"),
+            Self::Indent(width) => write!(f, "indent:{width}"),
+            Self::Quote => f.write_str("quote"),
+            Self::JsonEscape => f.write_str("json-escape"),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int => f.write_str("int"),
+            Self::Number => f.write_str("number"),
+            Self::Regex(regex) => write!(f, "regex:{}", regex.as_str()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Token {
+    Text(String),
+    /// `{name}`, or `{name!}` when the value is required.
+    Var {
+        name: String,
+        desc: Option<String>,
+        required: bool,
+        rule: Option<Rule>,
+        filters: Vec<Filter>,
+        raw: String,
+    },
+    List {
+        name: String,
+        desc: Option<String>,
+        required: bool,
+        rule: Option<Rule>,
+        filters: Vec<Filter>,
+        raw: String,
+    },
+    Select {
+        name: String,
+        options: Vec<String>,
+        desc: Option<String>,
+        raw: String,
+    },
+    Random {
+        options: Vec<String>,
+        choice: String,
+        desc: Option<String>,
+        raw: String,
+    },
+    /// `{date}` or `{time}`, optionally with a strftime format; expands to
+    /// the current local time whenever it is rendered.
+    Timestamp {
+        format: String,
+        raw: String,
+    },
+    /// `{file|path}`: the file's contents once read, with the path they
+    /// were read from.
+    File {
+        path: String,
+        resolved: Option<PathBuf>,
+        content: Option<String>,
+        raw: String,
+    },
+    /// `{cmd|command}`: the command's stdout once it has been run.
+    Command {
+        command: String,
+        output: Option<String>,
+        raw: String,
+    },
+    /// `{clipboard}`: the clipboard's text, read when the template is
+    /// opened or rendered.
+    Clipboard {
+        content: Option<String>,
+        raw: String,
+    },
+}
+
+/// A piece of rendered output tagged with where it came from, so the
+/// preview can style substituted values differently from template text.
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub text: String,
+    pub kind: SegmentKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SegmentKind {
+    Text,
+    Value,
+    Unfilled,
+    Random { token_index: usize },
+}
@@ -0,0 +1,1262 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::locale::tr;
+use crate::models::{
+    Field, Filter, Layer, Metadata, OptionPools, Rule, Segment, SegmentKind, Template, Token,
+    TreeItem,
+};
+
+/// Section whose body defines option pools, one `name = "a" "b"` per line.
+pub const POOLS_SECTION: &str = "@pools";
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+/// Templates of a prompts file: one per `## Title` heading, with the lines
+/// up to the next heading or `{{include}}` as its body.
+pub fn parse_templates(content: &str) -> Vec<Template> {
+    parse_sections(content, false)
+}
+
+/// Like [`parse_templates`], but `#` to `######` headings all start a
+/// template, named after the headings it is nested under: a `### 子类`
+/// under `## 评审` is `评审/子类`. A heading with nothing but a deeper
+/// heading below it is only a folder.
+pub fn parse_nested_templates(content: &str) -> Vec<Template> {
+    parse_sections(content, true)
+}
+
+/// A heading of [`parse_sections`] and what its template is named.
+struct Section {
+    level: usize,
+    prefix: String,
+    title: String,
+    line: usize,
+}
+
+fn parse_sections(content: &str, nested: bool) -> Vec<Template> {
+    let mut templates = Vec::new();
+    let mut parents: Vec<(usize, String)> = Vec::new();
+    let mut current: Option<Section> = None;
+    let mut body = String::new();
+    let finish = |section: Section, body: &str| {
+        let name = format!("{}{}", section.prefix, section.title);
+        Template {
+            prefix: section.prefix,
+            nested,
+            ..new_template(name, section.line, body)
+        }
+    };
+
+    for (line_no, line) in content.lines().enumerate() {
+        let heading = section_heading(line, nested);
+        if heading.is_some() || parse_include(line).is_some() {
+            if let Some(section) = current.take() {
+                let folder = body.trim().is_empty()
+                    && heading
+                        .as_ref()
+                        .is_some_and(|(level, _)| *level > section.level);
+                if !folder {
+                    templates.push(finish(section, &body));
+                }
+                body.clear();
+            }
+            current = heading.map(|(level, title)| {
+                parents.retain(|(parent, _)| *parent < level);
+                let prefix = parents
+                    .iter()
+                    .map(|(_, title)| format!("{title}/"))
+                    .collect();
+                parents.push((level, title.clone()));
+                Section {
+                    level,
+                    prefix,
+                    title,
+                    line: line_no,
+                }
+            });
+        } else if current.is_some() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+
+    if let Some(section) = current {
+        templates.push(finish(section, &body));
+    }
+    templates
+}
+
+pub fn new_template(name: String, line: usize, section: &str) -> Template {
+    let skip = frontmatter_lines(section.lines());
+    let (metadata, metadata_error) = match parse_frontmatter(section, skip) {
+        Ok(metadata) => (metadata, None),
+        Err(err) => (Metadata::default(), Some(err)),
+    };
+    let body: String = section.split_inclusive('\n').skip(skip).collect();
+    Template {
+        name,
+        body: trim_trailing_newline(&body).to_string(),
+        source: PathBuf::new(),
+        line,
+        include_depth: 0,
+        metadata,
+        metadata_error,
+        prefix: String::new(),
+        nested: false,
+        whole_file: false,
+        remote: None,
+        layer: Layer::Personal,
+        overrides: None,
+        duplicate_of: None,
+    }
+}
+
+/// Number of lines at the top of a section body taken by frontmatter: a
+/// block fenced by `---` (YAML) or `+++` (TOML) on its first line, and the
+/// blank lines after it. Zero when there is no closed block.
+pub fn frontmatter_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> usize {
+    let mut lines = lines.into_iter();
+    let fence = match lines.next().map(str::trim) {
+        Some(fence @ ("---" | "+++")) => fence,
+        _ => return 0,
+    };
+    let mut count = 1;
+    loop {
+        match lines.next() {
+            Some(line) => {
+                count += 1;
+                if line.trim() == fence {
+                    break;
+                }
+            }
+            None => return 0,
+        }
+    }
+    count + lines.take_while(|line| line.trim().is_empty()).count()
+}
+
+/// Reads the frontmatter taking the first `skip` lines of `section`.
+fn parse_frontmatter(section: &str, skip: usize) -> Result<Metadata, String> {
+    if skip == 0 {
+        return Ok(Metadata::default());
+    }
+    let mut lines = section.lines().take(skip);
+    let fence = lines.next().map(str::trim).unwrap_or_default();
+    let inner: Vec<&str> = lines.take_while(|line| line.trim() != fence).collect();
+    let inner = inner.join("\n");
+    if inner.trim().is_empty() {
+        return Ok(Metadata::default());
+    }
+    let parsed = if fence == "+++" {
+        toml::from_str(&inner).map_err(|err| err.message().to_string())
+    } else {
+        serde_yaml_ng::from_str(&inner).map_err(|err| err.to_string())
+    };
+    parsed.map_err(|err| tr!("元数据解析失败: {err}", "Invalid frontmatter: {err}"))
+}
+
+pub fn parse_heading(line: &str) -> Option<String> {
+    parse_nested_heading(line)
+        .filter(|(level, _)| *level == 2)
+        .map(|(_, title)| title)
+}
+
+/// The level and title of `line` when it starts a section: any `#` to
+/// `######` heading when `nested`, else only a `##` heading.
+pub fn section_heading(line: &str, nested: bool) -> Option<(usize, String)> {
+    if nested {
+        parse_nested_heading(line)
+    } else {
+        parse_heading(line).map(|title| (2, title))
+    }
+}
+
+/// The level and title of a `#` to `######` heading line.
+pub fn parse_nested_heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&ch| ch == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.starts_with(' ') || rest.starts_with('\t')) {
+        return None;
+    }
+    let title = rest.trim();
+    if title.is_empty() {
+        None
+    } else {
+        Some((level, title.to_string()))
+    }
+}
+
+/// Why a line starting with `##` is not read as a heading; such a line
+/// stays part of the template above it.
+pub fn malformed_heading(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("##")?;
+    if rest.starts_with('#') || parse_heading(line).is_some() {
+        return None;
+    }
+    if rest.trim().is_empty() {
+        Some(tr!(
+            "标题为空，此行并入上一个模板",
+            "Empty heading; the line stays in the template above"
+        ))
+    } else {
+        Some(tr!(
+            "`##` 后缺少空格，此行并入上一个模板: {line}",
+            "No space after `##`; the line stays in the template above: {line}"
+        ))
+    }
+}
+
+/// Recognizes a `{{include path}}` directive line. Includes end the
+/// preceding template body, like a heading does.
+pub fn parse_include(line: &str) -> Option<&str> {
+    let inner = line.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    let target = inner.trim().strip_prefix("include")?;
+    if !target.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let target = target.trim();
+    if target.is_empty() {
+        None
+    } else {
+        Some(target)
+    }
+}
+
+fn trim_trailing_newline(input: &str) -> &str {
+    let trimmed = input.strip_suffix('\n').unwrap_or(input);
+    trimmed.strip_suffix('\r').unwrap_or(trimmed)
+}
+
+/// The folder tree of the templates for which `include` holds.
+pub fn build_tree_items(
+    templates: &[Template],
+    include: impl Fn(&Template) -> bool,
+) -> Vec<TreeItem> {
+    let mut root = TreeNode::new("");
+    for (index, template) in templates.iter().enumerate() {
+        if !include(template) {
+            continue;
+        }
+        let parts: Vec<&str> = template
+            .name
+            .split('/')
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect();
+        root.insert(&parts, index);
+    }
+
+    let mut items = Vec::new();
+    root.flatten(0, &mut items);
+    items
+}
+
+#[derive(Clone, Debug)]
+struct TreeNode {
+    name: String,
+    template_index: Option<usize>,
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            template_index: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, parts: &[&str], template_index: usize) {
+        if parts.is_empty() {
+            self.template_index = Some(template_index);
+            return;
+        }
+        let part = parts[0];
+        let child = self.children.iter_mut().find(|child| child.name == part);
+        match child {
+            Some(node) => node.insert(&parts[1..], template_index),
+            None => {
+                let mut node = TreeNode::new(part);
+                node.insert(&parts[1..], template_index);
+                self.children.push(node);
+            }
+        }
+    }
+
+    fn flatten(&self, depth: usize, items: &mut Vec<TreeItem>) {
+        for child in &self.children {
+            items.push(TreeItem {
+                label: child.name.clone(),
+                depth,
+                template_index: child.template_index,
+            });
+            child.flatten(depth + 1, items);
+        }
+    }
+}
+
+pub fn parse_pools(templates: &[Template]) -> OptionPools {
+    let mut pools = OptionPools::new();
+    for template in templates.iter().filter(|t| t.name == POOLS_SECTION) {
+        for line in template.body.lines() {
+            let (name, options) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let name = name.trim();
+            let options = parse_random_options(options.trim().trim_matches(['[', ']']));
+            if !name.is_empty() && !options.is_empty() {
+                pools.insert(name.to_string(), options);
+            }
+        }
+    }
+    pools
+}
+
+/// Token streams of template bodies keyed by a hash of the body, so a reload
+/// only parses the bodies that changed. `{random|@pool}` expands from the
+/// pools, so other pools start the cache over.
+#[derive(Clone, Debug, Default)]
+pub struct TokenCache {
+    pools: OptionPools,
+    tokens: HashMap<u64, Rc<Vec<Token>>>,
+}
+
+impl TokenCache {
+    pub fn tokens(&mut self, body: &str, pools: &OptionPools) -> Rc<Vec<Token>> {
+        if self.pools != *pools {
+            self.pools = pools.clone();
+            self.tokens.clear();
+        }
+        let tokens = self
+            .tokens
+            .entry(body_hash(body))
+            .or_insert_with(|| Rc::new(parse_tokens(body, pools)));
+        Rc::clone(tokens)
+    }
+
+    /// Forgets the bodies that are no longer in `templates`.
+    pub fn retain(&mut self, templates: &[Template]) {
+        let current: HashSet<u64> = templates
+            .iter()
+            .map(|template| body_hash(&template.body))
+            .collect();
+        self.tokens.retain(|hash, _| current.contains(hash));
+    }
+}
+
+fn body_hash(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Splits a template body into text and placeholders. Braces that are not
+/// a known placeholder stay text.
+pub fn parse_tokens(body: &str, pools: &OptionPools) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while let Some(start) = body[index..].find('{') {
+        let start_idx = index + start;
+        if start_idx > index {
+            tokens.push(Token::Text(body[index..start_idx].to_string()));
+        }
+        let after = &body[start_idx + 1..];
+        if let Some(end_rel) = after.find('}') {
+            let end_idx = start_idx + 1 + end_rel;
+            let inner = &body[start_idx + 1..end_idx];
+            let raw = body[start_idx..=end_idx].to_string();
+            if let Some(token) = parse_placeholder(inner, &raw, pools) {
+                tokens.push(token);
+            } else {
+                tokens.push(Token::Text(raw));
+            }
+            index = end_idx + 1;
+        } else {
+            tokens.push(Token::Text(body[start_idx..].to_string()));
+            index = body.len();
+        }
+    }
+    if index < body.len() {
+        tokens.push(Token::Text(body[index..].to_string()));
+    }
+    tokens
+}
+
+/// Replaces every `{use|path}` with the body of the template at `path`,
+/// expanding references in that body too. Fails on an unknown path or a
+/// reference cycle.
+pub fn expand_uses(body: &str, templates: &[Template]) -> Result<String, String> {
+    expand_uses_in(body, templates, &mut Vec::new())
+}
+
+fn expand_uses_in(
+    body: &str,
+    templates: &[Template],
+    stack: &mut Vec<String>,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(body.len());
+    let mut index = 0;
+    while let Some(start) = body[index..].find('{') {
+        let start_idx = index + start;
+        let Some(end_rel) = body[start_idx + 1..].find('}') else {
+            break;
+        };
+        let end_idx = start_idx + 1 + end_rel;
+        output.push_str(&body[index..start_idx]);
+        let raw = &body[start_idx..=end_idx];
+        match use_target(&body[start_idx + 1..end_idx]) {
+            Some(path) => {
+                let wanted = normalize_path(path);
+                if stack.contains(&wanted) {
+                    stack.push(wanted);
+                    return Err(tr!(
+                        "循环引用: {}",
+                        "Circular reference: {}",
+                        stack.join(" → ")
+                    ));
+                }
+                let template = templates
+                    .iter()
+                    .find(|template| normalize_path(&template.name) == wanted)
+                    .ok_or_else(|| {
+                        tr!(
+                            "未找到引用的模板: {path}",
+                            "Referenced template not found: {path}"
+                        )
+                    })?;
+                stack.push(wanted);
+                output.push_str(&expand_uses_in(&template.body, templates, stack)?);
+                stack.pop();
+            }
+            None => output.push_str(raw),
+        }
+        index = end_idx + 1;
+    }
+    output.push_str(&body[index..]);
+    Ok(output)
+}
+
+fn use_target(inner: &str) -> Option<&str> {
+    let (head, path) = inner.split_once('|')?;
+    let path = path.trim();
+    (head.trim() == "use" && !path.is_empty()).then_some(path)
+}
+
+/// Template path with empty segments dropped and each segment trimmed.
+pub fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Canonical source form of a placeholder token: no padding around names
+/// and separators, options always quoted.
+pub fn canonical_placeholder(token: &Token) -> Option<String> {
+    let quoted = |options: &[String]| {
+        options
+            .iter()
+            .map(|option| format!("\"{option}\""))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let marker = |required: bool| if required { "!" } else { "" };
+    let with_desc = |head: String, desc: &Option<String>| match desc {
+        Some(desc) if !desc.is_empty() => format!("{{{head}|{desc}}}"),
+        _ => format!("{{{head}}}"),
+    };
+    // Filters come first since a `regex:` rule runs to the end.
+    let with_modifiers =
+        |head: String, desc: &Option<String>, rule: &Option<Rule>, filters: &[Filter]| {
+            let modifiers: Vec<String> = filters
+                .iter()
+                .map(ToString::to_string)
+                .chain(rule.iter().map(ToString::to_string))
+                .collect();
+            if modifiers.is_empty() {
+                return with_desc(head, desc);
+            }
+            format!(
+                "{{{head}|{}|{}}}",
+                desc.as_deref().unwrap_or(""),
+                modifiers.join(",")
+            )
+        };
+    match token {
+        Token::Text(_) => None,
+        Token::Var {
+            name,
+            desc,
+            required,
+            rule,
+            filters,
+            ..
+        } => Some(with_modifiers(
+            format!("{name}{}", marker(*required)),
+            desc,
+            rule,
+            filters,
+        )),
+        Token::List {
+            name,
+            desc,
+            required,
+            rule,
+            filters,
+            ..
+        } => Some(with_modifiers(
+            format!("list|{name}{}", marker(*required)),
+            desc,
+            rule,
+            filters,
+        )),
+        Token::Select {
+            name,
+            options,
+            desc,
+            ..
+        } => Some(with_desc(
+            format!("select|{name}|{}", quoted(options)),
+            desc,
+        )),
+        Token::Random { options, desc, .. } => {
+            Some(with_desc(format!("random|{}", quoted(options)), desc))
+        }
+        Token::Command { command, .. } => Some(format!("{{cmd|{command}}}")),
+        Token::Clipboard { .. } => Some("{clipboard}".to_string()),
+        Token::File { path, .. } => Some(format!("{{file|{path}}}")),
+        Token::Timestamp { raw, .. } => {
+            let inner = &raw[1..raw.len() - 1];
+            Some(match inner.split_once('|') {
+                Some((head, format)) => format!("{{{}|{}}}", head.trim(), format.trim()),
+                None => format!("{{{}}}", inner.trim()),
+            })
+        }
+    }
+}
+
+/// Renames every variable and select placeholder called `from` to `to`,
+/// leaving the rest of the body untouched. Returns the new body and how many
+/// placeholders were renamed.
+pub fn rename_variable(body: &str, from: &str, to: &str, pools: &OptionPools) -> (String, usize) {
+    let mut count = 0;
+    let mut output = String::with_capacity(body.len());
+    for token in parse_tokens(body, pools) {
+        match token {
+            Token::Text(text) => output.push_str(&text),
+            Token::Var { name, raw, .. } if name == from => {
+                output.push_str(&replace_name(&raw, 0, from, to));
+                count += 1;
+            }
+            Token::List { name, raw, .. } | Token::Select { name, raw, .. } if name == from => {
+                let skip = raw.find('|').map_or(0, |pos| pos + 1);
+                output.push_str(&replace_name(&raw, skip, from, to));
+                count += 1;
+            }
+            Token::Var { raw, .. }
+            | Token::List { raw, .. }
+            | Token::Select { raw, .. }
+            | Token::Random { raw, .. }
+            | Token::Timestamp { raw, .. }
+            | Token::Command { raw, .. }
+            | Token::Clipboard { raw, .. }
+            | Token::File { raw, .. } => {
+                output.push_str(&raw);
+            }
+        }
+    }
+    (output, count)
+}
+
+/// Replaces the first `from` at or after byte `skip` of a placeholder.
+fn replace_name(raw: &str, skip: usize, from: &str, to: &str) -> String {
+    match raw[skip..].find(from) {
+        Some(pos) => {
+            let start = skip + pos;
+            format!("{}{to}{}", &raw[..start], &raw[start + from.len()..])
+        }
+        None => raw.to_string(),
+    }
+}
+
+fn parse_placeholder(inner: &str, raw: &str, pools: &OptionPools) -> Option<Token> {
+    let trimmed = inner.trim();
+    // Left over only when `expand_uses` failed; keep it as written.
+    if use_target(trimmed).is_some() {
+        return None;
+    }
+    if let Some(rest) = trimmed.strip_prefix("random|") {
+        let (options_str, desc) = split_random_desc(rest);
+        let options = resolve_options(options_str, pools);
+        if options.is_empty() {
+            return Some(Token::Text(raw.to_string()));
+        }
+        let mut rng = rand::rng();
+        let choice = options.choose(&mut rng).cloned().unwrap_or_default();
+        return Some(Token::Random {
+            options,
+            choice,
+            desc: desc.map(|s| s.to_string()),
+            raw: raw.to_string(),
+        });
+    }
+
+    if let Some(rest) = trimmed.strip_prefix("select|") {
+        let (name, rest) = rest.split_once('|')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let (options_str, desc) = split_random_desc(rest);
+        let options = resolve_options(options_str, pools);
+        if options.is_empty() {
+            return Some(Token::Text(raw.to_string()));
+        }
+        return Some(Token::Select {
+            name: name.to_string(),
+            options,
+            desc: desc.map(|s| s.to_string()),
+            raw: raw.to_string(),
+        });
+    }
+
+    if let Some(token) = parse_timestamp(trimmed, raw) {
+        return Some(token);
+    }
+
+    if let Some(rest) = trimmed.strip_prefix("file|") {
+        let path = rest.trim();
+        if path.is_empty() {
+            return None;
+        }
+        return Some(Token::File {
+            path: path.to_string(),
+            resolved: None,
+            content: None,
+            raw: raw.to_string(),
+        });
+    }
+
+    if trimmed == "clipboard" {
+        return Some(Token::Clipboard {
+            content: None,
+            raw: raw.to_string(),
+        });
+    }
+
+    if let Some(rest) = trimmed.strip_prefix("cmd|") {
+        let command = rest.trim();
+        if command.is_empty() {
+            return None;
+        }
+        return Some(Token::Command {
+            command: command.to_string(),
+            output: None,
+            raw: raw.to_string(),
+        });
+    }
+
+    if let Some(rest) = trimmed.strip_prefix("list|") {
+        let mut parts = rest.splitn(2, '|');
+        let (name, required) = split_required(parts.next()?)?;
+        let (desc, rule, filters) = split_modifiers(parts.next());
+        return Some(Token::List {
+            name: name.to_string(),
+            desc,
+            required,
+            rule,
+            filters,
+            raw: raw.to_string(),
+        });
+    }
+
+    let mut parts = trimmed.splitn(2, '|');
+    let (name, required) = split_required(parts.next()?)?;
+    let (desc, rule, filters) = split_modifiers(parts.next());
+    Some(Token::Var {
+        name: name.to_string(),
+        desc,
+        required,
+        rule,
+        filters,
+        raw: raw.to_string(),
+    })
+}
+
+/// Splits `description|rule` after a placeholder name. Text after the
+/// first `|` that is not a known rule stays part of the description.
+fn split_modifiers(rest: Option<&str>) -> (Option<String>, Option<Rule>, Vec<Filter>) {
+    let rest = match rest {
+        Some(rest) => rest,
+        None => return (None, None, Vec::new()),
+    };
+    if let Some((desc, spec)) = rest.split_once('|')
+        && let Some((rule, filters)) = parse_modifiers(spec)
+    {
+        return (Some(desc.trim().to_string()), rule, filters);
+    }
+    (Some(rest.trim().to_string()), None, Vec::new())
+}
+
+/// Reads a comma-separated list of filters and at most one rule, e.g.
+/// `trim,upper` or `trim,int`. `regex:` takes the rest of the list, commas
+/// included. `None` when any item is unknown.
+fn parse_modifiers(spec: &str) -> Option<(Option<Rule>, Vec<Filter>)> {
+    let mut rule = None;
+    let mut filters = Vec::new();
+    let mut rest = spec.trim();
+    while !rest.is_empty() {
+        let (item, tail) = if rest.starts_with("regex:") {
+            (rest, "")
+        } else {
+            rest.split_once(',').unwrap_or((rest, ""))
+        };
+        if let Some(filter) = Filter::parse(item) {
+            filters.push(filter);
+        } else if rule.is_none() {
+            rule = Some(Rule::parse(item)?);
+        } else {
+            return None;
+        }
+        rest = tail.trim_start();
+    }
+    (rule.is_some() || !filters.is_empty()).then_some((rule, filters))
+}
+
+/// A placeholder name and whether it ends in the required marker `!`.
+fn split_required(name: &str) -> Option<(&str, bool)> {
+    let name = name.trim();
+    let (name, required) = match name.strip_suffix('!') {
+        Some(name) => (name.trim_end(), true),
+        None => (name, false),
+    };
+    (!name.is_empty()).then_some((name, required))
+}
+
+/// `{date}`, `{time}` and their `|format` forms. An invalid format leaves
+/// the placeholder as plain text.
+fn parse_timestamp(inner: &str, raw: &str) -> Option<Token> {
+    let (head, format) = match inner.split_once('|') {
+        Some((head, format)) => (head.trim(), Some(format.trim())),
+        None => (inner, None),
+    };
+    let default = match head {
+        "date" => DEFAULT_DATE_FORMAT,
+        "time" => DEFAULT_TIME_FORMAT,
+        _ => return None,
+    };
+    let format = format
+        .filter(|format| !format.is_empty())
+        .unwrap_or(default);
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Some(Token::Text(raw.to_string()));
+    }
+    Some(Token::Timestamp {
+        format: format.to_string(),
+        raw: raw.to_string(),
+    })
+}
+
+fn split_random_desc(input: &str) -> (&str, Option<&str>) {
+    let mut in_quote = false;
+    for (i, ch) in input.char_indices() {
+        if ch == '"' {
+            in_quote = !in_quote;
+        } else if ch == '|' && !in_quote {
+            let desc = input[i + 1..].trim();
+            if desc.is_empty() {
+                return (input, None);
+            }
+            return (&input[..i], Some(desc));
+        }
+    }
+    (input, None)
+}
+
+/// Options written inline, or looked up from a pool when written as
+/// `@name`. Unknown pools yield no options, leaving the placeholder as text.
+fn resolve_options(input: &str, pools: &OptionPools) -> Vec<String> {
+    match input.trim().strip_prefix('@') {
+        Some(pool) => pools.get(pool.trim()).cloned().unwrap_or_default(),
+        None => parse_random_options(input),
+    }
+}
+
+fn parse_random_options(input: &str) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut in_quote = false;
+    let mut current = String::new();
+    for ch in input.chars() {
+        if ch == '"' {
+            if in_quote {
+                options.push(current.clone());
+                current.clear();
+                in_quote = false;
+            } else {
+                in_quote = true;
+            }
+        } else if in_quote {
+            current.push(ch);
+        }
+    }
+    if in_quote && !current.is_empty() {
+        options.push(current);
+    }
+    if options.is_empty() {
+        options = input
+            .split_whitespace()
+            .map(|part| part.trim_matches(',').to_string())
+            .filter(|part| !part.is_empty())
+            .collect();
+    }
+    options
+}
+
+/// One field per named placeholder, in order of first use, plus one per
+/// random pick and file. Selects start on their first option and random
+/// picks on their current choice; other fields start empty.
+pub fn collect_fields(tokens: &[Token]) -> Vec<Field> {
+    use crate::models::FieldKind;
+    let mut fields: Vec<Field> = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Var {
+                name,
+                desc,
+                required,
+                rule,
+                ..
+            } => {
+                // A later `{name!}` makes the field required.
+                if let Some(field) = fields.iter_mut().find(|field| field.name == *name) {
+                    field.required |= required;
+                    field.rule = field.rule.take().or_else(|| rule.clone());
+                    continue;
+                }
+                let label = match desc {
+                    Some(desc) if !desc.is_empty() => desc.clone(),
+                    _ => name.clone(),
+                };
+                fields.push(Field {
+                    name: name.clone(),
+                    label,
+                    value: String::new(),
+                    kind: FieldKind::Var,
+                    required: *required,
+                    rule: rule.clone(),
+                });
+            }
+            Token::List {
+                name,
+                desc,
+                required,
+                rule,
+                ..
+            } => {
+                if let Some(field) = fields.iter_mut().find(|field| field.name == *name) {
+                    field.required |= required;
+                    field.rule = field.rule.take().or_else(|| rule.clone());
+                    continue;
+                }
+                let label = match desc {
+                    Some(desc) if !desc.is_empty() => desc.clone(),
+                    _ => name.clone(),
+                };
+                fields.push(Field {
+                    name: name.clone(),
+                    label,
+                    value: String::new(),
+                    kind: FieldKind::List,
+                    required: *required,
+                    rule: rule.clone(),
+                });
+            }
+            Token::Select {
+                name,
+                options,
+                desc,
+                ..
+            } => {
+                if fields.iter().any(|field| field.name == *name) {
+                    continue;
+                }
+                let label = match desc {
+                    Some(desc) if !desc.is_empty() => desc.clone(),
+                    _ => name.clone(),
+                };
+                fields.push(Field {
+                    name: name.clone(),
+                    label,
+                    value: options[0].clone(),
+                    kind: FieldKind::Select {
+                        options: options.clone(),
+                    },
+                    required: false,
+                    rule: None,
+                });
+            }
+            Token::Random {
+                options,
+                choice,
+                desc,
+                ..
+            } => {
+                let label = match desc {
+                    Some(d) if !d.is_empty() => d.clone(),
+                    _ => options.join(" | "),
+                };
+                fields.push(Field {
+                    name: format!("__random_{index}"),
+                    label,
+                    value: choice.clone(),
+                    kind: FieldKind::Random {
+                        token_index: index,
+                        pinned: false,
+                    },
+                    required: false,
+                    rule: None,
+                });
+            }
+            Token::File { path, .. } => {
+                fields.push(Field {
+                    name: format!("__file_{index}"),
+                    label: path.clone(),
+                    value: String::new(),
+                    kind: FieldKind::File { token_index: index },
+                    required: false,
+                    rule: None,
+                });
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// The prompt with every placeholder replaced by the value of its field.
+pub fn render_template(tokens: &[Token], fields: &[Field]) -> String {
+    render_template_at(tokens, fields, Local::now())
+}
+
+/// [`render_template`] with `{date}` and `{time}` read from `time` instead
+/// of the clock.
+pub fn render_template_at(tokens: &[Token], fields: &[Field], time: DateTime<Local>) -> String {
+    render_segments(tokens, fields, time)
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect()
+}
+
+/// Rolls every random placeholder from `seed`, so the same seed and body
+/// give the same picks. Tokens in `keep` still use up their draw but keep
+/// their choice, leaving the other picks as the seed alone would make them.
+/// Returns the indices of the tokens whose choice changed.
+pub fn roll_random(tokens: &mut [Token], seed: u64, keep: &[usize]) -> Vec<usize> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut changed = Vec::new();
+    for (index, token) in tokens.iter_mut().enumerate() {
+        let (options, choice) = match token {
+            Token::Random {
+                options, choice, ..
+            } if !options.is_empty() => (options, choice),
+            _ => continue,
+        };
+        let pick = &options[rng.random_range(0..options.len())];
+        if keep.contains(&index) || pick == choice {
+            continue;
+        }
+        *choice = pick.clone();
+        changed.push(index);
+    }
+    changed
+}
+
+fn apply_filters(filters: &[Filter], value: String) -> String {
+    filters
+        .iter()
+        .fold(value, |value, filter| filter.apply(&value))
+}
+
+pub fn render_segments(tokens: &[Token], fields: &[Field], time: DateTime<Local>) -> Vec<Segment> {
+    let mut segments = Vec::with_capacity(tokens.len());
+    for (index, token) in tokens.iter().enumerate() {
+        let segment = match token {
+            Token::Text(text) => Segment {
+                text: text.clone(),
+                kind: SegmentKind::Text,
+            },
+            Token::Var { name, raw, .. }
+            | Token::List { name, raw, .. }
+            | Token::Select { name, raw, .. } => {
+                let value = fields
+                    .iter()
+                    .find(|field| field.name == *name)
+                    .map(|field| field.value.as_str())
+                    .unwrap_or("");
+                let value = match token {
+                    Token::List { filters, .. } if !value.is_empty() => {
+                        apply_filters(filters, format_list(value))
+                    }
+                    Token::Var { filters, .. } if !value.is_empty() => {
+                        apply_filters(filters, value.to_string())
+                    }
+                    _ => value.to_string(),
+                };
+                if value.is_empty() {
+                    Segment {
+                        text: raw.clone(),
+                        kind: SegmentKind::Unfilled,
+                    }
+                } else {
+                    Segment {
+                        text: value,
+                        kind: SegmentKind::Value,
+                    }
+                }
+            }
+            Token::File { content, raw, .. } => match content {
+                Some(content) => Segment {
+                    text: content.clone(),
+                    kind: SegmentKind::Value,
+                },
+                None => Segment {
+                    text: raw.clone(),
+                    kind: SegmentKind::Unfilled,
+                },
+            },
+            Token::Command { output, raw, .. }
+            | Token::Clipboard {
+                content: output,
+                raw,
+            } => match output {
+                Some(output) => Segment {
+                    text: output.clone(),
+                    kind: SegmentKind::Value,
+                },
+                None => Segment {
+                    text: raw.clone(),
+                    kind: SegmentKind::Unfilled,
+                },
+            },
+            Token::Timestamp { format, .. } => Segment {
+                text: time.format(format).to_string(),
+                kind: SegmentKind::Value,
+            },
+            Token::Random { choice, raw, .. } => {
+                if choice.is_empty() {
+                    Segment {
+                        text: raw.clone(),
+                        kind: SegmentKind::Unfilled,
+                    }
+                } else {
+                    Segment {
+                        text: choice.clone(),
+                        kind: SegmentKind::Random { token_index: index },
+                    }
+                }
+            }
+        };
+        segments.push(segment);
+    }
+    segments
+}
+
+/// One `- item` line per non-empty line of a list field's value.
+fn format_list(value: &str) -> String {
+    value
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("- {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FieldKind;
+
+    #[test]
+    fn nested_headings_are_named_after_their_parents() {
+        let library = "# 评审\n\n## 代码\n检查\n\n### 安全\n列出\n\n## 文档\n改进\n";
+        let templates = parse_nested_templates(library);
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["评审/代码", "评审/代码/安全", "评审/文档"]);
+        assert_eq!(templates[0].body, "检查\n");
+        assert_eq!(templates[1].prefix, "评审/代码/");
+        assert_eq!(parse_templates(library).len(), 2);
+    }
+
+    #[test]
+    fn frontmatter_is_read_into_the_metadata() {
+        let library = "## 甲\n---\ndescription: 问候\ntags: [写作, 邮件]\n---\n\n你好\n\n## 乙\n+++\ntags = [\"代码\"]\n+++\n再见\n";
+        let templates = parse_templates(library);
+        assert_eq!(templates[0].body, "你好\n");
+        assert_eq!(templates[0].metadata.description.as_deref(), Some("问候"));
+        assert_eq!(templates[0].metadata.tags, vec!["写作", "邮件"]);
+        assert_eq!(templates[1].metadata.tags, vec!["代码"]);
+        assert_eq!(templates[1].body, "再见");
+    }
+
+    #[test]
+    fn selects_start_on_their_first_option_and_share_the_name() {
+        let mut pools = OptionPools::new();
+        pools.insert(
+            "tones".to_string(),
+            vec!["幽默".to_string(), "严肃".to_string()],
+        );
+        let tokens = parse_tokens(
+            "{select|tone|\"正式\" \"随意\"|语气} {tone} {select|mood|@tones}",
+            &pools,
+        );
+        let mut fields = collect_fields(&tokens);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].label, "语气");
+        assert!(
+            matches!(&fields[0].kind, FieldKind::Select { options } if options == &["正式", "随意"])
+        );
+        assert_eq!(render_template(&tokens, &fields), "正式 正式 幽默");
+        fields[0].value = "随意".to_string();
+        assert_eq!(render_template(&tokens, &fields), "随意 随意 幽默");
+    }
+
+    #[test]
+    fn list_fields_render_one_item_per_line() {
+        let tokens = parse_tokens("要点：\n{list|points!|要点}", &OptionPools::new());
+        let mut fields = collect_fields(&tokens);
+        assert!(matches!(fields[0].kind, FieldKind::List));
+        assert!(fields[0].is_missing());
+        assert_eq!(
+            render_template(&tokens, &fields),
+            "要点：\n{list|points!|要点}"
+        );
+        fields[0].value = "  第一\n\n第二  \n".to_string();
+        assert_eq!(render_template(&tokens, &fields), "要点：\n- 第一\n- 第二");
+    }
+
+    #[test]
+    fn dates_and_times_use_the_given_time_and_format() {
+        use chrono::TimeZone;
+        let time = Local.with_ymd_and_hms(2024, 5, 1, 14, 30, 0).unwrap();
+        let tokens = parse_tokens(
+            "{date} {time} {date|%d.%m.%Y} {date|%Q}",
+            &OptionPools::new(),
+        );
+        assert!(collect_fields(&tokens).is_empty());
+        assert_eq!(
+            render_template_at(&tokens, &[], time),
+            "2024-05-01 14:30 01.05.2024 {date|%Q}"
+        );
+    }
+
+    #[test]
+    fn uses_are_expanded_and_cycles_reported() {
+        let templates = parse_templates(
+            "## 共享/前言\n你是 {role|角色}\n\n## 甲\n{use|乙}\n\n## 乙\n{use|甲}\n",
+        );
+        assert_eq!(
+            expand_uses("{use| 共享 / 前言 }写邮件 {name}", &templates).unwrap(),
+            "你是 {role|角色}\n写邮件 {name}"
+        );
+        assert_eq!(
+            expand_uses("{use|乙}", &templates).unwrap_err(),
+            "循环引用: 乙 → 甲 → 乙"
+        );
+        assert_eq!(
+            expand_uses("{use|丙}", &templates).unwrap_err(),
+            "未找到引用的模板: 丙"
+        );
+    }
+
+    #[test]
+    fn rules_check_field_values() {
+        let tokens = parse_tokens(
+            "{count|数量|int} {price||number} {mail||regex:^\\S+@\\S+$} {list|ids|编号|int} {topic|主题|正式}",
+            &OptionPools::new(),
+        );
+        let mut fields = collect_fields(&tokens);
+        assert_eq!(fields[4].label, "主题|正式");
+        assert!(fields[4].rule.is_none());
+        assert!(fields.iter().all(|field| field.invalid().is_none()));
+
+        let values = ["3.5", "1e3", "不是邮箱", "1\n二", "随意"];
+        for (field, value) in fields.iter_mut().zip(values) {
+            field.value = value.to_string();
+        }
+        let reasons: Vec<Option<String>> = fields.iter().map(Field::invalid).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                Some("需要整数".to_string()),
+                None,
+                Some("不符合格式 ^\\S+@\\S+$".to_string()),
+                Some("需要整数".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_apply_to_their_occurrence_only() {
+        let tokens = parse_tokens(
+            "{name} {name||upper} {name||quote,json-escape}\n{code||trim,indent:2}\n{list|items||lower}",
+            &OptionPools::new(),
+        );
+        let mut fields = collect_fields(&tokens);
+        assert_eq!(fields.len(), 3);
+        fields[0].value = "Ada \"L\"".to_string();
+        fields[1].value = "  a\n\nb  ".to_string();
+        fields[2].value = "One\nTWO".to_string();
+        let rendered = render_template(&tokens, &fields);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], r#"Ada "L" ADA "L" \"Ada \\\"L\\\"\""#);
+        assert_eq!(lines[1..], ["  a", "", "  b", "- one", "- two"]);
+        assert_eq!(Filter::parse("indent:x"), None);
+        assert_eq!(Filter::Indent(2).to_string(), "indent:2");
+    }
+
+    #[test]
+    fn the_same_seed_rolls_the_same_picks() {
+        let body = "{random|\"a\" \"b\" \"c\" \"d\"} {random|\"1\" \"2\" \"3\" \"4\"}";
+        let choices = |tokens: &[Token]| -> Vec<String> {
+            tokens
+                .iter()
+                .filter_map(|token| match token {
+                    Token::Random { choice, .. } => Some(choice.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let roll = |seed: u64| {
+            let mut tokens = parse_tokens(body, &OptionPools::new());
+            roll_random(&mut tokens, seed, &[]);
+            choices(&tokens)
+        };
+        let first = roll(42);
+        assert_eq!(roll(42), first);
+        assert!((0..20).any(|seed| roll(seed) != first));
+
+        // A kept pick leaves the others as the seed alone makes them.
+        let mut tokens = parse_tokens(body, &OptionPools::new());
+        roll_random(&mut tokens, 7, &[]);
+        let seven = choices(&tokens);
+        let mut tokens = parse_tokens(body, &OptionPools::new());
+        roll_random(&mut tokens, 42, &[]);
+        roll_random(&mut tokens, 7, &[0]);
+        assert_eq!(choices(&tokens), vec![first[0].clone(), seven[1].clone()]);
+    }
+}
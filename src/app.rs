@@ -1,30 +1,232 @@
-use std::env;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
-use rand::seq::IndexedRandom;
-use ratatui::layout::Rect;
+use ratatui::layout::{Position, Rect};
 use ratatui::widgets::ListState;
 
-use crate::models::{Field, FieldKind, Template, Token, TreeItem};
-use crate::parser::{build_tree_items, collect_fields, parse_tokens, render_template};
-use crate::system::{ensure_prompts_file, load_templates, run_editor_command, set_clipboard};
+use crate::config::{Config, EditorConfig, KeyAction, MAX_SPLIT, MIN_SPLIT};
+use crate::cursor::{
+    FieldCursor, VimMode, VimState, backward_word, blank_word_start, byte_index, first_non_blank,
+    forward_word, line_bounds, next_grapheme, next_word_start, normal_limit, pop_grapheme,
+    prev_grapheme, prev_word_start, whole_line, word_end, word_range,
+};
+use crate::diagnostics::{Diagnostic, Problem, Thresholds, diagnose, find_problems};
+use crate::error::PmtError;
+use crate::llm::{AnswerEvent, ChatRequest};
+use crate::locale::{Locale, pick, set_locale, tr};
+use crate::models::{
+    Field, FieldKind, Layer, LoadWarning, OptionPools, Preset, RenderRecord, Template, Token,
+    TreeItem, TreeLine, UsageEntry,
+};
+use crate::parser::{
+    POOLS_SECTION, TokenCache, build_tree_items, collect_fields, expand_uses, parse_pools,
+    parse_tokens, rename_variable, render_template_at, roll_random,
+};
+use crate::platform::Platform;
+use crate::quality::{QualityReport, analyze};
+use crate::sanitize::strip_control;
+use crate::search::search_templates;
+use crate::stats::{TemplateStats, template_stats};
+use crate::store::{StateStore, TemplateSource};
+use crate::system::{
+    ClipboardBackend, load_config, load_file_tokens, read_clipboard_tokens, spawn_commands,
+};
 
 const DOUBLE_CLICK_MS: u128 = 400;
+const AUTOSAVE_MS: u128 = 2000;
+/// Enter or Esc this soon after typed non-ASCII text came with it from the
+/// input method, under `[editor] compose`.
+const COMPOSE_GRACE_MS: u128 = 50;
+/// Pause after which type-ahead in the list starts over.
+const TYPE_AHEAD_MS: u128 = 1000;
+/// How long status messages and the reroll highlight stay on screen.
+pub(crate) const STATUS_DURATION_MS: u128 = 1500;
+pub(crate) const REROLL_HIGHLIGHT_MS: u128 = 1000;
+/// How often a streaming answer or running commands are checked again.
+const STREAM_REDRAW_MS: u128 = 100;
+/// How often background fetches of templates are checked on.
+const FETCH_CHECK_MS: u128 = 250;
+const PREVIEW_SCROLL_STEP: u16 = 5;
+const WHEEL_SCROLL_STEP: u16 = 3;
+/// Percent Ctrl+Left/Right moves the divider between fields and preview by.
+const SPLIT_STEP: u16 = 5;
+/// Undo steps kept per editor.
+const UNDO_LIMIT: usize = 100;
 
 #[derive(Clone, Debug)]
 pub(crate) enum View {
     List,
     Editor,
+    Form,
     Error,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FormFocus {
+    Name,
+    Body,
+}
+
+/// State of the new/edit template form opened from the list view.
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateForm {
+    /// Template being edited, or `None` when creating a new one.
+    pub(crate) template_index: Option<usize>,
+    pub(crate) name: String,
+    pub(crate) body: String,
+    pub(crate) focus: FormFocus,
+    pub(crate) status: Option<StatusMessage>,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct StatusMessage {
     pub(crate) text: String,
     pub(crate) since: Instant,
 }
 
+impl StatusMessage {
+    /// Whether the message is still shown at `now`.
+    pub(crate) fn shown_at(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.since).as_millis() < STATUS_DURATION_MS
+    }
+}
+
+/// The open template changed on disk during a reload; the user decides
+/// whether to carry the entered values over to the new version.
+#[derive(Clone, Debug)]
+pub(crate) struct ReloadConflict {
+    pub(crate) template_index: usize,
+    pub(crate) body: String,
+}
+
+/// Option list opened on a select or random field. Moving the highlight
+/// previews the option in place; `original` is restored on cancel.
+#[derive(Clone, Debug)]
+pub(crate) struct OptionPicker {
+    pub(crate) options: Vec<String>,
+    pub(crate) selected: usize,
+    pub(crate) original: String,
+    /// Text of a one-off option being typed for a random field.
+    pub(crate) adding: Option<String>,
+}
+
+/// New name typed for the selected template, `d` to duplicate it or `r` to
+/// rename it. A `/` in the name moves it to another folder.
+#[derive(Clone, Debug)]
+pub(crate) struct NamePrompt {
+    pub(crate) action: NameAction,
+    pub(crate) template_index: usize,
+    pub(crate) name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum NameAction {
+    Duplicate,
+    Rename,
+}
+
+/// Tags of all templates with their template counts, opened with `t`.
+/// `chosen` becomes the tag filter on Enter.
+#[derive(Clone, Debug)]
+pub(crate) struct TagPicker {
+    pub(crate) tags: Vec<(String, usize)>,
+    pub(crate) selected: usize,
+    pub(crate) chosen: Vec<String>,
+}
+
+/// Answer of the chat endpoint, shown in a pane next to the preview.
+#[derive(Clone, Debug)]
+pub(crate) struct Answer {
+    pub(crate) model: String,
+    pub(crate) text: String,
+    /// Open while the answer is streaming in. Shared so the editor state
+    /// stays cloneable; dropping it ends the request.
+    pub(crate) stream: Option<Rc<Receiver<AnswerEvent>>>,
+    pub(crate) error: Option<String>,
+    pub(crate) scroll: u16,
+    /// Keeps the newest text in view until the user scrolls up.
+    pub(crate) follow: bool,
+}
+
+/// Command placeholders running on a background thread.
+#[derive(Clone, Debug)]
+pub(crate) struct CommandRun {
+    /// Results by token index. Shared so the editor state stays cloneable.
+    pub(crate) results: Rc<Receiver<(usize, Result<String, PmtError>)>>,
+    pub(crate) done: usize,
+    pub(crate) total: usize,
+    pub(crate) errors: Vec<String>,
+}
+
+/// Kind of change recorded for undo. Runs of typing or deleting in one
+/// field are undone as a single step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Edit {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// Field values, pins and random choices as they were before an edit.
+#[derive(Clone, Debug)]
+pub(crate) struct EditSnapshot {
+    fields: Vec<Field>,
+    choices: Vec<(usize, String)>,
+}
+
+/// Presets of the open template, opened with Ctrl+O.
+#[derive(Clone, Debug)]
+pub(crate) struct PresetPicker {
+    pub(crate) presets: Vec<Preset>,
+    pub(crate) selected: usize,
+}
+
+/// Line-wise selection in the preview. `anchor` is set once `v` starts the
+/// selection; until then only the cursor line is marked.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PreviewSelection {
+    pub(crate) cursor: usize,
+    pub(crate) anchor: Option<usize>,
+}
+
+impl PreviewSelection {
+    /// First and last selected line.
+    pub(crate) fn range(&self) -> (usize, usize) {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        (anchor.min(self.cursor), anchor.max(self.cursor))
+    }
+}
+
+/// Library-wide rename of the active field's variable. `plan` is filled
+/// once the new name is entered and waits for confirmation.
+#[derive(Clone, Debug)]
+pub(crate) struct RenamePrompt {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) plan: Option<Vec<RenameChange>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct RenameChange {
+    pub(crate) template_index: usize,
+    pub(crate) body: String,
+    pub(crate) count: usize,
+}
+
+/// Where the user left an editor, restored when the template is reopened
+/// in the same session.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct EditorPosition {
+    pub(crate) active_field: usize,
+    pub(crate) field_scroll: usize,
+    pub(crate) preview_scroll: u16,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct EditorState {
     pub(crate) template_index: usize,
@@ -32,291 +234,2765 @@ pub(crate) struct EditorState {
     pub(crate) fields: Vec<Field>,
     pub(crate) active_field: usize,
     pub(crate) field_scroll: usize,
+    pub(crate) preview_scroll: u16,
     pub(crate) fields_area: Rect,
-    pub(crate) field_height: u16,
+    /// Preview pane drawn in the last frame, for wheel scrolling.
+    pub(crate) preview_area: Rect,
+    /// Screen rects of the fields drawn in the last frame, for hit-testing.
+    pub(crate) field_rects: Vec<(usize, Rect)>,
     pub(crate) status: Option<StatusMessage>,
+    /// Seed the random picks were last rolled from.
+    pub(crate) seed: u64,
+    pub(crate) pending_draft: Option<Vec<(String, String)>>,
+    pub(crate) draft_dirty: bool,
+    pub(crate) last_autosave: Instant,
+    pub(crate) reroll_highlight: Option<(Vec<usize>, Instant)>,
+    pub(crate) reload_conflict: Option<ReloadConflict>,
+    pub(crate) picker: Option<OptionPicker>,
+    pub(crate) rename: Option<RenamePrompt>,
+    pub(crate) selection: Option<PreviewSelection>,
+    /// Name being typed for a new preset (Ctrl+S).
+    pub(crate) preset_name: Option<String>,
+    /// Seed being typed to roll the random picks from.
+    pub(crate) seed_prompt: Option<String>,
+    pub(crate) undo: Vec<EditSnapshot>,
+    pub(crate) redo: Vec<EditSnapshot>,
+    /// Last recorded edit and its field, for merging runs of typing.
+    pub(crate) last_edit: Option<(Edit, usize)>,
+    /// Cursor in the active field, when vim mode or the readline keys are
+    /// on. Without it typing appends to the value.
+    pub(crate) cursor: Option<FieldCursor>,
+    /// Vim-style editing state, when `[editor] vim` is on.
+    pub(crate) vim: Option<VimState>,
+    /// Ctrl+A/E/W/U and Alt+B/F edit at the cursor (`[editor] readline`).
+    pub(crate) readline: bool,
+    pub(crate) preset_picker: Option<PresetPicker>,
+    /// Waiting for the user to allow the template's `{cmd|...}` commands.
+    pub(crate) command_prompt: bool,
+    /// The template's commands while they run.
+    pub(crate) commands: Option<CommandRun>,
+    pub(crate) answer: Option<Answer>,
+    /// Answer pane drawn in the last frame, for wheel scrolling.
+    pub(crate) answer_area: Rect,
+    /// Fields and preview drawn in the last frame, for dragging the divider.
+    pub(crate) content_area: Rect,
+    /// The divider between fields and preview is held with the mouse.
+    pub(crate) dragging_divider: bool,
 }
 
 #[derive(Clone, Debug)]
-pub(crate) struct App {
+pub(crate) struct App<S: TemplateSource, T: StateStore, P: Platform> {
+    pub(crate) source: S,
+    pub(crate) store: T,
+    pub(crate) platform: P,
     pub(crate) templates: Vec<Template>,
+    pub(crate) pools: OptionPools,
     pub(crate) tree_items: Vec<TreeItem>,
+    /// Rows of `tree_items` as drawn, built when the list changes rather
+    /// than every frame, and whether they were built for a narrow terminal.
+    pub(crate) tree_lines: Option<(bool, Vec<TreeLine>)>,
     pub(crate) list_state: ListState,
     pub(crate) list_scroll: usize,
     pub(crate) view: View,
     pub(crate) editor: Option<EditorState>,
-    pub(crate) error_message: Option<String>,
+    pub(crate) form: Option<TemplateForm>,
+    pub(crate) error_message: Option<PmtError>,
     pub(crate) last_click: Option<(usize, Instant)>,
+    /// When non-ASCII text was last typed, for `[editor] compose`.
+    pub(crate) composed_at: Option<Instant>,
     pub(crate) tree_area: Rect,
     pub(crate) should_quit: bool,
     pub(crate) list_status: Option<StatusMessage>,
     pub(crate) needs_redraw: bool,
+    pub(crate) exit_output: Option<String>,
+    /// Query typed after `/`; while set the list shows ranked matches
+    /// instead of the tree.
+    pub(crate) search: Option<String>,
+    pub(crate) thresholds: Thresholds,
+    /// Templates over a size or complexity threshold, refreshed on load.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    /// Mistakes found in the templates, refreshed on load.
+    pub(crate) problems: Vec<Problem>,
+    /// The diagnostics and problems are yet to be worked out. At startup
+    /// this waits for the first frame, so a large library shows its tree
+    /// before every body has been parsed.
+    pub(crate) analysis_due: bool,
+    pub(crate) token_cache: TokenCache,
+    /// Files and sections skipped while loading, listed in a panel that
+    /// opens whenever they change.
+    pub(crate) load_warnings: Vec<LoadWarning>,
+    pub(crate) show_load_warnings: bool,
+    pub(crate) show_diagnostics: bool,
+    /// Full-screen key reference, opened with `?` or F1 from any view.
+    pub(crate) show_help: bool,
+    pub(crate) help_scroll: u16,
+    /// Quality report of the selected template, shown as a popup.
+    pub(crate) quality: Option<(usize, QualityReport)>,
+    /// Usage of every template, opened with `S`.
+    pub(crate) stats: Option<Vec<TemplateStats>>,
+    pub(crate) stats_scroll: u16,
+    /// Templates are being fetched in the background, e.g. a remote source
+    /// without a cached copy at startup.
+    pub(crate) fetching: bool,
+    /// Background fetches already loaded, from `TemplateSource::fetched`.
+    pub(crate) fetched: usize,
+    pub(crate) name_prompt: Option<NamePrompt>,
+    /// Template grabbed with `m`; Up/Down move it within its folder.
+    pub(crate) moving: Option<String>,
+    /// Tags a template must all carry to be listed.
+    pub(crate) tag_filter: Vec<String>,
+    pub(crate) tag_picker: Option<TagPicker>,
+    /// Raw body of the selected template shown beside the list, toggled
+    /// with `p`.
+    pub(crate) show_body: bool,
+    /// Editor positions by template name.
+    pub(crate) positions: HashMap<String, EditorPosition>,
+    /// Tree selection and scroll to return to when search is closed.
+    pub(crate) tree_position: Option<(usize, usize)>,
+    /// Letters typed in the list to jump by name, and when the last came.
+    pub(crate) type_ahead: Option<(String, Instant)>,
+    /// Width of the editor's field column set with Ctrl+Left/Right or the
+    /// mouse, over `[layout] split`.
+    pub(crate) split: Option<u16>,
+    pub(crate) config: Config,
 }
 
-impl App {
-    pub(crate) fn load() -> Self {
-        match load_templates() {
-            Ok(templates) => {
-                let tree_items = build_tree_items(&templates);
-                let mut list_state = ListState::default();
-                if !tree_items.is_empty() {
-                    list_state.select(Some(0));
-                }
-                Self {
-                    templates,
-                    tree_items,
-                    list_state,
-                    list_scroll: 0,
-                    view: View::List,
-                    editor: None,
-                    error_message: None,
-                    last_click: None,
-                    tree_area: Rect::default(),
-                    should_quit: false,
-                    list_status: None,
-                    needs_redraw: false,
-                }
-            }
-            Err(err) => Self {
-                templates: Vec::new(),
-                tree_items: Vec::new(),
-                list_state: ListState::default(),
-                list_scroll: 0,
-                view: View::Error,
-                editor: None,
-                error_message: Some(err),
-                last_click: None,
-                tree_area: Rect::default(),
-                should_quit: false,
-                list_status: None,
-                needs_redraw: false,
-            },
+impl<S: TemplateSource, T: StateStore, P: Platform> App<S, T, P> {
+    pub(crate) fn new(source: S, store: T, platform: P) -> Self {
+        let split = store.load_split();
+        let loaded = source.load();
+        let (fetching, fetched) = (source.fetching(), source.fetched());
+        let mut app = Self {
+            source,
+            store,
+            platform,
+            templates: Vec::new(),
+            pools: OptionPools::new(),
+            tree_items: Vec::new(),
+            tree_lines: None,
+            list_state: ListState::default(),
+            list_scroll: 0,
+            view: View::List,
+            editor: None,
+            form: None,
+            error_message: None,
+            last_click: None,
+            composed_at: None,
+            tree_area: Rect::default(),
+            should_quit: false,
+            list_status: None,
+            needs_redraw: false,
+            exit_output: None,
+            search: None,
+            thresholds: Thresholds::from_env(),
+            diagnostics: Vec::new(),
+            problems: Vec::new(),
+            analysis_due: false,
+            token_cache: TokenCache::default(),
+            load_warnings: Vec::new(),
+            show_load_warnings: false,
+            show_diagnostics: false,
+            show_help: false,
+            help_scroll: 0,
+            quality: None,
+            stats: None,
+            stats_scroll: 0,
+            fetching,
+            fetched,
+            name_prompt: None,
+            moving: None,
+            tag_filter: Vec::new(),
+            tag_picker: None,
+            show_body: false,
+            positions: HashMap::new(),
+            tree_position: None,
+            type_ahead: None,
+            split,
+            config: Config::default(),
+        };
+        match loaded {
+            Ok((templates, load_warnings)) => {
+                app.tree_items = build_tree_items(&templates, |_| true);
+                if !app.tree_items.is_empty() {
+                    app.list_state.select(Some(0));
+                }
+                app.pools = parse_pools(&templates);
+                app.templates = templates;
+                app.show_load_warnings = !load_warnings.is_empty();
+                app.load_warnings = load_warnings;
+                app.analysis_due = true;
+            }
+            Err(err) => {
+                app.view = View::Error;
+                app.error_message = Some(err);
+            }
+        }
+        app
+    }
+
+    pub(crate) fn tick(&mut self) {
+        let now = self.platform.now();
+        let due = self.editor.as_ref().is_some_and(|editor| {
+            editor.draft_dirty
+                && now
+                    .saturating_duration_since(editor.last_autosave)
+                    .as_millis()
+                    >= AUTOSAVE_MS
+        });
+        if due {
+            self.autosave_draft();
+        }
+        if self
+            .type_ahead
+            .as_ref()
+            .is_some_and(|(_, at)| now.saturating_duration_since(*at).as_millis() >= TYPE_AHEAD_MS)
+        {
+            self.type_ahead = None;
+        }
+        if let Some(editor) = self.editor.as_mut() {
+            editor.receive_commands(now);
+            if let Some(answer) = editor.answer.as_mut() {
+                answer.receive();
+            }
+        }
+        self.fetching = self.source.fetching();
+        let fetched = self.source.fetched();
+        if fetched != self.fetched {
+            self.fetched = fetched;
+            self.on_files_changed();
+        }
+    }
+
+    /// When the screen next changes without input: a status message or the
+    /// reroll highlight running out, a draft autosaved, type-ahead dropped,
+    /// more of a streaming answer or command output come in or a background
+    /// fetch finish. None while nothing is pending.
+    pub(crate) fn next_change(&self) -> Option<Instant> {
+        let now = self.platform.now();
+        let after = |since: Instant, ms: u128| since + Duration::from_millis(ms as u64);
+        let mut deadlines = Vec::new();
+        if let Some(status) = &self.list_status {
+            deadlines.push(after(status.since, STATUS_DURATION_MS));
+        }
+        if let Some((_, at)) = &self.type_ahead {
+            deadlines.push(after(*at, TYPE_AHEAD_MS));
+        }
+        if let Some(editor) = &self.editor {
+            if let Some(status) = &editor.status {
+                deadlines.push(after(status.since, STATUS_DURATION_MS));
+            }
+            if let Some((_, since)) = &editor.reroll_highlight {
+                deadlines.push(after(*since, REROLL_HIGHLIGHT_MS));
+            }
+            if editor.draft_dirty {
+                deadlines.push(after(editor.last_autosave, AUTOSAVE_MS));
+            }
+            let streaming = editor
+                .answer
+                .as_ref()
+                .is_some_and(|answer| answer.stream.is_some());
+            if streaming || editor.commands.is_some() {
+                deadlines.push(after(now, STREAM_REDRAW_MS));
+            }
         }
+        if self.fetching {
+            deadlines.push(after(now, FETCH_CHECK_MS));
+        }
+        deadlines.into_iter().filter(|at| *at > now).min()
     }
 
     pub(crate) fn on_key(&mut self, key: KeyEvent) {
+        if self.config.editor.compose && self.is_compose_echo(&key) {
+            return;
+        }
+        // Input methods may hand over text where none is typed; it is never
+        // taken as a command. The list uses it to jump by name.
+        if matches!(key.code, KeyCode::Char(ch) if !ch.is_ascii())
+            && !self.accepts_text()
+            && !matches!(self.view, View::List)
+        {
+            return;
+        }
+        if self.show_help {
+            self.on_key_help(key);
+            return;
+        }
+        // A character bound to help is still typed where text is entered.
+        if self.config.keys.is(KeyAction::Help, &key)
+            && !(matches!(key.code, KeyCode::Char(_)) && self.accepts_text())
+        {
+            self.show_help = true;
+            self.help_scroll = 0;
+            return;
+        }
         match self.view {
             View::List => self.on_key_list(key),
             View::Editor => self.on_key_editor(key),
+            View::Form => self.on_key_form(key),
             View::Error => self.on_key_error(key),
         }
     }
 
+    /// The terminal changed size. Screen areas kept for the mouse are stale
+    /// until the next frame, so they are dropped, and the screen is cleared
+    /// so nothing of the old layout is left behind.
+    pub(crate) fn on_resize(&mut self) {
+        self.needs_redraw = true;
+        self.tree_area = Rect::default();
+        self.last_click = None;
+        if let Some(editor) = self.editor.as_mut() {
+            editor.fields_area = Rect::default();
+            editor.preview_area = Rect::default();
+            editor.answer_area = Rect::default();
+            editor.content_area = Rect::default();
+            editor.field_rects.clear();
+            editor.dragging_divider = false;
+        }
+    }
+
+    /// Bracketed paste. A paste into a text field is one edit with its
+    /// newlines kept; other text inputs get it on one line.
+    pub(crate) fn on_paste(&mut self, text: &str) {
+        if self.show_help || !self.accepts_text() {
+            return;
+        }
+        let text = text.replace("\r\n", "\n");
+        if matches!(self.view, View::Editor)
+            && let Some(editor) = self.editor.as_mut()
+            && editor.paste(&text)
+        {
+            return;
+        }
+        for ch in text.chars() {
+            let ch = if ch == '\n' { ' ' } else { ch };
+            if !ch.is_control() {
+                self.on_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE));
+            }
+        }
+    }
+
+    /// Whether `key` is an Enter or Esc some input methods pass on right
+    /// after the text they commit, which would otherwise move on or leave.
+    fn is_compose_echo(&mut self, key: &KeyEvent) -> bool {
+        let now = self.platform.now();
+        match key.code {
+            KeyCode::Char(ch) if !ch.is_ascii() => {
+                self.composed_at = Some(now);
+                false
+            }
+            KeyCode::Enter | KeyCode::Esc => self
+                .composed_at
+                .take()
+                .is_some_and(|at| now.saturating_duration_since(at).as_millis() < COMPOSE_GRACE_MS),
+            _ => {
+                self.composed_at = None;
+                false
+            }
+        }
+    }
+
     pub(crate) fn on_mouse(&mut self, mouse: MouseEvent) {
+        if self.show_help {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => {
+                    self.help_scroll = self.help_scroll.saturating_add(WHEEL_SCROLL_STEP);
+                }
+                MouseEventKind::ScrollUp => {
+                    self.help_scroll = self.help_scroll.saturating_sub(WHEEL_SCROLL_STEP);
+                }
+                _ => {}
+            }
+            return;
+        }
         match self.view {
             View::List => self.on_mouse_list(mouse),
             View::Editor => self.on_mouse_editor(mouse),
-            View::Error => {}
+            View::Form | View::Error => {}
         }
     }
 
-    fn on_key_error(&mut self, key: KeyEvent) {
+    fn on_key_help(&mut self, key: KeyEvent) {
         match key.code {
-            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+            _ if self.config.keys.is(KeyAction::Help, &key) => self.show_help = false,
+            KeyCode::Esc | KeyCode::Char('q') => self.show_help = false,
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.help_scroll = self.help_scroll.saturating_add(1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.help_scroll = self.help_scroll.saturating_sub(1);
+            }
+            KeyCode::PageDown => {
+                self.help_scroll = self.help_scroll.saturating_add(PREVIEW_SCROLL_STEP);
+            }
+            KeyCode::PageUp => {
+                self.help_scroll = self.help_scroll.saturating_sub(PREVIEW_SCROLL_STEP);
+            }
             _ => {}
         }
     }
 
-    fn on_key_list(&mut self, key: KeyEvent) {
+    /// Whether typed characters go into a text input in the current view.
+    fn accepts_text(&self) -> bool {
+        match self.view {
+            View::Form => true,
+            View::Error => false,
+            View::List => {
+                self.search.is_some() || self.name_prompt.is_some() || self.tag_picker.is_some()
+            }
+            View::Editor => self.editor.as_ref().is_some_and(EditorState::accepts_text),
+        }
+    }
+
+    fn on_key_error(&mut self, key: KeyEvent) {
         match key.code {
             KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
-            KeyCode::Down | KeyCode::Char('j') => self.move_list(1),
-            KeyCode::Up | KeyCode::Char('k') => self.move_list(-1),
-            KeyCode::Enter => self.open_selected_template(),
-            KeyCode::Char('e') => self.open_prompts_in_editor(),
             _ => {}
         }
     }
 
-    fn on_mouse_list(&mut self, mouse: MouseEvent) {
-        if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
+    fn on_key_list(&mut self, key: KeyEvent) {
+        if self.show_load_warnings {
+            if matches!(key.code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q')) {
+                self.show_load_warnings = false;
+            }
             return;
         }
-        if let Some(index) = self.index_from_mouse(mouse) {
-            self.list_state.select(Some(index));
-            let now = Instant::now();
-            if let Some((last_index, last_time)) = self.last_click {
-                if last_index == index && last_time.elapsed().as_millis() <= DOUBLE_CLICK_MS {
-                    self.open_selected_template();
-                }
+        if self.show_diagnostics {
+            if matches!(
+                key.code,
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q' | 'D')
+            ) {
+                self.show_diagnostics = false;
             }
-            self.last_click = Some((index, now));
-        }
-    }
-
-    fn on_mouse_editor(&mut self, mouse: MouseEvent) {
-        if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
             return;
         }
-        let editor = match self.editor.as_mut() {
-            Some(editor) => editor,
-            None => return,
-        };
-        if editor.fields.is_empty() {
+        if self.quality.is_some() {
+            if matches!(
+                key.code,
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q' | 'A')
+            ) {
+                self.quality = None;
+            }
             return;
         }
-        let area = editor.fields_area;
-        if area.width == 0 || area.height == 0 {
+        if self.stats.is_some() {
+            self.on_key_stats(key);
             return;
         }
-        if mouse.column < area.x
-            || mouse.column >= area.x + area.width
-            || mouse.row < area.y
-            || mouse.row >= area.y + area.height
-        {
+        if self.name_prompt.is_some() {
+            self.on_key_name_prompt(key);
             return;
         }
-        let field_height = editor.field_height;
-        if field_height == 0 {
+        if self.moving.is_some() {
+            self.on_key_move(key);
             return;
         }
-        let view_capacity = (area.height / field_height) as usize;
-        let row_offset = (mouse.row - area.y) / field_height;
-        if row_offset as usize >= view_capacity {
+        if self.tag_picker.is_some() {
+            self.on_key_tag_picker(key);
             return;
         }
-        let index = editor.field_scroll + row_offset as usize;
-        if index < editor.fields.len() {
-            editor.active_field = index;
+        if self.search.is_some() {
+            self.on_key_search(key);
+            return;
         }
-    }
-
-    fn on_key_editor(&mut self, key: KeyEvent) {
-        let editor = match self.editor.as_mut() {
-            Some(editor) => editor,
-            None => return,
-        };
-
+        let typed = self.type_ahead.take();
+        let keys = &self.config.keys;
         match key.code {
-            KeyCode::Esc => {
-                self.view = View::List;
-                return;
-            }
-            KeyCode::Tab | KeyCode::Down => {
-                editor.next_field();
-            }
-            KeyCode::Up => {
-                editor.prev_field();
-            }
-            KeyCode::Left => {
-                editor.cycle_random(-1);
-            }
-            KeyCode::Right => {
-                editor.cycle_random(1);
+            _ if keys.is(KeyAction::Quit, &key) => self.should_quit = true,
+            _ if keys.is(KeyAction::OpenEditor, &key) => self.open_prompts_in_editor(),
+            KeyCode::Down | KeyCode::Char('j') => self.move_list(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_list(-1),
+            KeyCode::PageDown => self.move_list(self.list_page()),
+            KeyCode::PageUp => self.move_list(-self.list_page()),
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.move_list((self.list_page() / 2).max(1));
             }
-            KeyCode::Backspace => {
-                editor.backspace();
+            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.move_list(-(self.list_page() / 2).max(1));
             }
-            KeyCode::F(5) => {
-                editor.reroll_random();
+            KeyCode::Home => self.move_list(-(self.tree_items.len() as isize)),
+            KeyCode::End => self.move_list(self.tree_items.len() as isize),
+            KeyCode::Enter => self.open_selected_template(),
+            KeyCode::Char('n') => self.open_new_template_form(),
+            KeyCode::Char('E') => self.open_edit_template_form(),
+            KeyCode::Char('D') => self.show_diagnostics = true,
+            KeyCode::Char('A') => self.open_quality(),
+            KeyCode::Char('S') => {
+                let usage = self.store.load_usage();
+                self.stats = Some(template_stats(&self.templates, &self.pools, &usage));
+                self.stats_scroll = 0;
             }
-            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.copy_rendered();
+            KeyCode::Char('d') => self.open_name_prompt(NameAction::Duplicate),
+            KeyCode::Char('r') => self.open_name_prompt(NameAction::Rename),
+            KeyCode::Char('t') => self.open_tag_picker(),
+            KeyCode::Char('p') => self.show_body = !self.show_body,
+            KeyCode::Char('m') => match self.selected_template_index() {
+                Some(index) => self.moving = Some(self.templates[index].name.clone()),
+                None => self.set_list_status(pick("请先选择一个模板", "Select a template first")),
+            },
+            KeyCode::F(5) => self.refresh_from_disk(),
+            KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.refresh_from_disk();
             }
-            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                editor.reroll_random();
+            KeyCode::Char('/') => {
+                self.tree_position = self
+                    .list_state
+                    .selected()
+                    .map(|selected| (selected, self.list_scroll));
+                self.search = Some(String::new());
+                self.refresh_tree_items();
             }
-            KeyCode::Char(ch) => {
-                if ch == ' ' && editor.active_field_is_random() {
-                    editor.toggle_pin();
-                } else {
-                    editor.push_char(ch);
-                }
+            KeyCode::Char(ch)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.jump_to_typed(typed, ch);
             }
             _ => {}
         }
     }
 
-    fn move_list(&mut self, delta: isize) {
+    /// Type-ahead for letters no command takes: jumps to the next template
+    /// whose name starts with the typed text, else to one containing it.
+    /// While the typing goes on the selection stays as long as it matches.
+    fn jump_to_typed(&mut self, typed: Option<(String, Instant)>, ch: char) {
+        let (mut buffer, first) = match typed {
+            Some((buffer, _)) => (buffer, 0),
+            None => (String::new(), 1),
+        };
+        buffer.push(ch);
+        self.type_ahead = Some((buffer.clone(), self.platform.now()));
         let len = self.tree_items.len();
         if len == 0 {
             return;
         }
-        let current = self.list_state.selected().unwrap_or(0) as isize;
-        let next = (current + delta).clamp(0, (len - 1) as isize) as usize;
-        self.list_state.select(Some(next));
+        let needle = buffer.to_lowercase();
+        let current = self.list_state.selected().unwrap_or(0);
+        let order: Vec<usize> = (0..len)
+            .map(|offset| (current + first + offset) % len)
+            .collect();
+        let names: Vec<Option<(String, String)>> = order
+            .iter()
+            .map(|index| {
+                let item = &self.tree_items[*index];
+                let template = &self.templates[item.template_index?];
+                Some((item.label.to_lowercase(), template.name.to_lowercase()))
+            })
+            .collect();
+        let starts = names.iter().position(|names| {
+            names.as_ref().is_some_and(|(label, name)| {
+                label.starts_with(&needle) || name.starts_with(&needle)
+            })
+        });
+        let found = starts.or_else(|| {
+            names.iter().position(|names| {
+                names
+                    .as_ref()
+                    .is_some_and(|(_, name)| name.contains(&needle))
+            })
+        });
+        match found {
+            Some(position) => self.list_state.select(Some(order[position])),
+            None => self.set_list_status(&tr!(
+                "没有名称含 {buffer} 的模板",
+                "No template name contains {buffer}"
+            )),
+        }
     }
 
-    fn open_selected_template(&mut self) {
-        let index = match self.list_state.selected() {
-            Some(index) => index,
-            None => return,
-        };
-        let template_index = match self
-            .tree_items
-            .get(index)
-            .and_then(|item| item.template_index)
-        {
-            Some(template_index) => template_index,
-            None => return,
-        };
-        let template = match self.templates.get(template_index) {
-            Some(template) => template.clone(),
+    fn on_key_search(&mut self, key: KeyEvent) {
+        let query = match self.search.as_mut() {
+            Some(query) => query,
             None => return,
         };
-        let editor = EditorState::new(template_index, &template.body);
-        self.editor = Some(editor);
-        self.view = View::Editor;
+        match key.code {
+            KeyCode::Esc => {
+                self.search = None;
+                self.refresh_tree_items();
+                if let Some((selected, scroll)) = self.tree_position.take()
+                    && selected < self.tree_items.len()
+                {
+                    self.list_state.select(Some(selected));
+                    self.list_scroll = scroll;
+                }
+            }
+            KeyCode::Down => self.move_list(1),
+            KeyCode::Up => self.move_list(-1),
+            KeyCode::Enter => self.open_selected_template(),
+            KeyCode::Backspace => {
+                pop_grapheme(query);
+                self.refresh_tree_items();
+            }
+            KeyCode::Char(ch) => {
+                query.push(ch);
+                self.refresh_tree_items();
+            }
+            _ => {}
+        }
     }
 
-    fn copy_rendered(&mut self) {
-        let editor = match self.editor.as_mut() {
-            Some(editor) => editor,
-            None => return,
+    /// Rebuilds the list from the templates: the folder tree, or a flat list
+    /// of ranked matches while a search query is set. Only templates with
+    /// every tag of the tag filter are listed. Resets the selection.
+    fn refresh_tree_items(&mut self) {
+        let tags = &self.tag_filter;
+        let include = |template: &Template| tags.iter().all(|tag| template.has_tag(tag));
+        self.tree_items = match self.search.as_deref() {
+            Some(query) if !query.trim().is_empty() => search_templates(&self.templates, query)
+                .into_iter()
+                .filter(|hit| include(&self.templates[hit.template_index]))
+                .map(|hit| TreeItem {
+                    label: self.templates[hit.template_index].name.clone(),
+                    depth: 0,
+                    template_index: Some(hit.template_index),
+                })
+                .collect(),
+            _ => build_tree_items(&self.templates, include),
         };
-        let rendered = render_template(&editor.tokens, &editor.fields);
-        match set_clipboard(&rendered) {
-            Ok(_) => editor.set_status("已复制"),
-            Err(err) => editor.set_status(&err),
+        self.tree_lines = None;
+        let mut list_state = ListState::default();
+        if !self.tree_items.is_empty() {
+            list_state.select(Some(0));
         }
+        self.list_state = list_state;
+        self.list_scroll = 0;
     }
 
-    fn set_list_status(&mut self, text: &str) {
-        self.list_status = Some(StatusMessage {
-            text: text.to_string(),
-            since: Instant::now(),
+    fn open_new_template_form(&mut self) {
+        let folder = self.selected_folder_prefix();
+        self.form = Some(TemplateForm {
+            template_index: None,
+            name: folder,
+            body: String::new(),
+            focus: FormFocus::Name,
+            status: None,
         });
+        self.view = View::Form;
     }
 
-    fn open_prompts_in_editor(&mut self) {
-        let editor = match env::var("EDITOR") {
-            Ok(value) if !value.trim().is_empty() => value,
-            _ => {
-                self.set_list_status("未设置 EDITOR 环境变量");
-                return;
-            }
-        };
-
-        let path = match ensure_prompts_file() {
-            Ok(path) => path,
-            Err(err) => {
-                self.set_list_status(&err);
-                return;
-            }
-        };
-
-        if let Err(err) = run_editor_command(&editor, &path) {
-            self.set_list_status(&err);
-            return;
-        }
-
-        self.needs_redraw = true;
-
-        match load_templates() {
-            Ok(templates) => {
-                self.tree_items = build_tree_items(&templates);
-                self.templates = templates;
-                let mut list_state = ListState::default();
-                if !self.tree_items.is_empty() {
-                    list_state.select(Some(0));
-                }
-                self.list_state = list_state;
-                self.list_scroll = 0;
-            }
-            Err(err) => self.set_list_status(&err),
+    fn open_edit_template_form(&mut self) {
+        match self.selected_template_index() {
+            Some(index) => self.edit_template(index),
+            None => self.set_list_status(pick("请选择一个模板", "Select a template")),
         }
     }
 
+    fn edit_template(&mut self, template_index: usize) {
+        let template = &self.templates[template_index];
+        self.form = Some(TemplateForm {
+            template_index: Some(template_index),
+            name: template.name.clone(),
+            body: template.body.trim_end_matches(['\n', '\r']).to_string(),
+            focus: FormFocus::Body,
+            status: None,
+        });
+        self.view = View::Form;
+    }
+
+    fn on_key_form(&mut self, key: KeyEvent) {
+        let form = match self.form.as_mut() {
+            Some(form) => form,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => {
+                self.form = None;
+                self.view = View::List;
+            }
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.save_template_form();
+            }
+            KeyCode::Tab if form.template_index.is_none() => {
+                form.focus = match form.focus {
+                    FormFocus::Name => FormFocus::Body,
+                    FormFocus::Body => FormFocus::Name,
+                };
+            }
+            KeyCode::Enter => match form.focus {
+                FormFocus::Name => form.focus = FormFocus::Body,
+                FormFocus::Body => form.body.push('\n'),
+            },
+            KeyCode::Backspace => {
+                pop_grapheme(form.focused_value());
+            }
+            KeyCode::Char(ch) => form.focused_value().push(ch),
+            _ => {}
+        }
+    }
+
+    fn save_template_form(&mut self) {
+        let form = match self.form.as_mut() {
+            Some(form) => form,
+            None => return,
+        };
+        let name = normalize_name(&form.name);
+        if name.is_empty() {
+            form.set_status(
+                pick("模板名称不能为空", "The template name cannot be empty"),
+                self.platform.now(),
+            );
+            return;
+        }
+        let result = match form.template_index {
+            Some(index) => match self.templates.get(index) {
+                Some(template) => self.source.replace_body(template, &form.body),
+                None => Err(PmtError::Io(tr!(
+                    "模板已不存在，请刷新",
+                    "The template no longer exists; refresh"
+                ))),
+            },
+            None if self.templates.iter().any(|template| template.name == name) => {
+                form.set_status(
+                    pick("已存在同名模板", "A template with this name exists"),
+                    self.platform.now(),
+                );
+                return;
+            }
+            None => self.source.append(&name, &form.body),
+        };
+        if let Err(err) = result {
+            form.set_status(err.message(), self.platform.now());
+            return;
+        }
+
+        self.form = None;
+        self.view = View::List;
+        self.reload_templates();
+        self.select_template_by_name(&name);
+        self.set_list_status(pick("已保存", "Saved"));
+    }
+
+    fn open_name_prompt(&mut self, action: NameAction) {
+        let index = match self.selected_template_index() {
+            Some(index) => index,
+            None => {
+                self.set_list_status(pick("请先选择一个模板", "Select a template first"));
+                return;
+            }
+        };
+        let current = &self.templates[index].name;
+        let shared = self.templates[index].layer != Layer::Personal;
+        let name = match action {
+            NameAction::Duplicate if shared => current.clone(),
+            NameAction::Duplicate => tr!("{current} 副本", "{current} copy"),
+            NameAction::Rename => current.clone(),
+        };
+        self.name_prompt = Some(NamePrompt {
+            action,
+            template_index: index,
+            name,
+        });
+    }
+
+    fn on_key_name_prompt(&mut self, key: KeyEvent) {
+        let prompt = match self.name_prompt.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => self.name_prompt = None,
+            KeyCode::Enter => self.apply_name_prompt(),
+            KeyCode::Backspace => {
+                pop_grapheme(&mut prompt.name);
+            }
+            KeyCode::Char(ch) => prompt.name.push(ch),
+            _ => {}
+        }
+    }
+
+    /// Writes the duplicate or rename back to the template's file and moves
+    /// drafts and presets along with a renamed template.
+    fn apply_name_prompt(&mut self) {
+        let prompt = match self.name_prompt.as_ref() {
+            Some(prompt) => prompt.clone(),
+            None => return,
+        };
+        let template = match self.templates.get(prompt.template_index) {
+            Some(template) => template.clone(),
+            None => {
+                self.name_prompt = None;
+                return;
+            }
+        };
+        let name = normalize_name(&prompt.name);
+        if name.is_empty() {
+            self.set_list_status(pick(
+                "模板名称不能为空",
+                "The template name cannot be empty",
+            ));
+            return;
+        }
+        if prompt.action == NameAction::Rename && name == template.name {
+            self.name_prompt = None;
+            return;
+        }
+        // A shared template may be copied under its own name, which makes
+        // the copy override it.
+        let overriding = prompt.action == NameAction::Duplicate
+            && template.layer != Layer::Personal
+            && name == template.name;
+        if !overriding && self.templates.iter().any(|existing| existing.name == name) {
+            self.set_list_status(pick("已存在同名模板", "A template with this name exists"));
+            return;
+        }
+        let result = match prompt.action {
+            NameAction::Duplicate => self.source.duplicate(&template, &name),
+            NameAction::Rename => self
+                .source
+                .rename(&template, &name)
+                .and_then(|()| self.move_template_state(&template.name, &name)),
+        };
+        if let Err(err) = result {
+            self.set_list_status(err.message());
+            return;
+        }
+        self.name_prompt = None;
+        self.reload_templates();
+        self.select_template_by_name(&name);
+        match prompt.action {
+            NameAction::Duplicate => {
+                self.set_list_status(&tr!("已复制为 {name}", "Duplicated as {name}"))
+            }
+            NameAction::Rename => {
+                self.set_list_status(&tr!("已重命名为 {name}", "Renamed to {name}"))
+            }
+        }
+    }
+
+    /// Re-keys the draft, last values, presets and editor position of a
+    /// renamed template.
+    fn move_template_state(&mut self, from: &str, to: &str) -> Result<(), PmtError> {
+        if let Some(draft) = self.store.load_draft(from) {
+            self.store.save_draft(to, &draft)?;
+            self.store.remove_draft(from)?;
+        }
+        if let Some(values) = self.store.load_last_values(from) {
+            self.store.save_last_values(to, &values)?;
+            self.store.remove_last_values(from)?;
+        }
+        for preset in self.store.load_presets(from) {
+            self.store.save_preset(to, &preset)?;
+            self.store.remove_preset(from, &preset.name)?;
+        }
+        self.store.rename_usage(from, to)?;
+        if let Some(position) = self.positions.remove(from) {
+            self.positions.insert(to.to_string(), position);
+        }
+        Ok(())
+    }
+
+    fn open_tag_picker(&mut self) {
+        let mut tags: Vec<(String, usize)> = Vec::new();
+        for template in &self.templates {
+            for tag in &template.metadata.tags {
+                match tags
+                    .iter_mut()
+                    .find(|(known, _)| known.to_lowercase() == tag.to_lowercase())
+                {
+                    Some((_, count)) => *count += 1,
+                    None => tags.push((tag.clone(), 1)),
+                }
+            }
+        }
+        if tags.is_empty() {
+            self.set_list_status(pick(
+                "没有带标签的模板，可在 frontmatter 中添加 tags",
+                "No tagged templates; add tags in the frontmatter",
+            ));
+            return;
+        }
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.tag_picker = Some(TagPicker {
+            tags,
+            selected: 0,
+            chosen: self.tag_filter.clone(),
+        });
+    }
+
+    fn on_key_tag_picker(&mut self, key: KeyEvent) {
+        let picker = match self.tag_picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => self.tag_picker = None,
+            KeyCode::Down | KeyCode::Char('j') => {
+                picker.selected = (picker.selected + 1).min(picker.tags.len() - 1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+            KeyCode::Char(' ') => {
+                let tag = &picker.tags[picker.selected].0;
+                match picker.chosen.iter().position(|chosen| chosen == tag) {
+                    Some(position) => {
+                        picker.chosen.remove(position);
+                    }
+                    None => picker.chosen.push(tag.clone()),
+                }
+            }
+            KeyCode::Char('c') => picker.chosen.clear(),
+            KeyCode::Enter => {
+                let chosen = std::mem::take(&mut picker.chosen);
+                self.tag_picker = None;
+                self.tag_filter = chosen;
+                self.refresh_tree_items();
+                if self.tag_filter.is_empty() {
+                    self.set_list_status(pick("已清除标签筛选", "Tag filter cleared"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_move(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.move_template(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_template(1),
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Char('m') => {
+                self.moving = None;
+                self.set_list_status(pick("已放下", "Dropped"));
+            }
+            _ => {}
+        }
+    }
+
+    /// Swaps the grabbed template with its previous (`-1`) or next (`1`)
+    /// sibling in the same folder and file, writing the change right away.
+    fn move_template(&mut self, delta: isize) {
+        let name = match self.moving.clone() {
+            Some(name) => name,
+            None => return,
+        };
+        let index = match self.templates.iter().position(|t| t.name == name) {
+            Some(index) => index,
+            None => {
+                self.moving = None;
+                return;
+            }
+        };
+        let template = &self.templates[index];
+        let folder = template.name.rsplit_once('/').map(|(folder, _)| folder);
+        let sibling = |other: &&Template| {
+            other.source == template.source
+                && other.name.rsplit_once('/').map(|(folder, _)| folder) == folder
+        };
+        let target = if delta < 0 {
+            self.templates[..index].iter().rfind(sibling)
+        } else {
+            self.templates[index + 1..].iter().find(sibling)
+        };
+        let target = match target {
+            Some(target) => target.clone(),
+            None => {
+                self.set_list_status(pick("已经到头了", "Already at the edge"));
+                return;
+            }
+        };
+        let template = template.clone();
+        if let Err(err) = self.source.swap(&template, &target) {
+            self.set_list_status(err.message());
+            return;
+        }
+        self.reload_templates();
+        self.select_template_by_name(&name);
+    }
+
+    fn on_key_stats(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q' | 'S') => self.stats = None,
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.stats_scroll = self.stats_scroll.saturating_add(1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.stats_scroll = self.stats_scroll.saturating_sub(1);
+            }
+            KeyCode::PageDown => {
+                self.stats_scroll = self.stats_scroll.saturating_add(PREVIEW_SCROLL_STEP);
+            }
+            KeyCode::PageUp => {
+                self.stats_scroll = self.stats_scroll.saturating_sub(PREVIEW_SCROLL_STEP);
+            }
+            _ => {}
+        }
+    }
+
+    fn open_quality(&mut self) {
+        match self.selected_template_index() {
+            Some(index) => {
+                let report = analyze(&self.templates[index], &self.pools);
+                self.quality = Some((index, report));
+            }
+            None => self.set_list_status(pick("请先选择一个模板", "Select a template first")),
+        }
+    }
+
+    pub(crate) fn selected_template_index(&self) -> Option<usize> {
+        let index = self.list_state.selected()?;
+        self.tree_items.get(index)?.template_index
+    }
+
+    /// Labels from the root of the tree down to the selected item.
+    pub(crate) fn selected_path(&self) -> Vec<&str> {
+        let selected = match self.list_state.selected() {
+            Some(selected) if selected < self.tree_items.len() => selected,
+            _ => return Vec::new(),
+        };
+        let mut parts: Vec<&str> = Vec::new();
+        let mut depth = self.tree_items[selected].depth + 1;
+        for item in self.tree_items[..=selected].iter().rev() {
+            if item.depth < depth {
+                parts.push(&item.label);
+                depth = item.depth;
+            }
+        }
+        parts.reverse();
+        parts
+    }
+
+    /// Folder path of the selected tree item with a trailing `/`, used to
+    /// prefill the name of a new template.
+    fn selected_folder_prefix(&self) -> String {
+        let selected = match self.list_state.selected() {
+            Some(selected) if selected < self.tree_items.len() => selected,
+            _ => return String::new(),
+        };
+        let mut parts = self.selected_path();
+        let is_leaf = self
+            .tree_items
+            .get(selected + 1)
+            .is_none_or(|next| next.depth <= self.tree_items[selected].depth);
+        if is_leaf && self.tree_items[selected].template_index.is_some() {
+            parts.pop();
+        }
+        if parts.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", parts.join("/"))
+        }
+    }
+
+    fn select_template_by_name(&mut self, name: &str) {
+        let position = self.tree_items.iter().position(|item| {
+            item.template_index
+                .and_then(|index| self.templates.get(index))
+                .is_some_and(|template| template.name == name)
+        });
+        if let Some(position) = position {
+            self.list_state.select(Some(position));
+        }
+    }
+
+    fn on_mouse_list(&mut self, mouse: MouseEvent) {
+        if self.stats.is_some() {
+            match mouse.kind {
+                MouseEventKind::ScrollDown => {
+                    self.stats_scroll = self.stats_scroll.saturating_add(WHEEL_SCROLL_STEP);
+                }
+                MouseEventKind::ScrollUp => {
+                    self.stats_scroll = self.stats_scroll.saturating_sub(WHEEL_SCROLL_STEP);
+                }
+                _ => {}
+            }
+            return;
+        }
+        // The wheel moves the selection, as the tree keeps it on screen.
+        // Popups and a grabbed template leave it alone.
+        let popup = self.show_load_warnings
+            || self.show_diagnostics
+            || self.quality.is_some()
+            || self.name_prompt.is_some()
+            || self.moving.is_some()
+            || self.tag_picker.is_some();
+        let over_tree = self
+            .tree_area
+            .contains(Position::new(mouse.column, mouse.row));
+        match mouse.kind {
+            MouseEventKind::ScrollDown if over_tree && !popup => {
+                self.move_list(WHEEL_SCROLL_STEP as isize);
+                return;
+            }
+            MouseEventKind::ScrollUp if over_tree && !popup => {
+                self.move_list(-(WHEEL_SCROLL_STEP as isize));
+                return;
+            }
+            MouseEventKind::Down(MouseButton::Left) => {}
+            _ => return,
+        }
+        if let Some(index) = self.index_from_mouse(mouse) {
+            self.list_state.select(Some(index));
+            let now = self.platform.now();
+            if let Some((last_index, last_time)) = self.last_click
+                && last_index == index
+                && now.saturating_duration_since(last_time).as_millis() <= DOUBLE_CLICK_MS
+            {
+                self.open_selected_template();
+            }
+            self.last_click = Some((index, now));
+        }
+    }
+
+    /// Dragging the border between fields and preview resizes them; the new
+    /// width is kept once the button is released.
+    fn on_mouse_divider(&mut self, mouse: MouseEvent) -> bool {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return false,
+        };
+        let content = editor.content_area;
+        // In the stacked layout the divider is a row instead of a column.
+        let stacked = editor.preview_area.y > content.y;
+        let (along, across, start, length, divider, span) = if stacked {
+            (
+                mouse.row,
+                mouse.column,
+                content.y,
+                content.height,
+                editor.preview_area.y,
+                content.x..content.right(),
+            )
+        } else {
+            (
+                mouse.column,
+                mouse.row,
+                content.x,
+                content.width,
+                editor.preview_area.x,
+                content.y..content.bottom(),
+            )
+        };
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                editor.dragging_divider = length > 0
+                    && (divider.saturating_sub(1)..=divider).contains(&along)
+                    && span.contains(&across);
+                editor.dragging_divider
+            }
+            MouseEventKind::Drag(MouseButton::Left) if editor.dragging_divider => {
+                let offset = u32::from(along.saturating_sub(start));
+                let length = u32::from(length);
+                let percent = (offset * 100 + length / 2) / length;
+                self.split = Some((percent.min(100) as u16).clamp(MIN_SPLIT, MAX_SPLIT));
+                true
+            }
+            MouseEventKind::Up(MouseButton::Left) if editor.dragging_divider => {
+                editor.dragging_divider = false;
+                self.resize_split(self.field_percent());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn on_mouse_editor(&mut self, mouse: MouseEvent) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        // Popups own the input until they are closed.
+        if editor.reload_conflict.is_some()
+            || editor.pending_draft.is_some()
+            || editor.command_prompt
+            || editor.rename.is_some()
+            || editor.picker.is_some()
+            || editor.selection.is_some()
+            || editor.preset_name.is_some()
+            || editor.seed_prompt.is_some()
+            || editor.preset_picker.is_some()
+        {
+            return;
+        }
+        if self.on_mouse_divider(mouse) {
+            return;
+        }
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let position = Position::new(mouse.column, mouse.row);
+        let in_preview = editor.preview_area.contains(position);
+        let in_fields = editor.fields_area.contains(position);
+        let in_answer = editor.answer.is_some() && editor.answer_area.contains(position);
+        match mouse.kind {
+            MouseEventKind::ScrollDown if in_preview => {
+                editor.preview_scroll = editor.preview_scroll.saturating_add(WHEEL_SCROLL_STEP);
+            }
+            MouseEventKind::ScrollUp if in_preview => {
+                editor.preview_scroll = editor.preview_scroll.saturating_sub(WHEEL_SCROLL_STEP);
+            }
+            MouseEventKind::ScrollDown if in_answer => {
+                if let Some(answer) = editor.answer.as_mut() {
+                    answer.scroll = answer.scroll.saturating_add(WHEEL_SCROLL_STEP);
+                }
+            }
+            MouseEventKind::ScrollUp if in_answer => {
+                if let Some(answer) = editor.answer.as_mut() {
+                    answer.scroll = answer.scroll.saturating_sub(WHEEL_SCROLL_STEP);
+                    answer.follow = false;
+                }
+            }
+            MouseEventKind::ScrollDown if in_fields => {
+                editor.active_field =
+                    (editor.active_field + 1).min(editor.fields.len().saturating_sub(1));
+            }
+            MouseEventKind::ScrollUp if in_fields => {
+                editor.active_field = editor.active_field.saturating_sub(1);
+            }
+            // A click in the upper half of the preview pages up, in the
+            // lower half pages down.
+            MouseEventKind::Down(MouseButton::Left) if in_preview => {
+                let middle = editor.preview_area.y + editor.preview_area.height / 2;
+                editor.preview_scroll = if mouse.row < middle {
+                    editor.preview_scroll.saturating_sub(PREVIEW_SCROLL_STEP)
+                } else {
+                    editor.preview_scroll.saturating_add(PREVIEW_SCROLL_STEP)
+                };
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let hit = editor
+                    .field_rects
+                    .iter()
+                    .find(|(_, rect)| rect.contains(position));
+                if let Some((index, _)) = hit
+                    && *index < editor.fields.len()
+                {
+                    editor.active_field = *index;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_editor(&mut self, key: KeyEvent) {
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.reload_conflict.is_some())
+        {
+            self.on_key_reload_conflict(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.pending_draft.is_some())
+        {
+            self.on_key_draft_prompt(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.command_prompt)
+        {
+            self.on_key_command_prompt(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.rename.is_some())
+        {
+            self.on_key_rename(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.picker.is_some())
+        {
+            self.on_key_picker(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.selection.is_some())
+        {
+            self.on_key_selection(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.preset_name.is_some())
+        {
+            self.on_key_preset_name(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.seed_prompt.is_some())
+        {
+            self.on_key_seed_prompt(key);
+            return;
+        }
+        if self
+            .editor
+            .as_ref()
+            .is_some_and(|editor| editor.preset_picker.is_some())
+        {
+            self.on_key_preset_picker(key);
+            return;
+        }
+        // Ctrl+Left/Right move the divider, even in a text field.
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Left => {
+                    self.resize_split(self.field_percent().saturating_sub(SPLIT_STEP));
+                    return;
+                }
+                KeyCode::Right => {
+                    self.resize_split(self.field_percent() + SPLIT_STEP);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        let now = self.platform.now();
+        if self
+            .editor
+            .as_mut()
+            .is_some_and(|editor| editor.on_key_cursor(key, now))
+        {
+            return;
+        }
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+
+        match key.code {
+            KeyCode::Char('c' | 'C')
+                if key
+                    .modifiers
+                    .contains(KeyModifiers::CONTROL | KeyModifiers::SHIFT) =>
+            {
+                self.copy_and_quit();
+            }
+            _ if self.config.keys.is(KeyAction::Copy, &key) => self.copy_rendered(),
+            _ if self.config.keys.is(KeyAction::CopyRecord, &key) => self.copy_record(),
+            _ if self.config.keys.is(KeyAction::Send, &key) => self.send_rendered(),
+            _ if self.config.keys.is(KeyAction::Ask, &key) => self.ask_llm(),
+            _ if self.config.keys.is(KeyAction::CopyAnswer, &key) => self.copy_answer(),
+            _ if self.config.keys.is(KeyAction::ClearValues, &key) => self.clear_values(),
+            _ if self.config.keys.is(KeyAction::Undo, &key) => editor.undo(self.platform.now()),
+            _ if self.config.keys.is(KeyAction::Redo, &key) => editor.redo(self.platform.now()),
+            _ if self.config.keys.is(KeyAction::Seed, &key) => {
+                if editor.has_random() {
+                    editor.seed_prompt = Some(editor.seed.to_string());
+                } else {
+                    editor.set_status(
+                        pick(
+                            "模板中没有随机项",
+                            "The template has no random placeholders",
+                        ),
+                        self.platform.now(),
+                    );
+                }
+            }
+            // Esc closes the answer pane before it leaves the editor.
+            KeyCode::Esc if editor.answer.is_some() => editor.answer = None,
+            _ if self.config.keys.is(KeyAction::Select, &key) => editor.start_selection(),
+            _ if self.config.keys.is(KeyAction::Reroll, &key) => {
+                editor.reroll_random(&mut self.platform);
+            }
+            KeyCode::Esc => {
+                if editor.draft_dirty {
+                    self.autosave_draft();
+                }
+                self.remember_values();
+                self.remember_position();
+                self.view = View::List;
+            }
+            KeyCode::PageDown => {
+                editor.preview_scroll = editor.preview_scroll.saturating_add(PREVIEW_SCROLL_STEP);
+            }
+            KeyCode::PageUp => {
+                editor.preview_scroll = editor.preview_scroll.saturating_sub(PREVIEW_SCROLL_STEP);
+            }
+            KeyCode::Tab | KeyCode::Down => {
+                editor.next_field();
+            }
+            KeyCode::Up => {
+                editor.prev_field();
+            }
+            KeyCode::Left => {
+                editor.cycle_option(-1);
+            }
+            KeyCode::Right => {
+                editor.cycle_option(1);
+            }
+            KeyCode::Backspace => {
+                editor.backspace();
+            }
+            KeyCode::Enter => {
+                let opened = editor.open_picker();
+                if !opened {
+                    editor.push_char('\n');
+                }
+            }
+            KeyCode::F(2) => {
+                editor.open_rename(self.platform.now());
+            }
+            KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if editor.var_values().is_empty() {
+                    editor.set_status(
+                        pick("没有可保存的值", "No values to save"),
+                        self.platform.now(),
+                    );
+                } else {
+                    editor.preset_name = Some(String::new());
+                }
+            }
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                let presets = template_name
+                    .map(|name| self.store.load_presets(&name))
+                    .unwrap_or_default();
+                if presets.is_empty() {
+                    editor.set_status(
+                        pick(
+                            "还没有预设，Ctrl+S 保存当前的值",
+                            "No presets yet; Ctrl+S saves the current values",
+                        ),
+                        self.platform.now(),
+                    );
+                } else {
+                    editor.preset_picker = Some(PresetPicker {
+                        presets,
+                        selected: 0,
+                    });
+                }
+            }
+            KeyCode::F(6) => {
+                let enabled = self.config.commands.enabled;
+                editor.request_commands(enabled, self.platform.now());
+            }
+            KeyCode::Char(ch) => {
+                if ch == ' ' && editor.active_field_is_random() && !editor.active_choice_is_typed()
+                {
+                    editor.toggle_pin(self.platform.now());
+                } else if ch == ' ' && editor.active_field_is_select() {
+                    editor.cycle_option(1);
+                } else {
+                    editor.push_char(ch);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_selection(&mut self, key: KeyEvent) {
+        let line_count = match self.copy_text() {
+            Some(text) => text.split('\n').count(),
+            None => return,
+        };
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let selection = match editor.selection.as_mut() {
+            Some(selection) => selection,
+            None => return,
+        };
+        let last = line_count.saturating_sub(1);
+        match key.code {
+            KeyCode::Esc => {
+                editor.selection = None;
+                return;
+            }
+            KeyCode::Char('v') => {
+                selection.anchor = match selection.anchor {
+                    Some(_) => None,
+                    None => Some(selection.cursor),
+                };
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                selection.cursor = (selection.cursor + 1).min(last);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                selection.cursor = selection.cursor.saturating_sub(1);
+            }
+            KeyCode::PageDown => {
+                selection.cursor = (selection.cursor + PREVIEW_SCROLL_STEP as usize).min(last);
+            }
+            KeyCode::PageUp => {
+                selection.cursor = selection
+                    .cursor
+                    .saturating_sub(PREVIEW_SCROLL_STEP as usize);
+            }
+            KeyCode::Char('g') => selection.cursor = 0,
+            KeyCode::Char('G') => selection.cursor = last,
+            KeyCode::Char('y') | KeyCode::Enter => {
+                self.copy_selection();
+                return;
+            }
+            _ => return,
+        }
+        editor.scroll_to_selection();
+    }
+
+    fn on_key_preset_name(&mut self, key: KeyEvent) {
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let name = match editor.preset_name.as_mut() {
+            Some(name) => name,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => editor.preset_name = None,
+            KeyCode::Backspace => {
+                pop_grapheme(name);
+            }
+            KeyCode::Char(ch) => name.push(ch),
+            KeyCode::Enter => {
+                let name = name.trim().to_string();
+                if name.is_empty() {
+                    editor.set_status(
+                        pick("预设名称不能为空", "The preset name cannot be empty"),
+                        self.platform.now(),
+                    );
+                    return;
+                }
+                let template_name = match template_name {
+                    Some(template_name) => template_name,
+                    None => return,
+                };
+                editor.preset_name = None;
+                let preset = Preset {
+                    name,
+                    values: editor.var_values(),
+                };
+                match self.store.save_preset(&template_name, &preset) {
+                    Ok(()) => editor.set_status(
+                        &tr!("已保存预设: {}", "Preset saved: {}", preset.name),
+                        self.platform.now(),
+                    ),
+                    Err(err) => editor.set_status(err.message(), self.platform.now()),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_seed_prompt(&mut self, key: KeyEvent) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let text = match editor.seed_prompt.as_mut() {
+            Some(text) => text,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => editor.seed_prompt = None,
+            KeyCode::Backspace => {
+                pop_grapheme(text);
+            }
+            KeyCode::Char(ch) if ch.is_ascii_digit() => text.push(ch),
+            KeyCode::Enter => {
+                let seed = match text.parse() {
+                    Ok(seed) => seed,
+                    Err(_) => {
+                        editor.set_status(
+                            pick(
+                                "种子需要是非负整数",
+                                "The seed must be a non-negative integer",
+                            ),
+                            self.platform.now(),
+                        );
+                        return;
+                    }
+                };
+                editor.seed_prompt = None;
+                editor.reseed(seed, self.platform.now());
+                editor.set_status(
+                    &tr!("已按种子 {seed} 重随", "Rerolled with seed {seed}"),
+                    self.platform.now(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_preset_picker(&mut self, key: KeyEvent) {
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let picker = match editor.preset_picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Esc => editor.preset_picker = None,
+            KeyCode::Down | KeyCode::Char('j') => {
+                picker.selected = (picker.selected + 1).min(picker.presets.len() - 1);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+            KeyCode::Enter => {
+                let preset = picker.presets[picker.selected].clone();
+                editor.preset_picker = None;
+                editor.checkpoint(Edit::Other);
+                editor.restore_values(&preset.values);
+                editor.draft_dirty = true;
+                editor.set_status(
+                    &tr!("已载入预设: {}", "Preset loaded: {}", preset.name),
+                    self.platform.now(),
+                );
+            }
+            KeyCode::Delete | KeyCode::Char('d') => {
+                let template_name = match template_name {
+                    Some(template_name) => template_name,
+                    None => return,
+                };
+                let preset = picker.presets.remove(picker.selected);
+                picker.selected = picker.selected.min(picker.presets.len().saturating_sub(1));
+                if picker.presets.is_empty() {
+                    editor.preset_picker = None;
+                }
+                match self.store.remove_preset(&template_name, &preset.name) {
+                    Ok(()) => editor.set_status(
+                        &tr!("已删除预设: {}", "Preset deleted: {}", preset.name),
+                        self.platform.now(),
+                    ),
+                    Err(err) => editor.set_status(err.message(), self.platform.now()),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_command_prompt(&mut self, key: KeyEvent) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                editor.command_prompt = false;
+                editor.start_commands();
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                editor.command_prompt = false;
+                editor.set_status(
+                    pick(
+                        "未执行命令，F6 可重新运行",
+                        "Commands not run; F6 runs them",
+                    ),
+                    self.platform.now(),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn on_key_picker(&mut self, key: KeyEvent) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let is_random = editor.active_field_is_random();
+        let picker = match editor.picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        if let Some(text) = picker.adding.as_mut() {
+            match key.code {
+                KeyCode::Esc => picker.adding = None,
+                KeyCode::Enter => {
+                    let option = text.trim().to_string();
+                    picker.adding = None;
+                    if !option.is_empty() {
+                        editor.add_random_option(&option);
+                    }
+                }
+                KeyCode::Backspace => {
+                    pop_grapheme(text);
+                }
+                KeyCode::Char(ch) => text.push(ch),
+                _ => {}
+            }
+            return;
+        }
+        let len = picker.options.len();
+        match key.code {
+            KeyCode::Char('a') if is_random => {
+                picker.adding = Some(String::new());
+                return;
+            }
+            KeyCode::Char('e') if is_random => {
+                let original = picker.original.clone();
+                editor.picker = None;
+                editor.set_active_option(&original);
+                let template_index = editor.template_index;
+                if editor.draft_dirty {
+                    self.autosave_draft();
+                }
+                self.edit_template(template_index);
+                return;
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j') => {
+                picker.selected = (picker.selected + 1) % len;
+            }
+            KeyCode::Up | KeyCode::BackTab | KeyCode::Char('k') => {
+                picker.selected = (picker.selected + len - 1) % len;
+            }
+            KeyCode::Enter => {
+                let original = picker.original.clone();
+                let chosen = picker.options[picker.selected].clone();
+                editor.picker = None;
+                // The picker previews in place; undo returns to before it.
+                editor.set_active_option(&original);
+                editor.checkpoint(Edit::Other);
+                editor.set_active_option(&chosen);
+                if is_random {
+                    editor.set_pinned(true, self.platform.now());
+                } else {
+                    editor.draft_dirty = true;
+                }
+                return;
+            }
+            KeyCode::Esc => {
+                let original = picker.original.clone();
+                editor.picker = None;
+                editor.set_active_option(&original);
+                return;
+            }
+            _ => return,
+        }
+        let value = picker.options[picker.selected].clone();
+        editor.set_active_option(&value);
+    }
+
+    fn on_key_rename(&mut self, key: KeyEvent) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let prompt = match editor.rename.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        if prompt.plan.is_some() {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => self.apply_rename(),
+                KeyCode::Char('n') | KeyCode::Esc => editor.rename = None,
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => editor.rename = None,
+            KeyCode::Enter => self.plan_rename(),
+            KeyCode::Backspace => {
+                pop_grapheme(&mut prompt.to);
+            }
+            KeyCode::Char(ch) => prompt.to.push(ch),
+            _ => {}
+        }
+    }
+
+    /// Collects the templates that use the variable being renamed, refusing
+    /// names that would not parse back or that collide with another field.
+    fn plan_rename(&mut self) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let prompt = match editor.rename.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let to = prompt.to.trim().to_string();
+        let error = if to.is_empty() {
+            Some(tr!("变量名不能为空", "The variable name cannot be empty"))
+        } else if to == prompt.from {
+            Some(tr!(
+                "新名称与原名称相同",
+                "The new name is the same as the old one"
+            ))
+        } else if to.contains(['{', '}', '|', '\n']) {
+            Some(tr!(
+                "变量名不能包含 {{ }} |",
+                "Variable names cannot contain {{ }} |"
+            ))
+        } else if matches!(
+            to.as_str(),
+            "random" | "select" | "list" | "file" | "cmd" | "use"
+        ) {
+            Some(tr!("{to} 是保留名称", "{to} is a reserved name"))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            editor.set_status(&error, self.platform.now());
+            return;
+        }
+
+        let mut plan = Vec::new();
+        for (index, template) in self.templates.iter().enumerate() {
+            if template.name == POOLS_SECTION {
+                continue;
+            }
+            let (body, count) = rename_variable(&template.body, &prompt.from, &to, &self.pools);
+            if count == 0 {
+                continue;
+            }
+            let fields = collect_fields(&parse_tokens(&template.body, &self.pools));
+            if fields.iter().any(|field| field.name == to) {
+                let message = tr!(
+                    "模板 {} 中已有变量 {to}",
+                    "Template {} already has a variable {to}",
+                    template.name
+                );
+                editor.set_status(&message, self.platform.now());
+                return;
+            }
+            plan.push(RenameChange {
+                template_index: index,
+                body,
+                count,
+            });
+        }
+        prompt.to = to;
+        prompt.plan = Some(plan);
+    }
+
+    fn apply_rename(&mut self) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let (from, to, plan) = match editor.rename.take() {
+            Some(RenamePrompt {
+                from,
+                to,
+                plan: Some(plan),
+            }) => (from, to, plan),
+            _ => return,
+        };
+        let values = rename_values(editor.var_values(), &from, &to);
+        let draft_dirty = editor.draft_dirty;
+        let active_field = editor.active_field;
+        let seed = editor.seed;
+
+        let result = self.write_rename(&plan, &from, &to);
+        self.reload_templates();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        // The open template is among the renamed ones; carry the typed values
+        // over instead of asking about the reload.
+        if let Some(conflict) = editor.reload_conflict.take() {
+            let mut fresh = EditorState::new(
+                conflict.template_index,
+                &conflict.body,
+                &self.templates,
+                &self.pools,
+                &mut self.token_cache,
+                seed,
+                self.platform.now(),
+            );
+            fresh.restore_values(&values);
+            fresh.draft_dirty = draft_dirty;
+            fresh.active_field = active_field.min(fresh.fields.len().saturating_sub(1));
+            fresh.set_editing(&self.config.editor);
+            *editor = fresh;
+        }
+        match result {
+            Ok(()) => {
+                let total: usize = plan.iter().map(|change| change.count).sum();
+                let message = tr!(
+                    "已在 {} 个模板中重命名 {total} 处",
+                    "Renamed {total} occurrences in {} templates",
+                    plan.len()
+                );
+                editor.set_status(&message, self.platform.now());
+            }
+            Err(err) => editor.set_status(err.message(), self.platform.now()),
+        }
+    }
+
+    /// Writes the renamed bodies, bottom of each file first so earlier line
+    /// numbers stay valid, then moves saved drafts, last values and presets
+    /// to the new name.
+    fn write_rename(
+        &mut self,
+        plan: &[RenameChange],
+        from: &str,
+        to: &str,
+    ) -> Result<(), PmtError> {
+        let mut order: Vec<&RenameChange> = plan.iter().collect();
+        order.sort_by_key(|change| std::cmp::Reverse(self.templates[change.template_index].line));
+        for change in order {
+            self.source
+                .replace_body(&self.templates[change.template_index], &change.body)?;
+        }
+        for change in plan {
+            let name = &self.templates[change.template_index].name;
+            if let Some(draft) = self.store.load_draft(name) {
+                self.store
+                    .save_draft(name, &rename_values(draft, from, to))?;
+            }
+            if let Some(values) = self.store.load_last_values(name) {
+                self.store
+                    .save_last_values(name, &rename_values(values, from, to))?;
+            }
+            for preset in self.store.load_presets(name) {
+                let preset = Preset {
+                    values: rename_values(preset.values, from, to),
+                    ..preset
+                };
+                self.store.save_preset(name, &preset)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_key_reload_conflict(&mut self, key: KeyEvent) {
+        let keep = match key.code {
+            KeyCode::Char('k') | KeyCode::Enter => true,
+            KeyCode::Char('d') => false,
+            _ => return,
+        };
+        let conflict = match self
+            .editor
+            .as_mut()
+            .and_then(|editor| editor.reload_conflict.take())
+        {
+            Some(conflict) => conflict,
+            None => return,
+        };
+        let defaults = self.field_defaults(conflict.template_index);
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let mut fresh = EditorState::new(
+            conflict.template_index,
+            &conflict.body,
+            &self.templates,
+            &self.pools,
+            &mut self.token_cache,
+            editor.seed,
+            self.platform.now(),
+        );
+        fresh.fill_globals(defaults, self.platform.now());
+        if keep {
+            fresh.restore_values(&editor.var_values());
+            fresh.draft_dirty = editor.draft_dirty;
+            fresh.set_status(
+                pick("已保留填写的值", "Kept the filled-in values"),
+                self.platform.now(),
+            );
+        } else {
+            fresh.set_status(
+                pick("已重新加载模板", "Template reloaded"),
+                self.platform.now(),
+            );
+        }
+        fresh.set_editing(&self.config.editor);
+        *editor = fresh;
+    }
+
+    /// Re-targets the open editor after templates were reloaded. Unchanged
+    /// templates are followed silently; changed ones raise a conflict prompt
+    /// instead of discarding what the user typed.
+    fn reconcile_editor(&mut self, previous: &[Template]) {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let old = match previous.get(editor.template_index) {
+            Some(template) => template,
+            None => return,
+        };
+        let new_index = self
+            .templates
+            .iter()
+            .position(|template| template.name == old.name);
+        match new_index {
+            Some(index) if self.templates[index].body == old.body => {
+                editor.template_index = index;
+            }
+            Some(index) if matches!(self.view, View::Editor) => {
+                editor.reload_conflict = Some(ReloadConflict {
+                    template_index: index,
+                    body: self.templates[index].body.clone(),
+                });
+            }
+            Some(index) => {
+                editor.template_index = index;
+            }
+            None if matches!(self.view, View::Editor) => {
+                self.editor = None;
+                self.view = View::List;
+                self.set_list_status(pick("当前模板已被删除", "The open template was deleted"));
+            }
+            None => self.editor = None,
+        }
+    }
+
+    fn on_key_draft_prompt(&mut self, key: KeyEvent) {
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                if let Some(values) = editor.pending_draft.take() {
+                    editor.checkpoint(Edit::Other);
+                    editor.restore_values(&values);
+                    editor.set_status(pick("已恢复草稿", "Draft restored"), self.platform.now());
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Esc => {
+                editor.pending_draft = None;
+                if let Some(name) = template_name
+                    && let Err(err) = self.store.remove_draft(&name)
+                {
+                    editor.set_status(err.message(), self.platform.now());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn autosave_draft(&mut self) {
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let name = match template_name {
+            Some(name) => name,
+            None => return,
+        };
+        editor.draft_dirty = false;
+        editor.last_autosave = self.platform.now();
+        let values = editor.var_values();
+        let result = if values.is_empty() {
+            self.store.remove_draft(&name)
+        } else {
+            self.store.save_draft(&name, &values)
+        };
+        if let Err(err) = result {
+            editor.set_status(err.message(), self.platform.now());
+        }
+    }
+
+    pub(crate) fn terminal_title(&self) -> String {
+        match (&self.view, self.current_template_name()) {
+            (View::Editor, Some(name)) => format!("pmt – {name}"),
+            _ => "pmt".to_string(),
+        }
+    }
+
+    /// Frontmatter `defaults` of the template followed by `globals.toml`, so
+    /// the template's own values win.
+    fn field_defaults(&self, template_index: usize) -> Result<Vec<(String, String)>, PmtError> {
+        let mut values: Vec<(String, String)> = self
+            .templates
+            .get(template_index)
+            .map(|template| template.metadata.defaults.clone().into_iter().collect())
+            .unwrap_or_default();
+        values.extend(self.store.load_globals()?);
+        Ok(values)
+    }
+
+    fn current_template_name(&self) -> Option<String> {
+        let editor = self.editor.as_ref()?;
+        self.templates
+            .get(editor.template_index)
+            .map(|template| template.name.clone())
+    }
+
+    /// Rows of the tree on screen, at least one, for paging.
+    fn list_page(&self) -> isize {
+        (self.tree_area.height as isize).max(1)
+    }
+
+    fn move_list(&mut self, delta: isize) {
+        let len = self.tree_items.len();
+        if len == 0 {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, (len - 1) as isize) as usize;
+        self.list_state.select(Some(next));
+    }
+
+    fn open_selected_template(&mut self) {
+        let index = match self.list_state.selected() {
+            Some(index) => index,
+            None => return,
+        };
+        let template_index = match self
+            .tree_items
+            .get(index)
+            .and_then(|item| item.template_index)
+        {
+            Some(template_index) => template_index,
+            None => return,
+        };
+        let template = match self.templates.get(template_index) {
+            Some(template) => template.clone(),
+            None => return,
+        };
+        if template.name == POOLS_SECTION {
+            self.open_edit_template_form();
+            return;
+        }
+        let mut editor = EditorState::new(
+            template_index,
+            &template.body,
+            &self.templates,
+            &self.pools,
+            &mut self.token_cache,
+            self.platform.seed(),
+            self.platform.now(),
+        );
+        editor.set_editing(&self.config.editor);
+        editor.fill_globals(self.field_defaults(template_index), self.platform.now());
+        if let Some(values) = self.store.load_last_values(&template.name) {
+            editor.restore_values(&values);
+        }
+        editor.pending_draft = self.store.load_draft(&template.name);
+        if editor.has_commands() {
+            editor.request_commands(self.config.commands.enabled, self.platform.now());
+        }
+        if let Some(position) = self.positions.get(&template.name)
+            && position.active_field < editor.fields.len()
+        {
+            editor.active_field = position.active_field;
+            editor.field_scroll = position.field_scroll;
+            editor.preview_scroll = position.preview_scroll;
+        }
+        self.editor = Some(editor);
+        self.view = View::Editor;
+    }
+
+    fn remember_position(&mut self) {
+        let name = match self.current_template_name() {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some(editor) = self.editor.as_ref() {
+            let position = EditorPosition {
+                active_field: editor.active_field,
+                field_scroll: editor.field_scroll,
+                preview_scroll: editor.preview_scroll,
+            };
+            self.positions.insert(name, position);
+        }
+    }
+
+    fn copy_rendered(&mut self) {
+        if self.focus_incomplete_field() {
+            return;
+        }
+        let rendered = match self.copy_text() {
+            Some(rendered) => rendered,
+            None => return,
+        };
+        self.copy_prompt(rendered);
+    }
+
+    /// Copies the template name, the field values and the rendered prompt
+    /// in the configured record format.
+    fn copy_record(&mut self) {
+        if self.focus_incomplete_field() {
+            return;
+        }
+        let (editor, template) = match (self.editor.as_ref(), self.current_template_name()) {
+            (Some(editor), Some(template)) => (editor, template),
+            _ => return,
+        };
+        let variables = editor
+            .fields
+            .iter()
+            .filter(|field| field.is_named())
+            .map(|field| (field.name.clone(), field.value.clone()))
+            .collect();
+        let output = match self.copy_text() {
+            Some(output) => output,
+            None => return,
+        };
+        let seed = editor.has_random().then_some(editor.seed);
+        let record = RenderRecord {
+            template,
+            variables,
+            seed,
+            output,
+        };
+        self.copy_prompt(record.serialize(self.config.copy.record_format));
+    }
+
+    /// Puts a whole prompt on the clipboard; the draft is no longer needed
+    /// once it has left pmt.
+    /// Copies `text` and, when that worked, records the use. Returns whether
+    /// the copy succeeded.
+    fn copy_prompt(&mut self, text: String) -> bool {
+        let copied = self.copy_to_clipboard(text);
+        if copied {
+            self.remember_values();
+            self.forget_draft();
+            self.record_use();
+            self.append_journal();
+        }
+        copied
+    }
+
+    /// Archives the copied prompt in `[copy] journal`, if set.
+    fn append_journal(&mut self) {
+        let path = match self.config.copy.journal.clone() {
+            Some(path) => path,
+            None => return,
+        };
+        let (name, output) = match (self.current_template_name(), self.copy_text()) {
+            (Some(name), Some(output)) => (name, output),
+            _ => return,
+        };
+        let time = self.platform.local_time();
+        let result = self.store.append_journal(&path, &name, &output, time);
+        if let (Err(err), Some(editor)) = (result, self.editor.as_mut()) {
+            editor.set_status(err.message(), self.platform.now());
+        }
+    }
+
+    /// Width of the editor's field column in percent.
+    pub(crate) fn field_percent(&self) -> u16 {
+        match self.split {
+            Some(split) => split.clamp(MIN_SPLIT, MAX_SPLIT),
+            None => self.config.layout.field_percent(),
+        }
+    }
+
+    /// Moves the divider between fields and preview and keeps the new width
+    /// for later sessions.
+    fn resize_split(&mut self, split: u16) {
+        let split = split.clamp(MIN_SPLIT, MAX_SPLIT);
+        self.split = Some(split);
+        let result = self.store.save_split(split);
+        if let (Err(err), Some(editor)) = (result, self.editor.as_mut()) {
+            editor.set_status(err.message(), self.platform.now());
+        }
+    }
+
+    /// Logs a use of the open template for the stats view.
+    fn record_use(&mut self) {
+        let (name, output) = match (self.current_template_name(), self.copy_text()) {
+            (Some(name), Some(output)) => (name, output),
+            _ => return,
+        };
+        let entry = UsageEntry::at(&name, &output, self.platform.local_time());
+        let result = self.store.record_use(&entry);
+        if let (Err(err), Some(editor)) = (result, self.editor.as_mut()) {
+            editor.set_status(err.message(), self.platform.now());
+        }
+    }
+
+    /// Pipes the rendered prompt into `[send] command` and reports the
+    /// command's exit status and first line of output.
+    fn send_rendered(&mut self) {
+        if self.focus_incomplete_field() {
+            return;
+        }
+        let command = self.config.send.command.clone();
+        let rendered = self.copy_text();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let (command, rendered) = match (command, rendered) {
+            (Some(command), Some(rendered)) if !command.trim().is_empty() => (command, rendered),
+            _ => {
+                editor.set_status(
+                    pick(
+                        "未配置发送命令: 请在 config.toml 中设置 [send] command",
+                        "No send command: set [send] command in config.toml",
+                    ),
+                    self.platform.now(),
+                );
+                return;
+            }
+        };
+        match self.platform.send(&command, &rendered) {
+            Ok(output) => {
+                let first = output.lines().find(|line| !line.trim().is_empty());
+                editor.set_status(
+                    &match first {
+                        Some(line) => tr!(
+                            "已发送 (退出码 0): {}",
+                            "Sent (exit code 0): {}",
+                            line.trim()
+                        ),
+                        None => tr!("已发送 (退出码 0)", "Sent (exit code 0)"),
+                    },
+                    self.platform.now(),
+                );
+                self.remember_values();
+                self.forget_draft();
+                self.record_use();
+            }
+            Err(err) => editor.set_status(err.message(), self.platform.now()),
+        }
+    }
+
+    /// Sends the rendered prompt to `[llm]` with the template's frontmatter
+    /// model, or the configured one, and opens the answer pane.
+    fn ask_llm(&mut self) {
+        if self.focus_incomplete_field() {
+            return;
+        }
+        let model = self
+            .editor
+            .as_ref()
+            .and_then(|editor| self.templates.get(editor.template_index))
+            .and_then(|template| template.metadata.model.clone())
+            .or_else(|| self.config.llm.model.clone());
+        let prompt = self.copy_text();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let (model, prompt) = match (model, prompt) {
+            (Some(model), Some(prompt)) => (model, prompt),
+            _ => {
+                editor.set_status(
+                    pick(
+                        "未配置模型: 请在 config.toml 中设置 [llm] model",
+                        "No model: set [llm] model in config.toml",
+                    ),
+                    self.platform.now(),
+                );
+                return;
+            }
+        };
+        let request = ChatRequest {
+            api_base: self.config.llm.api_base.clone(),
+            api_key: self.config.llm.api_key(),
+            model: model.clone(),
+            prompt,
+        };
+        editor.answer = Some(Answer {
+            model,
+            text: String::new(),
+            stream: Some(Rc::new(self.platform.ask(request))),
+            error: None,
+            scroll: 0,
+            follow: true,
+        });
+    }
+
+    fn copy_answer(&mut self) {
+        let text = match self
+            .editor
+            .as_ref()
+            .and_then(|editor| editor.answer.as_ref())
+        {
+            Some(answer) if !answer.text.is_empty() => answer.text.clone(),
+            _ => return,
+        };
+        self.copy_to_clipboard(text);
+    }
+
+    /// Moves the focus to the first required field without a value or
+    /// field breaking its rule and says so, so an incomplete prompt is not
+    /// copied or sent. Returns whether one was found.
+    fn focus_incomplete_field(&mut self) -> bool {
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return false,
+        };
+        let problem = editor.fields.iter().enumerate().find_map(|(index, field)| {
+            if field.is_missing() {
+                Some((
+                    index,
+                    tr!(
+                        "必填项未填写: {}",
+                        "Required field not filled: {}",
+                        field.label
+                    ),
+                ))
+            } else {
+                field
+                    .invalid()
+                    .map(|err| (index, format!("{}: {err}", field.label)))
+            }
+        });
+        let (index, message) = match problem {
+            Some(problem) => problem,
+            None => return false,
+        };
+        editor.active_field = index;
+        editor.set_status(&message, self.platform.now());
+        true
+    }
+
+    /// Drops the draft of the open template once its prompt has left pmt.
+    /// Saves the values so the template opens with them next time. Values
+    /// that only repeat a global are left out, so later edits to
+    /// `globals.toml` still show up.
+    fn remember_values(&mut self) {
+        let template_name = self.current_template_name();
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let name = match template_name {
+            Some(name) => name,
+            None => return,
+        };
+        let globals = self.store.load_globals().unwrap_or_default();
+        let values: Vec<(String, String)> = editor
+            .var_values()
+            .into_iter()
+            .filter(|value| !globals.contains(value))
+            .collect();
+        let result = if values.is_empty() {
+            self.store.remove_last_values(&name)
+        } else {
+            self.store.save_last_values(&name, &values)
+        };
+        if let Err(err) = result {
+            editor.set_status(err.message(), self.platform.now());
+        }
+    }
+
+    /// Empties the fields, back to globals and select defaults, and forgets
+    /// the remembered values and draft of the template.
+    fn clear_values(&mut self) {
+        let template_name = self.current_template_name();
+        let globals = match self.editor.as_ref() {
+            Some(editor) => self.field_defaults(editor.template_index),
+            None => return,
+        };
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return,
+        };
+        let name = match template_name {
+            Some(name) => name,
+            None => return,
+        };
+        editor.clear_values();
+        editor.fill_globals(globals, self.platform.now());
+        let result = self
+            .store
+            .remove_last_values(&name)
+            .and_then(|()| self.store.remove_draft(&name));
+        editor.draft_dirty = false;
+        match result {
+            Ok(()) => editor.set_status(
+                pick("已清空填写的值", "Values cleared"),
+                self.platform.now(),
+            ),
+            Err(err) => editor.set_status(err.message(), self.platform.now()),
+        }
+    }
+
+    fn forget_draft(&mut self) {
+        let template_name = self.current_template_name();
+        if let Some(editor) = self.editor.as_mut() {
+            editor.draft_dirty = false;
+        }
+        if let Some(name) = template_name {
+            let _ = self.store.remove_draft(&name);
+        }
+    }
+
+    /// Copies the selected preview lines and leaves the selection. The
+    /// draft is kept since only part of the prompt left pmt.
+    fn copy_selection(&mut self) {
+        let rendered = match self.copy_text() {
+            Some(rendered) => rendered,
+            None => return,
+        };
+        let (first, last) = match self
+            .editor
+            .as_mut()
+            .and_then(|editor| editor.selection.take())
+        {
+            Some(selection) => selection.range(),
+            None => return,
+        };
+        let lines: Vec<&str> = rendered.split('\n').collect();
+        let last = last.min(lines.len().saturating_sub(1));
+        self.copy_to_clipboard(lines[first.min(last)..=last].join("\n"));
+    }
+
+    /// Puts `text` on the clipboard and reports the outcome in the editor's
+    /// status line. Returns whether the copy succeeded.
+    fn copy_to_clipboard(&mut self, text: String) -> bool {
+        let wanted = self.platform.clipboard_backend();
+        let result = self.platform.set_clipboard(&text);
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => return false,
+        };
+        match result {
+            Ok(used) => {
+                match used {
+                    ClipboardBackend::System => {
+                        editor.set_status(
+                            pick("已复制到系统剪贴板", "Copied to the system clipboard"),
+                            self.platform.now(),
+                        );
+                    }
+                    ClipboardBackend::Command => editor.set_status(
+                        pick("已通过剪贴板命令复制", "Copied with the clipboard command"),
+                        self.platform.now(),
+                    ),
+                    ClipboardBackend::Capture => editor.set_status(
+                        pick(
+                            "已复制 (演练模式，未写入剪贴板)",
+                            "Copied (dry run, clipboard untouched)",
+                        ),
+                        self.platform.now(),
+                    ),
+                    ClipboardBackend::Osc52 => {
+                        if wanted == ClipboardBackend::System {
+                            editor.set_status(pick(
+                                "系统剪贴板不可用: 已改用 OSC 52 复制，退出时也会输出到终端",
+                                "System clipboard unavailable: copied with OSC 52, also printed on exit",
+                            ), self.platform.now());
+                        } else {
+                            editor.set_status(
+                                pick(
+                                    "无图形会话: 已通过 OSC 52 复制，退出时也会输出到终端",
+                                    "No display: copied with OSC 52, also printed on exit",
+                                ),
+                                self.platform.now(),
+                            );
+                        }
+                        self.exit_output = Some(text);
+                    }
+                }
+                true
+            }
+            Err(err) => {
+                editor.set_status(err.message(), self.platform.now());
+                false
+            }
+        }
+    }
+
+    /// Copies the rendered prompt, exits, and prints it to stdout so the
+    /// invoking shell can capture it. A failed copy stays in the editor with
+    /// the error in the status line.
+    fn copy_and_quit(&mut self) {
+        if self.focus_incomplete_field() {
+            return;
+        }
+        let rendered = match self.copy_text() {
+            Some(rendered) => rendered,
+            None => return,
+        };
+        if self.copy_prompt(rendered.clone()) {
+            self.exit_output = Some(rendered);
+            self.should_quit = true;
+        }
+    }
+
+    /// The rendered prompt as it should leave pmt, stripped of control
+    /// characters when configured.
+    fn copy_text(&self) -> Option<String> {
+        let editor = self.editor.as_ref()?;
+        let rendered =
+            render_template_at(&editor.tokens, &editor.fields, self.platform.local_time());
+        if self.config.copy.strip_control {
+            Some(strip_control(&rendered))
+        } else {
+            Some(rendered)
+        }
+    }
+
+    fn set_list_status(&mut self, text: &str) {
+        self.list_status = Some(StatusMessage {
+            text: text.to_string(),
+            since: self.platform.now(),
+        });
+    }
+
+    fn open_prompts_in_editor(&mut self) {
+        let path = match self.source.editable_path() {
+            Ok(path) => path,
+            Err(err) => {
+                self.set_list_status(err.message());
+                return;
+            }
+        };
+
+        if let Err(err) = self.platform.launch_editor(&path) {
+            self.set_list_status(err.message());
+            return;
+        }
+
+        self.needs_redraw = true;
+        self.reload_templates();
+    }
+
+    /// Checks the templates against the thresholds and for problems. Only
+    /// bodies that changed since the last time are parsed again.
+    pub(crate) fn analyze(&mut self) {
+        self.diagnostics = diagnose(
+            &self.templates,
+            &self.pools,
+            &mut self.token_cache,
+            &self.thresholds,
+        );
+        self.problems = find_problems(&self.templates, &self.pools, &mut self.token_cache);
+        self.token_cache.retain(&self.templates);
+        self.analysis_due = false;
+    }
+
+    /// Returns whether the templates could be read; on failure the old ones
+    /// stay and the error is shown in the status line.
+    fn reload_templates(&mut self) -> bool {
+        match self.source.load() {
+            Ok((templates, warnings)) => {
+                if warnings != self.load_warnings {
+                    self.show_load_warnings = !warnings.is_empty();
+                    self.load_warnings = warnings;
+                }
+                let selected = self
+                    .selected_template_index()
+                    .map(|index| self.templates[index].name.clone());
+                self.pools = parse_pools(&templates);
+                let previous = std::mem::replace(&mut self.templates, templates);
+                self.analyze();
+                self.reconcile_editor(&previous);
+                self.refresh_tree_items();
+                if let Some(name) = selected {
+                    self.select_template_by_name(&name);
+                }
+                true
+            }
+            Err(err) => {
+                self.set_list_status(err.message());
+                false
+            }
+        }
+    }
+
+    /// Files the templates were read from, for the file watcher.
+    pub(crate) fn source_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.source.editable_path().into_iter().collect();
+        for template in &self.templates {
+            if !template.source.as_os_str().is_empty() && !files.contains(&template.source) {
+                files.push(template.source.clone());
+            }
+        }
+        files
+    }
+
+    /// Reloads after a template file changed on disk. Changes pmt made
+    /// itself were already reloaded and leave nothing to report.
+    pub(crate) fn on_files_changed(&mut self) {
+        let previous = self.template_bodies();
+        if !self.reload_templates() {
+            return;
+        }
+        let changes = self.template_changes(&previous);
+        if !changes.is_empty() {
+            self.set_list_status(&tr!(
+                "文件已变更，已重新加载: {}",
+                "Files changed, reloaded: {}",
+                changes.join(pick("，", ", "))
+            ));
+        }
+    }
+
+    /// Rereads templates and config from disk and reports what changed.
+    fn refresh_from_disk(&mut self) {
+        let previous = self.template_bodies();
+        // The config goes first: it may name another prompts file, and the
+        // templates are read and their tree drawn in its language.
+        let config_note = match load_config() {
+            Ok(config) if config != self.config => {
+                set_locale(Locale::resolve(config.language));
+                self.source.configure(&config);
+                self.config = config;
+                Some(tr!("配置已更新", "config updated"))
+            }
+            Ok(_) => None,
+            Err(err) => Some(err.to_string()),
+        };
+        if !self.reload_templates() {
+            return;
+        }
+        let mut parts = self.template_changes(&previous);
+        parts.extend(config_note);
+        if parts.is_empty() {
+            self.set_list_status(pick("已刷新，没有变化", "Refreshed, nothing changed"));
+        } else {
+            self.set_list_status(&tr!(
+                "已刷新: {}",
+                "Refreshed: {}",
+                parts.join(pick("，", ", "))
+            ));
+        }
+    }
+
+    fn template_bodies(&self) -> HashMap<String, String> {
+        self.templates
+            .iter()
+            .map(|template| (template.name.clone(), template.body.clone()))
+            .collect()
+    }
+
+    /// Counts of templates added, removed and changed since `previous`.
+    fn template_changes(&self, previous: &HashMap<String, String>) -> Vec<String> {
+        let added = self
+            .templates
+            .iter()
+            .filter(|template| !previous.contains_key(&template.name))
+            .count();
+        let changed = self
+            .templates
+            .iter()
+            .filter(|template| {
+                previous
+                    .get(&template.name)
+                    .is_some_and(|body| *body != template.body)
+            })
+            .count();
+        let removed = previous
+            .keys()
+            .filter(|name| {
+                !self
+                    .templates
+                    .iter()
+                    .any(|template| template.name == **name)
+            })
+            .count();
+
+        let mut parts = Vec::new();
+        if added > 0 {
+            parts.push(tr!("新增 {added}", "{added} added"));
+        }
+        if removed > 0 {
+            parts.push(tr!("删除 {removed}", "{removed} removed"));
+        }
+        if changed > 0 {
+            parts.push(tr!("修改 {changed}", "{changed} changed"));
+        }
+        parts
+    }
+
     fn index_from_mouse(&self, mouse: MouseEvent) -> Option<usize> {
         let area = self.tree_area;
         if area.width == 0 || area.height == 0 {
@@ -329,69 +3005,722 @@ impl App {
         {
             return None;
         }
-        let row_offset = (mouse.row - area.y) as usize;
-        let index = self.list_scroll + row_offset;
-        if index >= self.tree_items.len() {
-            return None;
+        let row_offset = (mouse.row - area.y) as usize;
+        let index = self.list_scroll + row_offset;
+        if index >= self.tree_items.len() {
+            return None;
+        }
+        Some(index)
+    }
+}
+
+/// Trims each `/`-separated part of a template name and drops empty ones.
+fn normalize_name(name: &str) -> String {
+    name.split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn rename_values(values: Vec<(String, String)>, from: &str, to: &str) -> Vec<(String, String)> {
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            if name == from {
+                (to.to_string(), value)
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+impl TemplateForm {
+    fn focused_value(&mut self) -> &mut String {
+        match self.focus {
+            FormFocus::Name => &mut self.name,
+            FormFocus::Body => &mut self.body,
+        }
+    }
+
+    fn set_status(&mut self, text: &str, now: Instant) {
+        self.status = Some(StatusMessage {
+            text: text.to_string(),
+            since: now,
+        });
+    }
+}
+
+impl Answer {
+    /// Appends what has arrived on the stream since the last tick.
+    fn receive(&mut self) {
+        let stream = match &self.stream {
+            Some(stream) => Rc::clone(stream),
+            None => return,
+        };
+        loop {
+            match stream.try_recv() {
+                Ok(AnswerEvent::Chunk(text)) => self.text.push_str(&text),
+                Ok(AnswerEvent::Done) | Err(TryRecvError::Disconnected) => {
+                    self.stream = None;
+                    return;
+                }
+                Ok(AnswerEvent::Failed(err)) => {
+                    self.error = Some(err);
+                    self.stream = None;
+                    return;
+                }
+                Err(TryRecvError::Empty) => return,
+            }
+        }
+    }
+}
+
+impl EditorState {
+    fn new(
+        template_index: usize,
+        body: &str,
+        templates: &[Template],
+        pools: &OptionPools,
+        cache: &mut TokenCache,
+        seed: u64,
+        now: Instant,
+    ) -> Self {
+        let mut errors = Vec::new();
+        let body = expand_uses(body, templates).unwrap_or_else(|err| {
+            errors.push(err);
+            body.to_string()
+        });
+        let mut tokens = cache.tokens(&body, pools).to_vec();
+        roll_random(&mut tokens, seed, &[]);
+        errors.extend(load_file_tokens(&mut tokens));
+        errors.extend(read_clipboard_tokens(&mut tokens).map(|err| err.to_string()));
+        let fields = collect_fields(&tokens);
+        let status = errors
+            .into_iter()
+            .next()
+            .map(|text| StatusMessage { text, since: now });
+        Self {
+            template_index,
+            tokens,
+            fields,
+            active_field: 0,
+            field_scroll: 0,
+            preview_scroll: 0,
+            fields_area: Rect::default(),
+            preview_area: Rect::default(),
+            field_rects: Vec::new(),
+            status,
+            seed,
+            pending_draft: None,
+            draft_dirty: false,
+            last_autosave: now,
+            reroll_highlight: None,
+            reload_conflict: None,
+            picker: None,
+            rename: None,
+            selection: None,
+            preset_name: None,
+            seed_prompt: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: None,
+            cursor: None,
+            vim: None,
+            readline: false,
+            preset_picker: None,
+            command_prompt: false,
+            commands: None,
+            answer: None,
+            answer_area: Rect::default(),
+            content_area: Rect::default(),
+            dragging_divider: false,
+        }
+    }
+
+    fn set_editing(&mut self, config: &EditorConfig) {
+        self.cursor = (config.vim || config.readline).then(FieldCursor::default);
+        self.vim = config.vim.then(VimState::default);
+        self.readline = config.readline;
+    }
+
+    fn var_values(&self) -> Vec<(String, String)> {
+        self.fields
+            .iter()
+            .filter(|field| field.is_named() && !field.value.is_empty())
+            .map(|field| (field.name.clone(), field.value.clone()))
+            .collect()
+    }
+
+    fn restore_values(&mut self, values: &[(String, String)]) {
+        for field in &mut self.fields {
+            let value = match values.iter().find(|(name, _)| *name == field.name) {
+                Some((_, value)) => value,
+                None => continue,
+            };
+            match &field.kind {
+                FieldKind::Var | FieldKind::List => field.value = value.clone(),
+                FieldKind::Select { options } if options.contains(value) => {
+                    field.value = value.clone();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Fills fields from frontmatter defaults and `globals.toml`. Drafts,
+    /// presets and typing still override them, and they do not count as
+    /// unsaved edits.
+    fn fill_globals(&mut self, globals: Result<Vec<(String, String)>, PmtError>, now: Instant) {
+        match globals {
+            Ok(values) => self.restore_values(&values),
+            Err(err) => self.set_status(err.message(), now),
+        }
+    }
+
+    fn clear_values(&mut self) {
+        self.checkpoint(Edit::Other);
+        for field in &mut self.fields {
+            match &field.kind {
+                FieldKind::Var | FieldKind::List => field.value.clear(),
+                FieldKind::Select { options } => {
+                    field.value = options.first().cloned().unwrap_or_default();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Enters preview selection with the cursor on the top visible line.
+    fn start_selection(&mut self) {
+        self.selection = Some(PreviewSelection {
+            cursor: self.preview_scroll as usize,
+            anchor: None,
+        });
+    }
+
+    /// Scrolls the preview so the selection cursor stays on screen.
+    fn scroll_to_selection(&mut self) {
+        let cursor = match self.selection {
+            Some(selection) => selection.cursor as u16,
+            None => return,
+        };
+        let height = self.preview_area.height.saturating_sub(2).max(1);
+        if cursor < self.preview_scroll {
+            self.preview_scroll = cursor;
+        } else if cursor >= self.preview_scroll + height {
+            self.preview_scroll = cursor + 1 - height;
+        }
+    }
+
+    fn next_field(&mut self) {
+        if self.fields.is_empty() {
+            return;
+        }
+        self.active_field = (self.active_field + 1) % self.fields.len();
+    }
+
+    fn prev_field(&mut self) {
+        if self.fields.is_empty() {
+            return;
+        }
+        if self.active_field == 0 {
+            self.active_field = self.fields.len() - 1;
+        } else {
+            self.active_field -= 1;
+        }
+    }
+
+    fn push_char(&mut self, ch: char) {
+        if self.active_field_is_random() {
+            // Typing over a pick starts a choice of one's own.
+            let mut choice = if self.active_choice_is_typed() {
+                self.fields[self.active_field].value.clone()
+            } else {
+                String::new()
+            };
+            choice.push(ch);
+            self.checkpoint(Edit::Insert);
+            self.set_typed_choice(&choice);
+            return;
+        }
+        if !self.active_field_is_text() {
+            return;
+        }
+        self.checkpoint(Edit::Insert);
+        self.fields[self.active_field].value.push(ch);
+        self.draft_dirty = true;
+    }
+
+    /// Inserts pasted text into the active text field as one edit. False
+    /// when the keys go elsewhere, such as to a prompt or a choice.
+    fn paste(&mut self, text: &str) -> bool {
+        let prompt = self.preset_name.is_some()
+            || self.seed_prompt.is_some()
+            || self.rename.is_some()
+            || self.picker.is_some();
+        if prompt || !self.active_field_is_text() {
+            return false;
+        }
+        self.checkpoint(Edit::Other);
+        match self.cursor {
+            Some(_) => {
+                let cursor = self.cursor_index();
+                let value = &mut self.fields[self.active_field].value;
+                value.insert_str(byte_index(value, cursor), text);
+                self.set_cursor_index(cursor + text.chars().count());
+            }
+            None => self.fields[self.active_field].value.push_str(text),
+        }
+        self.draft_dirty = true;
+        true
+    }
+
+    fn backspace(&mut self) {
+        if self.active_choice_is_typed() {
+            let mut choice = self.fields[self.active_field].value.clone();
+            pop_grapheme(&mut choice);
+            self.checkpoint(Edit::Delete);
+            self.set_typed_choice(&choice);
+            return;
+        }
+        if !self.active_field_is_text() || self.fields[self.active_field].value.is_empty() {
+            return;
+        }
+        self.checkpoint(Edit::Delete);
+        pop_grapheme(&mut self.fields[self.active_field].value);
+        self.draft_dirty = true;
+    }
+
+    fn accepts_text(&self) -> bool {
+        if self.preset_name.is_some() || self.seed_prompt.is_some() || self.rename.is_some() {
+            return true;
+        }
+        if let Some(picker) = &self.picker {
+            return picker.adding.is_some();
+        }
+        self.selection.is_none()
+            && self.preset_picker.is_none()
+            && (self.active_field_is_text() || self.active_field_is_random())
+            && self
+                .vim
+                .as_ref()
+                .is_none_or(|vim| vim.mode == VimMode::Insert)
+    }
+
+    fn active_field_is_text(&self) -> bool {
+        self.fields
+            .get(self.active_field)
+            .is_some_and(|field| matches!(field.kind, FieldKind::Var | FieldKind::List))
+    }
+
+    /// Handles keys that move or edit at the cursor, when vim mode or the
+    /// readline keys are on. Returns false for keys the editor handles as
+    /// usual, such as Tab, arrows between fields and Ctrl/Alt shortcuts.
+    fn on_key_cursor(&mut self, key: KeyEvent, now: Instant) -> bool {
+        let len = self
+            .fields
+            .get(self.active_field)
+            .map_or(0, |field| field.value.chars().count());
+        let cursor = match self.cursor.as_mut() {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+        if cursor.field != self.active_field {
+            cursor.field = self.active_field;
+            cursor.index = len;
+            if let Some(vim) = self.vim.as_mut() {
+                vim.pending.clear();
+            }
+        }
+        if self.readline && self.on_key_readline(key) {
+            return true;
+        }
+        if key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return false;
+        }
+        match self.vim.as_ref().map(|vim| vim.mode) {
+            Some(VimMode::Normal) => self.on_key_vim_normal(key, now),
+            _ => self.on_key_insert(key),
+        }
+    }
+
+    fn on_key_insert(&mut self, key: KeyEvent) -> bool {
+        let cursor = self.cursor_index();
+        if key.code == KeyCode::Esc {
+            match self.vim.as_mut() {
+                Some(vim) => vim.mode = VimMode::Normal,
+                None => return false,
+            }
+            let chars = self.active_chars();
+            let (start, _) = line_bounds(&chars, cursor);
+            self.set_cursor_index(prev_grapheme(&chars, cursor).max(start));
+            return true;
+        }
+        if !self.active_field_is_text() {
+            return false;
+        }
+        match key.code {
+            KeyCode::Char(ch) => self.insert_at_cursor(ch),
+            KeyCode::Enter => self.insert_at_cursor('\n'),
+            KeyCode::Backspace if cursor > 0 => {
+                self.checkpoint(Edit::Delete);
+                self.remove_range(prev_grapheme(&self.active_chars(), cursor)..cursor);
+            }
+            KeyCode::Left => self.set_cursor_index(prev_grapheme(&self.active_chars(), cursor)),
+            KeyCode::Right => self.set_cursor_index(next_grapheme(&self.active_chars(), cursor)),
+            KeyCode::Backspace => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Ctrl+A/E, Ctrl+W/U and Alt+B/F in text fields.
+    fn on_key_readline(&mut self, key: KeyEvent) -> bool {
+        if !self.active_field_is_text() {
+            return false;
+        }
+        let chars = self.active_chars();
+        let cursor = self.cursor_index();
+        let (start, end) = line_bounds(&chars, cursor);
+        let ctrl = key.modifiers == KeyModifiers::CONTROL;
+        let alt = key.modifiers == KeyModifiers::ALT;
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.set_cursor_index(start),
+            KeyCode::Char('e') if ctrl => self.set_cursor_index(end),
+            KeyCode::Char('w') if ctrl => {
+                self.delete_range(blank_word_start(&chars, cursor).max(start)..cursor, false);
+            }
+            KeyCode::Char('u') if ctrl => self.delete_range(start..cursor, false),
+            KeyCode::Char('b') if alt => self.set_cursor_index(backward_word(&chars, cursor)),
+            KeyCode::Char('f') if alt => self.set_cursor_index(forward_word(&chars, cursor)),
+            _ => return false,
+        }
+        true
+    }
+
+    fn on_key_vim_normal(&mut self, key: KeyEvent, now: Instant) -> bool {
+        let text = self.active_field_is_text();
+        let pending = match self.vim.as_mut() {
+            Some(vim) => std::mem::take(&mut vim.pending),
+            None => return false,
+        };
+        let ch = match key.code {
+            KeyCode::Char(ch) => ch,
+            KeyCode::Esc if !pending.is_empty() => return true,
+            KeyCode::Left | KeyCode::Backspace if text => 'h',
+            KeyCode::Right if text => 'l',
+            KeyCode::Enter if text => 'j',
+            _ => return false,
+        };
+        if !text {
+            match ch {
+                'j' => self.next_field(),
+                'k' => self.prev_field(),
+                'h' => self.cycle_option(-1),
+                'l' => self.cycle_option(1),
+                'u' => self.undo(now),
+                'i' | 'a' if self.active_field_is_random() => {
+                    self.start_insert(self.cursor_index());
+                }
+                // Space pins and cycles as usual.
+                ' ' => return false,
+                _ => {}
+            }
+            return true;
+        }
+
+        let chars = self.active_chars();
+        let cursor = self.cursor_index();
+        let (start, end) = line_bounds(&chars, cursor);
+        let column = cursor - start;
+        match (pending.as_str(), ch) {
+            ("", 'h') => self.set_cursor_index(prev_grapheme(&chars, cursor).max(start)),
+            ("", 'l') => self.set_cursor_index(next_grapheme(&chars, cursor)),
+            ("", 'j') if end == chars.len() => self.next_field(),
+            ("", 'j') => {
+                self.set_cursor_index((end + 1 + column).min(normal_limit(&chars, end + 1)));
+            }
+            ("", 'k') if start == 0 => self.prev_field(),
+            ("", 'k') => {
+                let (above, _) = line_bounds(&chars, start - 1);
+                self.set_cursor_index((above + column).min(normal_limit(&chars, above)));
+            }
+            ("", '0') => self.set_cursor_index(start),
+            ("", '^') => self.set_cursor_index(first_non_blank(&chars, cursor)),
+            ("", '$') => self.set_cursor_index(end),
+            ("", 'w') => self.set_cursor_index(next_word_start(&chars, cursor)),
+            ("", 'b') => self.set_cursor_index(prev_word_start(&chars, cursor)),
+            ("", 'e') => self.set_cursor_index(word_end(&chars, cursor)),
+            ("", 'x') => self.delete_range(cursor..next_grapheme(&chars, cursor).min(end), false),
+            ("", 'X') => {
+                self.delete_range(prev_grapheme(&chars, cursor).max(start)..cursor, false);
+            }
+            ("", 'D') | ("d", '$') => self.delete_range(cursor..end, false),
+            ("", 'C') | ("c", '$') => self.delete_range(cursor..end, true),
+            ("", 'i') => self.start_insert(cursor),
+            ("", 'a') => self.start_insert(next_grapheme(&chars, cursor).min(end)),
+            ("", 'I') => self.start_insert(first_non_blank(&chars, cursor)),
+            ("", 'A') => self.start_insert(end),
+            ("", 'o') => {
+                self.start_insert(end);
+                self.insert_at_cursor('\n');
+            }
+            ("", 'O') => {
+                self.start_insert(start);
+                self.insert_at_cursor('\n');
+                self.set_cursor_index(start);
+            }
+            ("", 'u') => self.undo(now),
+            ("", 'd' | 'c') | ("d" | "c", 'i' | 'a') => {
+                if let Some(vim) = self.vim.as_mut() {
+                    vim.pending = format!("{pending}{ch}");
+                }
+            }
+            ("d", 'd') => {
+                let line = whole_line(&chars, cursor);
+                self.delete_range(line, false);
+                let cursor = first_non_blank(&self.active_chars(), self.cursor_index());
+                self.set_cursor_index(cursor);
+            }
+            ("c", 'c') => self.delete_range(start..end, true),
+            ("d", 'w') => {
+                self.delete_range(cursor..next_word_start(&chars, cursor).min(end), false);
+            }
+            // Like vim, `cw` keeps the blanks after the word.
+            ("d", 'e') | ("c", 'w' | 'e') => {
+                self.delete_range(
+                    cursor..(word_end(&chars, cursor) + 1).min(end),
+                    pending == "c",
+                );
+            }
+            ("d" | "c", 'b') => {
+                self.delete_range(
+                    prev_word_start(&chars, cursor).max(start)..cursor,
+                    pending == "c",
+                );
+            }
+            ("d" | "c", '0') => self.delete_range(start..cursor, pending == "c"),
+            ("di" | "ci" | "da" | "ca", 'w') => {
+                let range = word_range(&chars, cursor, pending.ends_with('a'));
+                self.delete_range(range, pending.starts_with('c'));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn active_chars(&self) -> Vec<char> {
+        self.fields
+            .get(self.active_field)
+            .map(|field| field.value.chars().collect())
+            .unwrap_or_default()
+    }
+
+    /// Cursor in the active field. Vim's normal mode rests on a char,
+    /// otherwise the cursor may sit after the last one.
+    pub(crate) fn cursor_index(&self) -> usize {
+        let cursor = match &self.cursor {
+            Some(cursor) => cursor,
+            None => return 0,
+        };
+        let chars = self.active_chars();
+        let index = if cursor.field == self.active_field {
+            cursor.index.min(chars.len())
+        } else {
+            chars.len()
+        };
+        match self.vim.as_ref().map(|vim| vim.mode) {
+            Some(VimMode::Normal) => index.min(normal_limit(&chars, index)),
+            _ => index,
+        }
+    }
+
+    fn set_cursor_index(&mut self, index: usize) {
+        let chars = self.active_chars();
+        let index = match self.vim.as_ref().map(|vim| vim.mode) {
+            Some(VimMode::Normal) => index.min(normal_limit(&chars, index)),
+            _ => index.min(chars.len()),
+        };
+        if let Some(cursor) = self.cursor.as_mut() {
+            cursor.index = index;
+        }
+    }
+
+    /// Enters insert mode at `cursor`. What is typed until Esc undoes as
+    /// one step.
+    fn start_insert(&mut self, cursor: usize) {
+        if let Some(vim) = self.vim.as_mut() {
+            vim.mode = VimMode::Insert;
+        }
+        self.set_cursor_index(cursor);
+        self.last_edit = None;
+    }
+
+    fn insert_at_cursor(&mut self, ch: char) {
+        let cursor = self.cursor_index();
+        self.checkpoint(Edit::Insert);
+        let value = &mut self.fields[self.active_field].value;
+        value.insert(byte_index(value, cursor), ch);
+        self.draft_dirty = true;
+        self.set_cursor_index(cursor + 1);
+    }
+
+    fn remove_range(&mut self, range: Range<usize>) {
+        let value = &mut self.fields[self.active_field].value;
+        let start = byte_index(value, range.start);
+        let end = byte_index(value, range.end);
+        value.replace_range(start..end, "");
+        self.draft_dirty = true;
+        self.set_cursor_index(range.start);
+    }
+
+    /// Deletes `range` of the active field; `change` then starts vim's
+    /// insert mode so the deletion and the replacement undo together.
+    fn delete_range(&mut self, range: Range<usize>, change: bool) {
+        if !range.is_empty() {
+            self.checkpoint(Edit::Other);
+            self.remove_range(range.clone());
+        }
+        if change {
+            self.start_insert(range.start);
+            self.last_edit = Some((Edit::Insert, self.active_field));
         }
-        Some(index)
     }
-}
 
-impl EditorState {
-    fn new(template_index: usize, body: &str) -> Self {
-        let tokens = parse_tokens(body);
-        let fields = collect_fields(&tokens);
-        Self {
-            template_index,
-            tokens,
-            fields,
-            active_field: 0,
-            field_scroll: 0,
-            fields_area: Rect::default(),
-            field_height: 0,
-            status: None,
+    fn snapshot(&self) -> EditSnapshot {
+        let choices = self
+            .tokens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, token)| match token {
+                Token::Random { choice, .. } => Some((index, choice.clone())),
+                _ => None,
+            })
+            .collect();
+        EditSnapshot {
+            fields: self.fields.clone(),
+            choices,
         }
     }
 
-    fn next_field(&mut self) {
-        if self.fields.is_empty() {
-            return;
+    fn apply_snapshot(&mut self, snapshot: EditSnapshot) {
+        self.fields = snapshot.fields;
+        for (index, value) in snapshot.choices {
+            if let Some(Token::Random { choice, .. }) = self.tokens.get_mut(index) {
+                *choice = value;
+            }
         }
-        self.active_field = (self.active_field + 1) % self.fields.len();
+        self.active_field = self.active_field.min(self.fields.len().saturating_sub(1));
+        self.last_edit = None;
+        self.draft_dirty = true;
     }
 
-    fn prev_field(&mut self) {
-        if self.fields.is_empty() {
+    /// Records the state before an edit for undo. Typing or deleting right
+    /// after the same kind of edit in the same field extends that step.
+    fn checkpoint(&mut self, edit: Edit) {
+        let current = (edit, self.active_field);
+        if edit != Edit::Other && self.last_edit == Some(current) {
             return;
         }
-        if self.active_field == 0 {
-            self.active_field = self.fields.len() - 1;
-        } else {
-            self.active_field -= 1;
+        self.undo.push(self.snapshot());
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
         }
+        self.redo.clear();
+        self.last_edit = Some(current);
     }
 
-    fn push_char(&mut self, ch: char) {
-        if let Some(field) = self.fields.get_mut(self.active_field) {
-            if matches!(&field.kind, FieldKind::Var) {
-                field.value.push(ch);
+    fn undo(&mut self, now: Instant) {
+        let snapshot = match self.undo.pop() {
+            Some(snapshot) => snapshot,
+            None => {
+                self.set_status(pick("没有可撤销的操作", "Nothing to undo"), now);
+                return;
             }
-        }
+        };
+        self.redo.push(self.snapshot());
+        self.apply_snapshot(snapshot);
+        self.set_status(pick("已撤销", "Undone"), now);
     }
 
-    fn backspace(&mut self) {
-        if let Some(field) = self.fields.get_mut(self.active_field) {
-            if matches!(&field.kind, FieldKind::Var) {
-                field.value.pop();
+    fn redo(&mut self, now: Instant) {
+        let snapshot = match self.redo.pop() {
+            Some(snapshot) => snapshot,
+            None => {
+                self.set_status(pick("没有可重做的操作", "Nothing to redo"), now);
+                return;
             }
+        };
+        self.undo.push(self.snapshot());
+        self.apply_snapshot(snapshot);
+        self.set_status(pick("已重做", "Redone"), now);
+    }
+
+    /// Whether the active random field holds text typed over its options.
+    pub(crate) fn active_choice_is_typed(&self) -> bool {
+        match self.fields.get(self.active_field).map(|field| &field.kind) {
+            Some(FieldKind::Random { token_index, .. }) => matches!(
+                self.tokens.get(*token_index),
+                Some(Token::Random { options, choice, .. }) if !options.contains(choice)
+            ),
+            _ => false,
         }
     }
 
-    fn reroll_random(&mut self) {
-        let mut rng = rand::rng();
-        let pinned_indices: Vec<usize> = self
-            .fields
+    /// Sets a typed choice on the active random field and pins it so a
+    /// reroll does not throw it away.
+    fn set_typed_choice(&mut self, choice: &str) {
+        self.set_active_option(choice);
+        if let Some(field) = self.fields.get_mut(self.active_field)
+            && let FieldKind::Random { pinned, .. } = &mut field.kind
+        {
+            *pinned = true;
+        }
+    }
+
+    fn reroll_random(&mut self, platform: &mut impl Platform) {
+        let now = platform.now();
+        let random_count = self
+            .tokens
+            .iter()
+            .filter(|token| matches!(token, Token::Random { options, .. } if !options.is_empty()))
+            .count();
+        if random_count > 0 && self.pinned_indices().len() >= random_count {
+            self.set_status(
+                pick(
+                    "随机项都已固定，Space 取消固定",
+                    "Every random pick is pinned; Space unpins",
+                ),
+                now,
+            );
+            return;
+        }
+        let kept = self.reseed(platform.seed(), now);
+        if kept == 0 {
+            self.set_status(
+                &tr!("已重随 (种子 {})", "Rerolled (seed {})", self.seed),
+                now,
+            );
+        } else {
+            self.set_status(
+                &tr!(
+                    "已重随，保留 {kept} 个固定项 (种子 {})",
+                    "Rerolled, kept {kept} pinned picks (seed {})",
+                    self.seed
+                ),
+                now,
+            );
+        }
+    }
+
+    /// Token indices of the pinned random fields.
+    fn pinned_indices(&self) -> Vec<usize> {
+        self.fields
             .iter()
             .filter_map(|f| match &f.kind {
                 FieldKind::Random {
@@ -400,76 +3729,250 @@ impl EditorState {
                 } => Some(*token_index),
                 _ => None,
             })
-            .collect();
+            .collect()
+    }
+
+    /// Rolls the unpinned random fields from `seed` and flashes the picks
+    /// that changed. Returns how many pinned picks were kept.
+    fn reseed(&mut self, seed: u64, now: Instant) -> usize {
+        self.checkpoint(Edit::Other);
+        let pinned_indices = self.pinned_indices();
+        let changed = roll_random(&mut self.tokens, seed, &pinned_indices);
+        self.seed = seed;
+        self.reroll_highlight = Some((changed, now));
 
-        for (idx, token) in self.tokens.iter_mut().enumerate() {
-            if let Token::Random {
-                options, choice, ..
-            } = token
+        for field in &mut self.fields {
+            if let FieldKind::Random { token_index, .. } = &field.kind
+                && let Token::Random { choice, .. } = &self.tokens[*token_index]
             {
-                if pinned_indices.contains(&idx) {
-                    continue;
-                }
-                if let Some(pick) = options.choose(&mut rng) {
-                    *choice = pick.clone();
-                }
+                field.value = choice.clone();
             }
         }
+        pinned_indices.len()
+    }
 
-        for field in &mut self.fields {
-            if let FieldKind::Random { token_index, .. } = &field.kind {
-                if let Token::Random { choice, .. } = &self.tokens[*token_index] {
-                    field.value = choice.clone();
+    pub(crate) fn has_random(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|token| matches!(token, Token::Random { .. }))
+    }
+
+    fn cycle_option(&mut self, delta: isize) {
+        let options = match self.active_options() {
+            Some(options) if !options.is_empty() => options,
+            _ => return,
+        };
+        let current = &self.fields[self.active_field].value;
+        let current_idx = options.iter().position(|o| o == current).unwrap_or(0);
+        let new_idx = (current_idx as isize + delta).rem_euclid(options.len() as isize) as usize;
+        self.checkpoint(Edit::Other);
+        self.set_active_option(&options[new_idx]);
+        if self.active_field_is_select() {
+            self.draft_dirty = true;
+        }
+    }
+
+    /// Opens the option picker on a select or random field. Returns false
+    /// when the active field has no options to pick from.
+    fn open_picker(&mut self) -> bool {
+        let options = match self.active_options() {
+            Some(options) if !options.is_empty() => options,
+            _ => return false,
+        };
+        let original = self.fields[self.active_field].value.clone();
+        let selected = options.iter().position(|o| *o == original).unwrap_or(0);
+        self.picker = Some(OptionPicker {
+            options,
+            selected,
+            original,
+            adding: None,
+        });
+        true
+    }
+
+    fn active_options(&self) -> Option<Vec<String>> {
+        let field = self.fields.get(self.active_field)?;
+        match &field.kind {
+            FieldKind::Select { options } => Some(options.clone()),
+            FieldKind::Random { token_index, .. } => match self.tokens.get(*token_index)? {
+                Token::Random { options, .. } => Some(options.clone()),
+                _ => None,
+            },
+            FieldKind::Var | FieldKind::List | FieldKind::File { .. } => None,
+        }
+    }
+
+    /// Sets the active select field's value, or the active random field's
+    /// choice, without marking the draft dirty.
+    fn set_active_option(&mut self, value: &str) {
+        let field = match self.fields.get_mut(self.active_field) {
+            Some(f) => f,
+            None => return,
+        };
+        match &field.kind {
+            FieldKind::Select { .. } => field.value = value.to_string(),
+            FieldKind::Random { token_index, .. } => {
+                if let Some(Token::Random { choice, .. }) = self.tokens.get_mut(*token_index) {
+                    *choice = value.to_string();
+                    field.value = value.to_string();
                 }
             }
+            FieldKind::Var | FieldKind::List | FieldKind::File { .. } => {}
         }
+    }
 
-        self.set_status("已重随");
+    pub(crate) fn has_commands(&self) -> bool {
+        self.tokens
+            .iter()
+            .any(|token| matches!(token, Token::Command { .. }))
     }
 
-    fn cycle_random(&mut self, delta: isize) {
-        let field = match self.fields.get(self.active_field) {
-            Some(f) => f,
+    /// Asks before running the template's commands, or explains why they
+    /// cannot run.
+    fn request_commands(&mut self, enabled: bool, now: Instant) {
+        if !self.has_commands() {
+            self.set_status(pick("模板中没有命令", "The template has no commands"), now);
+        } else if self.commands.is_some() {
+            self.set_status(pick("命令正在运行", "The commands are still running"), now);
+        } else if enabled {
+            self.command_prompt = true;
+        } else {
+            self.set_status(
+                pick(
+                    "命令占位符未启用，见 config.toml [commands]",
+                    "Command placeholders are disabled, see [commands] in config.toml",
+                ),
+                now,
+            );
+        }
+    }
+
+    /// Starts the template's commands in the background; the preview fills
+    /// in as each one finishes.
+    fn start_commands(&mut self) {
+        let commands: Vec<(usize, String)> = self
+            .tokens
+            .iter()
+            .enumerate()
+            .filter_map(|(index, token)| match token {
+                Token::Command { command, .. } => Some((index, command.clone())),
+                _ => None,
+            })
+            .collect();
+        self.commands = Some(CommandRun {
+            total: commands.len(),
+            results: Rc::new(spawn_commands(commands)),
+            done: 0,
+            errors: Vec::new(),
+        });
+    }
+
+    /// Takes the output of the commands that finished since the last tick,
+    /// and reports once all of them have.
+    fn receive_commands(&mut self, now: Instant) {
+        let run = match self.commands.as_mut() {
+            Some(run) => run,
             None => return,
         };
-        let token_index = match &field.kind {
-            FieldKind::Random { token_index, .. } => *token_index,
-            _ => return,
+        loop {
+            match run.results.try_recv() {
+                Ok((index, Ok(stdout))) => {
+                    run.done += 1;
+                    if let Some(Token::Command { output, .. }) = self.tokens.get_mut(index) {
+                        *output = Some(stdout);
+                    }
+                }
+                Ok((_, Err(err))) => {
+                    run.done += 1;
+                    run.errors.push(err.to_string());
+                }
+                Err(TryRecvError::Empty) if run.done < run.total => return,
+                Err(_) => break,
+            }
+        }
+        let message = match run.errors.first() {
+            Some(err) => err.clone(),
+            None => pick("命令已执行", "Commands ran").to_string(),
         };
-        let token = match self.tokens.get_mut(token_index) {
-            Some(t) => t,
+        self.commands = None;
+        self.set_status(&message, now);
+    }
+
+    fn open_rename(&mut self, now: Instant) {
+        let field = match self.fields.get(self.active_field) {
+            Some(field) => field,
             None => return,
         };
-        if let Token::Random {
-            options, choice, ..
-        } = token
+        if !field.is_named() {
+            self.set_status(
+                pick("随机项没有变量名", "The random placeholder has no name"),
+                now,
+            );
+            return;
+        }
+        self.rename = Some(RenamePrompt {
+            from: field.name.clone(),
+            to: field.name.clone(),
+            plan: None,
+        });
+    }
+
+    /// Adds an option to the active random field for this session only and
+    /// picks it.
+    fn add_random_option(&mut self, option: &str) {
+        let token_index = match self.fields.get(self.active_field).map(|field| &field.kind) {
+            Some(FieldKind::Random { token_index, .. }) => *token_index,
+            _ => return,
+        };
+        if let Some(Token::Random { options, .. }) = self.tokens.get_mut(token_index)
+            && !options.iter().any(|existing| existing == option)
         {
-            if options.is_empty() {
-                return;
-            }
-            let current_idx = options.iter().position(|o| o == choice).unwrap_or(0);
-            let new_idx =
-                (current_idx as isize + delta).rem_euclid(options.len() as isize) as usize;
-            *choice = options[new_idx].clone();
+            options.push(option.to_string());
         }
-        if let Token::Random { choice, .. } = &self.tokens[token_index] {
-            self.fields[self.active_field].value = choice.clone();
+        if let Some(picker) = self.picker.as_mut()
+            && let Some(Token::Random { options, .. }) = self.tokens.get(token_index)
+        {
+            picker.options = options.clone();
+            picker.selected = options.iter().position(|o| o == option).unwrap_or(0);
         }
+        self.set_active_option(option);
     }
 
-    fn toggle_pin(&mut self) {
-        if let Some(field) = self.fields.get_mut(self.active_field) {
-            if let FieldKind::Random { pinned, .. } = &mut field.kind {
-                *pinned = !*pinned;
-                if *pinned {
-                    self.set_status("已固定");
+    fn set_pinned(&mut self, value: bool, now: Instant) {
+        if let Some(field) = self.fields.get_mut(self.active_field)
+            && let FieldKind::Random { pinned, .. } = &mut field.kind
+        {
+            *pinned = value;
+            self.set_status(
+                if value {
+                    pick("已固定", "Pinned")
                 } else {
-                    self.set_status("已取消固定");
-                }
-            }
+                    pick("已取消固定", "Unpinned")
+                },
+                now,
+            );
+        }
+    }
+
+    fn toggle_pin(&mut self, now: Instant) {
+        if let Some(Field {
+            kind: FieldKind::Random { pinned, .. },
+            ..
+        }) = self.fields.get(self.active_field)
+        {
+            let pinned = *pinned;
+            self.checkpoint(Edit::Other);
+            self.set_pinned(!pinned, now);
         }
     }
 
+    pub(crate) fn active_field_is_select(&self) -> bool {
+        self.fields
+            .get(self.active_field)
+            .map(|f| matches!(&f.kind, FieldKind::Select { .. }))
+            .unwrap_or(false)
+    }
+
     pub(crate) fn active_field_is_random(&self) -> bool {
         self.fields
             .get(self.active_field)
@@ -477,10 +3980,794 @@ impl EditorState {
             .unwrap_or(false)
     }
 
-    fn set_status(&mut self, text: &str) {
+    fn set_status(&mut self, text: &str, now: Instant) {
         self.status = Some(StatusMessage {
             text: text.to_string(),
-            since: Instant::now(),
+            since: now,
         });
     }
 }
+
+/// Presses `code` without modifiers.
+#[cfg(test)]
+pub(crate) fn press<S: TemplateSource, T: StateStore, P: Platform>(
+    app: &mut App<S, T, P>,
+    code: KeyCode,
+) {
+    app.on_key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+/// Types `text` one key per character.
+#[cfg(test)]
+pub(crate) fn type_text<S: TemplateSource, T: StateStore, P: Platform>(
+    app: &mut App<S, T, P>,
+    text: &str,
+) {
+    for ch in text.chars() {
+        press(app, KeyCode::Char(ch));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{LIBRARY, MemoryStateStore, TestApp, test_app, test_app_with};
+
+    #[test]
+    fn leaving_the_editor_saves_a_draft() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.view, View::Editor));
+        type_text(&mut app, "张三");
+        press(&mut app, KeyCode::Esc);
+
+        let draft = app.store.load_draft("示例/问候");
+        assert_eq!(draft, Some(vec![("name".to_string(), "张三".to_string())]));
+    }
+
+    #[test]
+    fn globals_fill_fields_and_drafts_override_them() {
+        let store = MemoryStateStore {
+            globals: vec![("name".to_string(), "张三".to_string())],
+            ..MemoryStateStore::default()
+        };
+        let mut app = test_app_with(LIBRARY, store);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied, vec!["你好 张三\n".to_string()]);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.store.load_draft("示例/问候"), None);
+
+        let draft = vec![("name".to_string(), "李四".to_string())];
+        app.store.save_draft("示例/问候", &draft).unwrap();
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Char('y'));
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied.last(), Some(&"你好 李四\n".to_string()));
+    }
+
+    #[test]
+    fn reopening_a_template_restores_its_last_values_until_cleared() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Enter);
+        assert!(app.editor.as_ref().unwrap().pending_draft.is_none());
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied.last(), Some(&"你好 张三\n".to_string()));
+
+        app.on_key(KeyEvent::new(KeyCode::Backspace, KeyModifiers::ALT));
+        assert_eq!(app.store.load_last_values("示例/问候"), None);
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(
+            app.platform.copied.last(),
+            Some(&"你好 {name|收件人}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn runs_of_typing_and_deleting_undo_as_one_step() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        let value = |app: &TestApp| app.editor.as_ref().unwrap().fields[0].value.clone();
+        assert_eq!(value(&app), "");
+
+        app.on_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app), "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app), "");
+        app.on_key(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app), "张三");
+    }
+
+    #[test]
+    fn picked_options_preview_in_place_and_commit_as_one_step() {
+        let mut app = test_app("## 甲\n{select|size|小 中 大} {random|\"红\" \"蓝\"}\n");
+        press(&mut app, KeyCode::Enter);
+        let value =
+            |app: &TestApp, field: usize| app.editor.as_ref().unwrap().fields[field].value.clone();
+        let original = value(&app, 0);
+
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Down);
+        let previewed = value(&app, 0);
+        assert_ne!(previewed, original);
+        assert!(app.copy_text().unwrap().starts_with(&previewed));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(value(&app, 0), original);
+
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(value(&app, 0), previewed);
+        app.on_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app, 0), original);
+
+        // `a` adds a one-off option to a random field, and Enter pins it.
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Char('a'));
+        type_text(&mut app, "绿");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(value(&app, 1), "绿");
+        assert!(matches!(
+            app.editor.as_ref().unwrap().fields[1].kind,
+            FieldKind::Random { pinned: true, .. }
+        ));
+        assert!(app.copy_text().unwrap().ends_with("绿"));
+    }
+
+    #[test]
+    fn backspace_removes_a_whole_grapheme_cluster() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张e\u{301}👍🏽");
+        let value = |app: &TestApp| app.editor.as_ref().unwrap().fields[0].value.clone();
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(value(&app), "张e\u{301}");
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(value(&app), "张");
+    }
+
+    #[test]
+    fn vim_operators_edit_words_and_lines() {
+        let mut app = test_app("## 笔记\n{note}\n");
+        app.config.editor.vim = true;
+        press(&mut app, KeyCode::Enter);
+        let value = |app: &TestApp| app.editor.as_ref().unwrap().fields[0].value.clone();
+        type_text(&mut app, "zi");
+        type_text(&mut app, "foo bar baz");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "second line");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(value(&app), "foo bar baz\nsecond line");
+
+        type_text(&mut app, "k0wciw");
+        type_text(&mut app, "qux");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(value(&app), "foo qux baz\nsecond line");
+        type_text(&mut app, "bcw");
+        type_text(&mut app, "x");
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(value(&app), "foo x baz\nsecond line");
+        type_text(&mut app, "dd");
+        assert_eq!(value(&app), "second line");
+
+        type_text(&mut app, "u");
+        assert_eq!(value(&app), "foo x baz\nsecond line");
+        type_text(&mut app, "u");
+        assert_eq!(value(&app), "foo qux baz\nsecond line");
+    }
+
+    #[test]
+    fn readline_keys_move_and_delete_at_the_cursor() {
+        let mut app = test_app("## 笔记\n{note}\n");
+        app.config.editor.readline = true;
+        press(&mut app, KeyCode::Enter);
+        let value = |app: &TestApp| app.editor.as_ref().unwrap().fields[0].value.clone();
+        let ctrl = |app: &mut TestApp, ch: char| {
+            app.on_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::CONTROL));
+        };
+        let alt = |app: &mut TestApp, ch: char| {
+            app.on_key(KeyEvent::new(KeyCode::Char(ch), KeyModifiers::ALT));
+        };
+        type_text(&mut app, "hello big world");
+        ctrl(&mut app, 'w');
+        assert_eq!(value(&app), "hello big ");
+        alt(&mut app, 'b');
+        alt(&mut app, 'b');
+        alt(&mut app, 'f');
+        type_text(&mut app, "!");
+        assert_eq!(value(&app), "hello! big ");
+        ctrl(&mut app, 'a');
+        type_text(&mut app, "> ");
+        assert_eq!(value(&app), "> hello! big ");
+        alt(&mut app, 'f');
+        ctrl(&mut app, 'u');
+        assert_eq!(value(&app), "! big ");
+        ctrl(&mut app, 'e');
+        type_text(&mut app, "end");
+        assert_eq!(value(&app), "! big end");
+    }
+
+    #[test]
+    fn pasted_and_composed_text_is_typed_not_run_as_commands() {
+        let mut app = test_app(LIBRARY);
+        app.config.editor.compose = true;
+        app.on_paste("q");
+        press(&mut app, KeyCode::Char('删'));
+        assert!(!app.should_quit);
+        assert!(app.search.is_none());
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        app.on_paste("张\r\n三");
+        let value = |app: &TestApp| app.editor.as_ref().unwrap().fields[0].value.clone();
+        assert_eq!(value(&app), "张\n三");
+        type_text(&mut app, "四");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "五");
+        press(&mut app, KeyCode::Esc);
+        assert!(matches!(app.view, View::Editor));
+        assert_eq!(value(&app), "张\n三四五");
+
+        app.platform.advance(Duration::from_secs(1));
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(value(&app), "张\n三四五\n");
+        app.on_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app), "张\n三");
+        app.on_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(value(&app), "");
+    }
+
+    #[test]
+    fn idle_edits_are_autosaved_and_copies_stay_in_memory() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.tick();
+        assert_eq!(app.store.load_draft("示例/问候"), None);
+
+        app.platform.advance(Duration::from_secs(3));
+        app.tick();
+        let draft = app.store.load_draft("示例/问候");
+        assert_eq!(draft, Some(vec![("name".to_string(), "张三".to_string())]));
+
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied, vec!["你好 张三\n".to_string()]);
+    }
+
+    #[test]
+    fn selected_preview_lines_are_copied() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "李四");
+
+        app.on_key(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::ALT));
+        press(&mut app, KeyCode::Char('v'));
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('y'));
+        assert_eq!(app.platform.copied, vec!["你好 张三\n李四".to_string()]);
+        assert!(app.editor.as_ref().is_some_and(|e| e.selection.is_none()));
+    }
+
+    #[test]
+    fn presets_are_saved_and_loaded_per_template() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        type_text(&mut app, "常用");
+        press(&mut app, KeyCode::Enter);
+        let presets = app.store.load_presets("示例/问候");
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].name, "常用");
+
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        app.on_key(KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL));
+        press(&mut app, KeyCode::Enter);
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied, vec!["你好 张三\n".to_string()]);
+    }
+
+    #[test]
+    fn creating_a_template_writes_to_the_source() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Char('n'));
+        type_text(&mut app, "新建");
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "内容");
+        app.on_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+
+        assert!(matches!(app.view, View::List));
+        let names: Vec<String> = app.templates.iter().map(|t| t.name.clone()).collect();
+        assert!(names.iter().any(|name| name == "示例/新建"), "{names:?}");
+        assert!(app.source.content.contains("## 示例/新建\n内容\n"));
+    }
+
+    #[test]
+    fn duplicated_and_renamed_templates_are_written_back() {
+        let mut store = MemoryStateStore::default();
+        store
+            .save_draft("示例/问候", &[("name".to_string(), "张三".to_string())])
+            .unwrap();
+        let mut app = test_app_with(LIBRARY, store);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('d'));
+        press(&mut app, KeyCode::Enter);
+        assert!(
+            app.source
+                .content
+                .starts_with("## 示例/问候\n你好 {name|收件人}\n\n## 示例/问候 副本\n你好")
+        );
+
+        press(&mut app, KeyCode::Char('r'));
+        for _ in 0.."示例/问候 副本".chars().count() {
+            press(&mut app, KeyCode::Backspace);
+        }
+        type_text(&mut app, "其他/问候");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.source.content.contains("\n## 其他/问候\n"));
+        assert!(app.templates.iter().any(|t| t.name == "其他/问候"));
+
+        let index = app
+            .templates
+            .iter()
+            .position(|t| t.name == "示例/问候")
+            .unwrap();
+        app.list_state.select(
+            app.tree_items
+                .iter()
+                .position(|item| item.template_index == Some(index)),
+        );
+        press(&mut app, KeyCode::Char('r'));
+        type_text(&mut app, "2");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.store.load_draft("示例/问候"), None);
+        assert!(app.store.load_draft("示例/问候2").is_some());
+    }
+
+    #[test]
+    fn moving_a_template_swaps_it_with_its_sibling() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('m'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+
+        assert_eq!(
+            app.source.content,
+            "## 示例/告别\n再见\n\n## 示例/问候\n你好 {name|收件人}\n"
+        );
+        assert!(app.moving.is_none());
+        assert_eq!(app.templates[1].name, "示例/问候");
+    }
+
+    #[test]
+    fn frontmatter_is_kept_when_the_body_is_edited() {
+        let library = "## 甲\n---\ndescription: 问候\ntags: [写作, 邮件]\n---\n\n你好\n\n## 乙\n+++\ntags = [\"代码\"]\n+++\n再见\n";
+        let mut app = test_app(library);
+        press(&mut app, KeyCode::Char('E'));
+        type_text(&mut app, "！");
+        app.on_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL));
+        assert!(
+            app.source
+                .content
+                .starts_with("## 甲\n---\ndescription: 问候\ntags: [写作, 邮件]\n---\n\n你好！\n")
+        );
+        assert_eq!(app.templates[0].metadata.tags, vec!["写作", "邮件"]);
+    }
+
+    #[test]
+    fn copy_waits_for_required_fields() {
+        let mut app = test_app("## 邮件\n{topic|主题} {name!|收件人}\n");
+        press(&mut app, KeyCode::Enter);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        app.on_key(ctrl_c);
+        assert!(app.platform.copied.is_empty());
+        assert_eq!(app.editor.as_ref().map(|e| e.active_field), Some(1));
+
+        type_text(&mut app, "张三");
+        app.on_key(ctrl_c);
+        assert_eq!(app.platform.copied, vec!["{topic|主题} 张三".to_string()]);
+    }
+
+    #[test]
+    fn copy_and_quit_stays_in_the_editor_when_the_copy_fails() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.platform.clipboard_error = Some("没有剪贴板".to_string());
+        let copy_and_quit = KeyEvent::new(
+            KeyCode::Char('C'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        app.on_key(copy_and_quit);
+        assert!(!app.should_quit);
+        assert_eq!(app.exit_output, None);
+        assert!(matches!(app.view, View::Editor));
+        let status = app.editor.as_ref().unwrap().status.as_ref().unwrap();
+        assert!(status.text.contains("没有剪贴板"));
+
+        app.platform.clipboard_error = None;
+        app.on_key(copy_and_quit);
+        assert!(app.should_quit);
+        assert_eq!(app.exit_output.as_deref(), Some("你好 张三\n"));
+    }
+
+    #[test]
+    fn typing_in_the_list_jumps_to_a_matching_name() {
+        let library = "## 写作/邮件\na\n\n## 写作/周报\nb\n\n## code/review\nc\n";
+        let mut app = test_app(library);
+        let selected = |app: &TestApp| {
+            app.templates[app.selected_template_index().unwrap()]
+                .name
+                .clone()
+        };
+        press(&mut app, KeyCode::Char('c'));
+        press(&mut app, KeyCode::Char('o'));
+        assert_eq!(selected(&app), "code/review");
+        assert_eq!(app.type_ahead.as_ref().unwrap().0, "co");
+
+        app.platform.advance(Duration::from_secs(2));
+        app.tick();
+        assert!(app.type_ahead.is_none());
+        press(&mut app, KeyCode::Char('周'));
+        assert_eq!(selected(&app), "写作/周报");
+    }
+
+    #[test]
+    fn the_list_pages_by_the_rows_on_screen() {
+        let library: String = (0..20).map(|n| format!("## t{n:02}\nx\n\n")).collect();
+        let mut app = test_app(&library);
+        app.tree_area = ratatui::layout::Rect::new(0, 0, 30, 6);
+        let selected = |app: &TestApp| app.list_state.selected().unwrap();
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(selected(&app), 6);
+        app.on_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        assert_eq!(selected(&app), 9);
+        press(&mut app, KeyCode::End);
+        assert_eq!(selected(&app), 19);
+        app.on_key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(selected(&app), 10);
+        press(&mut app, KeyCode::Home);
+        assert_eq!(selected(&app), 0);
+    }
+
+    #[test]
+    fn the_wheel_over_the_tree_moves_the_selection() {
+        let library: String = (0..10).map(|n| format!("## t{n}\nx\n\n")).collect();
+        let mut app = test_app(&library);
+        app.tree_area = ratatui::layout::Rect::new(0, 1, 30, 5);
+        let wheel = |kind, row| MouseEvent {
+            kind,
+            column: 2,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        app.on_mouse(wheel(MouseEventKind::ScrollDown, 2));
+        app.on_mouse(wheel(MouseEventKind::ScrollDown, 2));
+        assert_eq!(app.list_state.selected(), Some(6));
+        app.on_mouse(wheel(MouseEventKind::ScrollUp, 2));
+        assert_eq!(app.list_state.selected(), Some(3));
+        app.on_mouse(wheel(MouseEventKind::ScrollDown, 9));
+        assert_eq!(app.list_state.selected(), Some(3));
+    }
+
+    #[test]
+    fn the_field_column_width_is_kept_for_the_next_session() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        let ctrl = |code| KeyEvent::new(code, KeyModifiers::CONTROL);
+        app.on_key(ctrl(KeyCode::Right));
+        app.on_key(ctrl(KeyCode::Right));
+        assert_eq!(app.field_percent(), 45);
+        assert_eq!(app.editor.as_ref().unwrap().fields[0].value, "");
+
+        let editor = app.editor.as_mut().unwrap();
+        editor.content_area = ratatui::layout::Rect::new(0, 0, 100, 20);
+        editor.preview_area = ratatui::layout::Rect::new(45, 0, 55, 20);
+        let mouse = |kind, column| MouseEvent {
+            kind,
+            column,
+            row: 5,
+            modifiers: KeyModifiers::NONE,
+        };
+        app.on_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 44));
+        app.on_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), 60));
+        assert_eq!(app.field_percent(), 60);
+        assert_eq!(app.store.split, Some(45));
+        app.on_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), 98));
+        app.on_mouse(mouse(MouseEventKind::Up(MouseButton::Left), 98));
+        assert_eq!(app.store.split, Some(90));
+
+        let reopened = test_app_with(LIBRARY, app.store.clone());
+        assert_eq!(reopened.field_percent(), 90);
+    }
+
+    #[test]
+    fn the_stacked_divider_is_dragged_by_rows() {
+        let mut app = test_app(LIBRARY);
+        assert!(app.config.layout.is_narrow(60));
+        assert!(!app.config.layout.is_narrow(120));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        let editor = app.editor.as_mut().unwrap();
+        editor.content_area = ratatui::layout::Rect::new(0, 0, 60, 30);
+        editor.preview_area = ratatui::layout::Rect::new(0, 11, 60, 19);
+        let mouse = |kind, row| MouseEvent {
+            kind,
+            column: 30,
+            row,
+            modifiers: KeyModifiers::NONE,
+        };
+        app.on_mouse(mouse(MouseEventKind::Down(MouseButton::Left), 10));
+        app.on_mouse(mouse(MouseEventKind::Drag(MouseButton::Left), 15));
+        app.on_mouse(mouse(MouseEventKind::Up(MouseButton::Left), 15));
+        assert_eq!(app.store.split, Some(50));
+    }
+
+    #[test]
+    fn a_resize_forgets_the_old_layout_until_the_next_frame() {
+        let mut app = test_app(LIBRARY);
+        app.tree_area = ratatui::layout::Rect::new(0, 1, 30, 5);
+        app.on_resize();
+        assert!(app.needs_redraw);
+        app.on_mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 2,
+            row: 3,
+            modifiers: KeyModifiers::NONE,
+        });
+        assert_eq!(app.list_state.selected(), Some(0));
+    }
+
+    #[test]
+    fn an_idle_app_waits_for_nothing() {
+        let mut app = test_app(LIBRARY);
+        assert_eq!(app.next_change(), None);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        assert!(app.next_change().is_some());
+        app.platform.advance(Duration::from_secs(3));
+        app.tick();
+        assert_eq!(app.next_change(), None);
+
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('z'));
+        assert!(app.next_change().is_some());
+        app.platform.advance(Duration::from_secs(2));
+        assert_eq!(app.next_change(), None);
+    }
+
+    #[test]
+    fn commands_run_in_the_background_and_fill_the_prompt() {
+        let mut app = test_app("## 日报\n今天 {cmd|echo 完成}\n");
+        app.config.commands.enabled = true;
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::F(6));
+        press(&mut app, KeyCode::Char('y'));
+        assert!(app.editor.as_ref().unwrap().commands.is_some());
+        for _ in 0..100 {
+            app.tick();
+            if app.editor.as_ref().unwrap().commands.is_none() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(app.editor.as_ref().unwrap().commands.is_none());
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(app.platform.copied, vec!["今天 完成".to_string()]);
+    }
+
+    #[test]
+    fn commands_only_run_when_enabled_and_allowed() {
+        let mut app = test_app("## 日报\n今天 {cmd|echo 完成}\n");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::F(6));
+        let editor = app.editor.as_ref().unwrap();
+        assert!(!editor.command_prompt);
+        assert!(editor.commands.is_none());
+        assert!(editor.status.as_ref().unwrap().text.contains("未启用"));
+
+        press(&mut app, KeyCode::Esc);
+        app.config.commands.enabled = true;
+        press(&mut app, KeyCode::Enter);
+        assert!(app.editor.as_ref().unwrap().command_prompt);
+        press(&mut app, KeyCode::Char('n'));
+        let editor = app.editor.as_ref().unwrap();
+        assert!(!editor.command_prompt);
+        assert!(editor.commands.is_none());
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert_eq!(
+            app.platform.copied,
+            vec!["今天 {cmd|echo 完成}".to_string()]
+        );
+    }
+
+    #[test]
+    fn duplicate_names_are_kept_apart_and_reported() {
+        let mut app = test_app("## 示例/问候\n一\n\n## 示例/问候\n二\n");
+        let names: Vec<&str> = app.templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["示例/问候", "示例/问候 (2)"]);
+        assert_eq!(app.templates[1].written_name(), "示例/问候");
+        let leaves: Vec<Option<usize>> = app
+            .tree_items
+            .iter()
+            .map(|item| item.template_index)
+            .collect();
+        assert_eq!(leaves, vec![None, Some(0), Some(1)]);
+        app.analyze();
+        assert_eq!(app.problems.len(), 1);
+        assert_eq!(app.problems[0].template_index, 1);
+        assert_eq!(app.problems[0].message, "重名模板，另一个在  第 1 行");
+    }
+
+    #[test]
+    fn the_library_is_checked_after_the_first_frame_from_cached_tokens() {
+        let mut app = test_app("## 甲\n一 {a}\n\n## 甲\n二\n");
+        assert!(app.analysis_due);
+        assert!(app.problems.is_empty());
+        app.analyze();
+        assert!(!app.analysis_due);
+        assert_eq!(app.problems.len(), 1);
+
+        let parsed = app.token_cache.tokens("一 {a}", &app.pools);
+        assert!(std::rc::Rc::ptr_eq(
+            &parsed,
+            &app.token_cache.tokens("一 {a}", &app.pools)
+        ));
+        let pools = crate::models::OptionPools::from([("色".to_string(), vec!["红".to_string()])]);
+        assert!(!std::rc::Rc::ptr_eq(
+            &parsed,
+            &app.token_cache.tokens("一 {a}", &pools)
+        ));
+    }
+
+    #[test]
+    fn a_library_without_templates_is_a_template_error() {
+        let app = test_app("no headings here\n");
+        assert!(matches!(app.view, View::Error));
+        assert!(matches!(app.error_message, Some(PmtError::Parse(_))));
+    }
+
+    #[test]
+    fn tag_filter_narrows_the_tree() {
+        let library = "## a/甲\n---\ntags: [写作, 邮件]\n---\n甲\n\n## b/乙\n---\ntags: [写作]\n---\n乙\n\n## 丙\n丙\n";
+        let mut app = test_app(library);
+        press(&mut app, KeyCode::Char('t'));
+        let picker = app.tag_picker.as_ref().unwrap();
+        assert_eq!(
+            picker.tags,
+            vec![("写作".to_string(), 2), ("邮件".to_string(), 1)]
+        );
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Enter);
+        let listed: Vec<&str> = app
+            .tree_items
+            .iter()
+            .filter_map(|item| item.template_index)
+            .map(|index| app.templates[index].name.as_str())
+            .collect();
+        assert_eq!(listed, vec!["a/甲", "b/乙"]);
+
+        press(&mut app, KeyCode::Char('t'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.tag_filter, vec!["写作", "邮件"]);
+        assert_eq!(app.tree_items.len(), 2);
+    }
+
+    #[test]
+    fn copies_go_to_the_journal_when_one_is_set() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        assert!(app.store.journal.is_empty());
+
+        app.config.copy.journal = Some(PathBuf::from("journal.md"));
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        let entry = (
+            PathBuf::from("journal.md"),
+            "示例/问候".to_string(),
+            "你好 张三\n".to_string(),
+        );
+        assert_eq!(app.store.journal, vec![entry]);
+        // Both copies are logged at the platform's time, not the wall clock.
+        let time = app.platform.local_time().timestamp();
+        let times: Vec<i64> = app.store.usage.iter().map(|entry| entry.time).collect();
+        assert_eq!(times, vec![time, time]);
+    }
+
+    #[test]
+    fn renaming_a_variable_updates_every_template_and_draft() {
+        let library = "## 甲\n你好 {name|收件人}\n\n## 乙\n{ name }，{select|name|a b}\n";
+        let mut store = MemoryStateStore::default();
+        store
+            .save_draft("乙", &[("name".to_string(), "李四".to_string())])
+            .unwrap();
+        let mut app = test_app_with(library, store);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        press(&mut app, KeyCode::F(2));
+        for _ in 0.."name".len() {
+            press(&mut app, KeyCode::Backspace);
+        }
+        type_text(&mut app, "who");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Char('y'));
+
+        assert_eq!(
+            app.source.content,
+            "## 甲\n你好 {who|收件人}\n\n## 乙\n{ who }，{select|who|a b}\n"
+        );
+        let draft = app.store.load_draft("乙");
+        assert_eq!(draft, Some(vec![("who".to_string(), "李四".to_string())]));
+        let editor = app.editor.as_ref().unwrap();
+        assert_eq!(editor.fields[0].name, "who");
+        assert_eq!(editor.fields[0].value, "张三");
+    }
+
+    #[test]
+    fn copies_are_counted_in_the_stats_and_follow_a_rename() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('r'));
+        type_text(&mut app, "2");
+        press(&mut app, KeyCode::Enter);
+        press(&mut app, KeyCode::Char('S'));
+
+        let stats = app.stats.as_ref().unwrap();
+        assert_eq!(stats[0].name, "示例/问候2");
+        assert_eq!(stats[0].uses, 2);
+        assert_eq!(stats[0].placeholders, 1);
+        assert_eq!(stats[0].average_length, Some("你好 张三\n".chars().count()));
+        assert_eq!(stats[1].uses, 0);
+        assert_eq!(stats[1].last_used, None);
+    }
+
+    #[test]
+    fn a_malformed_heading_is_reported_without_losing_the_library() {
+        let library = "## 甲\n你好\n##乙\n再见\n\n## 丙\n好\n";
+        let mut app = test_app(library);
+        assert!(matches!(app.view, View::List));
+        assert_eq!(app.templates.len(), 2);
+        assert_eq!(app.load_warnings.len(), 1);
+        assert_eq!(app.load_warnings[0].line, Some(2));
+        assert!(app.show_load_warnings);
+
+        press(&mut app, KeyCode::Esc);
+        assert!(!app.show_load_warnings);
+        assert!(!app.should_quit);
+    }
+}
@@ -0,0 +1,1450 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::ptr;
+
+use serde_json::{Value, json};
+
+use crate::diagnostics::find_problems;
+use crate::error::PmtError;
+use crate::formatter::format_document;
+use crate::importer::{ExportFormat, read_export, to_templates};
+use crate::interchange::{file_format, read_records, to_records, to_sections, write_records};
+use crate::locale::{pick, tr};
+use crate::models::{
+    FieldKind, OptionPools, RecordFormat, RenderRecord, Template, Token, UsageEntry,
+};
+use crate::parser::{
+    POOLS_SECTION, TokenCache, collect_fields, expand_uses, normalize_path, parse_pools,
+    parse_templates, parse_tokens, render_template, roll_random,
+};
+use crate::quality::{analyze, template_description};
+use crate::search::search_templates;
+use crate::stats::{format_row, stats_header, template_stats};
+use crate::system::{
+    append_templates_to, backup_prompts_file, backup_time, ensure_prompts_file, list_backups,
+    load_config, load_file_tokens, load_globals, load_library, load_templates, load_usage,
+    read_clipboard_tokens, record_use, restore_backup, run_command_tokens,
+};
+
+const USAGE_ZH: &str = "用法: pmt [--file 模板文件] [--format text|json | --json] [子命令]\n\n不带子命令时启动 TUI；--file 或环境变量 PMT_PROMPTS 指定代替 ~/.config/pmt/prompts.md 的模板文件；pmt --dry-run 启动的 TUI 不写剪贴板、不启动编辑器，退出时输出本应发生的操作；pmt --script <按键文件> 在 dry-run 中依次按下文件里的按键，并输出最后的屏幕。\n\n子命令:\n  render <模板路径> [--var 名称=值]... [--run-commands] [--seed N] [--emit text|json|yaml]  渲染模板并输出到 stdout\n  render-all [目录] --out-dir <输出目录> [--var 名称=值]... [--run-commands] [--seed N]  将目录下所有模板渲染为文件\n  fmt [文件] [--check] [--sort]  将模板文件整理为规范格式\n  list [目录] [--tag 标签]...  列出模板路径\n  search <关键词>... [--limit N]  按相关度搜索模板\n  lint [模板路径]... [--min 分数]  检查模板中的错误，并评分、给出改进建议\n  import --format chatgpt|claude <导出文件> [--all] [--out 文件]  从对话导出中提取提示词为待整理模板\n  import --format vscode|fabric|templater <路径> [--out 文件]  导入 VS Code 代码片段、fabric patterns 或 Templater 模板到 导入/\n  import <模板.json|yaml> [--out 文件]  导入 pmt export 格式的模板\n  export [--format json|yaml] [--out 文件]  将所有模板导出为 JSON 或 YAML\n  restore [编号|备份文件]  列出 prompts.md 的备份，或用其中一份恢复\n  stats [--unused]  按使用次数列出模板: 次数、最近使用、占位符、平均长度\n\n退出码:\n  0  成功\n  1  参数错误\n  2  模板不存在\n  3  校验失败\n  4  读写失败\n";
+const USAGE_EN: &str = "Usage: pmt [--file prompts file] [--format text|json | --json] [command]\n\nWithout a command pmt starts the TUI; --file or the PMT_PROMPTS environment variable names a prompts file to use instead of ~/.config/pmt/prompts.md; the TUI of pmt --dry-run does not touch the clipboard or start an editor, and prints what it would have done on exit; pmt --script <keys file> presses the keys of the file in a dry run and prints the screen they lead to.\n\nCommands:\n  render <template path> [--var name=value]... [--run-commands] [--seed N] [--emit text|json|yaml]  render a template to stdout\n  render-all [folder] --out-dir <output folder> [--var name=value]... [--run-commands] [--seed N]  render every template in a folder to files\n  fmt [file] [--check] [--sort]  rewrite a templates file in canonical form\n  list [folder] [--tag tag]...  list template paths\n  search <keyword>... [--limit N]  search templates by relevance\n  lint [template path]... [--min score]  check templates for mistakes, score them and suggest improvements\n  import --format chatgpt|claude <export file> [--all] [--out file]  extract prompts from a chat export as templates to sort out\n  import --format vscode|fabric|templater <path> [--out file]  import VS Code snippets, fabric patterns or Templater templates into Imported/\n  import <templates.json|yaml> [--out file]  import templates in the pmt export format\n  export [--format json|yaml] [--out file]  export every template as JSON or YAML\n  restore [number|backup file]  list the backups of prompts.md, or roll back to one\n  stats [--unused]  list templates by use: uses, last used, fields, average length\n\nExit codes:\n  0  success\n  1  usage error\n  2  template not found\n  3  validation failed\n  4  read or write failed\n";
+
+fn usage() -> &'static str {
+    pick(USAGE_ZH, USAGE_EN)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum CliError {
+    Usage(String),
+    NotFound(String),
+    Validation(String),
+    Io(String),
+    /// A failure that still has a report to show, such as the results of a
+    /// lint that found problems.
+    Reported(Box<CliError>, Box<Report>),
+}
+
+impl CliError {
+    pub(crate) fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Usage(_) => 1,
+            CliError::NotFound(_) => 2,
+            CliError::Validation(_) => 3,
+            CliError::Io(_) => 4,
+            CliError::Reported(err, _) => err.exit_code(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            CliError::Usage(_) => "usage",
+            CliError::NotFound(_) => "not_found",
+            CliError::Validation(_) => "validation",
+            CliError::Io(_) => "io",
+            CliError::Reported(err, _) => err.kind(),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            CliError::Usage(message)
+            | CliError::NotFound(message)
+            | CliError::Validation(message)
+            | CliError::Io(message) => message,
+            CliError::Reported(err, _) => err.message(),
+        }
+    }
+
+    fn with_report(self, report: Report) -> Self {
+        CliError::Reported(Box::new(self), Box::new(report))
+    }
+
+    fn report(&self) -> Option<&Report> {
+        match self {
+            CliError::Reported(_, report) => Some(report),
+            _ => None,
+        }
+    }
+}
+
+/// Broken templates and config, and changes to read-only templates, fail
+/// validation; everything else failed to read or write.
+impl From<PmtError> for CliError {
+    fn from(err: PmtError) -> Self {
+        match err {
+            PmtError::Parse(message)
+            | PmtError::Config(message)
+            | PmtError::Validation(message) => CliError::Validation(message),
+            PmtError::Io(message) | PmtError::Clipboard(message) | PmtError::Editor(message) => {
+                CliError::Io(message)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Report {
+    pub(crate) text: String,
+    pub(crate) data: Value,
+    pub(crate) warnings: Vec<String>,
+}
+
+pub(crate) fn run(args: &[String]) -> ExitCode {
+    let (format, rest) = match split_format(args) {
+        Ok(parsed) => parsed,
+        Err(err) => return emit(OutputFormat::Text, Err(err)),
+    };
+    emit(format, dispatch(&rest))
+}
+
+fn split_format(args: &[String]) -> Result<(OutputFormat, Vec<String>), CliError> {
+    let mut format = OutputFormat::Text;
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = if arg == "--format" {
+            Some(
+                iter.next()
+                    .ok_or_else(|| {
+                        CliError::Usage(tr!("--format 缺少参数", "--format needs a value"))
+                    })?
+                    .as_str(),
+            )
+        } else {
+            arg.strip_prefix("--format=")
+        };
+        match value {
+            // `import --format` names the export format and `export
+            // --format` the file format, not the output one.
+            Some(other)
+                if rest.first().is_some_and(|c| c == "import")
+                    && ExportFormat::parse(other).is_some() =>
+            {
+                rest.push(format!("--format={other}"));
+            }
+            Some(other)
+                if rest.first().is_some_and(|c| c == "export")
+                    && RecordFormat::parse(other).is_some() =>
+            {
+                rest.push(format!("--format={other}"));
+            }
+            Some("text") => format = OutputFormat::Text,
+            Some("json") => format = OutputFormat::Json,
+            None if arg == "--json" => format = OutputFormat::Json,
+            Some(other) => {
+                return Err(CliError::Usage(tr!(
+                    "未知输出格式: {other}",
+                    "Unknown output format: {other}"
+                )));
+            }
+            None => rest.push(arg.clone()),
+        }
+    }
+    Ok((format, rest))
+}
+
+fn dispatch(args: &[String]) -> Result<Report, CliError> {
+    let command = args.first().map(String::as_str).unwrap_or("help");
+    match command {
+        "help" | "-h" | "--help" => Ok(Report {
+            text: usage().to_string(),
+            data: json!({ "usage": usage() }),
+            warnings: Vec::new(),
+        }),
+        "-V" | "--version" => Ok(Report {
+            text: format!("pmt {}\n", env!("CARGO_PKG_VERSION")),
+            data: json!({ "version": env!("CARGO_PKG_VERSION") }),
+            warnings: Vec::new(),
+        }),
+        "render" => run_render(&args[1..]),
+        "render-all" => run_render_all(&args[1..]),
+        "fmt" => run_fmt(&args[1..]),
+        "list" => run_list(&args[1..]),
+        "search" => run_search(&args[1..]),
+        "import" => run_import(&args[1..]),
+        "export" => run_export(&args[1..]),
+        "lint" => run_lint(&args[1..]),
+        "restore" => run_restore(&args[1..]),
+        "stats" => run_stats(&args[1..]),
+        other => Err(CliError::Usage(tr!(
+            "未知子命令: {other}\n\n{}",
+            "Unknown command: {other}\n\n{}",
+            usage()
+        ))),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct ParsedArgs {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+
+impl ParsedArgs {
+    fn values(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.options
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).last()
+    }
+
+    fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn vars(&self) -> Result<Vec<(String, String)>, CliError> {
+        self.values("--var").map(parse_var).collect()
+    }
+
+    /// `--seed`, or a fresh one so the picks can still be reproduced from
+    /// the record.
+    fn seed(&self) -> Result<u64, CliError> {
+        match self.value("--seed") {
+            Some(value) => value.parse().map_err(|_| {
+                CliError::Usage(tr!(
+                    "--seed 需要非负整数: {value}",
+                    "--seed needs a non-negative integer: {value}"
+                ))
+            }),
+            None => Ok(rand::random()),
+        }
+    }
+}
+
+/// Splits subcommand arguments into positionals, `--name value` /
+/// `--name=value` options listed in `value_options`, and boolean flags
+/// listed in `flags`. Anything else starting with `--` is a usage error.
+fn parse_args(
+    args: &[String],
+    value_options: &[&str],
+    flags: &[&str],
+) -> Result<ParsedArgs, CliError> {
+    let mut parsed = ParsedArgs::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            parsed.positional.push(arg.clone());
+            continue;
+        }
+        if flags.contains(&arg.as_str()) {
+            parsed.flags.push(arg.clone());
+            continue;
+        }
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        if !value_options.contains(&name) {
+            return Err(CliError::Usage(tr!(
+                "未知参数: {arg}",
+                "Unknown argument: {arg}"
+            )));
+        }
+        let value = match inline {
+            Some(value) => value,
+            None => iter
+                .next()
+                .ok_or_else(|| CliError::Usage(tr!("{name} 缺少参数", "{name} needs a value")))?
+                .clone(),
+        };
+        parsed.options.push((name.to_string(), value));
+    }
+    Ok(parsed)
+}
+
+struct Rendered {
+    output: String,
+    variables: BTreeMap<String, String>,
+    /// Seed of the random picks, when the template has any.
+    seed: Option<u64>,
+    unknown: Vec<String>,
+    unfilled: Vec<String>,
+    /// Commands left unrun because `--run-commands` was not given.
+    skipped_commands: Vec<String>,
+    /// Why `{clipboard}` was left unfilled.
+    clipboard_error: Option<String>,
+}
+
+impl Rendered {
+    fn warnings(&self) -> Vec<String> {
+        let unknown = self.unknown.iter().map(|name| {
+            tr!(
+                "模板中没有变量: {name}",
+                "The template has no variable: {name}"
+            )
+        });
+        let unfilled = self
+            .unfilled
+            .iter()
+            .map(|name| tr!("变量未填写: {name}", "Variable not filled: {name}"));
+        let skipped = self.skipped_commands.iter().map(|command| {
+            tr!(
+                "未执行命令 (需要 --run-commands): {command}",
+                "Command not run (needs --run-commands): {command}"
+            )
+        });
+        unknown
+            .chain(unfilled)
+            .chain(skipped)
+            .chain(self.clipboard_error.clone())
+            .collect()
+    }
+}
+
+fn render_with_vars(
+    template: &Template,
+    templates: &[Template],
+    pools: &OptionPools,
+    vars: &[(String, String)],
+    globals: &[(String, String)],
+    run_commands: bool,
+    seed: u64,
+) -> Result<Rendered, CliError> {
+    let body = expand_uses(&template.body, templates)
+        .map_err(|err| CliError::Validation(format!("{}: {err}", template.name)))?;
+    let mut tokens = parse_tokens(&body, pools);
+    roll_random(&mut tokens, seed, &[]);
+    let seed = tokens
+        .iter()
+        .any(|token| matches!(token, Token::Random { .. }))
+        .then_some(seed);
+    if let Some(err) = load_file_tokens(&mut tokens).into_iter().next() {
+        return Err(CliError::Io(format!("{}: {err}", template.name)));
+    }
+    let clipboard_error = read_clipboard_tokens(&mut tokens).map(|err| err.to_string());
+    let mut skipped_commands = Vec::new();
+    if run_commands {
+        if let Some(err) = run_command_tokens(&mut tokens).into_iter().next() {
+            return Err(CliError::Io(format!("{}: {err}", template.name)));
+        }
+    } else {
+        skipped_commands = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Command { command, .. } => Some(command.clone()),
+                _ => None,
+            })
+            .collect();
+    }
+    let mut fields = collect_fields(&tokens);
+    let mut unknown = Vec::new();
+
+    for (name, value) in vars {
+        let field = match fields
+            .iter_mut()
+            .find(|field| field.is_named() && field.name == *name)
+        {
+            Some(field) => field,
+            None => {
+                unknown.push(name.clone());
+                continue;
+            }
+        };
+        if let FieldKind::Select { options } = &field.kind
+            && !options.contains(value)
+        {
+            return Err(CliError::Validation(tr!(
+                "{}: {name} 只能取 {}",
+                "{}: {name} must be one of {}",
+                template.name,
+                options.join(" | ")
+            )));
+        }
+        // Repeating `--var` for a list field adds one item per occurrence.
+        if matches!(field.kind, FieldKind::List) && !field.value.is_empty() {
+            field.value.push('\n');
+            field.value.push_str(value);
+        } else {
+            field.value = value.clone();
+        }
+    }
+    // Globals, then the template's defaults, only fill what `--var` left
+    // alone; unmatched names are fine.
+    let defaults = template.metadata.defaults.iter();
+    for (name, value) in globals
+        .iter()
+        .map(|(name, value)| (name, value))
+        .chain(defaults)
+    {
+        if vars.iter().any(|(var, _)| var == name) {
+            continue;
+        }
+        let field = match fields
+            .iter_mut()
+            .find(|field| field.is_named() && field.name == *name)
+        {
+            Some(field) => field,
+            None => continue,
+        };
+        match &field.kind {
+            FieldKind::Var | FieldKind::List => field.value = value.clone(),
+            FieldKind::Select { options } if options.contains(value) => {
+                field.value = value.clone();
+            }
+            _ => {}
+        }
+    }
+    let missing: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.is_missing())
+        .map(|field| field.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(CliError::Validation(tr!(
+            "{}: 必填变量未填写: {}",
+            "{}: required variables not filled: {}",
+            template.name,
+            missing.join(", ")
+        )));
+    }
+    if let Some((name, err)) = fields
+        .iter()
+        .find_map(|field| field.invalid().map(|err| (&field.name, err)))
+    {
+        return Err(CliError::Validation(format!(
+            "{}: {name} {err}",
+            template.name
+        )));
+    }
+    let unfilled = fields
+        .iter()
+        .filter(|field| {
+            matches!(field.kind, FieldKind::Var | FieldKind::List) && field.value.is_empty()
+        })
+        .map(|field| field.name.clone())
+        .collect();
+
+    let output = render_template(&tokens, &fields);
+    let variables: BTreeMap<String, String> = fields
+        .iter()
+        .filter(|field| field.is_named())
+        .map(|field| (field.name.clone(), field.value.clone()))
+        .collect();
+    Ok(Rendered {
+        output,
+        variables,
+        seed,
+        unknown,
+        unfilled,
+        skipped_commands,
+        clipboard_error,
+    })
+}
+
+/// `--run-commands` is the confirmation for `{cmd|...}` placeholders; it
+/// only takes effect when commands are enabled in `config.toml`.
+fn commands_allowed(parsed: &ParsedArgs) -> Result<bool, CliError> {
+    if !parsed.has_flag("--run-commands") {
+        return Ok(false);
+    }
+    let config = load_config().map_err(CliError::from)?;
+    if !config.commands.enabled {
+        return Err(CliError::Validation(tr!(
+            "命令占位符未启用: 请在 config.toml 中设置 [commands] enabled = true",
+            "Command placeholders are disabled: set [commands] enabled = true in config.toml"
+        )));
+    }
+    Ok(true)
+}
+
+fn run_render(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--var", "--emit", "--seed"], &["--run-commands"])?;
+    let run_commands = commands_allowed(&parsed)?;
+    let path = match parsed.positional.as_slice() {
+        [path] => path,
+        [] => {
+            return Err(CliError::Usage(tr!(
+                "render 需要模板路径",
+                "render needs a template path"
+            )));
+        }
+        [_, extra, ..] => {
+            return Err(CliError::Usage(tr!(
+                "多余的参数: {extra}",
+                "Unexpected argument: {extra}"
+            )));
+        }
+    };
+    let emit = match parsed.value("--emit") {
+        Some("text") | None => None,
+        Some(value) => Some(RecordFormat::parse(value).ok_or_else(|| {
+            CliError::Usage(tr!(
+                "--emit 只能是 text、json 或 yaml: {value}",
+                "--emit must be text, json or yaml: {value}"
+            ))
+        })?),
+    };
+    let vars = parsed.vars()?;
+    let seed = parsed.seed()?;
+    let globals = load_globals().map_err(CliError::from)?;
+
+    let templates = load_templates().map_err(CliError::from)?;
+    let template = find_template(&templates, path)?;
+    let rendered = render_with_vars(
+        template,
+        &templates,
+        &parse_pools(&templates),
+        &vars,
+        &globals,
+        run_commands,
+        seed,
+    )?;
+
+    let mut warnings = rendered.warnings();
+    if let Err(err) = record_use(&UsageEntry::now(&template.name, &rendered.output)) {
+        warnings.push(err.to_string());
+    }
+    let record = RenderRecord {
+        template: template.name.clone(),
+        variables: rendered.variables,
+        seed: rendered.seed,
+        output: rendered.output,
+    };
+    let text = match emit {
+        Some(format) => record.serialize(format),
+        None => {
+            let mut text = record.output.clone();
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            text
+        }
+    };
+    Ok(Report {
+        text,
+        data: json!(record),
+        warnings,
+    })
+}
+
+fn run_render_all(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--var", "--out-dir", "--seed"], &["--run-commands"])?;
+    let run_commands = commands_allowed(&parsed)?;
+    let folder = match parsed.positional.as_slice() {
+        [folder] => normalize_path(folder),
+        [] => String::new(),
+        [_, extra, ..] => {
+            return Err(CliError::Usage(tr!(
+                "多余的参数: {extra}",
+                "Unexpected argument: {extra}"
+            )));
+        }
+    };
+    let out_dir = PathBuf::from(parsed.value("--out-dir").ok_or_else(|| {
+        CliError::Usage(tr!(
+            "render-all 需要 --out-dir",
+            "render-all needs --out-dir"
+        ))
+    })?);
+    let vars = parsed.vars()?;
+    let seed = parsed.seed()?;
+    let globals = load_globals().map_err(CliError::from)?;
+
+    let templates = load_templates().map_err(CliError::from)?;
+    let pools = parse_pools(&templates);
+    let selected: Vec<(&Template, String)> = templates
+        .iter()
+        .filter(|template| template.name != POOLS_SECTION)
+        .filter_map(|template| {
+            let name = normalize_path(&template.name);
+            let relative = if folder.is_empty() {
+                name
+            } else {
+                name.strip_prefix(&folder)?.strip_prefix('/')?.to_string()
+            };
+            Some((template, relative))
+        })
+        .collect();
+    if selected.is_empty() {
+        return Err(CliError::NotFound(tr!(
+            "目录下没有模板: {folder}",
+            "No templates in folder: {folder}"
+        )));
+    }
+
+    let mut warnings = Vec::new();
+    let mut files = Vec::new();
+    let mut text = String::new();
+    for (template, relative) in selected {
+        let rendered = render_with_vars(
+            template,
+            &templates,
+            &pools,
+            &vars,
+            &globals,
+            run_commands,
+            seed,
+        )?;
+        let target = out_dir.join(format!("{relative}.md"));
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                CliError::Io(tr!(
+                    "创建目录失败: {} ({err})",
+                    "Failed to create directory: {} ({err})",
+                    parent.display()
+                ))
+            })?;
+        }
+        fs::write(&target, &rendered.output).map_err(|err| {
+            CliError::Io(tr!(
+                "写入失败: {} ({err})",
+                "Failed to write: {} ({err})",
+                target.display()
+            ))
+        })?;
+        warnings.extend(rendered.unfilled.iter().map(|name| {
+            tr!(
+                "{}: 变量未填写: {name}",
+                "{}: variable not filled: {name}",
+                template.name
+            )
+        }));
+        text.push_str(&format!("{}\n", target.display()));
+        files.push(json!({
+            "template": template.name,
+            "path": target.display().to_string(),
+        }));
+    }
+
+    Ok(Report {
+        text,
+        data: json!({ "files": files }),
+        warnings,
+    })
+}
+
+fn run_fmt(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &[], &["--check", "--sort"])?;
+    let path = match parsed.positional.as_slice() {
+        [path] => PathBuf::from(path),
+        [] => ensure_prompts_file().map_err(CliError::from)?,
+        [_, extra, ..] => {
+            return Err(CliError::Usage(tr!(
+                "多余的参数: {extra}",
+                "Unexpected argument: {extra}"
+            )));
+        }
+    };
+    let content = fs::read_to_string(&path).map_err(|err| {
+        CliError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        ))
+    })?;
+    let config = load_config().map_err(CliError::from)?;
+    if parsed.has_flag("--sort") && config.prompts.nested_headings {
+        return Err(CliError::Usage(tr!(
+            "嵌套标题的文件不能用 --sort 排序",
+            "--sort cannot order a file with nested headings"
+        )));
+    }
+    let formatted = format_document(&content, parsed.has_flag("--sort"));
+    let changed = formatted != content;
+
+    if parsed.has_flag("--check") {
+        if changed {
+            return Err(CliError::Validation(tr!(
+                "需要格式化: {}",
+                "Needs formatting: {}",
+                path.display()
+            )));
+        }
+    } else if changed {
+        backup_prompts_file(&path, config.prompts.backup_limit()).map_err(CliError::from)?;
+        fs::write(&path, &formatted).map_err(|err| {
+            CliError::Io(tr!(
+                "写入失败: {} ({err})",
+                "Failed to write: {} ({err})",
+                path.display()
+            ))
+        })?;
+    }
+
+    let text = if changed {
+        tr!("已格式化: {}\n", "Formatted: {}\n", path.display())
+    } else {
+        tr!("无需改动: {}\n", "Unchanged: {}\n", path.display())
+    };
+    Ok(Report {
+        text,
+        data: json!({
+            "path": path.display().to_string(),
+            "changed": changed,
+        }),
+        warnings: Vec::new(),
+    })
+}
+
+fn run_list(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--tag"], &[])?;
+    let folder = match parsed.positional.as_slice() {
+        [folder] => normalize_path(folder),
+        [] => String::new(),
+        [_, extra, ..] => {
+            return Err(CliError::Usage(tr!(
+                "多余的参数: {extra}",
+                "Unexpected argument: {extra}"
+            )));
+        }
+    };
+    let tags: Vec<&str> = parsed
+        .values("--tag")
+        .map(|tag| tag.trim_start_matches('#'))
+        .collect();
+
+    let templates = load_templates().map_err(CliError::from)?;
+    let in_folder: Vec<&Template> = templates
+        .iter()
+        .filter(|template| template.name != POOLS_SECTION)
+        .filter(|template| {
+            let name = normalize_path(&template.name);
+            folder.is_empty()
+                || name
+                    .strip_prefix(&folder)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .collect();
+    if !folder.is_empty() && in_folder.is_empty() {
+        return Err(CliError::NotFound(tr!(
+            "目录下没有模板: {folder}",
+            "No templates in folder: {folder}"
+        )));
+    }
+    let selected: Vec<&Template> = in_folder
+        .into_iter()
+        .filter(|template| tags.iter().all(|tag| template.has_tag(tag)))
+        .collect();
+
+    let text: String = selected
+        .iter()
+        .map(|template| format!("{}\n", template.name))
+        .collect();
+    let results: Vec<Value> = selected
+        .iter()
+        .map(|template| template_summary(template))
+        .collect();
+    Ok(Report {
+        text,
+        data: json!({ "results": results }),
+        warnings: Vec::new(),
+    })
+}
+
+/// Path and frontmatter fields shared by the `list` and `search` results.
+fn template_summary(template: &Template) -> Value {
+    json!({
+        "path": template.name,
+        "description": template_description(template),
+        "tags": template.metadata.tags,
+        "model": template.metadata.model,
+        "language": template.metadata.language,
+    })
+}
+
+fn run_search(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--limit"], &[])?;
+    if parsed.positional.is_empty() {
+        return Err(CliError::Usage(tr!(
+            "search 需要关键词",
+            "search needs keywords"
+        )));
+    }
+    let limit = match parsed.value("--limit") {
+        Some(value) => Some(value.parse::<usize>().map_err(|_| {
+            CliError::Usage(tr!(
+                "--limit 需要数字: {value}",
+                "--limit needs a number: {value}"
+            ))
+        })?),
+        None => None,
+    };
+    let query = parsed.positional.join(" ");
+
+    let templates = load_templates().map_err(CliError::from)?;
+    let mut hits = search_templates(&templates, &query);
+    if let Some(limit) = limit {
+        hits.truncate(limit);
+    }
+
+    let text: String = hits
+        .iter()
+        .map(|hit| format!("{}\n", templates[hit.template_index].name))
+        .collect();
+    let results: Vec<Value> = hits
+        .iter()
+        .map(|hit| {
+            let mut result = template_summary(&templates[hit.template_index]);
+            result["score"] = json!(hit.score);
+            result["name_matches"] = json!(hit.name_matches);
+            result["snippet"] = json!(hit.snippet);
+            result["snippet_matches"] = json!(hit.snippet_matches);
+            result
+        })
+        .collect();
+    Ok(Report {
+        text,
+        data: json!({ "query": query, "results": results }),
+        warnings: Vec::new(),
+    })
+}
+
+fn run_lint(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--min"], &[])?;
+    let min = match parsed.value("--min") {
+        Some(value) => Some(value.parse::<u8>().map_err(|_| {
+            CliError::Usage(tr!(
+                "--min 需要 0-100 的数字: {value}",
+                "--min needs a number from 0 to 100: {value}"
+            ))
+        })?),
+        None => None,
+    };
+
+    let (templates, load_warnings) = load_library().map_err(CliError::from)?;
+    let pools = parse_pools(&templates);
+    let selected: Vec<&Template> = if parsed.positional.is_empty() {
+        templates
+            .iter()
+            .filter(|template| template.name != POOLS_SECTION)
+            .collect()
+    } else {
+        parsed
+            .positional
+            .iter()
+            .map(|path| find_template(&templates, path))
+            .collect::<Result<_, _>>()?
+    };
+
+    let all_problems = find_problems(&templates, &pools, &mut TokenCache::default());
+    let mut text = String::new();
+    let mut results = Vec::new();
+    let mut failing = Vec::new();
+    let warnings: Vec<String> = load_warnings.iter().map(ToString::to_string).collect();
+    let mut broken = warnings.clone();
+    for template in selected {
+        let report = analyze(template, &pools);
+        let problems: Vec<&str> = all_problems
+            .iter()
+            .filter(|problem| ptr::eq(&templates[problem.template_index], template))
+            .map(|problem| problem.message.as_str())
+            .collect();
+        text.push_str(&format!("{:>3}  {}\n", report.score, template.name));
+        for problem in &problems {
+            text.push_str(&format!("     ✗ {problem}\n"));
+            broken.push(format!("{}: {problem}", template.name));
+        }
+        for suggestion in report.suggestions() {
+            text.push_str(&format!("     - {suggestion}\n"));
+        }
+        if min.is_some_and(|min| report.score < min) {
+            failing.push(template.name.clone());
+        }
+        results.push(json!({
+            "path": template.name,
+            "score": report.score,
+            "problems": problems,
+            "suggestions": report.suggestions().collect::<Vec<_>>(),
+        }));
+    }
+    let report = Report {
+        text,
+        data: json!({ "results": results }),
+        warnings,
+    };
+    if !broken.is_empty() {
+        let err = CliError::Validation(tr!(
+            "发现 {} 个问题:\n{}",
+            "Found {} problems:\n{}",
+            broken.len(),
+            broken.join("\n")
+        ));
+        return Err(err.with_report(report));
+    }
+    if let Some(min) = min
+        && !failing.is_empty()
+    {
+        let err = CliError::Validation(tr!(
+            "{} 个模板低于 {min} 分: {}",
+            "{} templates score below {min}: {}",
+            failing.len(),
+            failing.join(", ")
+        ));
+        return Err(err.with_report(report));
+    }
+    Ok(report)
+}
+
+fn run_import(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--format", "--out"], &["--all"])?;
+    let [export] = parsed.positional.as_slice() else {
+        return Err(CliError::Usage(tr!(
+            "import 需要一个导出文件",
+            "import needs one export file"
+        )));
+    };
+    let format = match parsed.value("--format") {
+        Some(value) => ExportFormat::parse(value).ok_or_else(|| {
+            CliError::Usage(tr!(
+                "未知导出格式: {value}",
+                "Unknown export format: {value}"
+            ))
+        })?,
+        None => match file_format(Path::new(export)) {
+            Some(format) => return import_records(Path::new(export), format, &parsed),
+            None => {
+                return Err(CliError::Usage(tr!(
+                    "import 需要 --format chatgpt|claude|vscode|fabric|templater，或 .json/.yaml 模板文件",
+                    "import needs --format chatgpt|claude|vscode|fabric|templater, or a .json/.yaml templates file"
+                )));
+            }
+        },
+    };
+
+    let messages = read_export(Path::new(export), format).map_err(CliError::Io)?;
+    let target = match parsed.value("--out") {
+        Some(out) => PathBuf::from(out),
+        None => ensure_prompts_file().map_err(CliError::from)?,
+    };
+    let existing = fs::read_to_string(&target)
+        .map(|content| parse_templates(&content))
+        .unwrap_or_default();
+    let (templates, warnings) =
+        to_templates(&messages, format, parsed.has_flag("--all"), &existing);
+    if !templates.is_empty() {
+        let prompts = load_config().map_err(CliError::from)?.prompts;
+        append_templates_to(
+            &target,
+            &templates,
+            prompts.nested_headings,
+            prompts.backup_limit(),
+        )
+        .map_err(CliError::from)?;
+    }
+
+    let names: Vec<&str> = templates.iter().map(|(name, _)| name.as_str()).collect();
+    let mut text: String = names.iter().map(|name| format!("{name}\n")).collect();
+    let skipped = messages.len() - templates.len();
+    text.push_str(&if format.is_library() {
+        tr!(
+            "已导入 {} 个模板到 {}，跳过 {skipped} 个已有的\n",
+            "Imported {} templates into {}, skipped {skipped} already there\n",
+            templates.len(),
+            target.display()
+        )
+    } else {
+        tr!(
+            "已导入 {} 个模板到 {}，跳过 {skipped} 条消息\n",
+            "Imported {} templates into {}, skipped {skipped} messages\n",
+            templates.len(),
+            target.display()
+        )
+    });
+    Ok(Report {
+        text,
+        data: json!({
+            "imported": names,
+            "skipped": skipped,
+            "target": target.display().to_string(),
+        }),
+        warnings,
+    })
+}
+
+/// Adds the templates of a file written by `pmt export`, skipping names
+/// that already exist in the target.
+fn import_records(
+    path: &Path,
+    format: RecordFormat,
+    parsed: &ParsedArgs,
+) -> Result<Report, CliError> {
+    let records = read_records(path, format).map_err(CliError::Io)?;
+    let target = match parsed.value("--out") {
+        Some(out) => PathBuf::from(out),
+        None => ensure_prompts_file().map_err(CliError::from)?,
+    };
+    let existing = fs::read_to_string(&target)
+        .map(|content| parse_templates(&content))
+        .unwrap_or_default();
+    let (sections, warnings) = to_sections(&records, &existing);
+    if !sections.is_empty() {
+        let prompts = load_config().map_err(CliError::from)?.prompts;
+        append_templates_to(
+            &target,
+            &sections,
+            prompts.nested_headings,
+            prompts.backup_limit(),
+        )
+        .map_err(CliError::from)?;
+    }
+
+    let names: Vec<&str> = sections.iter().map(|(name, _)| name.as_str()).collect();
+    let mut text: String = names.iter().map(|name| format!("{name}\n")).collect();
+    text.push_str(&tr!(
+        "已导入 {} 个模板到 {}，跳过 {} 个\n",
+        "Imported {} templates into {}, skipped {}\n",
+        sections.len(),
+        target.display(),
+        records.len() - sections.len()
+    ));
+    Ok(Report {
+        text,
+        data: json!({
+            "imported": names,
+            "skipped": records.len() - sections.len(),
+            "target": target.display().to_string(),
+        }),
+        warnings,
+    })
+}
+
+fn run_export(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &["--format", "--out"], &[])?;
+    if let Some(extra) = parsed.positional.first() {
+        return Err(CliError::Usage(tr!(
+            "多余的参数: {extra}",
+            "Unexpected argument: {extra}"
+        )));
+    }
+    let format = match parsed.value("--format") {
+        Some(value) => RecordFormat::parse(value).ok_or_else(|| {
+            CliError::Usage(tr!(
+                "--format 只能是 json 或 yaml: {value}",
+                "--format must be json or yaml: {value}"
+            ))
+        })?,
+        None => parsed
+            .value("--out")
+            .and_then(|out| file_format(Path::new(out)))
+            .unwrap_or_default(),
+    };
+
+    let templates = load_templates().map_err(CliError::from)?;
+    let records = to_records(&templates);
+    let document = write_records(&records, format);
+    let text = match parsed.value("--out") {
+        Some(out) => {
+            fs::write(out, &document).map_err(|err| {
+                CliError::Io(tr!(
+                    "写入失败: {out} ({err})",
+                    "Failed to write: {out} ({err})"
+                ))
+            })?;
+            tr!(
+                "已导出 {} 个模板到 {out}\n",
+                "Exported {} templates to {out}\n",
+                records.len()
+            )
+        }
+        None => document,
+    };
+    Ok(Report {
+        text,
+        data: json!({ "templates": records }),
+        warnings: Vec::new(),
+    })
+}
+
+/// Uses per template from the usage log, most used first; `--unused` keeps
+/// only templates that were never used.
+fn run_stats(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &[], &["--unused"])?;
+    if let Some(extra) = parsed.positional.first() {
+        return Err(CliError::Usage(tr!(
+            "多余的参数: {extra}",
+            "Unexpected argument: {extra}"
+        )));
+    }
+    let templates = load_templates().map_err(CliError::from)?;
+    let mut stats = template_stats(&templates, &parse_pools(&templates), &load_usage());
+    if parsed.has_flag("--unused") {
+        stats.retain(|row| row.uses == 0);
+    }
+
+    let mut text = format!("{}\n", stats_header());
+    for row in &stats {
+        text.push_str(&format_row(row));
+        text.push('\n');
+    }
+    let results: Vec<Value> = stats
+        .iter()
+        .map(|row| {
+            json!({
+                "path": row.name,
+                "uses": row.uses,
+                "last_used": row.last_used,
+                "placeholders": row.placeholders,
+                "average_length": row.average_length,
+            })
+        })
+        .collect();
+    Ok(Report {
+        text,
+        data: json!({ "results": results }),
+        warnings: Vec::new(),
+    })
+}
+
+/// Lists the backups of the prompts file, newest first, or puts back the
+/// one picked by number or file name.
+fn run_restore(args: &[String]) -> Result<Report, CliError> {
+    let parsed = parse_args(args, &[], &[])?;
+    let backups = list_backups();
+    let wanted = match parsed.positional.as_slice() {
+        [wanted] => wanted,
+        [] => {
+            let mut text = String::new();
+            let mut results = Vec::new();
+            for (index, backup) in backups.iter().enumerate() {
+                let time = backup_time(backup)
+                    .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_default();
+                let count = fs::read_to_string(backup)
+                    .map(|content| parse_templates(&content).len())
+                    .unwrap_or(0);
+                let file = backup
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                text.push_str(&tr!(
+                    "{:>3}  {time}  {count} 个模板  {file}\n",
+                    "{:>3}  {time}  {count} templates  {file}\n",
+                    index + 1
+                ));
+                results.push(json!({
+                    "index": index + 1,
+                    "time": time,
+                    "templates": count,
+                    "path": backup.display().to_string(),
+                }));
+            }
+            if backups.is_empty() {
+                text = pick("还没有备份\n", "No backups yet\n").to_string();
+            }
+            return Ok(Report {
+                text,
+                data: json!({ "backups": results }),
+                warnings: Vec::new(),
+            });
+        }
+        [_, extra, ..] => {
+            return Err(CliError::Usage(tr!(
+                "多余的参数: {extra}",
+                "Unexpected argument: {extra}"
+            )));
+        }
+    };
+    let backup = match wanted.parse::<usize>() {
+        Ok(index) => index.checked_sub(1).and_then(|index| backups.get(index)),
+        Err(_) => backups.iter().find(|backup| {
+            backup
+                .file_name()
+                .is_some_and(|name| name == wanted.as_str())
+        }),
+    }
+    .ok_or_else(|| CliError::NotFound(tr!("备份不存在: {wanted}", "No such backup: {wanted}")))?;
+    let backups = load_config()
+        .map_err(CliError::from)?
+        .prompts
+        .backup_limit();
+    let path = restore_backup(backup, backups).map_err(CliError::from)?;
+    Ok(Report {
+        text: tr!(
+            "已恢复 {}（来自 {}）\n",
+            "Restored {} from {}\n",
+            path.display(),
+            backup.display()
+        ),
+        data: json!({
+            "path": path.display().to_string(),
+            "backup": backup.display().to_string(),
+        }),
+        warnings: Vec::new(),
+    })
+}
+
+fn parse_var(assignment: &str) -> Result<(String, String), CliError> {
+    let (name, value) = assignment.split_once('=').ok_or_else(|| {
+        CliError::Usage(tr!(
+            "变量格式应为 名称=值: {assignment}",
+            "Variables take the form name=value: {assignment}"
+        ))
+    })?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CliError::Usage(tr!(
+            "变量名为空: {assignment}",
+            "Empty variable name: {assignment}"
+        )));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn find_template<'a>(templates: &'a [Template], path: &str) -> Result<&'a Template, CliError> {
+    let wanted = normalize_path(path);
+    templates
+        .iter()
+        .find(|template| normalize_path(&template.name) == wanted)
+        .ok_or_else(|| CliError::NotFound(tr!("未找到模板: {path}", "Template not found: {path}")))
+}
+
+fn emit(format: OutputFormat, result: Result<Report, CliError>) -> ExitCode {
+    match format {
+        OutputFormat::Text => {
+            let (report, code) = match &result {
+                Ok(report) => (Some(report), 0),
+                Err(err) => (err.report(), err.exit_code()),
+            };
+            if let Some(report) = report {
+                for warning in &report.warnings {
+                    eprintln!("{}", tr!("警告: {warning}", "warning: {warning}"));
+                }
+                print!("{}", report.text);
+            }
+            if let Err(err) = &result {
+                eprintln!("{}", err.message());
+            }
+            ExitCode::from(code)
+        }
+        OutputFormat::Json => {
+            let (value, code) = match result {
+                Ok(report) => (
+                    json!({
+                        "ok": true,
+                        "data": report.data,
+                        "warnings": report.warnings,
+                    }),
+                    0,
+                ),
+                Err(err) => {
+                    let mut value = json!({
+                        "ok": false,
+                        "error": {
+                            "kind": err.kind(),
+                            "code": err.exit_code(),
+                            "message": err.message(),
+                        },
+                        "warnings": err.report().map_or(&[][..], |report| &report.warnings),
+                    });
+                    if let Some(report) = err.report() {
+                        value["data"] = report.data.clone();
+                    }
+                    (value, err.exit_code())
+                }
+            };
+            println!("{value}");
+            ExitCode::from(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::test_config_dir;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn template_errors_exit_with_3_and_system_errors_with_4() {
+        let exit_code = |err| CliError::from(err).exit_code();
+        assert_eq!(exit_code(PmtError::Parse(String::new())), 3);
+        assert_eq!(exit_code(PmtError::Config(String::new())), 3);
+        assert_eq!(exit_code(PmtError::Clipboard(String::new())), 4);
+        assert_eq!(exit_code(PmtError::Io(String::new())), 4);
+    }
+
+    #[test]
+    fn a_failure_with_a_report_keeps_its_code_and_warnings() {
+        let report = Report {
+            text: String::new(),
+            data: json!({ "results": [] }),
+            warnings: vec!["prompts.md:3: 标题缺少空格".to_string()],
+        };
+        let err = CliError::Validation("发现 1 个问题".to_string()).with_report(report);
+        assert_eq!(err.exit_code(), 3);
+        assert_eq!(err.kind(), "validation");
+        assert_eq!(err.message(), "发现 1 个问题");
+        assert_eq!(err.report().unwrap().warnings.len(), 1);
+    }
+
+    #[test]
+    fn render_prints_one_template_with_its_variables() {
+        let dir = test_config_dir("render");
+        fs::write(
+            dir.join("prompts.md"),
+            "## 邮件/问候\n你好 {name|收件人}，{topic}\n",
+        )
+        .unwrap();
+        let report = dispatch(&args("render 邮件/问候 --var name=张三 --var who=李四")).unwrap();
+        assert_eq!(report.text, "你好 张三，{topic}\n");
+        assert_eq!(report.data["variables"]["name"], "张三");
+        assert_eq!(
+            report.warnings,
+            vec!["模板中没有变量: who", "变量未填写: topic"]
+        );
+        assert!(
+            fs::read_to_string(dir.join("usage.jsonl")).is_ok_and(|log| log.contains("邮件/问候"))
+        );
+
+        assert_eq!(
+            dispatch(&args("render 邮件/告别")).unwrap_err().exit_code(),
+            2
+        );
+        assert_eq!(dispatch(&args("render")).unwrap_err().exit_code(), 1);
+        let err = dispatch(&args("render 邮件/问候 --var name")).unwrap_err();
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn render_all_writes_a_file_per_template_of_the_folder() {
+        let dir = test_config_dir("render-all");
+        fs::write(
+            dir.join("prompts.md"),
+            "## 邮件/问候\n你好 {name}\n\n## 邮件/周报/本周\n{name} 的周报\n\n## 其他\n再见\n",
+        )
+        .unwrap();
+        let out = dir.join("out");
+        let line = format!(
+            "render-all 邮件 --out-dir {} --var name=张三",
+            out.display()
+        );
+        let report = dispatch(&args(&line)).unwrap();
+        assert_eq!(report.data["files"].as_array().map(Vec::len), Some(2));
+        assert_eq!(
+            fs::read_to_string(out.join("问候.md")).unwrap(),
+            "你好 张三\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("周报").join("本周.md")).unwrap(),
+            "张三 的周报\n"
+        );
+        assert!(!out.join("其他.md").exists());
+
+        let line = format!("render-all 没有 --out-dir {}", out.display());
+        assert_eq!(dispatch(&args(&line)).unwrap_err().exit_code(), 2);
+        assert_eq!(
+            dispatch(&args("render-all 邮件")).unwrap_err().exit_code(),
+            1
+        );
+    }
+
+    #[test]
+    fn render_emits_a_record_with_the_values_and_seed() {
+        let dir = test_config_dir("render-emit");
+        fs::write(
+            dir.join("prompts.md"),
+            "## 问候\n{random|\"你好\" \"您好\"} {name}\n",
+        )
+        .unwrap();
+        let report = dispatch(&args("render 问候 --var name=张三 --seed 7 --emit json")).unwrap();
+        let record: serde_json::Value = serde_json::from_str(&report.text).unwrap();
+        assert_eq!(record, report.data);
+        assert_eq!(record["template"], "问候");
+        assert_eq!(record["variables"], json!({"name": "张三"}));
+        assert_eq!(record["seed"], 7);
+        let output = record["output"].as_str().unwrap();
+        assert!(output == "你好 张三" || output == "您好 张三");
+
+        let report = dispatch(&args("render 问候 --var name=张三 --seed 7 --emit yaml")).unwrap();
+        assert!(
+            report
+                .text
+                .starts_with("template: 问候\nvariables:\n  name: 张三\nseed: 7\n")
+        );
+        assert!(report.text.contains(output));
+        let err = dispatch(&args("render 问候 --emit xml")).unwrap_err();
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn restore_lists_backups_and_puts_one_back() {
+        let dir = test_config_dir("restore");
+        let prompts = dir.join("prompts.md");
+        fs::write(&prompts, "## 新\n新\n").unwrap();
+        fs::create_dir_all(dir.join("backups")).unwrap();
+        fs::write(
+            dir.join("backups").join("prompts-20200101-080000.md"),
+            "## 旧\n旧\n\n## 旧/二\n二\n",
+        )
+        .unwrap();
+
+        let report = dispatch(&args("restore")).unwrap();
+        assert_eq!(
+            report.text,
+            "  1  2020-01-01 08:00:00  2 个模板  prompts-20200101-080000.md\n"
+        );
+        dispatch(&args("restore 1")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&prompts).unwrap(),
+            "## 旧\n旧\n\n## 旧/二\n二\n"
+        );
+        // The content before the restore is the newest backup now.
+        let backups = list_backups();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "## 新\n新\n");
+
+        assert_eq!(dispatch(&args("restore 3")).unwrap_err().exit_code(), 2);
+        dispatch(&args("restore prompts-20200101-080000.md")).unwrap();
+        assert!(fs::read_to_string(&prompts).unwrap().starts_with("## 旧\n"));
+    }
+
+    #[test]
+    fn commands_need_the_flag_and_the_config() {
+        let dir = test_config_dir("render-commands");
+        fs::write(dir.join("prompts.md"), "## 日报\n今天 {cmd|echo 完成}\n").unwrap();
+        let report = dispatch(&args("render 日报")).unwrap();
+        assert_eq!(report.text, "今天 {cmd|echo 完成}\n");
+        assert_eq!(
+            report.warnings,
+            vec!["未执行命令 (需要 --run-commands): echo 完成"]
+        );
+        let err = dispatch(&args("render 日报 --run-commands")).unwrap_err();
+        assert_eq!(err.exit_code(), 3);
+
+        fs::write(dir.join("config.toml"), "[commands]\nenabled = true\n").unwrap();
+        let report = dispatch(&args("render 日报 --run-commands")).unwrap();
+        assert_eq!(report.text, "今天 完成\n");
+        assert!(report.warnings.is_empty());
+
+        // A command that fails is an outside failure, not a template error.
+        fs::write(dir.join("prompts.md"), "## 日报\n今天 {cmd|exit 1}\n").unwrap();
+        let err = dispatch(&args("render 日报 --run-commands")).unwrap_err();
+        assert_eq!(err.exit_code(), 4);
+    }
+}
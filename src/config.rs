@@ -0,0 +1,607 @@
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::Color;
+use serde::Deserialize;
+
+use crate::locale::{Locale, tr};
+use crate::models::RecordFormat;
+use crate::theme::{Theme, ThemeName};
+use crate::tokens::TokenEstimator;
+
+/// Settings read from `~/.config/pmt/config.toml`. Every key is optional.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// `zh-CN` or `en-US`; unset follows `LANG`.
+    pub(crate) language: Option<Locale>,
+    pub(crate) footer: FooterConfig,
+    pub(crate) commands: CommandsConfig,
+    pub(crate) copy: CopyConfig,
+    pub(crate) keys: KeysConfig,
+    pub(crate) prompts: PromptsConfig,
+    pub(crate) layout: LayoutConfig,
+    pub(crate) editor: EditorConfig,
+    pub(crate) mouse: MouseConfig,
+    pub(crate) theme: ThemeConfig,
+    pub(crate) preview: PreviewConfig,
+    pub(crate) send: SendConfig,
+    pub(crate) llm: LlmConfig,
+    pub(crate) tokens: TokensConfig,
+    /// `[[remote]]` prompt files fetched over HTTP and merged read-only.
+    pub(crate) remote: Vec<RemoteSource>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ThemeConfig {
+    /// Built-in theme the colors start from.
+    pub(crate) name: ThemeName,
+    /// Colors replacing the theme's, e.g. `selection_bg = "#005f87"`.
+    pub(crate) colors: ThemeColors,
+}
+
+/// Optional override per `Theme` role.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ThemeColors {
+    selection_fg: Option<ColorValue>,
+    selection_bg: Option<ColorValue>,
+    focus: Option<ColorValue>,
+    muted: Option<ColorValue>,
+    accent: Option<ColorValue>,
+    info: Option<ColorValue>,
+    success: Option<ColorValue>,
+    error: Option<ColorValue>,
+    flash_fg: Option<ColorValue>,
+    flash_bg: Option<ColorValue>,
+    unfilled_fg: Option<ColorValue>,
+    unfilled_bg: Option<ColorValue>,
+    status: Option<ColorValue>,
+    text: Option<ColorValue>,
+}
+
+impl ThemeConfig {
+    pub(crate) fn theme(&self) -> Theme {
+        let mut theme = self.name.theme();
+        let colors = &self.colors;
+        for (slot, color) in [
+            (&mut theme.selection_fg, colors.selection_fg),
+            (&mut theme.selection_bg, colors.selection_bg),
+            (&mut theme.focus, colors.focus),
+            (&mut theme.muted, colors.muted),
+            (&mut theme.accent, colors.accent),
+            (&mut theme.info, colors.info),
+            (&mut theme.success, colors.success),
+            (&mut theme.error, colors.error),
+            (&mut theme.flash_fg, colors.flash_fg),
+            (&mut theme.flash_bg, colors.flash_bg),
+            (&mut theme.unfilled_fg, colors.unfilled_fg),
+            (&mut theme.unfilled_bg, colors.unfilled_bg),
+            (&mut theme.status, colors.status),
+            (&mut theme.text, colors.text),
+        ] {
+            if let Some(ColorValue(color)) = color {
+                *slot = color;
+            }
+        }
+        theme
+    }
+}
+
+/// A color name such as `"dark-gray"` or a hex value such as `"#ff8800"`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+struct ColorValue(Color);
+
+impl TryFrom<String> for ColorValue {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        Color::from_str(&value)
+            .map(Self)
+            .map_err(|_| tr!("无效的颜色: {value}", "Invalid color: {value}"))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PromptsConfig {
+    /// Prompts file to use instead of `~/.config/pmt/prompts.md`. `~/` is
+    /// the home directory; relative paths start at `~/.config/pmt`.
+    /// `--file` and `PMT_PROMPTS` take precedence.
+    pub(crate) path: Option<PathBuf>,
+    /// Directory of `*.md` templates to use instead of or next to the
+    /// prompts file (default: `~/.config/pmt/prompts`).
+    pub(crate) dir: Option<PathBuf>,
+    /// Shared prompts files or directories, e.g. a team repository, resolved
+    /// like `path`. Personal templates hide team templates of the same name.
+    pub(crate) team: Vec<PathBuf>,
+    /// Directory with a system-wide `prompts.md` and/or `prompts/`
+    /// (default: `/etc/pmt`).
+    pub(crate) system: Option<PathBuf>,
+    /// Number of backups of the prompts file kept in `~/.config/pmt/backups`
+    /// (default: 50, 0 turns backups off).
+    pub(crate) backups: Option<usize>,
+    /// Read `#` to `######` headings as folders of the templates nested
+    /// under them, instead of only `## a/b` headings.
+    pub(crate) nested_headings: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LayoutConfig {
+    /// Width of the editor's field column, in percent of the screen.
+    pub(crate) split: u16,
+    /// Terminal width, in columns, below which the editor stacks the fields
+    /// over the preview and the tree is indented less. 0 never stacks.
+    pub(crate) narrow: u16,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RemoteSource {
+    /// URL of a prompts file in the usual `## Title` format.
+    pub(crate) source: String,
+    /// Seconds a fetched copy is used before it is fetched again.
+    pub(crate) ttl: u64,
+}
+
+impl Default for RemoteSource {
+    fn default() -> Self {
+        Self {
+            source: String::new(),
+            ttl: 3600,
+        }
+    }
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            split: 35,
+            narrow: 80,
+        }
+    }
+}
+
+/// Range of the field column's width where both columns stay usable.
+pub(crate) const MIN_SPLIT: u16 = 10;
+pub(crate) const MAX_SPLIT: u16 = 90;
+const DEFAULT_BACKUPS: usize = 50;
+
+impl PromptsConfig {
+    /// How many backups of the prompts file to keep.
+    pub(crate) fn backup_limit(&self) -> usize {
+        self.backups.unwrap_or(DEFAULT_BACKUPS)
+    }
+}
+
+impl LayoutConfig {
+    /// `split` kept within a range where both columns stay usable.
+    pub(crate) fn field_percent(&self) -> u16 {
+        self.split.clamp(MIN_SPLIT, MAX_SPLIT)
+    }
+
+    pub(crate) fn is_narrow(&self, width: u16) -> bool {
+        width < self.narrow
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct EditorConfig {
+    /// Edit text fields vim-style, starting in normal mode.
+    pub(crate) vim: bool,
+    /// Ctrl+A/E/W/U and Alt+B/F move and delete in text fields, shadowing
+    /// any `[keys]` binding on them.
+    pub(crate) readline: bool,
+    /// Compose-safe input for CJK input methods: an Enter or Esc arriving
+    /// with the text an input method commits is dropped.
+    pub(crate) compose: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct MouseConfig {
+    /// Turning this off leaves clicks and selection to the terminal.
+    pub(crate) capture: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self { capture: true }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PreviewConfig {
+    /// Style headings, lists, code and bold text in the preview.
+    pub(crate) markdown: bool,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self { markdown: true }
+    }
+}
+
+/// Remappable keys. Each action takes a list of keys such as `"q"`,
+/// `"esc"`, `"f5"` or `"ctrl+shift+c"`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct KeysConfig {
+    /// Leave pmt from the template list.
+    pub(crate) quit: Vec<KeyBinding>,
+    /// Copy the rendered prompt in the editor.
+    pub(crate) copy: Vec<KeyBinding>,
+    /// Roll random placeholders again in the editor.
+    pub(crate) reroll: Vec<KeyBinding>,
+    /// Open the prompts file in `$EDITOR` from the template list.
+    pub(crate) open_editor: Vec<KeyBinding>,
+    /// Start selecting lines of the preview in the editor.
+    pub(crate) select: Vec<KeyBinding>,
+    /// Copy the template name, values and rendered prompt as JSON or YAML.
+    pub(crate) copy_record: Vec<KeyBinding>,
+    /// Pipe the rendered prompt into `[send] command`.
+    pub(crate) send: Vec<KeyBinding>,
+    /// Ask `[llm]` and stream the answer into a pane next to the preview.
+    pub(crate) ask: Vec<KeyBinding>,
+    /// Copy the answer shown next to the preview.
+    pub(crate) copy_answer: Vec<KeyBinding>,
+    /// Empty the fields and forget the values the template was last used
+    /// with.
+    pub(crate) clear_values: Vec<KeyBinding>,
+    /// Type a seed to roll the random picks from.
+    pub(crate) seed: Vec<KeyBinding>,
+    /// Undo the last edit of the field values in the editor.
+    pub(crate) undo: Vec<KeyBinding>,
+    /// Redo an undone edit in the editor.
+    pub(crate) redo: Vec<KeyBinding>,
+    /// Show every key binding. Characters still type in text fields.
+    pub(crate) help: Vec<KeyBinding>,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        let keys = |specs: &[&str]| {
+            specs
+                .iter()
+                .map(|spec| KeyBinding::parse(spec).expect("valid default key"))
+                .collect()
+        };
+        Self {
+            quit: keys(&["q", "esc"]),
+            copy: keys(&["ctrl+c"]),
+            reroll: keys(&["f5", "ctrl+r"]),
+            open_editor: keys(&["e"]),
+            select: keys(&["alt+v"]),
+            copy_record: keys(&["alt+c"]),
+            send: keys(&["ctrl+enter", "alt+enter"]),
+            ask: keys(&["alt+a"]),
+            copy_answer: keys(&["alt+y"]),
+            clear_values: keys(&["alt+backspace"]),
+            seed: keys(&["alt+s"]),
+            undo: keys(&["ctrl+z"]),
+            redo: keys(&["ctrl+y", "ctrl+shift+z"]),
+            help: keys(&["?", "f1"]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum KeyAction {
+    Quit,
+    Copy,
+    Reroll,
+    OpenEditor,
+    Select,
+    CopyRecord,
+    Send,
+    Ask,
+    CopyAnswer,
+    ClearValues,
+    Seed,
+    Undo,
+    Redo,
+    Help,
+}
+
+impl KeysConfig {
+    fn bindings(&self, action: KeyAction) -> &[KeyBinding] {
+        match action {
+            KeyAction::Quit => &self.quit,
+            KeyAction::Copy => &self.copy,
+            KeyAction::Reroll => &self.reroll,
+            KeyAction::OpenEditor => &self.open_editor,
+            KeyAction::Select => &self.select,
+            KeyAction::CopyRecord => &self.copy_record,
+            KeyAction::Send => &self.send,
+            KeyAction::Ask => &self.ask,
+            KeyAction::CopyAnswer => &self.copy_answer,
+            KeyAction::ClearValues => &self.clear_values,
+            KeyAction::Seed => &self.seed,
+            KeyAction::Undo => &self.undo,
+            KeyAction::Redo => &self.redo,
+            KeyAction::Help => &self.help,
+        }
+    }
+
+    pub(crate) fn is(&self, action: KeyAction, key: &KeyEvent) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.matches(key))
+    }
+
+    /// The keys bound to `action` for the footer hints, e.g. `F5/Ctrl+R`.
+    pub(crate) fn label(&self, action: KeyAction) -> String {
+        self.bindings(action)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub(crate) struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub(crate) fn parse(spec: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = spec.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| tr!("无效的按键: {spec}", "Invalid key: {spec}"))?;
+        for modifier in parts {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => {
+                    return Err(tr!(
+                        "未知的修饰键: {modifier} ({spec})",
+                        "Unknown modifier: {modifier} ({spec})"
+                    ));
+                }
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match key.to_lowercase().as_str() {
+                "esc" => KeyCode::Esc,
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "space" => KeyCode::Char(' '),
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "backtab" => KeyCode::BackTab,
+                name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => {
+                        return Err(tr!(
+                            "未知的按键: {key} ({spec})",
+                            "Unknown key: {key} ({spec})"
+                        ));
+                    }
+                },
+            },
+        };
+        Ok(Self { code, modifiers })
+    }
+
+    /// The key press this binding stands for.
+    pub(crate) fn event(self) -> KeyEvent {
+        KeyEvent::new(self.code, self.modifiers)
+    }
+
+    pub(crate) fn matches(&self, key: &KeyEvent) -> bool {
+        let (KeyCode::Char(bound), KeyCode::Char(pressed)) = (self.code, key.code) else {
+            return self.code == key.code && self.modifiers == key.modifiers;
+        };
+        // Terminals disagree on whether typed capitals carry Shift, so
+        // letters compare by case and other characters ignore Shift.
+        let others = |modifiers: KeyModifiers| modifiers - KeyModifiers::SHIFT;
+        let shifted = |ch: char, modifiers: KeyModifiers| {
+            ch.is_uppercase() || modifiers.contains(KeyModifiers::SHIFT)
+        };
+        bound.to_lowercase().eq(pressed.to_lowercase())
+            && others(self.modifiers) == others(key.modifiers)
+            && (!bound.is_alphabetic()
+                || shifted(bound, self.modifiers) == shifted(pressed, key.modifiers))
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, String> {
+        Self::parse(&spec)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                f.write_str(name)?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(ch) if self.modifiers.is_empty() => write!(f, "{ch}"),
+            KeyCode::Char(ch) => write!(f, "{}", ch.to_uppercase()),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CopyConfig {
+    /// Remove ANSI escapes and control characters from copied prompts.
+    pub(crate) strip_control: bool,
+    /// Format of the record copied by the `copy_record` key.
+    pub(crate) record_format: RecordFormat,
+    /// Shell command the copied text is piped into instead of using the
+    /// system clipboard, e.g. `tmux load-buffer -`, `wl-copy` or
+    /// `xclip -selection clipboard`.
+    pub(crate) clipboard_cmd: Option<String>,
+    /// Markdown file every copied prompt is appended to under a heading
+    /// with the time and template name. `~/` is the home directory;
+    /// relative paths start at `~/.config/pmt`.
+    pub(crate) journal: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SendConfig {
+    /// Shell command the rendered prompt is piped into, e.g. `llm -`.
+    pub(crate) command: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TokensConfig {
+    pub(crate) estimator: TokenEstimator,
+    /// Count above which the `tokens` footer widget turns into a warning,
+    /// e.g. the model's context window.
+    pub(crate) limit: Option<usize>,
+}
+
+/// OpenAI-compatible chat endpoint the editor can ask directly.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct LlmConfig {
+    /// API root up to and including the version, e.g. `http://localhost:11434/v1`.
+    pub(crate) api_base: String,
+    /// Sent as a bearer token; `OPENAI_API_KEY` is used when unset.
+    pub(crate) api_key: Option<String>,
+    /// Model asked unless the template's frontmatter names one.
+    pub(crate) model: Option<String>,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            api_base: "https://api.openai.com/v1".to_string(),
+            api_key: None,
+            model: None,
+        }
+    }
+}
+
+impl LlmConfig {
+    pub(crate) fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| env::var("OPENAI_API_KEY").ok())
+            .filter(|key| !key.trim().is_empty())
+    }
+}
+
+/// `{cmd|...}` placeholders run shell commands, so they stay inert unless
+/// enabled here.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CommandsConfig {
+    pub(crate) enabled: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FooterConfig {
+    /// Widgets of the bottom bar, left to right.
+    pub(crate) widgets: Vec<FooterWidget>,
+    /// Widgets aligned to the right edge, like tmux's `status-right`.
+    pub(crate) right: Vec<FooterWidget>,
+    /// Name shown by the `workspace` widget; defaults to the prompts file
+    /// name.
+    pub(crate) workspace: Option<String>,
+    /// Shown by the `text` widget.
+    pub(crate) text: Option<String>,
+    pub(crate) hints: HintsConfig,
+    /// Git branch of the prompts file, looked up when the config is loaded.
+    #[serde(skip)]
+    pub(crate) branch: Option<String>,
+}
+
+/// Text replacing the built-in key hints of a view. Prompts and pickers
+/// keep their own hints.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HintsConfig {
+    pub(crate) list: Option<String>,
+    pub(crate) editor: Option<String>,
+}
+
+impl Default for FooterConfig {
+    fn default() -> Self {
+        Self {
+            widgets: vec![
+                FooterWidget::Tokens,
+                FooterWidget::Unfilled,
+                FooterWidget::Seed,
+                FooterWidget::Hints,
+                FooterWidget::Status,
+            ],
+            right: Vec::new(),
+            workspace: None,
+            text: None,
+            hints: HintsConfig::default(),
+            branch: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FooterWidget {
+    /// Key hints of the current view.
+    Hints,
+    /// Short-lived messages such as "已复制".
+    Status,
+    /// Number of loaded templates.
+    Count,
+    Workspace,
+    /// Which clipboard copies go to.
+    Clipboard,
+    Clock,
+    /// Estimated tokens of the rendered prompt, in the editor.
+    Tokens,
+    /// Number of placeholders still unfilled, in the editor.
+    Unfilled,
+    /// Seed of the random picks, in the editor of a template with any.
+    Seed,
+    /// Path of the open template, or the selected one in the list.
+    Template,
+    /// Git branch of the prompts file.
+    Branch,
+    /// `[footer] text`.
+    Text,
+}
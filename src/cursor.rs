@@ -0,0 +1,241 @@
+//! Cursor editing within field values, for vim mode and the readline keys.
+//! Cursors are char indices into the value; single steps and deletions
+//! move by grapheme cluster, so an emoji or a letter with combining marks
+//! is edited as one.
+
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Mode of the optional vim-style field editing (`[editor] vim = true`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum VimMode {
+    #[default]
+    Normal,
+    Insert,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct VimState {
+    pub(crate) mode: VimMode,
+    /// Operator keys typed so far, such as `d` or `ci`.
+    pub(crate) pending: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct FieldCursor {
+    /// Field the cursor belongs to. Moving to another field puts the cursor
+    /// at the end of its value.
+    pub(crate) field: usize,
+    pub(crate) index: usize,
+}
+
+impl VimMode {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Normal => "-- NORMAL --",
+            Self::Insert => "-- INSERT --",
+        }
+    }
+}
+
+/// Byte offset of the char at `cursor`, or the end of `text`.
+pub(crate) fn byte_index(text: &str, cursor: usize) -> usize {
+    text.char_indices()
+        .nth(cursor)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+/// Char indices where the grapheme clusters of `chars` end.
+fn grapheme_ends(chars: &[char]) -> Vec<usize> {
+    let text: String = chars.iter().collect();
+    let mut end = 0;
+    text.graphemes(true)
+        .map(|grapheme| {
+            end += grapheme.chars().count();
+            end
+        })
+        .collect()
+}
+
+/// Start of the grapheme cluster before `cursor`.
+pub(crate) fn prev_grapheme(chars: &[char], cursor: usize) -> usize {
+    grapheme_ends(chars)
+        .into_iter()
+        .take_while(|end| *end < cursor)
+        .last()
+        .unwrap_or(0)
+}
+
+/// End of the grapheme cluster at `cursor`, or the end of the text.
+pub(crate) fn next_grapheme(chars: &[char], cursor: usize) -> usize {
+    grapheme_ends(chars)
+        .into_iter()
+        .find(|end| *end > cursor)
+        .unwrap_or(chars.len())
+}
+
+/// Removes the last grapheme cluster of `text`, for the plain text
+/// prompts that only edit at the end.
+pub(crate) fn pop_grapheme(text: &mut String) {
+    let start = text
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(index, _)| index);
+    text.truncate(start);
+}
+
+/// Start and end of the line holding `cursor`, newline excluded.
+pub(crate) fn line_bounds(chars: &[char], cursor: usize) -> (usize, usize) {
+    let cursor = cursor.min(chars.len());
+    let start = chars[..cursor]
+        .iter()
+        .rposition(|ch| *ch == '\n')
+        .map_or(0, |index| index + 1);
+    let end = chars[start..]
+        .iter()
+        .position(|ch| *ch == '\n')
+        .map_or(chars.len(), |index| start + index);
+    (start, end)
+}
+
+/// Last position normal mode may rest on in the line: the start of the
+/// last grapheme cluster, or the line start when it is empty.
+pub(crate) fn normal_limit(chars: &[char], cursor: usize) -> usize {
+    let (start, end) = line_bounds(chars, cursor);
+    prev_grapheme(chars, end).max(start)
+}
+
+pub(crate) fn first_non_blank(chars: &[char], cursor: usize) -> usize {
+    let (start, end) = line_bounds(chars, cursor);
+    (start..end)
+        .find(|index| !chars[*index].is_whitespace())
+        .unwrap_or(start)
+}
+
+/// Word characters, other non-blank runs and blanks are separate words,
+/// as in vim's `w`.
+fn class(ch: char) -> u8 {
+    if ch.is_whitespace() {
+        0
+    } else if ch.is_alphanumeric() || ch == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// Start of the next word (`w`), or the end of the text.
+pub(crate) fn next_word_start(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor;
+    if let Some(ch) = chars.get(index) {
+        let current = class(*ch);
+        while current != 0 && index < chars.len() && class(chars[index]) == current {
+            index += 1;
+        }
+    }
+    while index < chars.len() && chars[index].is_whitespace() {
+        index += 1;
+    }
+    index
+}
+
+/// Start of the word before the cursor (`b`).
+pub(crate) fn prev_word_start(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor.min(chars.len());
+    while index > 0 && chars[index - 1].is_whitespace() {
+        index -= 1;
+    }
+    if index > 0 {
+        let current = class(chars[index - 1]);
+        while index > 0 && class(chars[index - 1]) == current {
+            index -= 1;
+        }
+    }
+    index
+}
+
+/// Last char of the word the cursor is in or before (`e`).
+pub(crate) fn word_end(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor + 1;
+    while index < chars.len() && chars[index].is_whitespace() {
+        index += 1;
+    }
+    if index >= chars.len() {
+        return chars.len().saturating_sub(1);
+    }
+    let current = class(chars[index]);
+    while index + 1 < chars.len() && class(chars[index + 1]) == current {
+        index += 1;
+    }
+    index
+}
+
+/// Start of the blank-separated word before the cursor (Ctrl+W).
+pub(crate) fn blank_word_start(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor.min(chars.len());
+    while index > 0 && chars[index - 1].is_whitespace() {
+        index -= 1;
+    }
+    while index > 0 && !chars[index - 1].is_whitespace() {
+        index -= 1;
+    }
+    index
+}
+
+/// End of the next run of letters and digits (Alt+F).
+pub(crate) fn forward_word(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor.min(chars.len());
+    while index < chars.len() && !chars[index].is_alphanumeric() {
+        index += 1;
+    }
+    while index < chars.len() && chars[index].is_alphanumeric() {
+        index += 1;
+    }
+    index
+}
+
+/// Start of the run of letters and digits before the cursor (Alt+B).
+pub(crate) fn backward_word(chars: &[char], cursor: usize) -> usize {
+    let mut index = cursor.min(chars.len());
+    while index > 0 && !chars[index - 1].is_alphanumeric() {
+        index -= 1;
+    }
+    while index > 0 && chars[index - 1].is_alphanumeric() {
+        index -= 1;
+    }
+    index
+}
+
+/// The word or blank run under the cursor (`iw`); `around` adds the blanks
+/// after it (`aw`).
+pub(crate) fn word_range(chars: &[char], cursor: usize, around: bool) -> Range<usize> {
+    if cursor >= chars.len() {
+        return chars.len()..chars.len();
+    }
+    let current = class(chars[cursor]);
+    let mut start = cursor;
+    while start > 0 && class(chars[start - 1]) == current {
+        start -= 1;
+    }
+    let mut end = cursor + 1;
+    while end < chars.len() && class(chars[end]) == current {
+        end += 1;
+    }
+    if around {
+        while end < chars.len() && chars[end].is_whitespace() && chars[end] != '\n' {
+            end += 1;
+        }
+    }
+    start..end
+}
+
+/// The line under the cursor with one of its newlines (`dd`).
+pub(crate) fn whole_line(chars: &[char], cursor: usize) -> Range<usize> {
+    let (start, end) = line_bounds(chars, cursor);
+    if end < chars.len() {
+        start..end + 1
+    } else {
+        start.saturating_sub(1)..end
+    }
+}
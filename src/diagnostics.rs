@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::env;
+
+use crate::locale::{pick, tr};
+use crate::models::{Filter, OptionPools, Rule, Template, Token};
+use crate::parser::{POOLS_SECTION, TokenCache, expand_uses};
+
+/// Characters of a line quoted in a problem.
+const SNIPPET_CHARS: usize = 40;
+
+/// Limits above which a template is reported. Each can be overridden with
+/// an environment variable of the same name in upper case, prefixed with
+/// `PMT_WARN_`, e.g. `PMT_WARN_BODY_BYTES=32768`.
+#[derive(Clone, Debug)]
+pub(crate) struct Thresholds {
+    pub(crate) body_bytes: usize,
+    pub(crate) tokens: usize,
+    pub(crate) include_depth: usize,
+    pub(crate) dynamic_tokens: usize,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            body_bytes: 16 * 1024,
+            tokens: 500,
+            include_depth: 4,
+            dynamic_tokens: 40,
+        }
+    }
+}
+
+impl Thresholds {
+    pub(crate) fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |key: &str, default: usize| {
+            env::var(key)
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            body_bytes: read("PMT_WARN_BODY_BYTES", defaults.body_bytes),
+            tokens: read("PMT_WARN_TOKENS", defaults.tokens),
+            include_depth: read("PMT_WARN_INCLUDE_DEPTH", defaults.include_depth),
+            dynamic_tokens: read("PMT_WARN_DYNAMIC_TOKENS", defaults.dynamic_tokens),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateMetrics {
+    pub(crate) body_bytes: usize,
+    pub(crate) tokens: usize,
+    pub(crate) include_depth: usize,
+    /// Placeholders of any kind: fields and random picks.
+    pub(crate) dynamic_tokens: usize,
+}
+
+/// A template with at least one metric over its threshold.
+#[derive(Clone, Debug)]
+pub(crate) struct Diagnostic {
+    pub(crate) template_index: usize,
+    pub(crate) metrics: TemplateMetrics,
+    pub(crate) exceeded: Vec<&'static str>,
+}
+
+fn measure(template: &Template, pools: &OptionPools, cache: &mut TokenCache) -> TemplateMetrics {
+    let tokens = cache.tokens(&template.body, pools);
+    TemplateMetrics {
+        body_bytes: template.body.len(),
+        tokens: tokens.len(),
+        include_depth: template.include_depth,
+        dynamic_tokens: tokens
+            .iter()
+            .filter(|token| !matches!(token, Token::Text(_)))
+            .count(),
+    }
+}
+
+pub(crate) fn diagnose(
+    templates: &[Template],
+    pools: &OptionPools,
+    cache: &mut TokenCache,
+    thresholds: &Thresholds,
+) -> Vec<Diagnostic> {
+    templates
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| template.name != POOLS_SECTION)
+        .filter_map(|(index, template)| {
+            let metrics = measure(template, pools, cache);
+            let checks = [
+                (
+                    pick("正文大小", "body size"),
+                    metrics.body_bytes > thresholds.body_bytes,
+                ),
+                (
+                    pick("片段数", "segments"),
+                    metrics.tokens > thresholds.tokens,
+                ),
+                (
+                    pick("include 层级", "include depth"),
+                    metrics.include_depth > thresholds.include_depth,
+                ),
+                (
+                    pick("占位符数", "placeholders"),
+                    metrics.dynamic_tokens > thresholds.dynamic_tokens,
+                ),
+            ];
+            let exceeded: Vec<&'static str> = checks
+                .iter()
+                .filter(|(_, over)| *over)
+                .map(|(label, _)| *label)
+                .collect();
+            if exceeded.is_empty() {
+                None
+            } else {
+                Some(Diagnostic {
+                    template_index: index,
+                    metrics,
+                    exceeded,
+                })
+            }
+        })
+        .collect()
+}
+
+/// A mistake that makes a template render differently than it reads.
+#[derive(Clone, Debug)]
+pub(crate) struct Problem {
+    pub(crate) template_index: usize,
+    pub(crate) message: String,
+}
+
+/// Unclosed braces, random or select placeholders without options,
+/// duplicate names, unknown filters and `{use|…}` references that are
+/// missing or loop.
+pub(crate) fn find_problems(
+    templates: &[Template],
+    pools: &OptionPools,
+    cache: &mut TokenCache,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    // Index of each name; a duplicate was renamed when loaded.
+    let index_of: HashMap<&str, usize> = templates
+        .iter()
+        .enumerate()
+        .map(|(index, template)| (template.name.as_str(), index))
+        .collect();
+    for (index, template) in templates.iter().enumerate() {
+        if template.name == POOLS_SECTION {
+            continue;
+        }
+        let mut report = |message: String| {
+            problems.push(Problem {
+                template_index: index,
+                message,
+            })
+        };
+        let first = template
+            .duplicate_of
+            .as_deref()
+            .and_then(|name| index_of.get(name));
+        if let Some(&first) = first {
+            let first = &templates[first];
+            report(tr!(
+                "重名模板，另一个在 {} 第 {} 行",
+                "Duplicate name, also at {} line {}",
+                first.source.display(),
+                first.line + 1
+            ));
+        }
+        for snippet in unclosed_braces(&template.body) {
+            report(tr!("未闭合的 {{: {snippet}", "Unclosed {{: {snippet}"));
+        }
+        for token in cache.tokens(&template.body, pools).iter() {
+            match token {
+                Token::Text(text) if is_optionless(text) => {
+                    report(tr!("没有可选项: {text}", "No options: {text}"));
+                }
+                Token::Var { desc, raw, .. } | Token::List { desc, raw, .. } => {
+                    for name in unknown_filters(desc.as_deref()) {
+                        report(tr!(
+                            "未知的过滤器 {name}: {raw}",
+                            "Unknown filter {name}: {raw}"
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Err(err) = expand_uses(&template.body, templates) {
+            report(err);
+        }
+    }
+    problems
+}
+
+/// The start of every `{` that is meant to open a placeholder but is not
+/// closed on its line. A `{` followed by a space, quote or line break, as
+/// in JSON, is taken as text.
+fn unclosed_braces(body: &str) -> Vec<String> {
+    let mut snippets = Vec::new();
+    for (start, _) in body.match_indices('{') {
+        let rest = &body[start + 1..];
+        let opens_placeholder = rest
+            .chars()
+            .next()
+            .is_some_and(|ch| !ch.is_whitespace() && !matches!(ch, '"' | '\'' | '{' | '}'));
+        if !opens_placeholder {
+            continue;
+        }
+        let closed = match rest.find('}') {
+            Some(end) => !rest[..end].contains(['{', '\n']),
+            None => false,
+        };
+        if !closed {
+            let line = body[start..].lines().next().unwrap_or_default();
+            snippets.push(line.chars().take(SNIPPET_CHARS).collect());
+        }
+    }
+    snippets
+}
+
+/// A `{random|…}` or `{select|…}` left as text because it has no options,
+/// e.g. an empty list or an unknown `@pool`.
+fn is_optionless(text: &str) -> bool {
+    text.strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .is_some_and(|inner| {
+            let inner = inner.trim_start();
+            inner.starts_with("random|") || inner.starts_with("select|")
+        })
+}
+
+/// Items after the second `|` of a placeholder that are neither a filter
+/// nor a rule. The parser keeps such a tail as part of the description.
+fn unknown_filters(desc: Option<&str>) -> Vec<String> {
+    let spec = match desc.and_then(|desc| desc.split_once('|')) {
+        Some((_, spec)) => spec,
+        None => return Vec::new(),
+    };
+    let mut unknown = Vec::new();
+    for item in spec.split(',').map(str::trim) {
+        if item.starts_with("regex:") {
+            break;
+        }
+        if Filter::parse(item).is_none() && Rule::parse(item).is_none() {
+            unknown.push(item.to_string());
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_templates;
+    use crate::system::disambiguate;
+
+    #[test]
+    fn problems_are_reported_per_template() {
+        let mut templates = parse_templates(
+            "## 甲\n你好 {name\n{\"json\": 1}\n{random|@none} {code||shout}\n\n## 乙\n{use|丙}\n\n## 甲\n二\n\n## 好\n{name||upper}\n",
+        );
+        disambiguate(&mut templates);
+        let problems: Vec<(usize, String)> =
+            find_problems(&templates, &OptionPools::new(), &mut TokenCache::default())
+                .into_iter()
+                .map(|problem| (problem.template_index, problem.message))
+                .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (0, "未闭合的 {: {name".to_string()),
+                (0, "没有可选项: {random|@none}".to_string()),
+                (0, "未知的过滤器 shout: {code||shout}".to_string()),
+                (1, "未找到引用的模板: 丙".to_string()),
+                (2, "重名模板，另一个在  第 1 行".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_metrics_over_their_threshold_are_reported() {
+        let templates = parse_templates("## 长\n{a} {b} {c}\n\n## 短\n{a}\n");
+        let thresholds = Thresholds {
+            body_bytes: 1024,
+            tokens: 100,
+            include_depth: 3,
+            dynamic_tokens: 2,
+        };
+        let diagnostics = diagnose(
+            &templates,
+            &OptionPools::new(),
+            &mut TokenCache::default(),
+            &thresholds,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].template_index, 0);
+        assert_eq!(diagnostics[0].metrics.dynamic_tokens, 3);
+        assert_eq!(diagnostics[0].exceeded, vec!["占位符数"]);
+    }
+}
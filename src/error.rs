@@ -0,0 +1,85 @@
+use std::fmt;
+
+use crate::locale::pick;
+
+/// Errors of the library, its files and the programs pmt hands text to,
+/// sorted by what the user can do about them.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum PmtError {
+    /// A file could not be read or written, or a command failed.
+    Io(String),
+    /// The templates could not be made sense of.
+    Parse(String),
+    /// Copying to or reading from the clipboard failed.
+    Clipboard(String),
+    /// `$EDITOR` could not be started or failed.
+    Editor(String),
+    /// `config.toml` or `globals.toml` is invalid, or the config directory
+    /// cannot be found.
+    Config(String),
+    /// The change is not allowed, such as editing a read-only template.
+    Validation(String),
+}
+
+impl PmtError {
+    /// Title of the error view.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            PmtError::Io(_) => pick("读写错误", "Read or write error"),
+            PmtError::Parse(_) => pick("模板错误", "Template error"),
+            PmtError::Clipboard(_) => pick("剪贴板错误", "Clipboard error"),
+            PmtError::Editor(_) => pick("编辑器错误", "Editor error"),
+            PmtError::Config(_) => pick("配置错误", "Config error"),
+            PmtError::Validation(_) => pick("无法修改", "Change refused"),
+        }
+    }
+
+    /// What to try next.
+    pub(crate) fn hint(&self) -> &'static str {
+        match self {
+            PmtError::Io(_) => pick(
+                "检查文件是否存在以及读写权限。",
+                "Check that the file exists and can be read and written.",
+            ),
+            PmtError::Parse(_) => pick(
+                "在 prompts.md 中用 `## 标题` 开始每个模板，或运行 pmt lint 查看问题。",
+                "Start each template in prompts.md with a `## Title` line, or run pmt lint to see what is wrong.",
+            ),
+            PmtError::Clipboard(_) => pick(
+                "在 config.toml 的 [copy] clipboard_cmd 中设置复制命令，如 xclip 或 pbcopy。",
+                "Set a copy command such as xclip or pbcopy as [copy] clipboard_cmd in config.toml.",
+            ),
+            PmtError::Editor(_) => pick(
+                "将 EDITOR 环境变量设为可用的编辑器，如 vim 或 nano。",
+                "Set the EDITOR environment variable to an editor that works, such as vim or nano.",
+            ),
+            PmtError::Config(_) => pick(
+                "修正 ~/.config/pmt 下的 config.toml 或 globals.toml，或将其删除以使用默认值。",
+                "Fix config.toml or globals.toml in ~/.config/pmt, or remove it to use the defaults.",
+            ),
+            PmtError::Validation(_) => pick(
+                "共享或远程模板只读，可在个人模板中建一个同名模板覆盖它。",
+                "Shared and remote templates are read-only; add a personal template of the same name to override it.",
+            ),
+        }
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        match self {
+            PmtError::Io(message)
+            | PmtError::Parse(message)
+            | PmtError::Clipboard(message)
+            | PmtError::Editor(message)
+            | PmtError::Config(message)
+            | PmtError::Validation(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for PmtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for PmtError {}
@@ -0,0 +1,146 @@
+use crate::models::{OptionPools, Token};
+use crate::parser::{
+    canonical_placeholder, frontmatter_lines, parse_heading, parse_include, parse_tokens,
+};
+
+#[derive(Clone, Debug)]
+enum Block {
+    Section {
+        name: String,
+        body: Vec<String>,
+    },
+    Include(String),
+    /// Text following an include that no heading owns.
+    Orphan(Vec<String>),
+}
+
+/// Rewrites a prompts file into canonical form: `## a/b` headings, one
+/// blank line between sections, no trailing blank lines in bodies and
+/// normalized placeholder spacing outside frontmatter. With `sort`, sections between include
+/// directives are ordered by path.
+pub(crate) fn format_document(content: &str, sort: bool) -> String {
+    let mut preamble: Vec<String> = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in content.lines() {
+        if let Some(name) = parse_heading(line) {
+            blocks.push(Block::Section {
+                name: normalize_name(&name),
+                body: Vec::new(),
+            });
+        } else if let Some(target) = parse_include(line) {
+            blocks.push(Block::Include(target.to_string()));
+        } else {
+            match blocks.last_mut() {
+                Some(Block::Section { body, .. }) => body.push(line.to_string()),
+                Some(Block::Orphan(lines)) => lines.push(line.to_string()),
+                Some(Block::Include(_)) => blocks.push(Block::Orphan(vec![line.to_string()])),
+                None => preamble.push(line.to_string()),
+            }
+        }
+    }
+
+    if sort {
+        for run in blocks.split_mut(|block| !matches!(block, Block::Section { .. })) {
+            run.sort_by(|a, b| block_name(a).cmp(block_name(b)));
+        }
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    trim_blank_edges(&mut preamble);
+    if !preamble.is_empty() {
+        out.extend(preamble);
+    }
+    for block in blocks {
+        if let Block::Orphan(mut lines) = block {
+            trim_blank_edges(&mut lines);
+            if !lines.is_empty() {
+                out.push(String::new());
+                out.extend(lines);
+            }
+            continue;
+        }
+        if !out.is_empty() {
+            out.push(String::new());
+        }
+        match block {
+            Block::Section { name, mut body } => {
+                out.push(format!("## {name}"));
+                while body.last().is_some_and(|line| line.trim().is_empty()) {
+                    body.pop();
+                }
+                // Frontmatter is kept as written.
+                let frontmatter = frontmatter_lines(body.iter().map(String::as_str));
+                let rest = body.split_off(frontmatter);
+                out.extend(body);
+                if !rest.is_empty() {
+                    out.extend(
+                        normalize_placeholders(&rest.join("\n"))
+                            .split('\n')
+                            .map(str::to_string),
+                    );
+                }
+            }
+            Block::Include(target) => out.push(format!("{{{{include {target}}}}}")),
+            Block::Orphan(_) => {}
+        }
+    }
+
+    let mut formatted = out.join("\n");
+    formatted.push('\n');
+    formatted
+}
+
+fn block_name(block: &Block) -> &str {
+    match block {
+        Block::Section { name, .. } => name,
+        Block::Include(target) => target,
+        Block::Orphan(_) => "",
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split('/')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn trim_blank_edges(lines: &mut Vec<String>) {
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let leading = lines
+        .iter()
+        .take_while(|line| line.trim().is_empty())
+        .count();
+    lines.drain(..leading);
+}
+
+fn normalize_placeholders(body: &str) -> String {
+    // Pool references stay as written: with no pools known they parse as
+    // plain text.
+    parse_tokens(body, &OptionPools::new())
+        .iter()
+        .map(|token| match token {
+            Token::Text(text) => text.clone(),
+            other => canonical_placeholder(other).unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_normalized_and_sorted_between_includes() {
+        let content = "说明\n\n\n## 写作 / 邮件\n---\ntags: [ 邮件 ]\n---\n你好 { name | 收件人 }\n\n\n## 代码\n检查\n{{include team.md}}\n## 乙\n二\n## 甲\n一\n";
+        let formatted = format_document(content, true);
+        assert_eq!(
+            formatted,
+            "说明\n\n## 代码\n检查\n\n## 写作/邮件\n---\ntags: [ 邮件 ]\n---\n你好 {name|收件人}\n\n{{include team.md}}\n\n## 乙\n二\n\n## 甲\n一\n"
+        );
+        assert_eq!(format_document(&formatted, true), formatted);
+    }
+}
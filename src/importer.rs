@@ -0,0 +1,767 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::locale::{pick, tr};
+use crate::models::Template;
+use crate::parser::{parse_heading, parse_include};
+
+/// Messages shorter than this are usually chat replies, not prompts.
+const MIN_PROMPT_CHARS: usize = 100;
+const CONVERSATIONS_FILE: &str = "conversations.json";
+
+/// What `pmt import --format` reads: a chat export, whose user messages
+/// become templates to sort out, or a prompt library, whose entries map to
+/// templates one to one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ExportFormat {
+    ChatGpt,
+    Claude,
+    /// A VS Code snippets file (`*.json` or `*.code-snippets`).
+    VsCode,
+    /// A fabric `patterns` directory, or one pattern's directory.
+    Fabric,
+    /// An Obsidian Templater folder or file.
+    Templater,
+}
+
+impl ExportFormat {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "chatgpt" => Some(Self::ChatGpt),
+            "claude" => Some(Self::Claude),
+            "vscode" => Some(Self::VsCode),
+            "fabric" => Some(Self::Fabric),
+            "templater" => Some(Self::Templater),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_library(self) -> bool {
+        matches!(self, Self::VsCode | Self::Fabric | Self::Templater)
+    }
+}
+
+/// A user message from an exported conversation, or an entry of a prompt
+/// library.
+#[derive(Clone, Debug)]
+pub(crate) struct ImportedMessage {
+    /// Conversation title, or the name of the library entry.
+    pub(crate) title: String,
+    pub(crate) text: String,
+}
+
+pub(crate) fn read_export(
+    path: &Path,
+    format: ExportFormat,
+) -> Result<Vec<ImportedMessage>, String> {
+    match format {
+        ExportFormat::ChatGpt | ExportFormat::Claude => read_chat_export(path, format),
+        ExportFormat::VsCode => read_vscode_snippets(path),
+        ExportFormat::Fabric => read_fabric_patterns(path),
+        ExportFormat::Templater => read_templater(path),
+    }
+}
+
+/// Reads the user messages of every conversation in an export, either the
+/// `.zip` archive as downloaded or its `conversations.json`.
+fn read_chat_export(path: &Path, format: ExportFormat) -> Result<Vec<ImportedMessage>, String> {
+    let content = if path.extension().is_some_and(|ext| ext == "zip") {
+        read_conversations_from_zip(path)?
+    } else {
+        fs::read_to_string(path).map_err(|err| {
+            tr!(
+                "读取失败: {} ({err})",
+                "Failed to read: {} ({err})",
+                path.display()
+            )
+        })?
+    };
+    let root: Value = serde_json::from_str(&content).map_err(|err| {
+        tr!(
+            "无法解析导出文件: {} ({err})",
+            "Cannot parse the export: {} ({err})",
+            path.display()
+        )
+    })?;
+    let conversations = root.as_array().ok_or_else(|| {
+        tr!(
+            "导出文件格式不符: {}",
+            "Unexpected export format: {}",
+            path.display()
+        )
+    })?;
+    let messages = conversations
+        .iter()
+        .flat_map(|conversation| {
+            if format == ExportFormat::ChatGpt {
+                chatgpt_messages(conversation)
+            } else {
+                claude_messages(conversation)
+            }
+        })
+        .collect();
+    Ok(messages)
+}
+
+fn read_conversations_from_zip(path: &Path) -> Result<String, String> {
+    let file = File::open(path).map_err(|err| {
+        tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        )
+    })?;
+    let mut archive = ZipArchive::new(file).map_err(|err| {
+        tr!(
+            "无法打开压缩包: {} ({err})",
+            "Cannot open the archive: {} ({err})",
+            path.display()
+        )
+    })?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|err| {
+            tr!(
+                "无法读取压缩包: {} ({err})",
+                "Cannot read the archive: {} ({err})",
+                path.display()
+            )
+        })?;
+        let name = entry
+            .name()
+            .map_err(|err| {
+                tr!(
+                    "无法读取压缩包: {} ({err})",
+                    "Cannot read the archive: {} ({err})",
+                    path.display()
+                )
+            })?
+            .to_string();
+        if name == CONVERSATIONS_FILE || name.ends_with(&format!("/{CONVERSATIONS_FILE}")) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content).map_err(|err| {
+                tr!(
+                    "无法读取压缩包: {name} ({err})",
+                    "Cannot read the archive: {name} ({err})"
+                )
+            })?;
+            return Ok(content);
+        }
+    }
+    Err(tr!(
+        "压缩包中没有 {CONVERSATIONS_FILE}: {}",
+        "No {CONVERSATIONS_FILE} in the archive: {}",
+        path.display()
+    ))
+}
+
+/// ChatGPT keeps messages in a `mapping` of nodes; user messages have
+/// `author.role == "user"` and text in `content.parts`.
+fn chatgpt_messages(conversation: &Value) -> Vec<ImportedMessage> {
+    let title = conversation_title(conversation, "title");
+    let mut messages: Vec<(f64, String)> = conversation["mapping"]
+        .as_object()
+        .into_iter()
+        .flat_map(|mapping| mapping.values())
+        .filter_map(|node| {
+            let message = &node["message"];
+            if message["author"]["role"] != "user" {
+                return None;
+            }
+            let text = message["content"]["parts"]
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let time = message["create_time"].as_f64().unwrap_or(0.0);
+            Some((time, text))
+        })
+        .collect();
+    messages.sort_by(|a, b| a.0.total_cmp(&b.0));
+    messages
+        .into_iter()
+        .map(|(_, text)| ImportedMessage {
+            title: title.clone(),
+            text,
+        })
+        .collect()
+}
+
+/// Claude lists `chat_messages` in order; user messages have
+/// `sender == "human"`.
+fn claude_messages(conversation: &Value) -> Vec<ImportedMessage> {
+    let title = conversation_title(conversation, "name");
+    conversation["chat_messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|message| message["sender"] == "human")
+        .map(|message| {
+            let text = match message["text"].as_str() {
+                Some(text) if !text.is_empty() => text.to_string(),
+                _ => message["content"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|part| part["text"].as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+            ImportedMessage {
+                title: title.clone(),
+                text,
+            }
+        })
+        .collect()
+}
+
+fn conversation_title(conversation: &Value, key: &str) -> String {
+    let title = conversation[key].as_str().unwrap_or("").replace('/', "-");
+    let title = title.trim();
+    if title.is_empty() {
+        pick("未命名", "Untitled").to_string()
+    } else {
+        title.to_string()
+    }
+}
+
+/// Folder imported prompts are placed in for later curation.
+fn import_folder() -> &'static str {
+    pick("导入/待整理", "Imported/Unsorted")
+}
+
+/// Folder the entries of an imported prompt library are placed in.
+fn library_folder() -> &'static str {
+    pick("导入", "Imported")
+}
+
+/// Turns messages into `(name, body)` template candidates under
+/// `import_folder`, or library entries under `library_folder`. Unless `all`
+/// is set only messages long enough to be prompts are kept; library entries
+/// are all kept. Messages already imported into `existing` are dropped, and
+/// new names never collide with existing ones; both are noted in the
+/// returned warnings.
+pub(crate) fn to_templates(
+    messages: &[ImportedMessage],
+    format: ExportFormat,
+    all: bool,
+    existing: &[Template],
+) -> (Vec<(String, String)>, Vec<String>) {
+    let all = all || format.is_library();
+    let folder = if format.is_library() {
+        library_folder()
+    } else {
+        import_folder()
+    };
+    let mut names: HashSet<String> = existing.iter().map(|t| t.name.clone()).collect();
+    let mut seen: HashSet<String> = existing.iter().map(|t| t.body.trim().to_string()).collect();
+    let mut templates = Vec::new();
+    let mut warnings = Vec::new();
+    for message in messages {
+        let text = message.text.trim();
+        if text.is_empty() || (!all && text.chars().count() < MIN_PROMPT_CHARS) {
+            continue;
+        }
+        let body = escape_structure(text);
+        if !seen.insert(body.trim().to_string()) {
+            warnings.push(tr!(
+                "{}: 内容已导入过，已跳过",
+                "{}: already imported, skipped",
+                message.title
+            ));
+            continue;
+        }
+        let base = format!("{folder}/{}", message.title);
+        let mut name = base.clone();
+        let mut counter = 2;
+        while names.contains(&name) {
+            name = format!("{base} {counter}");
+            counter += 1;
+        }
+        if name != base {
+            warnings.push(tr!(
+                "{base} 已存在，改名为 {name}",
+                "{base} already exists; imported as {name}"
+            ));
+        }
+        names.insert(name.clone());
+        templates.push((name, body));
+    }
+    (templates, warnings)
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|err| {
+        tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        )
+    })
+}
+
+/// Snippets of a VS Code snippets file, their placeholders rewritten by
+/// `vscode_body`.
+fn read_vscode_snippets(path: &Path) -> Result<Vec<ImportedMessage>, String> {
+    // `.code-snippets` files may carry `//` comments, which JSON has not.
+    let content: Vec<String> = read_text(path)?
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .map(str::to_string)
+        .collect();
+    let root: Value = serde_json::from_str(&content.join("\n")).map_err(|err| {
+        tr!(
+            "无法解析代码片段文件: {} ({err})",
+            "Cannot parse the snippets file: {} ({err})",
+            path.display()
+        )
+    })?;
+    let snippets = root.as_object().ok_or_else(|| {
+        tr!(
+            "代码片段文件应为 JSON 对象: {}",
+            "The snippets file should be a JSON object: {}",
+            path.display()
+        )
+    })?;
+    Ok(snippets
+        .iter()
+        .filter_map(|(name, snippet)| {
+            let body = match &snippet["body"] {
+                Value::String(body) => body.clone(),
+                Value::Array(lines) => lines
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => return None,
+            };
+            Some(ImportedMessage {
+                title: name.replace('/', "-"),
+                text: vscode_body(&body),
+            })
+        })
+        .collect())
+}
+
+/// Rewrites VS Code snippet syntax as placeholders: tabstops `$1`,
+/// `${1:label}` and `${1|a,b|}` become `{arg1}`, `{arg1|label}` and a
+/// select, `$0` is dropped and variables such as `$CLIPBOARD` map to their
+/// pmt counterparts.
+fn vscode_body(body: &str) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(pos) = rest.find(['$', '\\']) {
+        output.push_str(&rest[..pos]);
+        let tail = &rest[pos + 1..];
+        if rest[pos..].starts_with('\\') {
+            match tail.chars().next() {
+                Some(ch @ ('$' | '}' | '\\')) => {
+                    output.push(ch);
+                    rest = &tail[1..];
+                }
+                _ => {
+                    output.push('\\');
+                    rest = tail;
+                }
+            }
+            continue;
+        }
+        if let Some(inner) = tail.strip_prefix('{') {
+            match closing_brace(inner) {
+                Some(end) => {
+                    output.push_str(&vscode_placeholder(&inner[..end]));
+                    rest = &inner[end + 1..];
+                }
+                None => {
+                    output.push('$');
+                    rest = tail;
+                }
+            }
+        } else {
+            let len = tail
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .unwrap_or(tail.len());
+            if len == 0 {
+                output.push('$');
+            } else {
+                output.push_str(&vscode_placeholder(&tail[..len]));
+            }
+            rest = &tail[len..];
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Byte offset of the `}` closing a `${`, skipping nested ones.
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (index, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn vscode_placeholder(inner: &str) -> String {
+    let digits = inner
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(inner.len());
+    if digits > 0 {
+        let (number, rest) = inner.split_at(digits);
+        if number == "0" {
+            return rest.strip_prefix(':').map(vscode_body).unwrap_or_default();
+        }
+        let name = format!("arg{number}");
+        if let Some(choices) = rest
+            .strip_prefix('|')
+            .and_then(|rest| rest.strip_suffix('|'))
+        {
+            let options: Vec<String> = choices
+                .split(',')
+                .map(|choice| format!("\"{}\"", choice.trim()))
+                .collect();
+            return format!("{{select|{name}|{}}}", options.join(" "));
+        }
+        let label = rest.strip_prefix(':').map(vscode_label).unwrap_or_default();
+        if label.trim().is_empty() {
+            return format!("{{{name}}}");
+        }
+        return format!("{{{name}|{}}}", label.trim());
+    }
+    let variable = inner.split([':', '/']).next().unwrap_or_default();
+    match variable {
+        "CLIPBOARD" => "{clipboard}".to_string(),
+        "CURRENT_YEAR" => "{date|%Y}".to_string(),
+        "CURRENT_MONTH" => "{date|%m}".to_string(),
+        "CURRENT_DATE" => "{date|%d}".to_string(),
+        "CURRENT_HOUR" => "{time|%H}".to_string(),
+        "CURRENT_MINUTE" => "{time|%M}".to_string(),
+        "CURRENT_SECOND" => "{time|%S}".to_string(),
+        other => format!("{{{}}}", other.to_lowercase()),
+    }
+}
+
+/// The text of a placeholder label, nested placeholders replaced by their
+/// own labels.
+fn vscode_label(text: &str) -> String {
+    let mut label = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        label.push_str(&rest[..start]);
+        let inner = &rest[start + 2..];
+        let end = match closing_brace(inner) {
+            Some(end) => end,
+            None => {
+                rest = inner;
+                continue;
+            }
+        };
+        if let Some((_, nested)) = inner[..end].split_once(':') {
+            label.push_str(&vscode_label(nested));
+        }
+        rest = &inner[end + 1..];
+    }
+    label.push_str(rest);
+    label.replace(['{', '}', '|', '$', '\\'], "")
+}
+
+/// Every pattern of a fabric `patterns` directory, or the single pattern
+/// when `path` is one. A pattern is a folder with a `system.md`; the input
+/// fabric appends to it becomes an `{input}` field.
+fn read_fabric_patterns(path: &Path) -> Result<Vec<ImportedMessage>, String> {
+    let mut patterns: Vec<PathBuf> = if path.join("system.md").is_file() {
+        vec![path.to_path_buf()]
+    } else {
+        fs::read_dir(path)
+            .map_err(|err| {
+                tr!(
+                    "读取失败: {} ({err})",
+                    "Failed to read: {} ({err})",
+                    path.display()
+                )
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|pattern| pattern.join("system.md").is_file())
+            .collect()
+    };
+    patterns.sort();
+    patterns
+        .iter()
+        .map(|pattern| {
+            let system = read_text(&pattern.join("system.md"))?;
+            let title = pattern
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Ok(ImportedMessage {
+                title,
+                text: format!("{}\n\n{{input}}", system.trim_end()),
+            })
+        })
+        .collect()
+}
+
+/// The `*.md` files of a Templater folder, named by their path below it,
+/// or a single template file.
+fn read_templater(path: &Path) -> Result<Vec<ImportedMessage>, String> {
+    let (base, mut files) = if path.is_dir() {
+        let mut files = Vec::new();
+        collect_markdown(path, &mut files)?;
+        (path, files)
+    } else {
+        (
+            path.parent().unwrap_or_else(|| Path::new(".")),
+            vec![path.to_path_buf()],
+        )
+    };
+    files.sort();
+    files
+        .iter()
+        .map(|file| {
+            let title = file
+                .strip_prefix(base)
+                .unwrap_or(file)
+                .with_extension("")
+                .to_string_lossy()
+                .replace('\\', "/");
+            Ok(ImportedMessage {
+                title,
+                text: templater_body(&read_text(file)?),
+            })
+        })
+        .collect()
+}
+
+fn collect_markdown(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|err| {
+        tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            dir.display()
+        )
+    })?;
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            collect_markdown(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the Templater commands pmt has a counterpart for: prompts
+/// become fields, suggesters selects, and dates, the clipboard and the
+/// file title their placeholders. Other commands stay as written.
+fn templater_body(body: &str) -> String {
+    let mut output = String::with_capacity(body.len());
+    let mut rest = body;
+    let mut choices = 0;
+    while let Some(start) = rest.find("<%") {
+        let end = match rest[start..].find("%>") {
+            Some(end) => start + end + 2,
+            None => break,
+        };
+        output.push_str(&rest[..start]);
+        let tag = &rest[start..end];
+        // `<%* tR += await tp.system.prompt(...) %>` outputs the same as
+        // `<% tp.system.prompt(...) %>`.
+        let command = tag[2..tag.len() - 2]
+            .trim_start_matches(['-', '_', '*'])
+            .trim_end_matches(['-', '_'])
+            .trim();
+        let command = command.strip_prefix("tR +=").unwrap_or(command).trim();
+        let command = command.strip_prefix("await ").unwrap_or(command).trim();
+        let literals = string_literals(command);
+        let replacement = if command.starts_with("tp.system.prompt(") {
+            match literals.first() {
+                Some(question) if !question.trim().is_empty() => {
+                    format!("{{{}}}", question.replace(['{', '}', '|'], "").trim())
+                }
+                _ => "{input}".to_string(),
+            }
+        } else if command.starts_with("tp.system.suggester(") {
+            // The last list holds the values; a first one only labels them.
+            let values = command
+                .rfind('[')
+                .zip(command.rfind(']'))
+                .filter(|(open, close)| open < close)
+                .map(|(open, close)| string_literals(&command[open..close]))
+                .unwrap_or_default();
+            if values.is_empty() {
+                tag.to_string()
+            } else {
+                choices += 1;
+                let options: Vec<String> =
+                    values.iter().map(|value| format!("\"{value}\"")).collect();
+                format!("{{select|choice{choices}|{}}}", options.join(" "))
+            }
+        } else if command.starts_with("tp.date.now(")
+            || command.starts_with("tp.file.creation_date(")
+        {
+            "{date}".to_string()
+        } else if command.starts_with("tp.system.clipboard(") {
+            "{clipboard}".to_string()
+        } else if command == "tp.file.title" {
+            "{title}".to_string()
+        } else {
+            tag.to_string()
+        };
+        output.push_str(&replacement);
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Contents of the `"..."` and `'...'` literals in a JavaScript snippet.
+fn string_literals(code: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    let mut current: Option<(char, String)> = None;
+    let mut escaped = false;
+    for ch in code.chars() {
+        match current.as_mut() {
+            Some((quote, text)) => {
+                if escaped {
+                    text.push(ch);
+                    escaped = false;
+                } else if ch == '\\' {
+                    escaped = true;
+                } else if ch == *quote {
+                    literals.push(std::mem::take(text));
+                    current = None;
+                } else {
+                    text.push(ch);
+                }
+            }
+            None if ch == '"' || ch == '\'' => current = Some((ch, String::new())),
+            None => {}
+        }
+    }
+    literals
+}
+
+/// Keeps message lines from being read as headings or include directives.
+fn escape_structure(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if parse_heading(line).is_some() {
+                format!(" {line}")
+            } else if parse_include(line).is_some() {
+                line.replacen("{{", "{ {", 1)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_templates;
+
+    #[test]
+    fn repeated_and_renamed_entries_are_reported() {
+        let entry = |title: &str, text: &str| ImportedMessage {
+            title: title.to_string(),
+            text: text.to_string(),
+        };
+        let existing = parse_templates("## 导入/评审\n检查\n");
+        let messages = [
+            entry("评审", "列出问题"),
+            entry("重复", "检查"),
+            entry("摘要", "总结"),
+        ];
+        let (templates, warnings) = to_templates(&messages, ExportFormat::Fabric, false, &existing);
+        let names: Vec<&str> = templates.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["导入/评审 2", "导入/摘要"]);
+        assert_eq!(
+            warnings,
+            vec![
+                "导入/评审 已存在，改名为 导入/评审 2",
+                "重复: 内容已导入过，已跳过"
+            ]
+        );
+    }
+
+    #[test]
+    fn snippet_and_templater_syntax_becomes_placeholders() {
+        assert_eq!(
+            vscode_body("Hi ${1:Dear ${2:name}}, $3 ${4|a,b|} $CLIPBOARD ${CURRENT_YEAR} \\$5$0"),
+            "Hi {arg1|Dear name}, {arg3} {select|arg4|\"a\" \"b\"} {clipboard} {date|%Y} $5"
+        );
+        assert_eq!(
+            templater_body(
+                "<% tp.system.prompt(\"收件人\") %> <%* tR += await tp.system.suggester([\"正式\", \"随意\"], [\"formal\", \"casual\"]) %> <% tp.date.now(\"YYYY\") %> <% tp.file.title %> <% tp.user.x() %>"
+            ),
+            "{收件人} {select|choice1|\"formal\" \"casual\"} {date} {title} <% tp.user.x() %>"
+        );
+
+        let dir = crate::system::test_config_dir("import-fabric");
+        for name in ["summarize", "explain"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            fs::write(dir.join(name).join("system.md"), format!("{name}\n\n")).unwrap();
+        }
+        fs::create_dir_all(dir.join("not-a-pattern")).unwrap();
+        let patterns = read_export(&dir, ExportFormat::Fabric).unwrap();
+        let texts: Vec<(&str, &str)> = patterns
+            .iter()
+            .map(|p| (p.title.as_str(), p.text.as_str()))
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("explain", "explain\n\n{input}"),
+                ("summarize", "summarize\n\n{input}")
+            ]
+        );
+    }
+
+    #[test]
+    fn long_user_messages_of_a_chat_export_become_templates() {
+        let dir = crate::system::test_config_dir("import-chatgpt");
+        let long = format!("## 背景\n{}", "请".repeat(100));
+        let export = serde_json::json!([{
+            "title": "周报/草稿",
+            "mapping": {
+                "b": {"message": {"author": {"role": "user"}, "create_time": 2.0,
+                                  "content": {"parts": ["好的"]}}},
+                "a": {"message": {"author": {"role": "user"}, "create_time": 1.0,
+                                  "content": {"parts": [long]}}},
+                "c": {"message": {"author": {"role": "assistant"}, "create_time": 1.5,
+                                  "content": {"parts": ["回复"]}}}
+            }
+        }]);
+        let path = dir.join(CONVERSATIONS_FILE);
+        fs::write(&path, export.to_string()).unwrap();
+
+        let messages = read_export(&path, ExportFormat::ChatGpt).unwrap();
+        let texts: Vec<&str> = messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec![long.as_str(), "好的"]);
+        let (templates, warnings) = to_templates(&messages, ExportFormat::ChatGpt, false, &[]);
+        assert!(warnings.is_empty());
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].0, "导入/待整理/周报-草稿");
+        assert!(templates[0].1.starts_with(" ## 背景\n"));
+        let (templates, _) = to_templates(&messages, ExportFormat::ChatGpt, true, &[]);
+        assert_eq!(templates[1].0, "导入/待整理/周报-草稿 2");
+
+        assert!(read_export(&path, ExportFormat::Claude).unwrap().is_empty());
+        assert!(read_export(&dir.join("missing.json"), ExportFormat::ChatGpt).is_err());
+    }
+}
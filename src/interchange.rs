@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::locale::tr;
+use crate::models::{Metadata, RecordFormat, Template};
+
+/// A template as written to and read from JSON or YAML: its path, body and
+/// frontmatter fields side by side.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub(crate) struct TemplateRecord {
+    pub(crate) name: String,
+    pub(crate) body: String,
+    #[serde(flatten)]
+    pub(crate) metadata: Metadata,
+}
+
+pub(crate) fn to_records(templates: &[Template]) -> Vec<TemplateRecord> {
+    templates
+        .iter()
+        .map(|template| TemplateRecord {
+            name: template.name.clone(),
+            body: template.body.clone(),
+            metadata: template.metadata.clone(),
+        })
+        .collect()
+}
+
+/// Serializes `records` as a JSON or YAML list.
+pub(crate) fn write_records(records: &[TemplateRecord], format: RecordFormat) -> String {
+    match format {
+        RecordFormat::Json => {
+            let mut text = serde_json::to_string_pretty(records).expect("records are serializable");
+            text.push('\n');
+            text
+        }
+        RecordFormat::Yaml => serde_yaml_ng::to_string(records).expect("records are serializable"),
+    }
+}
+
+/// The format of a `.json`, `.yaml` or `.yml` file.
+pub(crate) fn file_format(path: &Path) -> Option<RecordFormat> {
+    match path.extension()?.to_str()? {
+        "json" => Some(RecordFormat::Json),
+        "yaml" | "yml" => Some(RecordFormat::Yaml),
+        _ => None,
+    }
+}
+
+/// Reads a list of records written by `write_records` or by hand.
+pub(crate) fn read_records(
+    path: &Path,
+    format: RecordFormat,
+) -> Result<Vec<TemplateRecord>, String> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        )
+    })?;
+    let parsed = match format {
+        RecordFormat::Json => serde_json::from_str(&content).map_err(|err| err.to_string()),
+        RecordFormat::Yaml => serde_yaml_ng::from_str(&content).map_err(|err| err.to_string()),
+    };
+    parsed.map_err(|err| {
+        tr!(
+            "无法解析模板文件: {} ({err})",
+            "Cannot parse the templates file: {} ({err})",
+            path.display()
+        )
+    })
+}
+
+/// `(name, section)` pairs for the records whose name is not taken yet,
+/// with the metadata written back as YAML frontmatter. Records without a
+/// name or with a taken one are skipped, with a warning each.
+pub(crate) fn to_sections(
+    records: &[TemplateRecord],
+    existing: &[Template],
+) -> (Vec<(String, String)>, Vec<String>) {
+    let mut names: HashSet<&str> = existing.iter().map(|t| t.name.as_str()).collect();
+    let mut sections = Vec::new();
+    let mut warnings = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let name = record.name.trim();
+        if name.is_empty() {
+            warnings.push(tr!(
+                "第 {} 个模板没有名称，已跳过",
+                "Template {} has no name, skipped",
+                index + 1
+            ));
+            continue;
+        }
+        if !names.insert(name) {
+            warnings.push(tr!(
+                "{name} 已存在，已跳过",
+                "{name} already exists, skipped"
+            ));
+            continue;
+        }
+        let mut section = String::new();
+        if record.metadata != Metadata::default() {
+            let yaml =
+                serde_yaml_ng::to_string(&record.metadata).expect("metadata is serializable");
+            section.push_str(&format!("---\n{yaml}---\n"));
+        }
+        section.push_str(&record.body);
+        sections.push((name.to_string(), section));
+    }
+    (sections, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_templates;
+
+    #[test]
+    fn exported_templates_import_back_unchanged() {
+        let dir = crate::system::test_config_dir("interchange");
+        let templates = parse_templates(
+            "## 写作/邮件\n---\ndescription: 跟进\ntags: [邮件]\n---\n给 {name} 写邮件\n\n## 其他\n再见\n",
+        );
+        let records = to_records(&templates);
+        for name in ["templates.json", "templates.yaml"] {
+            let path = dir.join(name);
+            let format = file_format(&path).unwrap();
+            fs::write(&path, write_records(&records, format)).unwrap();
+            assert_eq!(read_records(&path, format).unwrap(), records);
+        }
+
+        let (sections, warnings) = to_sections(&records, &[]);
+        assert!(warnings.is_empty());
+        let library: String = sections
+            .iter()
+            .map(|(name, section)| format!("## {name}\n{section}\n"))
+            .collect();
+        let imported = parse_templates(&library);
+        assert_eq!(to_records(&imported)[0], records[0]);
+        assert_eq!(imported[1].name, "其他");
+        assert_eq!(file_format(Path::new("templates.toml")), None);
+    }
+
+    #[test]
+    fn taken_and_missing_names_are_skipped_with_a_warning() {
+        let record = |name: &str| TemplateRecord {
+            name: name.to_string(),
+            body: "正文\n".to_string(),
+            ..TemplateRecord::default()
+        };
+        let existing = parse_templates("## 甲\n一\n");
+        let (sections, warnings) =
+            to_sections(&[record("甲"), record(" "), record("乙")], &existing);
+        assert_eq!(sections, vec![("乙".to_string(), "正文\n".to_string())]);
+        assert_eq!(
+            warnings,
+            vec!["甲 已存在，已跳过", "第 2 个模板没有名称，已跳过"]
+        );
+    }
+}
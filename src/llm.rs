@@ -0,0 +1,171 @@
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::locale::tr;
+
+/// Seconds to wait for the endpoint to accept the connection.
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// A prompt for an OpenAI-compatible `/chat/completions` endpoint.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ChatRequest {
+    pub(crate) api_base: String,
+    pub(crate) api_key: Option<String>,
+    pub(crate) model: String,
+    pub(crate) prompt: String,
+}
+
+/// What the request thread reports while the answer streams in.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum AnswerEvent {
+    Chunk(String),
+    Done,
+    Failed(String),
+}
+
+/// Sends `request` from a background thread and streams the answer back.
+/// Dropping the receiver stops the request at the next chunk.
+pub(crate) fn stream_chat(request: ChatRequest) -> Receiver<AnswerEvent> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let event = match run_chat(&request, &sender) {
+            Ok(()) => AnswerEvent::Done,
+            Err(err) => AnswerEvent::Failed(err),
+        };
+        let _ = sender.send(event);
+    });
+    receiver
+}
+
+fn run_chat(request: &ChatRequest, sender: &Sender<AnswerEvent>) -> Result<(), String> {
+    let url = format!(
+        "{}/chat/completions",
+        request.api_base.trim_end_matches('/')
+    );
+    let body = json!({
+        "model": request.model,
+        "stream": true,
+        "messages": [{ "role": "user", "content": request.prompt }],
+    });
+    let config = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_connect(Some(Duration::from_secs(CONNECT_TIMEOUT_SECS)))
+        .build();
+    let agent = ureq::Agent::new_with_config(config);
+    let mut call = agent
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream");
+    if let Some(key) = &request.api_key {
+        call = call.header("Authorization", &format!("Bearer {key}"));
+    }
+    let mut response = call
+        .send(body.to_string())
+        .map_err(|err| tr!("请求失败: {url} ({err})", "Request failed: {url} ({err})"))?;
+    let status = response.status().as_u16();
+    if status >= 400 {
+        let text = response.body_mut().read_to_string().unwrap_or_default();
+        let message = serde_json::from_str::<Value>(&text)
+            .ok()
+            .and_then(|value| error_message(&value))
+            .unwrap_or(text);
+        return Err(tr!(
+            "请求失败 (HTTP {status}): {}",
+            "Request failed (HTTP {status}): {}",
+            message.trim()
+        ));
+    }
+
+    let reader = BufReader::new(response.into_body().into_reader());
+    for line in reader.lines() {
+        let line =
+            line.map_err(|err| tr!("读取回答失败: {err}", "Failed to read the answer: {err}"))?;
+        let text = match parse_event(&line)? {
+            Some(Stream::Chunk(text)) => text,
+            Some(Stream::Done) => return Ok(()),
+            None => continue,
+        };
+        // A failed send means the answer pane was closed.
+        if sender.send(AnswerEvent::Chunk(text)).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Stream {
+    Chunk(String),
+    Done,
+}
+
+/// Reads one line of the server-sent event stream. Only `data:` lines with
+/// content carry anything; comments, blank lines and role-only deltas are
+/// skipped.
+fn parse_event(line: &str) -> Result<Option<Stream>, String> {
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.trim(),
+        None => return Ok(None),
+    };
+    if data == "[DONE]" {
+        return Ok(Some(Stream::Done));
+    }
+    let value: Value = serde_json::from_str(data)
+        .map_err(|err| tr!("无法解析回答: {err}", "Cannot parse the answer: {err}"))?;
+    if let Some(message) = error_message(&value) {
+        return Err(tr!(
+            "接口返回错误: {message}",
+            "The endpoint returned an error: {message}"
+        ));
+    }
+    let content = value["choices"][0]["delta"]["content"]
+        .as_str()
+        .filter(|content| !content.is_empty());
+    Ok(content.map(|content| Stream::Chunk(content.to_string())))
+}
+
+fn error_message(value: &Value) -> Option<String> {
+    let error = value.get("error")?;
+    Some(
+        error["message"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_lines_give_chunks_until_done() {
+        let chunk = |content: &str| {
+            format!(
+                "data: {}",
+                json!({"choices": [{"delta": {"content": content}}]})
+            )
+        };
+        assert_eq!(
+            parse_event(&chunk("你好")),
+            Ok(Some(Stream::Chunk("你好".to_string())))
+        );
+        assert_eq!(parse_event(&chunk("")), Ok(None));
+        assert_eq!(
+            parse_event(r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#),
+            Ok(None)
+        );
+        assert_eq!(parse_event(": keep-alive"), Ok(None));
+        assert_eq!(parse_event(""), Ok(None));
+        assert_eq!(parse_event("data: [DONE]"), Ok(Some(Stream::Done)));
+        assert_eq!(
+            parse_event(r#"data: {"error":{"message":"额度不足"}}"#),
+            Err("接口返回错误: 额度不足".to_string())
+        );
+        assert!(parse_event("data: {").is_err());
+    }
+}
@@ -1,55 +1,273 @@
 mod app;
+mod cli;
+mod config;
+mod cursor;
+mod diagnostics;
+mod error;
+mod formatter;
+mod importer;
+mod interchange;
+mod llm;
+mod markdown;
 mod models;
-mod parser;
+mod platform;
+mod quality;
+mod replay;
+mod sanitize;
+mod search;
+mod stats;
+mod store;
 mod system;
+mod theme;
+mod tokens;
 mod ui;
 
-use std::io;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Duration;
 
-use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind};
-use crossterm::execute;
+use crossterm::event::{self, Event, KeyEventKind};
+use pmt_core::{locale, parser};
 use ratatui::DefaultTerminal;
 
 use crate::app::App;
+use crate::config::Config;
+use crate::error::PmtError;
+use crate::locale::{Locale, set_locale, tr};
+use crate::platform::{DryRunPlatform, Platform, SystemPlatform};
+use crate::replay::{Script, replay_script};
+use crate::store::{FileSource, FileStateStore, MemoryStateStore, StateStore};
+use crate::system::{
+    FileWatcher, fetch_in_background, init_terminal, load_config, restore_terminal,
+    set_prompts_file, set_terminal_title,
+};
 use crate::ui::render_app;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let terminal = ratatui::init();
-    execute!(io::stdout(), EnableMouseCapture)?;
+/// Fixed so rerolls repeat between `--dry-run` sessions.
+const DRY_RUN_SEED: u64 = 0;
 
-    let app = App::load();
-    let result = run_app(terminal, app);
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let file = take_file_flag(&mut args);
+    let language = load_config().ok().and_then(|config| config.language);
+    set_locale(Locale::resolve(language));
+    match file {
+        Ok(Some(path)) => set_prompts_file(PathBuf::from(path)),
+        Ok(None) => {}
+        Err(()) => {
+            eprintln!("{}", tr!("--file 缺少参数", "--file needs a value"));
+            return ExitCode::from(1);
+        }
+    }
+    if let [flag, path] = args.as_slice()
+        && flag == "--script"
+    {
+        return match run_script(path) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let dry_run = args == ["--dry-run"];
+    if !args.is_empty() && !dry_run {
+        return cli::run(&args);
+    }
+    match run_tui(dry_run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Removes `--file <path>` or `--file=<path>`, which works with or
+/// without a command. Err when the path is missing.
+fn take_file_flag(args: &mut Vec<String>) -> Result<Option<String>, ()> {
+    let index = match args
+        .iter()
+        .position(|arg| arg == "--file" || arg.starts_with("--file="))
+    {
+        Some(index) => index,
+        None => return Ok(None),
+    };
+    let arg = args.remove(index);
+    let path = match arg.strip_prefix("--file=") {
+        Some(path) => path.to_string(),
+        None if index < args.len() => args.remove(index),
+        None => return Err(()),
+    };
+    if path.is_empty() {
+        return Err(());
+    }
+    Ok(Some(path))
+}
+
+fn run_tui(dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    if !dry_run {
+        let platform = SystemPlatform::new(config.mouse.capture, config.copy.clipboard_cmd.clone());
+        return run_session(config, FileStateStore, platform).map(|_| ());
+    }
+    // Drafts, last values and uses stay in memory. What would have left pmt
+    // is printed once the terminal is restored.
+    let platform = run_session(
+        config,
+        MemoryStateStore::default(),
+        DryRunPlatform::new(DRY_RUN_SEED),
+    )?;
+    for text in &platform.copied {
+        println!(
+            "{}",
+            tr!("[dry-run] 复制:\n{text}", "[dry-run] copy:\n{text}")
+        );
+    }
+    for path in &platform.edited {
+        println!(
+            "{}",
+            tr!(
+                "[dry-run] 打开编辑器: {}",
+                "[dry-run] open editor: {}",
+                path.display()
+            )
+        );
+    }
+    for (command, text) in &platform.sent {
+        println!(
+            "{}",
+            tr!(
+                "[dry-run] 发送到 {command}:\n{text}",
+                "[dry-run] send to {command}:\n{text}"
+            )
+        );
+    }
+    for request in &platform.asked {
+        println!(
+            "{}",
+            tr!(
+                "[dry-run] 提问 {} ({}):\n{}",
+                "[dry-run] ask {} ({}):\n{}",
+                request.model,
+                request.api_base,
+                request.prompt
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Presses the keys of a `--script` file in a dry run without a terminal
+/// and prints the screen they lead to.
+fn run_script(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        PmtError::Io(tr!(
+            "读取失败: {path} ({err})",
+            "Failed to read: {path} ({err})"
+        ))
+    })?;
+    let script = Script::parse(&content)?;
+    let config = load_config()?;
+    let source = FileSource::new(&config);
+    print!("{}", replay_script(source, config, &script, DRY_RUN_SEED));
+    Ok(())
+}
+
+fn run_session<T: StateStore, P: Platform>(
+    config: Config,
+    store: T,
+    platform: P,
+) -> Result<P, Box<dyn std::error::Error>> {
+    let terminal = init_terminal(config.mouse.capture)?;
+
+    fetch_in_background();
+    let mut app = App::new(FileSource::new(&config), store, platform);
+    app.config = config;
+    let result = run_app(terminal, &mut app);
 
-    execute!(io::stdout(), DisableMouseCapture)?;
-    ratatui::restore();
-    result
+    restore_terminal()?;
+    if let Some(output) = app.exit_output.take() {
+        println!("{output}");
+    }
+    result.map(|_| app.platform)
 }
 
-fn run_app(mut terminal: DefaultTerminal, mut app: App) -> Result<(), Box<dyn std::error::Error>> {
-    let tick_rate = Duration::from_millis(100);
+fn run_app<T: StateStore, P: Platform>(
+    mut terminal: DefaultTerminal,
+    app: &mut App<FileSource, T, P>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Longest wait for input; file changes are noticed this often.
+    let tick_rate = Duration::from_millis(250);
+    // Live reload is a convenience; without a watcher pmt still works.
+    let mut watcher = FileWatcher::new().ok();
+    if let Some(watcher) = watcher.as_mut() {
+        watcher.watch(&app.source_files());
+    }
+    let mut title = String::new();
+    // The screen is drawn after input, a reload, or when something timed
+    // runs out; an idle pmt draws nothing.
+    let mut dirty = true;
     loop {
+        app.tick();
+        if let Some(watcher) = watcher.as_mut()
+            && watcher.changed()
+        {
+            app.on_files_changed();
+            watcher.watch(&app.source_files());
+            dirty = true;
+        }
         if app.needs_redraw {
             terminal.clear()?;
+            title.clear();
             app.needs_redraw = false;
+            dirty = true;
+        }
+        if dirty {
+            let wanted_title = app.terminal_title();
+            if wanted_title != title {
+                set_terminal_title(&wanted_title)?;
+                title = wanted_title;
+            }
+            let now = app.platform.now();
+            terminal.draw(|frame| render_app(frame, app, now))?;
+            dirty = false;
         }
-        terminal.draw(|frame| render_app(frame, &mut app))?;
 
         if app.should_quit {
             break;
         }
+        if app.analysis_due {
+            app.analyze();
+            dirty = true;
+            continue;
+        }
 
-        if event::poll(tick_rate)? {
+        let wake = app.next_change();
+        let timeout = wake
+            .map(|at| at.saturating_duration_since(app.platform.now()))
+            .map_or(tick_rate, |until| until.min(tick_rate));
+        if event::poll(timeout)? {
             match event::read()? {
-                Event::Key(key) => {
-                    if key.kind == KeyEventKind::Press {
-                        app.on_key(key);
-                    }
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    app.on_key(key);
+                    dirty = true;
+                }
+                Event::Mouse(mouse) => {
+                    app.on_mouse(mouse);
+                    dirty = true;
+                }
+                Event::Paste(text) => {
+                    app.on_paste(&text);
+                    dirty = true;
                 }
-                Event::Mouse(mouse) => app.on_mouse(mouse),
-                Event::Resize(_, _) => {}
+                Event::Resize(_, _) => app.on_resize(),
                 _ => {}
             }
+        } else if wake.is_some_and(|at| app.platform.now() >= at) {
+            dirty = true;
         }
     }
     Ok(())
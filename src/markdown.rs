@@ -0,0 +1,191 @@
+use std::ops::Range;
+
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+use crate::theme::Theme;
+
+/// Styles rendered preview lines as markdown: headings, quotes, list
+/// markers, fenced code blocks, `**bold**` and `` `code` `` runs. Styles are
+/// patched under the existing span styles so reroll highlights stay visible.
+pub(crate) fn style_markdown(text: Text<'static>, theme: &Theme) -> Text<'static> {
+    let mut in_fence = false;
+    let lines = text
+        .lines
+        .into_iter()
+        .map(|line| {
+            let plain: String = line
+                .spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect();
+            let trimmed = plain.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                return restyle(line, &[(0..plain.len(), code_style(theme))]);
+            }
+            if in_fence {
+                return restyle(line, &[(0..plain.len(), code_style(theme))]);
+            }
+            let mut ranges = Vec::new();
+            let indent = plain.len() - trimmed.len();
+            if let Some(level) = heading_level(trimmed) {
+                let style = Style::new().fg(theme.accent).add_modifier(Modifier::BOLD);
+                let style = if level == 1 {
+                    style.add_modifier(Modifier::UNDERLINED)
+                } else {
+                    style
+                };
+                ranges.push((0..plain.len(), style));
+            } else if trimmed.starts_with('>') {
+                let style = Style::new().fg(theme.muted).add_modifier(Modifier::ITALIC);
+                ranges.push((0..plain.len(), style));
+            } else if let Some(marker) = list_marker(trimmed) {
+                ranges.push((indent..indent + marker, Style::new().fg(theme.focus)));
+            }
+            ranges.extend(inline_ranges(&plain, theme));
+            restyle(line, &ranges)
+        })
+        .collect::<Vec<_>>();
+    Text::from(lines)
+}
+
+fn code_style(theme: &Theme) -> Style {
+    Style::new().fg(theme.info)
+}
+
+/// Number of `#` for an ATX heading such as `## Title`.
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|ch| *ch == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level)
+}
+
+/// Byte length of a bullet (`- `, `* `, `+ `) or numbered (`1. `) marker.
+fn list_marker(line: &str) -> Option<usize> {
+    if ["- ", "* ", "+ "]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+    {
+        return Some(1);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    (digits > 0 && (rest.starts_with(". ") || rest.starts_with(") "))).then_some(digits + 1)
+}
+
+/// Ranges of `` `code` `` and `**bold**` runs, delimiters included. Bold
+/// markers inside code are left alone.
+fn inline_ranges(line: &str, theme: &Theme) -> Vec<(Range<usize>, Style)> {
+    let mut ranges = Vec::new();
+    let mut index = 0;
+    while index < line.len() {
+        let rest = &line[index..];
+        if rest.starts_with('`')
+            && let Some(end) = rest[1..].find('`')
+        {
+            let end = index + 1 + end + 1;
+            ranges.push((index..end, code_style(theme)));
+            index = end;
+        } else if let Some(inner) = rest.strip_prefix("**")
+            && let Some(end) = inner.find("**")
+            && end > 0
+        {
+            let end = index + 2 + end + 2;
+            ranges.push((index..end, Style::new().add_modifier(Modifier::BOLD)));
+            index = end;
+        } else {
+            index += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    ranges
+}
+
+/// Splits the spans of `line` at the range boundaries and patches each
+/// range's style under the span's own style. Ranges are byte offsets into
+/// the line's concatenated text.
+fn restyle(line: Line<'static>, ranges: &[(Range<usize>, Style)]) -> Line<'static> {
+    if ranges.is_empty() {
+        return line;
+    }
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for span in line.spans {
+        let content = span.content.as_ref();
+        let end = offset + content.len();
+        let mut cuts: Vec<usize> = ranges
+            .iter()
+            .flat_map(|(range, _)| [range.start, range.end])
+            .filter(|cut| *cut > offset && *cut < end)
+            .collect();
+        cuts.push(end);
+        cuts.sort_unstable();
+        cuts.dedup();
+        let mut start = offset;
+        for cut in cuts {
+            let style = ranges
+                .iter()
+                .filter(|(range, _)| range.start <= start && start < range.end)
+                .fold(Style::new(), |style, (_, range_style)| {
+                    style.patch(*range_style)
+                });
+            spans.push(Span::styled(
+                content[start - offset..cut - offset].to_string(),
+                style.patch(span.style),
+            ));
+            start = cut;
+        }
+        offset = end;
+    }
+    Line::from(spans).style(line.style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::ThemeName;
+
+    fn styled(line: &Line<'static>) -> Vec<(String, Style)> {
+        line.spans
+            .iter()
+            .map(|span| (span.content.to_string(), span.style))
+            .collect()
+    }
+
+    #[test]
+    fn markdown_is_patched_under_the_span_styles() {
+        let theme = ThemeName::Dark.theme();
+        let flash = Style::new().bg(theme.flash_bg);
+        let text = Text::from(vec![
+            Line::from("# 标题"),
+            Line::from(vec![
+                Span::raw("- 项 "),
+                Span::styled("**粗**", flash),
+                Span::raw(" `代码`"),
+            ]),
+            Line::from("```"),
+            Line::from("**不是粗体**"),
+            Line::from("```"),
+        ]);
+        let styled_text = style_markdown(text, &theme);
+        let lines = &styled_text.lines;
+        let heading = Style::new()
+            .fg(theme.accent)
+            .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        assert_eq!(styled(&lines[0]), vec![("# 标题".to_string(), heading)]);
+        assert_eq!(
+            styled(&lines[1]),
+            vec![
+                ("-".to_string(), Style::new().fg(theme.focus)),
+                (" 项 ".to_string(), Style::new()),
+                ("**粗**".to_string(), flash.add_modifier(Modifier::BOLD)),
+                (" ".to_string(), Style::new()),
+                ("`代码`".to_string(), code_style(&theme)),
+            ]
+        );
+        assert_eq!(
+            styled(&lines[3]),
+            vec![("**不是粗体**".to_string(), code_style(&theme))]
+        );
+    }
+}
@@ -1,42 +1,116 @@
-#[derive(Clone, Debug)]
-pub(crate) struct Template {
-    pub(crate) name: String,
-    pub(crate) body: String,
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+pub(crate) use pmt_core::models::*;
+
+/// A file or section that was skipped or read differently than written
+/// while loading; the other templates still load.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LoadWarning {
+    pub(crate) source: PathBuf,
+    /// Zero-based line within `source`, when the problem has one.
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
 }
 
-#[derive(Clone, Debug)]
-pub(crate) struct TreeItem {
-    pub(crate) label: String,
-    pub(crate) depth: usize,
-    pub(crate) template_index: Option<usize>,
+impl fmt::Display for LoadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}",
+                self.source.display(),
+                line + 1,
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.source.display(), self.message),
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) enum FieldKind {
-    Var,
-    Random { token_index: usize, pinned: bool },
+/// One use of a template: its prompt was copied, sent, or rendered on the
+/// command line. Appended to `usage.jsonl`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct UsageEntry {
+    pub(crate) template: String,
+    /// Unix time in seconds.
+    pub(crate) time: i64,
+    /// Characters of the rendered prompt.
+    pub(crate) length: usize,
 }
 
-#[derive(Clone, Debug)]
-pub(crate) struct Field {
-    pub(crate) name: String,
-    pub(crate) label: String,
-    pub(crate) value: String,
-    pub(crate) kind: FieldKind,
+impl UsageEntry {
+    pub(crate) fn now(template: &str, output: &str) -> Self {
+        Self::at(template, output, Local::now())
+    }
+
+    pub(crate) fn at(template: &str, output: &str, time: DateTime<Local>) -> Self {
+        Self {
+            template: template.to_string(),
+            time: time.timestamp(),
+            length: output.chars().count(),
+        }
+    }
 }
 
+/// A rendered prompt with the template and values it came from, for
+/// tooling that logs which prompt was used.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct RenderRecord {
+    pub(crate) template: String,
+    pub(crate) variables: BTreeMap<String, String>,
+    /// Seed the random picks were rolled from, when there are any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) seed: Option<u64>,
+    pub(crate) output: String,
+}
+
+impl RenderRecord {
+    pub(crate) fn serialize(&self, format: RecordFormat) -> String {
+        match format {
+            RecordFormat::Json => {
+                let mut text = serde_json::to_string_pretty(self).expect("record is serializable");
+                text.push('\n');
+                text
+            }
+            RecordFormat::Yaml => serde_yaml_ng::to_string(self).expect("record is serializable"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RecordFormat {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl RecordFormat {
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(Self::Json),
+            "yaml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// A row of the tree as drawn: guides, icon and label, and the number of
+/// templates below a folder.
 #[derive(Clone, Debug)]
-pub(crate) enum Token {
-    Text(String),
-    Var {
-        name: String,
-        desc: Option<String>,
-        raw: String,
-    },
-    Random {
-        options: Vec<String>,
-        choice: String,
-        desc: Option<String>,
-        raw: String,
-    },
+pub(crate) struct TreeLine {
+    pub(crate) text: String,
+    pub(crate) folder_count: Option<usize>,
+}
+
+/// Field values saved under a name for one template.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Preset {
+    pub(crate) name: String,
+    pub(crate) values: Vec<(String, String)>,
 }
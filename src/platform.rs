@@ -0,0 +1,227 @@
+use std::env;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::PmtError;
+use crate::llm::{AnswerEvent, ChatRequest, stream_chat};
+use crate::locale::tr;
+use crate::system::{
+    ClipboardBackend, detect_clipboard_backend, pipe_to_command, run_editor_command, set_clipboard,
+};
+
+/// Effects of the TUI outside the terminal and the template files: the
+/// clipboard, the external editor, the send command, the chat endpoint, the
+/// clock and random picks.
+pub(crate) trait Platform: Debug {
+    fn clipboard_backend(&self) -> ClipboardBackend;
+    /// Copies `text`; returns the backend that took it, which differs from
+    /// `clipboard_backend` when the system clipboard fell back to OSC 52.
+    fn set_clipboard(&mut self, text: &str) -> Result<ClipboardBackend, PmtError>;
+    /// Opens `path` in `$EDITOR` and waits for it to exit.
+    fn launch_editor(&mut self, path: &Path) -> Result<(), PmtError>;
+    /// Pipes `text` into the shell command `command`; returns its stdout.
+    fn send(&mut self, command: &str, text: &str) -> Result<String, PmtError>;
+    /// Starts `request`; the answer streams in on the returned channel.
+    fn ask(&mut self, request: ChatRequest) -> Receiver<AnswerEvent>;
+    fn now(&self) -> Instant;
+    /// The local time of day, for `{date}` placeholders and the footer
+    /// clock.
+    fn local_time(&self) -> DateTime<Local>;
+    /// A fresh seed for rolling random placeholders.
+    fn seed(&mut self) -> u64;
+}
+
+/// The real clipboard, `$EDITOR`, wall clock and thread RNG.
+#[derive(Clone, Debug)]
+pub(crate) struct SystemPlatform {
+    clipboard: ClipboardBackend,
+    /// `[copy] clipboard_cmd`, read at startup.
+    clipboard_cmd: Option<String>,
+    /// Whether the terminal reports mouse events, restored after `$EDITOR`.
+    mouse_capture: bool,
+}
+
+impl SystemPlatform {
+    pub(crate) fn new(mouse_capture: bool, clipboard_cmd: Option<String>) -> Self {
+        Self {
+            clipboard: detect_clipboard_backend(clipboard_cmd.as_deref()),
+            clipboard_cmd,
+            mouse_capture,
+        }
+    }
+}
+
+impl Platform for SystemPlatform {
+    fn clipboard_backend(&self) -> ClipboardBackend {
+        self.clipboard
+    }
+
+    /// After a fallback later copies go straight to OSC 52 rather than
+    /// waiting on the failing clipboard again.
+    fn set_clipboard(&mut self, text: &str) -> Result<ClipboardBackend, PmtError> {
+        let used = set_clipboard(self.clipboard, self.clipboard_cmd.as_deref(), text)?;
+        self.clipboard = used;
+        Ok(used)
+    }
+
+    fn launch_editor(&mut self, path: &Path) -> Result<(), PmtError> {
+        let editor = match env::var("EDITOR") {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => {
+                return Err(PmtError::Editor(tr!(
+                    "未设置 EDITOR 环境变量",
+                    "EDITOR is not set"
+                )));
+            }
+        };
+        run_editor_command(&editor, path, self.mouse_capture)
+    }
+
+    fn send(&mut self, command: &str, text: &str) -> Result<String, PmtError> {
+        pipe_to_command(command, text)
+    }
+
+    fn ask(&mut self, request: ChatRequest) -> Receiver<AnswerEvent> {
+        stream_chat(request)
+    }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn local_time(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn seed(&mut self) -> u64 {
+        rand::rng().random()
+    }
+}
+
+/// Keeps every effect in memory: copies, editor launches, sends and chat
+/// requests are recorded, random picks come from a fixed seed and the local
+/// time starts at 09:00 on 2025-01-01. The clock runs for `--dry-run`; for
+/// `--script` and the tests it stands still unless moved forward.
+#[derive(Clone, Debug)]
+pub(crate) struct DryRunPlatform {
+    pub(crate) copied: Vec<String>,
+    pub(crate) edited: Vec<PathBuf>,
+    /// Command and text of every send.
+    pub(crate) sent: Vec<(String, String)>,
+    pub(crate) asked: Vec<ChatRequest>,
+    /// Message every copy fails with, standing in for a broken clipboard.
+    pub(crate) clipboard_error: Option<String>,
+    start: Instant,
+    offset: Duration,
+    /// The clock only moves with `advance`.
+    frozen: bool,
+    rng: StdRng,
+}
+
+impl DryRunPlatform {
+    /// A platform whose clock runs, for an interactive dry run.
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {
+            copied: Vec::new(),
+            edited: Vec::new(),
+            sent: Vec::new(),
+            asked: Vec::new(),
+            clipboard_error: None,
+            start: Instant::now(),
+            offset: Duration::ZERO,
+            frozen: false,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// A platform whose clock stands still, so a replay draws the same
+    /// screen however long it takes.
+    pub(crate) fn frozen(seed: u64) -> Self {
+        Self {
+            frozen: true,
+            ..Self::new(seed)
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn advance(&mut self, duration: Duration) {
+        self.offset += duration;
+    }
+}
+
+impl Platform for DryRunPlatform {
+    fn clipboard_backend(&self) -> ClipboardBackend {
+        ClipboardBackend::Capture
+    }
+
+    fn set_clipboard(&mut self, text: &str) -> Result<ClipboardBackend, PmtError> {
+        if let Some(message) = &self.clipboard_error {
+            return Err(PmtError::Clipboard(message.clone()));
+        }
+        self.copied.push(text.to_string());
+        Ok(ClipboardBackend::Capture)
+    }
+
+    fn launch_editor(&mut self, path: &Path) -> Result<(), PmtError> {
+        self.edited.push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn send(&mut self, command: &str, text: &str) -> Result<String, PmtError> {
+        self.sent.push((command.to_string(), text.to_string()));
+        Ok(String::new())
+    }
+
+    fn ask(&mut self, request: ChatRequest) -> Receiver<AnswerEvent> {
+        self.asked.push(request);
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(AnswerEvent::Chunk(tr!(
+            "[dry-run] 未发送请求",
+            "[dry-run] request not sent"
+        )));
+        let _ = sender.send(AnswerEvent::Done);
+        receiver
+    }
+
+    fn now(&self) -> Instant {
+        if self.frozen {
+            self.start + self.offset
+        } else {
+            Instant::now() + self.offset
+        }
+    }
+
+    fn local_time(&self) -> DateTime<Local> {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .and_then(|date| date.and_hms_opt(9, 0, 0))
+            .and_then(|time| Local.from_local_datetime(&time).earliest())
+            .expect("09:00 on New Year's Day exists in every time zone");
+        start + self.now().saturating_duration_since(self.start)
+    }
+
+    fn seed(&mut self) -> u64 {
+        self.rng.random()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_dry_run_clock_runs_unless_frozen() {
+        let running = DryRunPlatform::new(0);
+        let frozen = DryRunPlatform::frozen(0);
+        let (started, frozen_at) = (running.now(), frozen.now());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(running.now() > started);
+        assert!(running.local_time() > frozen.local_time());
+        assert_eq!(frozen.now(), frozen_at);
+    }
+}
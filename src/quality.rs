@@ -0,0 +1,161 @@
+use crate::locale::{pick, tr};
+use crate::models::{OptionPools, Template, Token};
+use crate::parser::parse_tokens;
+
+/// Lines longer than this are hard to review and usually hide several
+/// instructions.
+const MAX_LINE_CHARS: usize = 200;
+/// Phrases that suggest the template says what the answer should look like.
+const OUTPUT_FORMAT_HINTS: [&str; 9] = [
+    "输出", "格式", "返回", "回答", "列表", "表格", "output", "format", "json",
+];
+
+/// How well a template follows the library conventions: one check per
+/// entry, with a suggestion for every failed one.
+#[derive(Clone, Debug)]
+pub(crate) struct QualityReport {
+    pub(crate) score: u8,
+    pub(crate) checks: Vec<QualityCheck>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct QualityCheck {
+    pub(crate) label: &'static str,
+    /// What to change; `None` when the check passed.
+    pub(crate) suggestion: Option<String>,
+}
+
+impl QualityReport {
+    pub(crate) fn suggestions(&self) -> impl Iterator<Item = &str> {
+        self.checks
+            .iter()
+            .filter_map(|check| check.suggestion.as_deref())
+    }
+}
+
+/// The template's description: `description` from the frontmatter, or
+/// the text of a leading `> ` line in the body.
+pub(crate) fn template_description(template: &Template) -> Option<&str> {
+    if let Some(description) = &template.metadata.description
+        && !description.trim().is_empty()
+    {
+        return Some(description.trim());
+    }
+    let first = template.body.lines().find(|line| !line.trim().is_empty())?;
+    let description = first.trim().strip_prefix('>')?.trim();
+    (!description.is_empty()).then_some(description)
+}
+
+/// Scores `template` from 0 to 100, each check weighing the same.
+pub(crate) fn analyze(template: &Template, pools: &OptionPools) -> QualityReport {
+    let checks = vec![
+        QualityCheck {
+            label: pick("描述", "description"),
+            suggestion: template_description(template).is_none().then(|| {
+                tr!(
+                    "在 frontmatter 中填写 description，或在正文第一行用 `> 描述` 说明模板的用途",
+                    "Add a description to the frontmatter, or a `> description` first line saying what the template is for"
+                )
+            }),
+        },
+        QualityCheck {
+            label: pick("占位符说明", "placeholder descriptions"),
+            suggestion: undescribed_placeholders(template, pools),
+        },
+        QualityCheck {
+            label: pick("行长度", "line length"),
+            suggestion: long_lines(&template.body),
+        },
+        QualityCheck {
+            label: pick("输出格式", "output format"),
+            suggestion: (!mentions_output_format(&template.body))
+                .then(|| {
+                    tr!(
+                        "说明期望的输出格式，如列表、表格或 JSON",
+                        "Say which output format you expect, such as a list, a table or JSON"
+                    )
+                }),
+        },
+    ];
+    let passed = checks
+        .iter()
+        .filter(|check| check.suggestion.is_none())
+        .count();
+    QualityReport {
+        score: (passed * 100 / checks.len()) as u8,
+        checks,
+    }
+}
+
+fn undescribed_placeholders(template: &Template, pools: &OptionPools) -> Option<String> {
+    let mut names: Vec<String> = Vec::new();
+    for token in parse_tokens(&template.body, pools) {
+        let name = match &token {
+            Token::Var { name, desc, .. }
+            | Token::List { name, desc, .. }
+            | Token::Select { name, desc, .. }
+                if desc.is_none() =>
+            {
+                name.clone()
+            }
+            Token::Random { raw, desc, .. } if desc.is_none() => raw.clone(),
+            _ => continue,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+    Some(tr!(
+        "为占位符添加说明 (`{{名称|说明}}`): {}",
+        "Describe the placeholders (`{{name|description}}`): {}",
+        names.join(pick("、", ", "))
+    ))
+}
+
+fn long_lines(body: &str) -> Option<String> {
+    let lines: Vec<String> = body
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.chars().count() > MAX_LINE_CHARS)
+        .map(|(index, _)| (index + 1).to_string())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    Some(tr!(
+        "拆分超过 {MAX_LINE_CHARS} 字的行: 第 {} 行",
+        "Split lines over {MAX_LINE_CHARS} characters: line {}",
+        lines.join(pick("、", ", "))
+    ))
+}
+
+fn mentions_output_format(body: &str) -> bool {
+    let body = body.to_lowercase();
+    OUTPUT_FORMAT_HINTS.iter().any(|hint| body.contains(hint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_templates;
+
+    #[test]
+    fn failed_checks_lower_the_score_and_say_what_to_change() {
+        let templates = parse_templates(&format!(
+            "## 好\n> 给客户的跟进邮件\n给 {{name|收件人}} 写邮件，用列表输出要点\n\n## 差\n{{name}} {{topic}} {{name}}\n{}\n",
+            "长".repeat(201)
+        ));
+        let good = analyze(&templates[0], &OptionPools::new());
+        assert_eq!(good.score, 100);
+        assert_eq!(good.suggestions().count(), 0);
+
+        let poor = analyze(&templates[1], &OptionPools::new());
+        assert_eq!(poor.score, 0);
+        let suggestions: Vec<&str> = poor.suggestions().collect();
+        assert!(suggestions[1].ends_with(": name、topic"));
+        assert!(suggestions[2].ends_with(": 第 2 行"));
+    }
+}
@@ -0,0 +1,234 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::App;
+use crate::config::{Config, KeyBinding};
+use crate::locale::tr;
+use crate::platform::{DryRunPlatform, Platform};
+use crate::store::{MemoryStateStore, StateStore, TemplateSource};
+use crate::ui::render_app;
+
+/// Screen size of a script without a `size` line.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// Keys to press and the screen to press them on, read from a `--script`
+/// file. Each line is `size 60x20`, keys separated by spaces such as
+/// `down down ctrl+s`, or text in double quotes to type as is. Empty lines
+/// and lines starting with `#` are skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Script {
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) keys: Vec<KeyEvent>,
+}
+
+impl Script {
+    pub(crate) fn parse(content: &str) -> Result<Self, String> {
+        let (mut width, mut height) = DEFAULT_SIZE;
+        let mut keys = Vec::new();
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at = |message: String| format!("{}: {message}", line_no + 1);
+            if let Some(text) = line
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+            {
+                keys.extend(
+                    text.chars()
+                        .map(|ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)),
+                );
+            } else if let Some(size) = line.strip_prefix("size ") {
+                (width, height) = parse_size(size.trim()).ok_or_else(|| {
+                    at(tr!(
+                        "无效的尺寸: {size}，应为 宽x高",
+                        "Invalid size: {size}; expected WIDTHxHEIGHT"
+                    ))
+                })?;
+            } else {
+                for spec in line.split_whitespace() {
+                    keys.push(KeyBinding::parse(spec).map_err(at)?.event());
+                }
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            keys,
+        })
+    }
+}
+
+fn parse_size(size: &str) -> Option<(u16, u16)> {
+    let (width, height) = size.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Presses the keys of `script` one after another the way the terminal
+/// would deliver them, drawing after each, and returns the last screen.
+/// Stops early when a key quits pmt.
+pub(crate) fn replay<S: TemplateSource, T: StateStore, P: Platform>(
+    app: &mut App<S, T, P>,
+    script: &Script,
+) -> String {
+    let backend = TestBackend::new(script.width, script.height);
+    let mut terminal = Terminal::new(backend).expect("the test backend cannot fail");
+    let mut draw = |app: &mut App<S, T, P>| {
+        app.tick();
+        let now = app.platform.now();
+        terminal
+            .draw(|frame| render_app(frame, app, now))
+            .expect("the test backend cannot fail");
+        if app.analysis_due {
+            app.analyze();
+            terminal
+                .draw(|frame| render_app(frame, app, now))
+                .expect("the test backend cannot fail");
+        }
+    };
+    draw(app);
+    for key in &script.keys {
+        app.on_key(*key);
+        if app.should_quit {
+            break;
+        }
+        draw(app);
+    }
+    screen_text(terminal.backend().buffer())
+}
+
+/// Replays `script` on the templates of `source` with the dry-run platform
+/// rolling from `seed`. Drafts and last values start out empty and are
+/// dropped afterwards, so a script draws the same screen every time.
+pub(crate) fn replay_script<S: TemplateSource>(
+    source: S,
+    config: Config,
+    script: &Script,
+    seed: u64,
+) -> String {
+    let mut app = App::new(
+        source,
+        MemoryStateStore::default(),
+        DryRunPlatform::frozen(seed),
+    );
+    app.config = config;
+    replay(&mut app, script)
+}
+
+/// One frame of `app` on a `width` × `height` screen, as text.
+#[cfg(test)]
+pub(crate) fn screen<S: TemplateSource, T: StateStore, P: Platform>(
+    app: &mut App<S, T, P>,
+    width: u16,
+    height: u16,
+) -> String {
+    let script = Script {
+        width,
+        height,
+        keys: Vec::new(),
+    };
+    replay(app, &script)
+}
+
+/// The rows of `buffer` without styles or trailing spaces. A wide character
+/// covers the cell after it, which is left out.
+fn screen_text(buffer: &Buffer) -> String {
+    let mut text = String::new();
+    for row in buffer.content.chunks(usize::from(buffer.area.width)) {
+        let mut line = String::new();
+        let mut covered = 0;
+        for cell in row {
+            if covered > 0 {
+                covered -= 1;
+                continue;
+            }
+            line.push_str(cell.symbol());
+            covered = cell.symbol().width().saturating_sub(1);
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::*;
+    use crate::config::FooterWidget;
+    use crate::store::{LIBRARY, MemorySource, test_app};
+
+    #[test]
+    fn a_script_replays_keys_and_typed_text() {
+        let script =
+            Script::parse("# open the greeting\nsize 60x14\n\ndown enter\n\"小明\"\n").unwrap();
+        assert_eq!(
+            (script.width, script.height, script.keys.len()),
+            (60, 14, 4)
+        );
+        let mut app = test_app(LIBRARY);
+        assert_eq!(
+            replay(&mut app, &script),
+            "\
+┌参数──────────────────────────────────────────────────────┐
+│┌收件人──────────────────────────────────────────────────┐│
+││小明|                                                   ││
+│└────────────────────────────────────────────────────────┘│
+└──────────────────────────────────────────────────────────┘
+┌预览: 示例/问候───────────────────────────────────────────┐
+│你好 小明                                                 │
+│                                                          │
+│                                                          │
+│                                                          │
+│                                                          │
+│                                                          │
+└─────────────────────────── 6 字符 · 4 词 · 1 行 · 已填完 ┘
+≈4 tokens  |  Esc 返回  Tab/↑↓ 切换  Ctrl+C 复制  Alt+C 复制
+"
+        );
+        assert!(Script::parse("size 0x10").is_err());
+        assert_eq!(
+            Script::parse("ctrl+s esc").unwrap().keys,
+            vec![
+                KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+                KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_script_draws_the_same_screen_whenever_it_runs() {
+        let script = Script::parse("size 60x10\ndown enter\n\"张三\"\nctrl+c\n").unwrap();
+        let run = |late: Duration| {
+            let mut app = test_app(LIBRARY);
+            app.config.footer.widgets = vec![FooterWidget::Status];
+            app.config.footer.right = vec![FooterWidget::Clock];
+            app.platform.advance(late);
+            replay(&mut app, &script)
+        };
+        let first = run(Duration::ZERO);
+        assert!(first.contains("已复制 (演练模式，未写入剪贴板)"));
+        assert!(first.ends_with("09:00\n"));
+        assert_eq!(run(Duration::from_secs(1)), first);
+    }
+
+    #[test]
+    fn a_script_replays_the_same_on_every_run() {
+        // Leaving the editor keeps what was typed as a draft, which a second
+        // run on the same state would be asked to restore right away.
+        let script = Script::parse("size 60x8\ndown enter\n\"小明\"\nesc enter\n").unwrap();
+        let source = MemorySource::new(LIBRARY);
+        let first = replay_script(source.clone(), Config::default(), &script, 0);
+        assert!(first.contains("发现未完成的草稿"));
+        assert_eq!(replay_script(source, Config::default(), &script, 0), first);
+    }
+}
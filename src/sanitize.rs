@@ -0,0 +1,59 @@
+/// Makes control characters visible so pasted terminal output cannot move
+/// the cursor or restyle the TUI: C0 controls become their Unicode control
+/// pictures (`␛`, `␇`, ...), other controls `\u{..}`. Newlines and tabs are
+/// kept, and `\r\n` counts as a newline.
+pub(crate) fn escape_control(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' | '\t' => output.push(ch),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\u{0}'..='\u{1f}' => {
+                output.push(char::from_u32(0x2400 + ch as u32).unwrap_or('?'));
+            }
+            '\u{7f}' => output.push('␡'),
+            _ if ch.is_control() => output.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            _ => output.push(ch),
+        }
+    }
+    output
+}
+
+/// Removes ANSI escape sequences and other control characters, keeping
+/// newlines and tabs.
+pub(crate) fn strip_control(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' | '\t' => output.push(ch),
+            '\u{1b}' => match chars.next() {
+                // CSI: parameters up to a final byte in `@`..=`~`.
+                Some('[') => {
+                    for next in chars.by_ref() {
+                        if ('@'..='~').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: up to BEL or ESC `\`.
+                Some(']') => {
+                    while let Some(next) = chars.next() {
+                        if next == '\u{7}' {
+                            break;
+                        }
+                        if next == '\u{1b}' {
+                            chars.next_if_eq(&'\\');
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ if ch.is_control() => {}
+            _ => output.push(ch),
+        }
+    }
+    output
+}
@@ -0,0 +1,174 @@
+use crate::models::Template;
+use crate::parser::POOLS_SECTION;
+
+const SNIPPET_CHARS: usize = 80;
+const SCORE_SEGMENT: u32 = 100;
+const SCORE_SEGMENT_PREFIX: u32 = 60;
+const SCORE_NAME: u32 = 40;
+const SCORE_BODY: u32 = 10;
+const MAX_BODY_BONUS: u32 = 5;
+
+/// A template matching every term of a query. Ranges are character offsets,
+/// end-exclusive.
+#[derive(Clone, Debug)]
+pub(crate) struct SearchHit {
+    pub(crate) template_index: usize,
+    pub(crate) score: u32,
+    pub(crate) name_matches: Vec<(usize, usize)>,
+    pub(crate) snippet: String,
+    pub(crate) snippet_matches: Vec<(usize, usize)>,
+}
+
+/// Case-insensitive search over template paths and bodies, best match
+/// first. Each whitespace-separated term must occur in the path or body;
+/// path matches (whole segment > segment prefix > substring) outrank body
+/// matches. A `#tag` term keeps only templates with that frontmatter tag.
+pub(crate) fn search_templates(templates: &[Template], query: &str) -> Vec<SearchHit> {
+    let (tags, terms): (Vec<String>, Vec<String>) = query
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .partition(|term| term.len() > 1 && term.starts_with('#'));
+    if terms.is_empty() && tags.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<SearchHit> = templates
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| template.name != POOLS_SECTION)
+        .filter(|(_, template)| has_tags(template, &tags))
+        .filter_map(|(index, template)| score_template(index, template, &terms))
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score.cmp(&a.score).then_with(|| {
+            templates[a.template_index]
+                .name
+                .cmp(&templates[b.template_index].name)
+        })
+    });
+    hits
+}
+
+fn has_tags(template: &Template, tags: &[String]) -> bool {
+    tags.iter().all(|tag| template.has_tag(&tag[1..]))
+}
+
+fn score_template(index: usize, template: &Template, terms: &[String]) -> Option<SearchHit> {
+    let name = template.name.to_lowercase();
+    let body = template.body.to_lowercase();
+    let mut score = 0;
+    let mut name_matches = Vec::new();
+    let mut first_body_match: Option<usize> = None;
+
+    for term in terms {
+        let in_name = find_all(&name, term);
+        let in_body = find_all(&body, term);
+        if in_name.is_empty() && in_body.is_empty() {
+            return None;
+        }
+        if !in_name.is_empty() {
+            score += name_score(&name, term);
+            name_matches.extend(in_name);
+        }
+        if !in_body.is_empty() {
+            score += SCORE_BODY + (in_body.len() as u32).min(MAX_BODY_BONUS);
+            let start = in_body[0].0;
+            first_body_match = Some(first_body_match.map_or(start, |prev| prev.min(start)));
+        }
+    }
+    name_matches.sort_unstable();
+
+    let (snippet, snippet_matches) = match first_body_match {
+        Some(offset) => snippet_around(&template.body, &body, offset, terms),
+        None => (String::new(), Vec::new()),
+    };
+    Some(SearchHit {
+        template_index: index,
+        score,
+        name_matches,
+        snippet,
+        snippet_matches,
+    })
+}
+
+fn name_score(name: &str, term: &str) -> u32 {
+    let segments = name.split('/').map(str::trim);
+    let mut best = SCORE_NAME;
+    for segment in segments {
+        if segment == term {
+            return SCORE_SEGMENT;
+        }
+        if segment.starts_with(term) {
+            best = SCORE_SEGMENT_PREFIX;
+        }
+    }
+    best
+}
+
+/// Character ranges of every non-overlapping occurrence of `needle`.
+fn find_all(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    let needle_len = needle.chars().count();
+    let mut matches = Vec::new();
+    let mut byte_start = 0;
+    while let Some(found) = haystack[byte_start..].find(needle) {
+        let byte_index = byte_start + found;
+        let char_index = haystack[..byte_index].chars().count();
+        matches.push((char_index, char_index + needle_len));
+        byte_start = byte_index + needle.len().max(1);
+    }
+    matches
+}
+
+/// The body line containing `offset` (a character index into `lowered`),
+/// cut to `SNIPPET_CHARS` around the match, with term ranges relative to
+/// the snippet.
+fn snippet_around(
+    original: &str,
+    lowered: &str,
+    offset: usize,
+    terms: &[String],
+) -> (String, Vec<(usize, usize)>) {
+    let chars: Vec<char> = original.chars().collect();
+    let line_start = chars[..offset.min(chars.len())]
+        .iter()
+        .rposition(|ch| *ch == '\n')
+        .map_or(0, |pos| pos + 1);
+    let line_end = chars[offset.min(chars.len())..]
+        .iter()
+        .position(|ch| *ch == '\n')
+        .map_or(chars.len(), |pos| offset + pos);
+    let start = line_start.max(offset.saturating_sub(SNIPPET_CHARS / 2));
+    let end = line_end.min(start + SNIPPET_CHARS);
+    let snippet: String = chars[start..end].iter().collect();
+
+    let lowered_snippet: String = lowered.chars().skip(start).take(end - start).collect();
+    let mut ranges: Vec<(usize, usize)> = terms
+        .iter()
+        .flat_map(|term| find_all(&lowered_snippet, term))
+        .collect();
+    ranges.sort_unstable();
+    (snippet, ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_templates;
+
+    #[test]
+    fn path_segments_outrank_prefixes_and_bodies() {
+        let templates = parse_templates(
+            "## 代码/评审\n写一封邮件说明评审结果\n\n## 邮件模板/跟进\n跟进\n\n## 写作/邮件\n给客户写邮件\n\n## @pools\n邮件 = [\"甲\"]\n",
+        );
+        let hits = search_templates(&templates, "邮件");
+        let names: Vec<&str> = hits
+            .iter()
+            .map(|hit| templates[hit.template_index].name.as_str())
+            .collect();
+        assert_eq!(names, vec!["写作/邮件", "邮件模板/跟进", "代码/评审"]);
+        assert_eq!(hits[0].name_matches, vec![(3, 5)]);
+        assert_eq!(hits[2].snippet, "写一封邮件说明评审结果");
+        assert_eq!(hits[2].snippet_matches, vec![(3, 5)]);
+        assert!(search_templates(&templates, "邮件 不存在").is_empty());
+    }
+}
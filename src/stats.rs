@@ -0,0 +1,83 @@
+use chrono::{DateTime, Local};
+
+use crate::locale::pick;
+use crate::models::{OptionPools, Template, UsageEntry};
+use crate::parser::{POOLS_SECTION, collect_fields, parse_tokens};
+
+/// How much a template is used, from the usage log.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TemplateStats {
+    pub(crate) name: String,
+    pub(crate) uses: usize,
+    /// Unix time of the latest use.
+    pub(crate) last_used: Option<i64>,
+    pub(crate) placeholders: usize,
+    /// Average characters of the rendered prompts.
+    pub(crate) average_length: Option<usize>,
+}
+
+/// Stats of every template, most used first and never used ones last.
+/// Uses of templates that no longer exist are left out.
+pub(crate) fn template_stats(
+    templates: &[Template],
+    pools: &OptionPools,
+    usage: &[UsageEntry],
+) -> Vec<TemplateStats> {
+    let mut stats: Vec<TemplateStats> = templates
+        .iter()
+        .filter(|template| template.name != POOLS_SECTION)
+        .map(|template| {
+            let uses: Vec<&UsageEntry> = usage
+                .iter()
+                .filter(|entry| entry.template == template.name)
+                .collect();
+            let total: usize = uses.iter().map(|entry| entry.length).sum();
+            TemplateStats {
+                name: template.name.clone(),
+                uses: uses.len(),
+                last_used: uses.iter().map(|entry| entry.time).max(),
+                placeholders: collect_fields(&parse_tokens(&template.body, pools)).len(),
+                average_length: (!uses.is_empty()).then(|| total / uses.len()),
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.uses
+            .cmp(&a.uses)
+            .then(b.last_used.cmp(&a.last_used))
+            .then(a.name.cmp(&b.name))
+    });
+    stats
+}
+
+/// `last_used` as local `YYYY-MM-DD HH:MM`.
+fn format_time(time: i64) -> String {
+    DateTime::from_timestamp(time, 0)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// Column titles matching `format_row`.
+pub(crate) fn stats_header() -> &'static str {
+    pick(
+        "  次数  最近使用          占位符  平均长度  模板",
+        "  Uses  Last used         Fields  Avg. len  Template",
+    )
+}
+
+/// One template's stats as a fixed-width row, `-` where it was never used.
+pub(crate) fn format_row(row: &TemplateStats) -> String {
+    let last_used = row.last_used.map(format_time).unwrap_or_else(|| "-".into());
+    let average = row
+        .average_length
+        .map(|length| length.to_string())
+        .unwrap_or_else(|| "-".into());
+    format!(
+        "{:>6}  {last_used:<16}  {:>6}  {average:>8}  {}",
+        row.uses, row.placeholders, row.name
+    )
+}
@@ -0,0 +1,496 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+#[cfg(test)]
+use crate::app::App;
+use crate::config::Config;
+use crate::error::PmtError;
+#[cfg(test)]
+use crate::locale::{pick, tr};
+use crate::models::{LoadWarning, Preset, Template, UsageEntry};
+#[cfg(test)]
+use crate::parser::{frontmatter_lines, malformed_heading, parse_templates};
+#[cfg(test)]
+use crate::platform::DryRunPlatform;
+use crate::system::{
+    append_journal, append_template, duplicate_template, editable_path, load_draft, load_globals,
+    load_last_values, load_library, load_presets, load_split, load_usage, record_use,
+    remote_fetches_running, remote_fetches_written, remove_draft, remove_last_values,
+    remove_preset, rename_template, rename_usage, replace_template_body, save_draft,
+    save_last_values, save_preset, save_split, swap_templates,
+};
+#[cfg(test)]
+use crate::system::{disambiguate, swap_sections};
+
+/// Where templates are read from and written back to.
+pub(crate) trait TemplateSource: Debug {
+    /// The templates that could be read, and what was skipped on the way.
+    fn load(&self) -> Result<(Vec<Template>, Vec<LoadWarning>), PmtError>;
+    fn replace_body(&mut self, template: &Template, body: &str) -> Result<(), PmtError>;
+    fn append(&mut self, name: &str, body: &str) -> Result<(), PmtError>;
+    fn rename(&mut self, template: &Template, name: &str) -> Result<(), PmtError>;
+    /// Adds a copy of `template` named `name` right after it.
+    fn duplicate(&mut self, template: &Template, name: &str) -> Result<(), PmtError>;
+    /// Exchanges the positions of two templates of the same file.
+    fn swap(&mut self, first: &Template, second: &Template) -> Result<(), PmtError>;
+    /// File to open in `$EDITOR`, if the source is backed by one.
+    fn editable_path(&self) -> Result<PathBuf, PmtError>;
+    /// Takes up the settings of a reloaded `config.toml`.
+    fn configure(&mut self, config: &Config);
+    /// Templates are being fetched in the background.
+    fn fetching(&self) -> bool;
+    /// Background fetches that brought in templates so far; the templates
+    /// are loaded again whenever this grows.
+    fn fetched(&self) -> usize;
+}
+
+/// Per-template state that outlives a session, such as drafts and presets.
+pub(crate) trait StateStore: Debug {
+    fn load_draft(&self, template: &str) -> Option<Vec<(String, String)>>;
+    fn save_draft(&mut self, template: &str, values: &[(String, String)]) -> Result<(), PmtError>;
+    fn remove_draft(&mut self, template: &str) -> Result<(), PmtError>;
+    /// Presets of `template`, sorted by name.
+    fn load_presets(&self, template: &str) -> Vec<Preset>;
+    /// Saves `preset`, replacing one of the same name.
+    fn save_preset(&mut self, template: &str, preset: &Preset) -> Result<(), PmtError>;
+    fn remove_preset(&mut self, template: &str, name: &str) -> Result<(), PmtError>;
+    /// Values shared by every template, keyed by field name.
+    fn load_globals(&self) -> Result<Vec<(String, String)>, PmtError>;
+    /// Values `template` was last used with, filled in when it is opened.
+    fn load_last_values(&self, template: &str) -> Option<Vec<(String, String)>>;
+    fn save_last_values(
+        &mut self,
+        template: &str,
+        values: &[(String, String)],
+    ) -> Result<(), PmtError>;
+    fn remove_last_values(&mut self, template: &str) -> Result<(), PmtError>;
+    /// Uses of every template, oldest first.
+    fn load_usage(&self) -> Vec<UsageEntry>;
+    fn record_use(&mut self, entry: &UsageEntry) -> Result<(), PmtError>;
+    fn rename_usage(&mut self, from: &str, to: &str) -> Result<(), PmtError>;
+    /// Appends a prompt of `template` copied at `time` to the journal at
+    /// `path`.
+    fn append_journal(
+        &mut self,
+        path: &Path,
+        template: &str,
+        text: &str,
+        time: DateTime<Local>,
+    ) -> Result<(), PmtError>;
+    /// Width of the editor's field column last set in the editor, if any.
+    fn load_split(&self) -> Option<u16>;
+    fn save_split(&mut self, split: u16) -> Result<(), PmtError>;
+}
+
+/// The prompts file under `~/.config/pmt` and the files it includes.
+#[derive(Clone, Debug)]
+pub(crate) struct FileSource {
+    /// Backups of the prompts file to keep when rewriting it.
+    backups: usize,
+    /// `[prompts] nested_headings`, for the headings of added templates.
+    nested: bool,
+}
+
+impl FileSource {
+    pub(crate) fn new(config: &Config) -> Self {
+        Self {
+            backups: config.prompts.backup_limit(),
+            nested: config.prompts.nested_headings,
+        }
+    }
+}
+
+impl TemplateSource for FileSource {
+    fn load(&self) -> Result<(Vec<Template>, Vec<LoadWarning>), PmtError> {
+        load_library()
+    }
+
+    fn replace_body(&mut self, template: &Template, body: &str) -> Result<(), PmtError> {
+        replace_template_body(template, body, self.backups)
+    }
+
+    fn append(&mut self, name: &str, body: &str) -> Result<(), PmtError> {
+        append_template(name, body, self.nested, self.backups)
+    }
+
+    fn rename(&mut self, template: &Template, name: &str) -> Result<(), PmtError> {
+        rename_template(template, name, self.backups)
+    }
+
+    fn duplicate(&mut self, template: &Template, name: &str) -> Result<(), PmtError> {
+        duplicate_template(template, name, self.nested, self.backups)
+    }
+
+    fn swap(&mut self, first: &Template, second: &Template) -> Result<(), PmtError> {
+        swap_templates(first, second, self.backups)
+    }
+
+    fn editable_path(&self) -> Result<PathBuf, PmtError> {
+        editable_path()
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.backups = config.prompts.backup_limit();
+        self.nested = config.prompts.nested_headings;
+    }
+
+    fn fetching(&self) -> bool {
+        remote_fetches_running()
+    }
+
+    fn fetched(&self) -> usize {
+        remote_fetches_written()
+    }
+}
+
+/// JSON files under `~/.config/pmt`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FileStateStore;
+
+impl StateStore for FileStateStore {
+    fn load_draft(&self, template: &str) -> Option<Vec<(String, String)>> {
+        load_draft(template)
+    }
+
+    fn save_draft(&mut self, template: &str, values: &[(String, String)]) -> Result<(), PmtError> {
+        save_draft(template, values)
+    }
+
+    fn remove_draft(&mut self, template: &str) -> Result<(), PmtError> {
+        remove_draft(template)
+    }
+
+    fn load_presets(&self, template: &str) -> Vec<Preset> {
+        load_presets(template)
+    }
+
+    fn save_preset(&mut self, template: &str, preset: &Preset) -> Result<(), PmtError> {
+        save_preset(template, preset)
+    }
+
+    fn remove_preset(&mut self, template: &str, name: &str) -> Result<(), PmtError> {
+        remove_preset(template, name)
+    }
+
+    fn load_globals(&self) -> Result<Vec<(String, String)>, PmtError> {
+        load_globals()
+    }
+
+    fn load_last_values(&self, template: &str) -> Option<Vec<(String, String)>> {
+        load_last_values(template)
+    }
+
+    fn save_last_values(
+        &mut self,
+        template: &str,
+        values: &[(String, String)],
+    ) -> Result<(), PmtError> {
+        save_last_values(template, values)
+    }
+
+    fn remove_last_values(&mut self, template: &str) -> Result<(), PmtError> {
+        remove_last_values(template)
+    }
+
+    fn load_usage(&self) -> Vec<UsageEntry> {
+        load_usage()
+    }
+
+    fn record_use(&mut self, entry: &UsageEntry) -> Result<(), PmtError> {
+        record_use(entry)
+    }
+
+    fn rename_usage(&mut self, from: &str, to: &str) -> Result<(), PmtError> {
+        rename_usage(from, to)
+    }
+
+    fn append_journal(
+        &mut self,
+        path: &Path,
+        template: &str,
+        text: &str,
+        time: DateTime<Local>,
+    ) -> Result<(), PmtError> {
+        append_journal(path, template, text, time)
+    }
+
+    fn load_split(&self) -> Option<u16> {
+        load_split()
+    }
+
+    fn save_split(&mut self, split: u16) -> Result<(), PmtError> {
+        save_split(split)
+    }
+}
+
+/// Templates held in memory as prompts-file markdown.
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemorySource {
+    pub(crate) content: String,
+}
+
+#[cfg(test)]
+impl MemorySource {
+    pub(crate) fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl TemplateSource for MemorySource {
+    fn load(&self) -> Result<(Vec<Template>, Vec<LoadWarning>), PmtError> {
+        let mut templates = parse_templates(&self.content);
+        if templates.is_empty() {
+            return Err(PmtError::Parse(
+                pick(
+                    "未找到任何模板，请检查是否有 `## 标题` 段落。",
+                    "No templates found; check that the file has `## Title` sections.",
+                )
+                .to_string(),
+            ));
+        }
+        let warnings = self
+            .content
+            .lines()
+            .enumerate()
+            .filter_map(|(line, text)| {
+                Some(LoadWarning {
+                    source: PathBuf::new(),
+                    line: Some(line),
+                    message: malformed_heading(text)?,
+                })
+            })
+            .collect();
+        disambiguate(&mut templates);
+        Ok((templates, warnings))
+    }
+
+    fn replace_body(&mut self, template: &Template, body: &str) -> Result<(), PmtError> {
+        let mut lines: Vec<&str> = self.content.lines().collect();
+        let start = template.line;
+        if lines.get(start).is_none() {
+            return Err(PmtError::Parse(tr!(
+                "找不到模板: {}",
+                "Template not found: {}",
+                template.name
+            )));
+        }
+        let frontmatter = frontmatter_lines(lines[start + 1..].iter().copied());
+        let start = start + frontmatter;
+        let end = start + 1 + template.body.lines().count();
+        let body = body.trim_end_matches('\n');
+        lines.splice(start + 1..end.min(lines.len()), body.lines());
+        self.content = lines.join("\n");
+        self.content.push('\n');
+        Ok(())
+    }
+
+    fn append(&mut self, name: &str, body: &str) -> Result<(), PmtError> {
+        if !self.content.is_empty() && !self.content.ends_with("\n\n") {
+            self.content.push('\n');
+        }
+        self.content.push_str(&format!("## {name}\n{body}\n"));
+        Ok(())
+    }
+
+    fn rename(&mut self, template: &Template, name: &str) -> Result<(), PmtError> {
+        let mut lines: Vec<String> = self.content.lines().map(str::to_string).collect();
+        match lines.get_mut(template.line) {
+            Some(line) => *line = format!("## {name}"),
+            None => {
+                return Err(PmtError::Parse(tr!(
+                    "找不到模板: {}",
+                    "Template not found: {}",
+                    template.name
+                )));
+            }
+        }
+        self.content = lines.join("\n");
+        self.content.push('\n');
+        Ok(())
+    }
+
+    fn duplicate(&mut self, template: &Template, name: &str) -> Result<(), PmtError> {
+        let mut lines: Vec<&str> = self.content.lines().collect();
+        if lines.get(template.line).is_none() {
+            return Err(PmtError::Parse(tr!(
+                "找不到模板: {}",
+                "Template not found: {}",
+                template.name
+            )));
+        }
+        let end = template.line + 1 + template.body.lines().count();
+        let end = end.min(lines.len());
+        let heading = format!("## {name}");
+        let mut copy = vec!["", heading.as_str()];
+        copy.extend(template.body.lines());
+        lines.splice(end..end, copy);
+        self.content = lines.join("\n");
+        self.content.push('\n');
+        Ok(())
+    }
+
+    fn swap(&mut self, first: &Template, second: &Template) -> Result<(), PmtError> {
+        let lines: Vec<String> = self.content.lines().map(str::to_string).collect();
+        self.content = swap_sections(&lines, first, second)?.join("\n");
+        self.content.push('\n');
+        Ok(())
+    }
+
+    fn editable_path(&self) -> Result<PathBuf, PmtError> {
+        Err(PmtError::Editor(
+            pick(
+                "内存模板源不支持外部编辑",
+                "The in-memory source cannot be edited externally",
+            )
+            .to_string(),
+        ))
+    }
+
+    fn configure(&mut self, _config: &Config) {}
+
+    fn fetching(&self) -> bool {
+        false
+    }
+
+    fn fetched(&self) -> usize {
+        0
+    }
+}
+
+/// State kept for one run and written nowhere, for dry runs and script
+/// replays.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemoryStateStore {
+    pub(crate) drafts: HashMap<String, Vec<(String, String)>>,
+    pub(crate) presets: HashMap<String, Vec<Preset>>,
+    pub(crate) globals: Vec<(String, String)>,
+    pub(crate) last_values: HashMap<String, Vec<(String, String)>>,
+    pub(crate) usage: Vec<UsageEntry>,
+    /// Journal entries as (journal, template, prompt).
+    pub(crate) journal: Vec<(PathBuf, String, String)>,
+    pub(crate) split: Option<u16>,
+}
+
+impl StateStore for MemoryStateStore {
+    fn load_draft(&self, template: &str) -> Option<Vec<(String, String)>> {
+        self.drafts.get(template).cloned()
+    }
+
+    fn save_draft(&mut self, template: &str, values: &[(String, String)]) -> Result<(), PmtError> {
+        self.drafts.insert(template.to_string(), values.to_vec());
+        Ok(())
+    }
+
+    fn remove_draft(&mut self, template: &str) -> Result<(), PmtError> {
+        self.drafts.remove(template);
+        Ok(())
+    }
+
+    fn load_presets(&self, template: &str) -> Vec<Preset> {
+        let mut presets = self.presets.get(template).cloned().unwrap_or_default();
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+        presets
+    }
+
+    fn save_preset(&mut self, template: &str, preset: &Preset) -> Result<(), PmtError> {
+        let presets = self.presets.entry(template.to_string()).or_default();
+        presets.retain(|existing| existing.name != preset.name);
+        presets.push(preset.clone());
+        Ok(())
+    }
+
+    fn remove_preset(&mut self, template: &str, name: &str) -> Result<(), PmtError> {
+        if let Some(presets) = self.presets.get_mut(template) {
+            presets.retain(|preset| preset.name != name);
+        }
+        Ok(())
+    }
+
+    fn load_globals(&self) -> Result<Vec<(String, String)>, PmtError> {
+        Ok(self.globals.clone())
+    }
+
+    fn load_last_values(&self, template: &str) -> Option<Vec<(String, String)>> {
+        self.last_values.get(template).cloned()
+    }
+
+    fn save_last_values(
+        &mut self,
+        template: &str,
+        values: &[(String, String)],
+    ) -> Result<(), PmtError> {
+        self.last_values
+            .insert(template.to_string(), values.to_vec());
+        Ok(())
+    }
+
+    fn remove_last_values(&mut self, template: &str) -> Result<(), PmtError> {
+        self.last_values.remove(template);
+        Ok(())
+    }
+
+    fn load_usage(&self) -> Vec<UsageEntry> {
+        self.usage.clone()
+    }
+
+    fn record_use(&mut self, entry: &UsageEntry) -> Result<(), PmtError> {
+        self.usage.push(entry.clone());
+        Ok(())
+    }
+
+    fn rename_usage(&mut self, from: &str, to: &str) -> Result<(), PmtError> {
+        for entry in &mut self.usage {
+            if entry.template == from {
+                entry.template = to.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    fn append_journal(
+        &mut self,
+        path: &Path,
+        template: &str,
+        text: &str,
+        _time: DateTime<Local>,
+    ) -> Result<(), PmtError> {
+        self.journal
+            .push((path.to_path_buf(), template.to_string(), text.to_string()));
+        Ok(())
+    }
+
+    fn load_split(&self) -> Option<u16> {
+        self.split
+    }
+
+    fn save_split(&mut self, split: u16) -> Result<(), PmtError> {
+        self.split = Some(split);
+        Ok(())
+    }
+}
+
+/// Two templates of one folder, the first with a field.
+#[cfg(test)]
+pub(crate) const LIBRARY: &str = "## 示例/问候\n你好 {name|收件人}\n\n## 示例/告别\n再见\n";
+
+/// The app the tests drive: `library` as the prompts file, `store` for
+/// per-template state and the dry-run platform.
+#[cfg(test)]
+pub(crate) type TestApp = App<MemorySource, MemoryStateStore, DryRunPlatform>;
+
+#[cfg(test)]
+pub(crate) fn test_app(library: &str) -> TestApp {
+    test_app_with(library, MemoryStateStore::default())
+}
+
+#[cfg(test)]
+pub(crate) fn test_app_with(library: &str, store: MemoryStateStore) -> TestApp {
+    App::new(MemorySource::new(library), store, DryRunPlatform::frozen(0))
+}
@@ -1,96 +1,2491 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::PathBuf;
-use std::process::Command;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, channel};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use arboard::Clipboard;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Local, NaiveDateTime};
 use crossterm::cursor::MoveTo;
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+};
 use crossterm::execute;
 use crossterm::terminal::{
-    Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+    Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle, disable_raw_mode,
+    enable_raw_mode,
+};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use ratatui::DefaultTerminal;
+
+use serde_json::{Map, Value};
+
+use crate::config::{Config, RemoteSource};
+use crate::error::PmtError;
+use crate::locale::{pick, tr};
+use crate::models::{Layer, LoadWarning, Preset, Template, Token, UsageEntry};
+use crate::parser::{
+    POOLS_SECTION, frontmatter_lines, malformed_heading, new_template, parse_include,
+    parse_nested_templates, parse_templates, section_heading,
 };
 
-use crate::models::Template;
-use crate::parser::parse_templates;
+const REMOTE_TIMEOUT_SECS: u64 = 10;
+const SYSTEM_DIR: &str = "/etc/pmt";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Environment variable naming the prompts file, below `--file`.
+const PROMPTS_ENV: &str = "PMT_PROMPTS";
+
+/// Folder of the tree holding the templates of the project pmt runs in. The
+/// name stays the same in every UI language so that uses and last values
+/// keep their template; only the label drawn in the tree is translated.
+pub(crate) const PROJECT_FOLDER: &str = "项目/";
+
+/// Prompts file given with `--file`.
+static PROMPTS_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// URLs of remote sources being fetched in the background. Only set in the
+/// TUI; one-shot commands fetch before they go on.
+static BACKGROUND_FETCHES: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+/// Background fetches that have written a new cached copy so far.
+static BACKGROUND_WRITES: AtomicUsize = AtomicUsize::new(0);
 
 const DEFAULT_PROMPTS: &str = "## 示例/问候\n写一封给 {name|收件人} 的简短问候邮件，主题是 {topic|主题}。\n\n## 示例/评审/检查清单\n请评审 {area|模块}，并列出 {random|\"安全\" \"性能\" \"可用性\"} 风险。\n";
 
-pub(crate) fn load_templates() -> Result<Vec<Template>, String> {
-    let path = ensure_prompts_file()?;
-    let content =
-        fs::read_to_string(&path).map_err(|err| format!("读取失败: {} ({err})", path.display()))?;
-    let templates = parse_templates(&content);
+/// Puts the terminal into TUI mode. The current window title is pushed onto
+/// the xterm title stack so `restore_terminal` can put it back.
+pub(crate) fn init_terminal(mouse_capture: bool) -> io::Result<DefaultTerminal> {
+    let terminal = ratatui::init();
+    let mut stdout = io::stdout();
+    stdout.write_all(b"\x1b[22;0t")?;
+    execute!(stdout, EnableBracketedPaste)?;
+    if mouse_capture {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+    Ok(terminal)
+}
+
+pub(crate) fn restore_terminal() -> io::Result<()> {
+    let mut stdout = io::stdout();
+    execute!(stdout, DisableMouseCapture, DisableBracketedPaste)?;
+    stdout.write_all(b"\x1b[23;0t")?;
+    stdout.flush()?;
+    ratatui::restore();
+    Ok(())
+}
+
+pub(crate) fn set_terminal_title(title: &str) -> io::Result<()> {
+    execute!(io::stdout(), SetTitle(title))
+}
+
+/// `load_library` without the warnings, for the command line.
+pub(crate) fn load_templates() -> Result<Vec<Template>, PmtError> {
+    load_library().map(|(templates, _)| templates)
+}
+
+/// Templates of the prompts file followed by those of the prompts
+/// directory, then the shared layers below them: team paths, remote sources
+/// and the system directory. When only the prompts directory exists no
+/// prompts file is created. Files that cannot be read and malformed
+/// sections are skipped with a warning; only a library without any
+/// template is an error.
+pub(crate) fn load_library() -> Result<(Vec<Template>, Vec<LoadWarning>), PmtError> {
+    let config = read_config()?;
+    let nested = config.prompts.nested_headings;
+    let mut templates = Vec::new();
+    let mut warnings = Vec::new();
+    if only_prompts_dir().is_none() {
+        let path = ensure_prompts_file()?;
+        let result = load_file(
+            &path,
+            nested,
+            &mut Vec::new(),
+            &mut templates,
+            &mut warnings,
+        );
+        skip(&path, result, &mut warnings);
+    }
+    if let Some(dir) = prompts_dir().filter(|dir| dir.is_dir()) {
+        let result = load_dir(&dir, "", nested, &mut templates, &mut warnings);
+        skip(&dir, result, &mut warnings);
+    }
+    if let Some(path) = project_prompts() {
+        let result = load_project(&path, nested, &mut templates, &mut warnings);
+        skip(&path, result, &mut warnings);
+    }
+    let home = config_dir().unwrap_or_default();
+    let mut shared = Vec::new();
+    for path in &config.prompts.team {
+        let path = resolve_include(&home, &path.to_string_lossy());
+        let result = load_layer(&path, Layer::Team, nested, &mut shared, &mut warnings);
+        skip(&path, result, &mut warnings);
+    }
+    for remote in &config.remote {
+        load_remote(remote, nested, &mut shared, fetch_text);
+    }
+    let system = config
+        .prompts
+        .system
+        .unwrap_or_else(|| PathBuf::from(SYSTEM_DIR));
+    for path in [system.join("prompts.md"), system.join("prompts")] {
+        if path.exists() {
+            let result = load_layer(&path, Layer::System, nested, &mut shared, &mut warnings);
+            skip(&path, result, &mut warnings);
+        }
+    }
+    disambiguate(&mut templates);
+    disambiguate(&mut shared);
+    merge_layers(&mut templates, shared);
+    for template in &templates {
+        if let Some(err) = &template.metadata_error {
+            warnings.push(LoadWarning {
+                source: template.source.clone(),
+                line: Some(template.line),
+                message: format!("{}: {err}", template.name),
+            });
+        }
+    }
     if templates.is_empty() {
-        return Err("未找到任何模板，请检查是否有 `## 标题` 段落。".to_string());
+        let mut message = tr!(
+            "未找到任何模板，请检查是否有 `## 标题` 段落。",
+            "No templates found; check for `## Title` sections."
+        );
+        for warning in &warnings {
+            message.push_str(&format!("\n{warning}"));
+        }
+        return Err(PmtError::Parse(message));
+    }
+    Ok((templates, warnings))
+}
+
+/// Turns the error of loading `path` into a warning.
+fn skip(path: &Path, result: Result<(), PmtError>, warnings: &mut Vec<LoadWarning>) {
+    if let Err(err) = result {
+        warnings.push(LoadWarning {
+            source: path.to_path_buf(),
+            line: None,
+            message: err.to_string(),
+        });
+    }
+}
+
+/// Parses a prompts file and, in line order, the files it pulls in via
+/// `{{include path}}`. Relative include paths resolve against the including
+/// file's directory. An include that fails and a malformed heading add a
+/// warning for their line.
+fn load_file(
+    path: &Path,
+    nested: bool,
+    stack: &mut Vec<PathBuf>,
+    templates: &mut Vec<Template>,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<(), PmtError> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if stack.contains(&canonical) {
+        return Err(PmtError::Parse(tr!(
+            "检测到循环 include: {}",
+            "Circular include: {}",
+            path.display()
+        )));
+    }
+    let content = fs::read_to_string(path).map_err(|err| {
+        PmtError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        ))
+    })?;
+    stack.push(canonical);
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut own = parse_library_file(&content, nested).into_iter().peekable();
+    for (line_no, line) in content.lines().enumerate() {
+        if let Some(template) = own.next_if(|template| template.line == line_no) {
+            templates.push(Template {
+                source: path.to_path_buf(),
+                include_depth: stack.len() - 1,
+                ..template
+            });
+        } else if let Some(target) = parse_include(line) {
+            let target = resolve_include(base, target);
+            if let Err(err) = load_file(&target, nested, stack, templates, warnings) {
+                warnings.push(LoadWarning {
+                    source: path.to_path_buf(),
+                    line: Some(line_no),
+                    message: err.to_string(),
+                });
+            }
+        } else if let Some(message) = malformed_heading(line) {
+            warnings.push(LoadWarning {
+                source: path.to_path_buf(),
+                line: Some(line_no),
+                message,
+            });
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+/// Loads every `*.md` below `dir` in name order. Subdirectories become
+/// folders of the tree: `prefix` is put in front of the names of the
+/// templates inside. Entries that cannot be read are skipped with a warning.
+fn load_dir(
+    dir: &Path,
+    prefix: &str,
+    nested: bool,
+    templates: &mut Vec<Template>,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<(), PmtError> {
+    let entries = fs::read_dir(dir).map_err(|err| {
+        PmtError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            dir.display()
+        ))
+    })?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if stem.starts_with('.') {
+            continue;
+        }
+        let result = if path.is_dir() {
+            load_dir(
+                &path,
+                &format!("{prefix}{stem}/"),
+                nested,
+                templates,
+                warnings,
+            )
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            load_dir_file(&path, prefix, &stem, nested, templates, warnings)
+        } else {
+            Ok(())
+        };
+        skip(&path, result, warnings);
+    }
+    Ok(())
+}
+
+/// A file with headings or includes is read like the prompts file; any
+/// other file is a single template named after it.
+fn load_dir_file(
+    path: &Path,
+    prefix: &str,
+    stem: &str,
+    nested: bool,
+    templates: &mut Vec<Template>,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<(), PmtError> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        PmtError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        ))
+    })?;
+    let sectioned = content
+        .lines()
+        .any(|line| section_heading(line, nested).is_some() || parse_include(line).is_some());
+    if sectioned {
+        let start = templates.len();
+        load_file(path, nested, &mut Vec::new(), templates, warnings)?;
+        for template in &mut templates[start..] {
+            if template.include_depth == 0 {
+                template.name = format!("{prefix}{}", template.name);
+                template.prefix = format!("{prefix}{}", template.prefix);
+            }
+        }
+    } else if !content.trim().is_empty() {
+        templates.push(Template {
+            source: path.to_path_buf(),
+            prefix: prefix.to_string(),
+            whole_file: true,
+            ..new_template(format!("{prefix}{stem}"), 0, &content)
+        });
+    }
+    Ok(())
+}
+
+/// `.pmt/prompts.md` in the working directory or the nearest directory
+/// above it that has one, or `prompts.md` at the root of the git
+/// repository around it. None when that is the prompts file itself.
+fn project_prompts() -> Option<PathBuf> {
+    if in_test_config_dir() {
+        return None;
+    }
+    let found = find_project_prompts(&env::current_dir().ok()?)?;
+    let canonical = fs::canonicalize(&found).ok()?;
+    let own = prompts_path().and_then(|path| fs::canonicalize(path).ok());
+    (own.as_ref() != Some(&canonical)).then_some(found)
+}
+
+/// The project prompts file seen from `cwd`, whether or not it is the
+/// prompts file.
+fn find_project_prompts(cwd: &Path) -> Option<PathBuf> {
+    for dir in cwd.ancestors() {
+        let hidden = dir.join(".pmt").join("prompts.md");
+        if hidden.is_file() {
+            return Some(hidden);
+        }
+        if dir.join(".git").exists() {
+            let root = dir.join("prompts.md");
+            return root.is_file().then_some(root);
+        }
+    }
+    None
+}
+
+/// Loads the project's prompts file into the `项目/` folder. Its templates
+/// are edited in place like personal ones.
+fn load_project(
+    path: &Path,
+    nested: bool,
+    templates: &mut Vec<Template>,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<(), PmtError> {
+    let start = templates.len();
+    let result = load_file(path, nested, &mut Vec::new(), templates, warnings);
+    for template in &mut templates[start..] {
+        template.name = format!("{PROJECT_FOLDER}{}", template.name);
+        template.prefix = format!("{PROJECT_FOLDER}{}", template.prefix);
+    }
+    result
+}
+
+/// Loads a prompts file, or a directory laid out like the prompts
+/// directory, as templates of `layer`.
+fn load_layer(
+    path: &Path,
+    layer: Layer,
+    nested: bool,
+    templates: &mut Vec<Template>,
+    warnings: &mut Vec<LoadWarning>,
+) -> Result<(), PmtError> {
+    let start = templates.len();
+    let result = if path.is_dir() {
+        load_dir(path, "", nested, templates, warnings)
+    } else {
+        load_file(path, nested, &mut Vec::new(), templates, warnings)
+    };
+    for template in &mut templates[start..] {
+        template.layer = layer;
+    }
+    result
+}
+
+/// Adds the `shared` templates whose names no higher layer took yet, and
+/// marks the templates that hide one.
+fn merge_layers(templates: &mut Vec<Template>, shared: Vec<Template>) {
+    let start = templates.len();
+    let mut index: HashMap<&str, usize> = templates
+        .iter()
+        .enumerate()
+        .map(|(position, template)| (template.name.as_str(), position))
+        .collect();
+    let mut kept = vec![false; shared.len()];
+    let mut added = 0;
+    let mut hidden = Vec::new();
+    for (position, template) in shared.iter().enumerate() {
+        match index.entry(template.name.as_str()) {
+            Entry::Occupied(own) => hidden.push((*own.get(), template.layer)),
+            Entry::Vacant(slot) => {
+                slot.insert(start + added);
+                kept[position] = true;
+                added += 1;
+            }
+        }
+    }
+    templates.extend(
+        shared
+            .into_iter()
+            .zip(kept)
+            .filter_map(|(template, kept)| kept.then_some(template)),
+    );
+    for (position, layer) in hidden {
+        let own = &mut templates[position];
+        if own.layer > layer && own.overrides.is_none() {
+            own.overrides = Some(layer);
+        }
+    }
+}
+
+/// Renames each template whose name an earlier template of the same layer
+/// already has to `name (2)`, `name (3)`…, so that neither hides the
+/// other; `find_problems` reports them. Option pool sections are left
+/// alone: they add up instead.
+pub(crate) fn disambiguate(templates: &mut [Template]) {
+    let mut seen = HashSet::new();
+    let duplicates: Vec<usize> = templates
+        .iter()
+        .enumerate()
+        .filter(|(_, template)| {
+            template.name != POOLS_SECTION && !seen.insert((template.layer, template.name.as_str()))
+        })
+        .map(|(position, _)| position)
+        .collect();
+    if duplicates.is_empty() {
+        return;
+    }
+    let mut taken: HashSet<(Layer, String)> = templates
+        .iter()
+        .map(|template| (template.layer, template.name.clone()))
+        .collect();
+    for position in duplicates {
+        let template = &mut templates[position];
+        let name = (2..)
+            .map(|n| format!("{} ({n})", template.name))
+            .find(|name| !taken.contains(&(template.layer, name.clone())))
+            .expect("one of the suffixes is free");
+        taken.insert((template.layer, name.clone()));
+        template.duplicate_of = Some(std::mem::replace(&mut template.name, name));
+    }
+}
+
+/// Lets stale or missing remote sources be fetched off the UI thread: the
+/// cached copy, if any, is used right away, and the templates are loaded
+/// again once the fetch has written the new one.
+pub(crate) fn fetch_in_background() {
+    let _ = BACKGROUND_FETCHES.set(Mutex::new(HashSet::new()));
+}
+
+/// A remote source is being fetched in the background.
+pub(crate) fn remote_fetches_running() -> bool {
+    BACKGROUND_FETCHES
+        .get()
+        .is_some_and(|fetching| fetching.lock().is_ok_and(|urls| !urls.is_empty()))
+}
+
+/// Background fetches that have written a cached copy so far. A fetch that
+/// failed changes nothing, so it does not lead to another load and fetch.
+pub(crate) fn remote_fetches_written() -> usize {
+    BACKGROUND_WRITES.load(Ordering::Relaxed)
+}
+
+/// Adds the templates of a remote prompts file from its cached copy,
+/// fetching it first when the copy is missing or older than the source's
+/// `ttl`; the TUI fetches in the background instead. An unreachable source
+/// falls back to a stale copy, or adds nothing. Includes are not followed.
+/// The copy is fetched with `fetch`, `fetch_text` outside tests.
+fn load_remote(remote: &RemoteSource, nested: bool, templates: &mut Vec<Template>, fetch: Fetch) {
+    let cache = match remote_cache_path(&remote.source) {
+        Some(cache) => cache,
+        None => return,
+    };
+    let fresh = fs::metadata(&cache)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < Duration::from_secs(remote.ttl));
+    if !fresh {
+        match BACKGROUND_FETCHES.get() {
+            Some(fetching) => refresh_remote(fetching, &remote.source, &cache, fetch),
+            None => {
+                if let Ok(content) = fetch(&remote.source) {
+                    write_remote_cache(&cache, &content);
+                }
+            }
+        }
+    }
+    let content = match fs::read_to_string(&cache) {
+        Ok(content) => content,
+        Err(_) => return,
+    };
+    templates.extend(
+        parse_library_file(&content, nested)
+            .into_iter()
+            .map(|template| Template {
+                source: cache.clone(),
+                remote: Some(remote.source.clone()),
+                layer: Layer::Remote,
+                ..template
+            }),
+    );
+}
+
+/// Fetches `url` into `cache` on a background thread, unless a fetch of it
+/// is already under way.
+fn refresh_remote(
+    fetching: &'static Mutex<HashSet<String>>,
+    url: &str,
+    cache: &Path,
+    fetch: Fetch,
+) {
+    let started = fetching
+        .lock()
+        .is_ok_and(|mut urls| urls.insert(url.to_string()));
+    if !started {
+        return;
+    }
+    let (url, cache) = (url.to_string(), cache.to_path_buf());
+    thread::spawn(move || {
+        if let Ok(content) = fetch(&url) {
+            write_remote_cache(&cache, &content);
+            BACKGROUND_WRITES.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Ok(mut urls) = fetching.lock() {
+            urls.remove(&url);
+        }
+    });
+}
+
+/// Reads the text at a URL.
+type Fetch = fn(&str) -> Result<String, PmtError>;
+
+fn write_remote_cache(cache: &Path, content: &str) {
+    let _ = fs::create_dir_all(cache.parent().unwrap_or_else(|| Path::new(".")));
+    let _ = fs::write(cache, content);
+}
+
+fn fetch_text(url: &str) -> Result<String, PmtError> {
+    let config = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(REMOTE_TIMEOUT_SECS)))
+        .build();
+    let agent = ureq::Agent::new_with_config(config);
+    agent
+        .get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| PmtError::Io(err.to_string()))
+}
+
+/// Where the copy of `url` is kept: `cache/` in the config directory, under
+/// a file name made from the URL. Letters, digits, `-` and `.` are kept and
+/// every other byte is written as `_` and two hex digits, so no two URLs
+/// share a file.
+fn remote_cache_path(url: &str) -> Option<PathBuf> {
+    let mut name = String::new();
+    for byte in url.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.' {
+            name.push(char::from(byte));
+        } else {
+            name.push_str(&format!("_{byte:02x}"));
+        }
+    }
+    Some(config_dir()?.join("cache").join(format!("{name}.md")))
+}
+
+/// Refuses to change a template of a shared layer; such templates are
+/// overridden by a personal copy instead.
+fn ensure_local(template: &Template) -> Result<(), PmtError> {
+    if let Some(url) = &template.remote {
+        return Err(PmtError::Validation(tr!(
+            "远程模板只读: {url}",
+            "Remote templates are read-only: {url}"
+        )));
+    }
+    match template.layer {
+        Layer::Personal => Ok(()),
+        layer => Err(PmtError::Validation(tr!(
+            "{}模板只读: {}",
+            "{} templates are read-only: {}",
+            layer.label(),
+            template.source.display()
+        ))),
+    }
+}
+
+fn resolve_include(base: &Path, target: &str) -> PathBuf {
+    if let Some(rest) = target.strip_prefix("~/")
+        && let Some(home) = home_dir()
+    {
+        return home.join(rest);
+    }
+    let path = PathBuf::from(target);
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+/// Reads `config.toml`; a missing file means defaults.
+pub(crate) fn load_config() -> Result<Config, PmtError> {
+    let mut config = read_config()?;
+    if config.footer.workspace.is_none() {
+        config.footer.workspace = prompts_path().and_then(|path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        });
+    }
+    config.footer.branch = prompts_path()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .and_then(|dir| git_branch(&dir));
+    Ok(config)
+}
+
+/// Branch checked out in the git repository holding `dir`, or the short
+/// commit of a detached HEAD. Reads `.git/HEAD` instead of running git.
+fn git_branch(dir: &Path) -> Option<String> {
+    let git = dir
+        .ancestors()
+        .map(|dir| dir.join(".git"))
+        .find(|git| git.exists())?;
+    // Worktrees and submodules have a `.git` file pointing at the real one.
+    let git_dir = if git.is_file() {
+        let content = fs::read_to_string(&git).ok()?;
+        let target = content.trim().strip_prefix("gitdir:")?.trim();
+        git.parent()?.join(target)
+    } else {
+        git
+    };
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        ),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+fn read_config() -> Result<Config, PmtError> {
+    let path = config_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content).map_err(|err| {
+            PmtError::Config(tr!(
+                "配置文件有误: {} ({err})",
+                "Invalid config file: {} ({err})",
+                path.display()
+            ))
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(PmtError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        ))),
     }
-    Ok(templates)
 }
 
-pub(crate) fn ensure_prompts_file() -> Result<PathBuf, String> {
-    let path = prompts_path().ok_or_else(|| "无法定位用户目录".to_string())?;
+/// The templates of a file of the library, with `#` to `######` headings
+/// as folders when `nested`.
+fn parse_library_file(content: &str, nested: bool) -> Vec<Template> {
+    if nested {
+        parse_nested_templates(content)
+    } else {
+        parse_templates(content)
+    }
+}
+
+pub(crate) fn ensure_prompts_file() -> Result<PathBuf, PmtError> {
+    let path = prompts_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
     if path.exists() {
         return Ok(path);
     }
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|err| format!("创建目录失败: {} ({err})", parent.display()))?;
+        fs::create_dir_all(parent).map_err(|err| {
+            PmtError::Io(tr!(
+                "创建目录失败: {} ({err})",
+                "Failed to create directory: {} ({err})",
+                parent.display()
+            ))
+        })?;
     }
-    fs::write(&path, DEFAULT_PROMPTS)
-        .map_err(|err| format!("创建模板文件失败: {} ({err})", path.display()))?;
+    fs::write(&path, DEFAULT_PROMPTS).map_err(|err| {
+        PmtError::Io(tr!(
+            "创建模板文件失败: {} ({err})",
+            "Failed to create the templates file: {} ({err})",
+            path.display()
+        ))
+    })?;
     Ok(path)
 }
 
-pub(crate) fn run_editor_command(editor: &str, path: &PathBuf) -> Result<(), String> {
+/// The prompts directory when templates live only there, else the prompts
+/// file, created if missing.
+pub(crate) fn editable_path() -> Result<PathBuf, PmtError> {
+    match only_prompts_dir() {
+        Some(dir) => Ok(dir),
+        None => ensure_prompts_file(),
+    }
+}
+
+/// Replaces the body of `template` in its source file, keeping the heading
+/// and everything outside the section untouched.
+pub(crate) fn replace_template_body(
+    template: &Template,
+    body: &str,
+    backups: usize,
+) -> Result<(), PmtError> {
+    ensure_local(template)?;
+    let mut lines = read_lines(&template.source)?;
+    if template.whole_file {
+        lines.truncate(frontmatter_lines(lines.iter().map(String::as_str)));
+        lines.extend(body_lines(body));
+        return write_lines(&template.source, &lines, backups);
+    }
+    let start = locate_section(&lines, template)?;
+    let end = section_end(&lines, start, template.nested);
+    let frontmatter = frontmatter_lines(lines[start + 1..end].iter().map(String::as_str));
+    let mut section = body_lines(body);
+    if end < lines.len() {
+        section.push(String::new());
+    }
+    lines.splice(start + 1 + frontmatter..end, section);
+    write_lines(&template.source, &lines, backups)
+}
+
+/// Rewrites the heading of `template` to `## name`, or the level it has
+/// with nested headings, leaving the body and the section's position
+/// untouched.
+pub(crate) fn rename_template(
+    template: &Template,
+    name: &str,
+    backups: usize,
+) -> Result<(), PmtError> {
+    ensure_local(template)?;
+    if template.whole_file {
+        let target = new_template_file(name)?;
+        return fs::rename(&template.source, &target).map_err(|err| {
+            PmtError::Io(tr!(
+                "重命名失败: {} ({err})",
+                "Failed to rename: {} ({err})",
+                template.source.display()
+            ))
+        });
+    }
+    let heading = own_heading(template, name)?;
+    let mut lines = read_lines(&template.source)?;
+    let start = locate_section(&lines, template)?;
+    lines[start] = format!(
+        "{} {heading}",
+        heading_marks(&lines[start], template.nested)
+    );
+    write_lines(&template.source, &lines, backups)
+}
+
+/// Inserts a copy of `template` named `name` right after it and the
+/// templates nested under it, in the same file. A whole-file template is
+/// copied to a new file, and a shared one to the prompts file, where it can
+/// be changed.
+pub(crate) fn duplicate_template(
+    template: &Template,
+    name: &str,
+    nested: bool,
+    backups: usize,
+) -> Result<(), PmtError> {
+    if template.layer != Layer::Personal {
+        let lines = read_lines(&template.source)?;
+        let section = if template.whole_file {
+            lines.join("\n")
+        } else {
+            let start = locate_section(&lines, template)?;
+            let end = section_end(&lines, start, template.nested);
+            lines[start + 1..end].join("\n")
+        };
+        return append_template(name, &section, nested, backups);
+    }
+    if template.whole_file {
+        let target = new_template_file(name)?;
+        return fs::copy(&template.source, &target)
+            .map(|_| ())
+            .map_err(|err| {
+                PmtError::Io(tr!(
+                    "写入失败: {} ({err})",
+                    "Failed to write: {} ({err})",
+                    target.display()
+                ))
+            });
+    }
+    let heading = own_heading(template, name)?;
+    let mut lines = read_lines(&template.source)?;
+    let start = locate_section(&lines, template)?;
+    let end = section_end(&lines, start, template.nested);
+    let after = subtree_end(&lines, start, template.nested);
+    let heading = format!(
+        "{} {heading}",
+        heading_marks(&lines[start], template.nested)
+    );
+    let mut copy = Vec::new();
+    if lines[after - 1].trim().is_empty() {
+        copy.push(heading);
+    } else {
+        copy.extend([String::new(), heading]);
+    }
+    let body: Vec<String> = lines[start + 1..end].to_vec();
+    copy.extend(body_lines(&body.join("\n")));
+    if after < lines.len() {
+        copy.push(String::new());
+    }
+    lines.splice(after..after, copy);
+    write_lines(&template.source, &lines, backups)
+}
+
+/// Swaps the sections of two templates of the same file, keeping the blank
+/// lines between sections where they were.
+pub(crate) fn swap_templates(
+    first: &Template,
+    second: &Template,
+    backups: usize,
+) -> Result<(), PmtError> {
+    ensure_local(first)?;
+    if first.source != second.source {
+        return Err(PmtError::Validation(tr!(
+            "只能在同一文件内移动模板",
+            "Templates can only move within their file"
+        )));
+    }
+    let lines = read_lines(&first.source)?;
+    let lines = swap_sections(&lines, first, second)?;
+    write_lines(&first.source, &lines, backups)
+}
+
+/// `lines` with the sections of `first` and `second` swapped, each with the
+/// sections nested under it. Trailing blank lines stay in place so the
+/// spacing of the file is unchanged.
+pub(crate) fn swap_sections(
+    lines: &[String],
+    first: &Template,
+    second: &Template,
+) -> Result<Vec<String>, PmtError> {
+    let range = |template: &Template| -> Result<(usize, usize), PmtError> {
+        let start = locate_section(lines, template)?;
+        let mut end = subtree_end(lines, start, template.nested);
+        while end > start + 1 && lines[end - 1].trim().is_empty() {
+            end -= 1;
+        }
+        Ok((start, end))
+    };
+    let (mut a, mut b) = (range(first)?, range(second)?);
+    if heading_marks(&lines[a.0], first.nested) != heading_marks(&lines[b.0], second.nested) {
+        return Err(PmtError::Validation(tr!(
+            "只能与同级标题的模板交换位置",
+            "Templates can only swap places with ones of the same heading level"
+        )));
+    }
+    if a.0 > b.0 {
+        std::mem::swap(&mut a, &mut b);
+    }
+    if a.0 == b.0 {
+        return Ok(lines.to_vec());
+    }
+    let mut swapped = lines[..a.0].to_vec();
+    swapped.extend_from_slice(&lines[b.0..b.1]);
+    swapped.extend_from_slice(&lines[a.1..b.0]);
+    swapped.extend_from_slice(&lines[a.0..a.1]);
+    swapped.extend_from_slice(&lines[b.1..]);
+    Ok(swapped)
+}
+
+/// Appends a new `## name` section to the main prompts file, or writes
+/// `name.md` when templates live only in the prompts directory.
+pub(crate) fn append_template(
+    name: &str,
+    body: &str,
+    nested: bool,
+    backups: usize,
+) -> Result<(), PmtError> {
+    if only_prompts_dir().is_some() {
+        let path = new_template_file(name)?;
+        return write_lines(&path, &body_lines(body), backups);
+    }
+    let path = ensure_prompts_file()?;
+    append_templates_to(
+        &path,
+        &[(name.to_string(), body.to_string())],
+        nested,
+        backups,
+    )
+}
+
+/// Appends `(name, body)` sections to `path`, creating it if missing. With
+/// `nested` headings they get `#` headings so they do not land in the last
+/// folder of the file.
+pub(crate) fn append_templates_to(
+    path: &Path,
+    templates: &[(String, String)],
+    nested: bool,
+    backups: usize,
+) -> Result<(), PmtError> {
+    let mut lines = if path.exists() {
+        read_lines(path)?
+    } else {
+        Vec::new()
+    };
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let marks = if nested { "#" } else { "##" };
+    for (name, body) in templates {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(format!("{marks} {name}"));
+        lines.extend(body_lines(body));
+    }
+    write_lines(path, &lines, backups)
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>, PmtError> {
+    let content = fs::read_to_string(path).map_err(|err| {
+        PmtError::Io(tr!(
+            "读取失败: {} ({err})",
+            "Failed to read: {} ({err})",
+            path.display()
+        ))
+    })?;
+    Ok(content.lines().map(str::to_string).collect())
+}
+
+fn write_lines(path: &Path, lines: &[String], backups: usize) -> Result<(), PmtError> {
+    backup_prompts_file(path, backups)?;
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(path, content).map_err(|err| {
+        PmtError::Io(tr!(
+            "写入失败: {} ({err})",
+            "Failed to write: {} ({err})",
+            path.display()
+        ))
+    })
+}
+
+/// Copies the personal prompts file to `backups/prompts-<time>.md` in the
+/// config directory before pmt rewrites it, keeping the newest `keep`
+/// copies. Other files, including one chosen with `--file` or
+/// `PMT_PROMPTS`, and a missing prompts file are left alone; of several
+/// writes within a second the copy before the first is kept.
+pub(crate) fn backup_prompts_file(path: &Path, keep: usize) -> Result<(), PmtError> {
+    let prompts = match personal_prompts_path().and_then(|prompts| fs::canonicalize(prompts).ok()) {
+        Some(prompts) => prompts,
+        None => return Ok(()),
+    };
+    if fs::canonicalize(path).ok().as_ref() != Some(&prompts) {
+        return Ok(());
+    }
+    let dir = match backups_dir() {
+        Some(dir) if keep > 0 => dir,
+        _ => return Ok(()),
+    };
+    fs::create_dir_all(&dir).map_err(|err| {
+        PmtError::Io(tr!(
+            "创建目录失败: {} ({err})",
+            "Failed to create directory: {} ({err})",
+            dir.display()
+        ))
+    })?;
+    let name = format!("prompts-{}.md", Local::now().format(BACKUP_TIME_FORMAT));
+    let target = dir.join(name);
+    if !target.exists() {
+        fs::copy(&prompts, &target).map_err(|err| {
+            PmtError::Io(tr!(
+                "备份失败: {} ({err})",
+                "Failed to back up: {} ({err})",
+                target.display()
+            ))
+        })?;
+    }
+    for old in list_backups().iter().skip(keep) {
+        let _ = fs::remove_file(old);
+    }
+    Ok(())
+}
+
+/// Backups of the prompts file, newest first.
+pub(crate) fn list_backups() -> Vec<PathBuf> {
+    let entries = match backups_dir().and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| backup_time(path).is_some())
+        .collect();
+    backups.sort();
+    backups.reverse();
+    backups
+}
+
+/// When a backup was taken, read from its file name.
+pub(crate) fn backup_time(path: &Path) -> Option<NaiveDateTime> {
+    let stamp = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix("prompts-")?
+        .strip_suffix(".md")?;
+    NaiveDateTime::parse_from_str(stamp, BACKUP_TIME_FORMAT).ok()
+}
+
+/// Replaces the personal prompts file with `backup`, backing up the current
+/// content first so the restore can be undone. Returns the prompts file.
+pub(crate) fn restore_backup(backup: &Path, backups: usize) -> Result<PathBuf, PmtError> {
+    restore_backup_over(backup, chosen_prompts_file().as_deref(), backups)
+}
+
+/// `restore_backup` while `chosen` is the prompts file of the run. Backups
+/// are only taken of the personal prompts file, so a restore is refused
+/// while another file is in use rather than overwriting it.
+fn restore_backup_over(
+    backup: &Path,
+    chosen: Option<&Path>,
+    backups: usize,
+) -> Result<PathBuf, PmtError> {
+    let path = personal_prompts_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    if let Some(chosen) = chosen
+        && fs::canonicalize(chosen).ok() != fs::canonicalize(&path).ok()
+    {
+        return Err(PmtError::Validation(tr!(
+            "备份来自 {}，不能恢复到 {}",
+            "The backups are of {}, not of {}",
+            path.display(),
+            chosen.display()
+        )));
+    }
+    if path.exists() {
+        backup_prompts_file(&path, backups)?;
+    }
+    fs::copy(backup, &path)
+        .map(|_| path.clone())
+        .map_err(|err| {
+            PmtError::Io(tr!(
+                "写入失败: {} ({err})",
+                "Failed to write: {} ({err})",
+                path.display()
+            ))
+        })
+}
+
+fn backups_dir() -> Option<PathBuf> {
+    Some(config_dir()?.join("backups"))
+}
+
+/// Path for a new template file named `name` in the prompts directory,
+/// with its folders created.
+fn new_template_file(name: &str) -> Result<PathBuf, PmtError> {
+    let dir = prompts_dir().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    let path = dir.join(format!("{name}.md"));
+    if path.exists() {
+        return Err(PmtError::Io(tr!(
+            "文件已存在: {}",
+            "File already exists: {}",
+            path.display()
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            PmtError::Io(tr!(
+                "创建目录失败: {} ({err})",
+                "Failed to create directory: {} ({err})",
+                parent.display()
+            ))
+        })?;
+    }
+    Ok(path)
+}
+
+/// The heading for `name` in the file of `template`, which cannot move the
+/// template out of the folders its directory gives it.
+fn own_heading<'a>(template: &Template, name: &'a str) -> Result<&'a str, PmtError> {
+    name.strip_prefix(&template.prefix)
+        .filter(|heading| !heading.is_empty())
+        .ok_or_else(|| {
+            PmtError::Validation(tr!(
+                "该文件中的模板只能留在 {} 下",
+                "Templates of this file have to stay under {}",
+                template.prefix
+            ))
+        })
+}
+
+/// Finds the heading line of `template`, falling back to a search by name
+/// when the file changed since it was loaded. A duplicate is only found at
+/// its line; the search would find the template it duplicates.
+fn locate_section(lines: &[String], template: &Template) -> Result<usize, PmtError> {
+    let written = template.written_name();
+    let heading = written.strip_prefix(&template.prefix).unwrap_or(written);
+    let matches = |line: &String| {
+        section_heading(line, template.nested).is_some_and(|(_, name)| name == heading)
+    };
+    if lines.get(template.line).is_some_and(matches) {
+        return Ok(template.line);
+    }
+    let found = match template.duplicate_of {
+        Some(_) => None,
+        None => lines.iter().position(matches),
+    };
+    found.ok_or_else(|| {
+        PmtError::Parse(tr!(
+            "文件中找不到模板: {}",
+            "Template not found in its file: {}",
+            template.name
+        ))
+    })
+}
+
+fn section_end(lines: &[String], start: usize, nested: bool) -> usize {
+    lines[start + 1..]
+        .iter()
+        .position(|line| section_heading(line, nested).is_some() || parse_include(line).is_some())
+        .map(|offset| start + 1 + offset)
+        .unwrap_or(lines.len())
+}
+
+/// Like `section_end`, but past the sections nested under the one at
+/// `start`.
+fn subtree_end(lines: &[String], start: usize, nested: bool) -> usize {
+    let level = section_heading(&lines[start], nested).map_or(0, |(level, _)| level);
+    lines[start + 1..]
+        .iter()
+        .position(|line| {
+            parse_include(line).is_some()
+                || section_heading(line, nested).is_some_and(|(other, _)| other <= level)
+        })
+        .map(|offset| start + 1 + offset)
+        .unwrap_or(lines.len())
+}
+
+/// The `#`s of the heading at `line`: `##` unless nested deeper.
+fn heading_marks(line: &str, nested: bool) -> String {
+    let level = section_heading(line, nested).map_or(2, |(level, _)| level);
+    "#".repeat(level)
+}
+
+fn body_lines(body: &str) -> Vec<String> {
+    body.trim_end_matches(['\n', '\r'])
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads the file behind every `{file|...}` token. Relative paths resolve
+/// against the working directory. Returns one message per unreadable file.
+pub(crate) fn load_file_tokens(tokens: &mut [Token]) -> Vec<String> {
+    let base = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut errors = Vec::new();
+    for token in tokens {
+        if let Token::File {
+            path,
+            resolved,
+            content,
+            ..
+        } = token
+        {
+            let full = resolve_include(&base, path);
+            match fs::read_to_string(&full) {
+                Ok(text) => *content = Some(text),
+                Err(err) => errors.push(tr!(
+                    "读取失败: {} ({err})",
+                    "Failed to read: {} ({err})",
+                    full.display()
+                )),
+            }
+            *resolved = Some(full);
+        }
+    }
+    errors
+}
+
+/// Runs every `{cmd|...}` token through the shell and stores its stdout,
+/// without the trailing newline. Returns one message per failed command.
+pub(crate) fn run_command_tokens(tokens: &mut [Token]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for token in tokens {
+        if let Token::Command {
+            command, output, ..
+        } = token
+        {
+            match run_shell_command(command) {
+                Ok(stdout) => *output = Some(stdout),
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+    }
+    errors
+}
+
+/// Runs the given `{cmd|...}` tokens' commands, by token index, one after
+/// another on a background thread and reports each result as it finishes.
+pub(crate) fn spawn_commands(
+    commands: Vec<(usize, String)>,
+) -> Receiver<(usize, Result<String, PmtError>)> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for (index, command) in commands {
+            if sender.send((index, run_shell_command(&command))).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+/// Fills every `{clipboard}` token with the clipboard's current text.
+/// Returns an error when the clipboard could not be read.
+pub(crate) fn read_clipboard_tokens(tokens: &mut [Token]) -> Option<PmtError> {
+    if !tokens
+        .iter()
+        .any(|token| matches!(token, Token::Clipboard { .. }))
+    {
+        return None;
+    }
+    // OSC 52 can only write, and arboard would wait for a display that
+    // isn't there.
+    if is_headless() {
+        return Some(PmtError::Clipboard(tr!(
+            "无图形环境，无法读取剪贴板",
+            "No display, cannot read the clipboard"
+        )));
+    }
+    let text = match Clipboard::new().and_then(|mut cb| cb.get_text()) {
+        Ok(text) => text,
+        Err(err) => {
+            return Some(PmtError::Clipboard(tr!(
+                "读取剪贴板失败: {err}",
+                "Failed to read the clipboard: {err}"
+            )));
+        }
+    };
+    for token in tokens {
+        if let Token::Clipboard { content, .. } = token {
+            *content = Some(text.clone());
+        }
+    }
+    None
+}
+
+fn run_shell_command(command: &str) -> Result<String, PmtError> {
+    let result = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    };
+    let output = result.map_err(|err| {
+        PmtError::Io(tr!(
+            "执行命令失败: {command} ({err})",
+            "Failed to run command: {command} ({err})"
+        ))
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PmtError::Io(tr!(
+            "命令返回错误: {command} ({}) {}",
+            "Command failed: {command} ({}) {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Runs `command` through the shell with `input` on its stdin and returns
+/// its stdout. A non-zero exit is an error carrying the status and stderr.
+pub(crate) fn pipe_to_command(command: &str, input: &str) -> Result<String, PmtError> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    let mut child = shell
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            PmtError::Io(tr!(
+                "执行命令失败: {command} ({err})",
+                "Failed to run command: {command} ({err})"
+            ))
+        })?;
+    // Written from another thread so a command that answers before reading
+    // all of its input cannot block on a full stdout pipe.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|err| {
+        PmtError::Io(tr!(
+            "执行命令失败: {command} ({err})",
+            "Failed to run command: {command} ({err})"
+        ))
+    })?;
+    // A command may exit without reading its input; that is not an error.
+    let _ = writer.join();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PmtError::Io(tr!(
+            "命令返回错误: {command} ({}) {}",
+            "Command failed: {command} ({}) {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub(crate) fn run_editor_command(
+    editor: &str,
+    path: &Path,
+    mouse_capture: bool,
+) -> Result<(), PmtError> {
     let mut parts = editor.split_whitespace();
     let command = parts
         .next()
-        .ok_or_else(|| "EDITOR 为空".to_string())
+        .ok_or_else(|| PmtError::Editor(tr!("EDITOR 为空", "EDITOR is empty")))
         .map(|value| value.to_string())?;
     let args: Vec<String> = parts.map(|part| part.to_string()).collect();
 
-    disable_raw_mode().map_err(|err| format!("退出原始模式失败: {err}"))?;
-    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)
-        .map_err(|err| format!("退出全屏模式失败: {err}"))?;
+    disable_raw_mode().map_err(|err| {
+        PmtError::Editor(tr!(
+            "退出原始模式失败: {err}",
+            "Failed to leave raw mode: {err}"
+        ))
+    })?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture).map_err(|err| {
+        PmtError::Editor(tr!(
+            "退出全屏模式失败: {err}",
+            "Failed to leave the alternate screen: {err}"
+        ))
+    })?;
 
     let status_result = Command::new(&command).args(&args).arg(path).status();
 
+    let mut stdout = io::stdout();
     let restore_result = execute!(
-        io::stdout(),
+        stdout,
         EnterAlternateScreen,
-        EnableMouseCapture,
         Clear(ClearType::All),
         MoveTo(0, 0)
     )
-    .map_err(|err| format!("恢复全屏模式失败: {err}"))
-    .and_then(|_| enable_raw_mode().map_err(|err| format!("恢复原始模式失败: {err}")));
+    .and_then(|_| {
+        if mouse_capture {
+            execute!(stdout, EnableMouseCapture)
+        } else {
+            Ok(())
+        }
+    })
+    .map_err(|err| {
+        PmtError::Editor(tr!(
+            "恢复全屏模式失败: {err}",
+            "Failed to restore the alternate screen: {err}"
+        ))
+    })
+    .and_then(|_| {
+        enable_raw_mode().map_err(|err| {
+            PmtError::Editor(tr!(
+                "恢复原始模式失败: {err}",
+                "Failed to restore raw mode: {err}"
+            ))
+        })
+    });
 
     let status = match status_result {
         Ok(status) => status,
         Err(err) => {
             let _ = restore_result;
-            return Err(format!("启动编辑器失败: {err}"));
+            return Err(PmtError::Editor(tr!(
+                "启动编辑器失败: {err}",
+                "Failed to start the editor: {err}"
+            )));
         }
     };
-    if let Err(err) = restore_result {
-        return Err(err);
+    restore_result?;
+    if !status.success() {
+        return Err(PmtError::Editor(tr!(
+            "编辑器退出异常: {status}",
+            "The editor failed: {status}"
+        )));
     }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ClipboardBackend {
+    /// The desktop clipboard via arboard.
+    System,
+    /// An OSC 52 escape sequence asking the terminal to set its clipboard.
+    Osc52,
+    /// The `[copy] clipboard_cmd` shell command.
+    Command,
+    /// Nothing leaves pmt; copies are kept in memory (`--dry-run`).
+    Capture,
+}
+
+/// Picks the clipboard backend once at startup: the configured command if
+/// any. Without a graphical session arboard can only fail, so fall back to
+/// OSC 52 right away.
+pub(crate) fn detect_clipboard_backend(command: Option<&str>) -> ClipboardBackend {
+    if command.is_some() {
+        ClipboardBackend::Command
+    } else if is_headless() {
+        ClipboardBackend::Osc52
+    } else {
+        ClipboardBackend::System
+    }
+}
+
+fn is_headless() -> bool {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return false;
+    }
+    let has_display = |name: &str| env::var_os(name).is_some_and(|value| !value.is_empty());
+    !has_display("DISPLAY") && !has_display("WAYLAND_DISPLAY")
+}
+
+/// Copies `text` with `backend`, piping it into `command` for
+/// `ClipboardBackend::Command`, and returns the backend that took it. When
+/// the desktop clipboard fails, as over SSH or on Wayland without a
+/// clipboard portal, the copy goes out as OSC 52 instead. A failing command
+/// is an error: it was chosen on purpose.
+pub(crate) fn set_clipboard(
+    backend: ClipboardBackend,
+    command: Option<&str>,
+    text: &str,
+) -> Result<ClipboardBackend, PmtError> {
+    match backend {
+        ClipboardBackend::Command => {
+            let command = command.unwrap_or_default();
+            copy_with_command(command, text).map(|_| ClipboardBackend::Command)
+        }
+        ClipboardBackend::System => {
+            let copied = Clipboard::new().and_then(|mut cb| cb.set_text(text.to_string()));
+            match copied {
+                Ok(()) => Ok(ClipboardBackend::System),
+                Err(err) => match write_osc52(text) {
+                    Ok(()) => Ok(ClipboardBackend::Osc52),
+                    Err(_) => Err(PmtError::Clipboard(tr!(
+                        "复制失败: {err}",
+                        "Copy failed: {err}"
+                    ))),
+                },
+            }
+        }
+        ClipboardBackend::Osc52 => write_osc52(text)
+            .map(|_| ClipboardBackend::Osc52)
+            .map_err(|err| PmtError::Clipboard(tr!("复制失败: {err}", "Copy failed: {err}"))),
+        // Captured copies never reach the system.
+        ClipboardBackend::Capture => Ok(ClipboardBackend::Capture),
+    }
+}
+
+/// Pipes `text` into the shell command `command`. Its output is not
+/// captured: `xclip` and the like stay behind holding it until another
+/// program takes the clipboard.
+fn copy_with_command(command: &str, text: &str) -> Result<(), PmtError> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    let failed = |err: io::Error| {
+        PmtError::Clipboard(tr!(
+            "剪贴板命令失败: {command} ({err})",
+            "Clipboard command failed: {command} ({err})"
+        ))
+    };
+    let mut child = shell
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(failed)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // Closing stdin tells the command the text is complete.
+    let written = stdin.write_all(text.as_bytes());
+    drop(stdin);
+    let status = child.wait().map_err(failed)?;
+    written.map_err(failed)?;
     if !status.success() {
-        return Err(format!("编辑器退出异常: {status}"));
+        return Err(PmtError::Clipboard(tr!(
+            "剪贴板命令失败: {command} ({status})",
+            "Clipboard command failed: {command} ({status})"
+        )));
     }
     Ok(())
 }
 
-pub(crate) fn set_clipboard(text: &str) -> Result<(), String> {
-    Clipboard::new()
-        .and_then(|mut cb| cb.set_text(text.to_string()))
-        .map_err(|err| format!("复制失败: {err}"))
+fn write_osc52(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(osc52_sequence(text).as_bytes())?;
+    stdout.flush()
 }
 
-fn prompts_path() -> Option<PathBuf> {
-    let home = env::var_os("USERPROFILE")
+/// The escape sequence setting the terminal's clipboard to `text`.
+fn osc52_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", BASE64.encode(text))
+}
+
+pub(crate) fn load_draft(template: &str) -> Option<Vec<(String, String)>> {
+    let drafts = read_json_object(drafts_path());
+    let values = values_from_json(drafts.get(template)?);
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
+}
+
+pub(crate) fn save_draft(template: &str, values: &[(String, String)]) -> Result<(), PmtError> {
+    let mut drafts = read_json_object(drafts_path());
+    drafts.insert(template.to_string(), values_to_json(values));
+    write_json_object(
+        drafts_path(),
+        &drafts,
+        pick("保存草稿失败", "Failed to save drafts"),
+    )
+}
+
+pub(crate) fn remove_draft(template: &str) -> Result<(), PmtError> {
+    let mut drafts = read_json_object(drafts_path());
+    if drafts.remove(template).is_none() {
+        return Ok(());
+    }
+    write_json_object(
+        drafts_path(),
+        &drafts,
+        pick("保存草稿失败", "Failed to save drafts"),
+    )
+}
+
+pub(crate) fn load_last_values(template: &str) -> Option<Vec<(String, String)>> {
+    let last = read_json_object(last_values_path());
+    let values = values_from_json(last.get(template)?);
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
+}
+
+pub(crate) fn save_last_values(
+    template: &str,
+    values: &[(String, String)],
+) -> Result<(), PmtError> {
+    let mut last = read_json_object(last_values_path());
+    last.insert(template.to_string(), values_to_json(values));
+    write_json_object(
+        last_values_path(),
+        &last,
+        pick("保存上次的值失败", "Failed to save last values"),
+    )
+}
+
+pub(crate) fn remove_last_values(template: &str) -> Result<(), PmtError> {
+    let mut last = read_json_object(last_values_path());
+    if last.remove(template).is_none() {
+        return Ok(());
+    }
+    write_json_object(
+        last_values_path(),
+        &last,
+        pick("保存上次的值失败", "Failed to save last values"),
+    )
+}
+
+/// Values from `globals.toml` that fill fields of the same name in every
+/// template. A missing file means no globals.
+pub(crate) fn load_globals() -> Result<Vec<(String, String)>, PmtError> {
+    let path = globals_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(PmtError::Io(tr!(
+                "读取失败: {} ({err})",
+                "Failed to read: {} ({err})",
+                path.display()
+            )));
+        }
+    };
+    let table: toml::Table = toml::from_str(&content).map_err(|err| {
+        PmtError::Config(tr!(
+            "全局变量文件有误: {} ({err})",
+            "Invalid globals file: {} ({err})",
+            path.display()
+        ))
+    })?;
+    table
+        .into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::String(text) => Ok((name, text)),
+            toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                Ok((name, value.to_string()))
+            }
+            _ => Err(PmtError::Config(tr!(
+                "全局变量文件有误: {} ({name} 必须是字符串)",
+                "Invalid globals file: {} ({name} must be a string)",
+                path.display()
+            ))),
+        })
+        .collect()
+}
+
+/// Presets of `template`, sorted by name.
+pub(crate) fn load_presets(template: &str) -> Vec<Preset> {
+    let presets = read_json_object(presets_path());
+    let entries = match presets.get(template).and_then(Value::as_object) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+    entries
+        .iter()
+        .map(|(name, values)| Preset {
+            name: name.clone(),
+            values: values_from_json(values),
+        })
+        .collect()
+}
+
+/// Saves `preset`, replacing a preset of the same name.
+pub(crate) fn save_preset(template: &str, preset: &Preset) -> Result<(), PmtError> {
+    let mut presets = read_json_object(presets_path());
+    let entry = presets
+        .entry(template.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+    }
+    if let Value::Object(entries) = entry {
+        entries.insert(preset.name.clone(), values_to_json(&preset.values));
+    }
+    write_json_object(
+        presets_path(),
+        &presets,
+        pick("保存预设失败", "Failed to save presets"),
+    )
+}
+
+pub(crate) fn remove_preset(template: &str, name: &str) -> Result<(), PmtError> {
+    let mut presets = read_json_object(presets_path());
+    let removed = presets
+        .get_mut(template)
+        .and_then(Value::as_object_mut)
+        .and_then(|entries| entries.remove(name));
+    if removed.is_none() {
+        return Ok(());
+    }
+    if presets
+        .get(template)
+        .and_then(Value::as_object)
+        .is_some_and(Map::is_empty)
+    {
+        presets.remove(template);
+    }
+    write_json_object(
+        presets_path(),
+        &presets,
+        pick("保存预设失败", "Failed to save presets"),
+    )
+}
+
+/// Width of the editor's field column last set with Ctrl+Left/Right or by
+/// dragging the divider.
+pub(crate) fn load_split() -> Option<u16> {
+    let layout = read_json_object(layout_path());
+    let split = layout.get("split")?.as_u64()?;
+    u16::try_from(split).ok()
+}
+
+pub(crate) fn save_split(split: u16) -> Result<(), PmtError> {
+    let mut layout = read_json_object(layout_path());
+    layout.insert("split".to_string(), Value::from(split));
+    write_json_object(
+        layout_path(),
+        &layout,
+        pick("保存布局失败", "Failed to save the layout"),
+    )
+}
+
+/// Uses of templates in the order they happened. Lines that do not parse
+/// are skipped.
+pub(crate) fn load_usage() -> Vec<UsageEntry> {
+    let content = match usage_path().and_then(|path| fs::read_to_string(path).ok()) {
+        Some(content) => content,
+        None => return Vec::new(),
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub(crate) fn record_use(entry: &UsageEntry) -> Result<(), PmtError> {
+    let path = usage_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    let failure = pick("保存使用记录失败", "Failed to save the usage log");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| PmtError::Io(format!("{failure}: {} ({err})", parent.display())))?;
+    }
+    let line =
+        serde_json::to_string(entry).map_err(|err| PmtError::Io(format!("{failure}: {err}")))?;
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{line}"))
+        .map_err(|err| PmtError::Io(format!("{failure}: {} ({err})", path.display())))
+}
+
+/// Appends `text` to the journal at `path` under a `## <time> <template>`
+/// heading, creating the file and its folders if missing.
+pub(crate) fn append_journal(
+    path: &Path,
+    template: &str,
+    text: &str,
+    time: DateTime<Local>,
+) -> Result<(), PmtError> {
+    let home = config_dir().unwrap_or_default();
+    let path = resolve_include(&home, &path.to_string_lossy());
+    let failure = pick("写入日志失败", "Failed to write the journal");
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| PmtError::Io(format!("{failure}: {} ({err})", parent.display())))?;
+    }
+    let time = time.format("%Y-%m-%d %H:%M:%S");
+    let text = text.trim_end_matches(['\n', '\r']);
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| write!(file, "## {time} {template}\n\n{text}\n\n"))
+        .map_err(|err| PmtError::Io(format!("{failure}: {} ({err})", path.display())))
+}
+
+/// Moves the uses of a renamed template to its new name.
+pub(crate) fn rename_usage(from: &str, to: &str) -> Result<(), PmtError> {
+    let mut usage = load_usage();
+    if !usage.iter().any(|entry| entry.template == from) {
+        return Ok(());
+    }
+    for entry in &mut usage {
+        if entry.template == from {
+            entry.template = to.to_string();
+        }
+    }
+    let path = usage_path().ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    let content: String = usage
+        .iter()
+        .filter_map(|entry| serde_json::to_string(entry).ok())
+        .map(|line| line + "\n")
+        .collect();
+    fs::write(&path, content).map_err(|err| {
+        PmtError::Io(format!(
+            "{}: {} ({err})",
+            pick("保存使用记录失败", "Failed to save the usage log"),
+            path.display()
+        ))
+    })
+}
+
+fn values_from_json(value: &Value) -> Vec<(String, String)> {
+    value
+        .as_object()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn values_to_json(values: &[(String, String)]) -> Value {
+    Value::Object(
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+            .collect(),
+    )
+}
+
+fn read_json_object(path: Option<PathBuf>) -> Map<String, Value> {
+    path.and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|value| match value {
+            Value::Object(map) => Some(map),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn write_json_object(
+    path: Option<PathBuf>,
+    object: &Map<String, Value>,
+    failure: &str,
+) -> Result<(), PmtError> {
+    let path = path.ok_or_else(|| {
+        PmtError::Config(tr!("无法定位用户目录", "Cannot locate the home directory"))
+    })?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| {
+            PmtError::Io(tr!(
+                "创建目录失败: {} ({err})",
+                "Failed to create directory: {} ({err})",
+                parent.display()
+            ))
+        })?;
+    }
+    let content = serde_json::to_string_pretty(object)
+        .map_err(|err| PmtError::Io(format!("{failure}: {err}")))?;
+    fs::write(&path, content)
+        .map_err(|err| PmtError::Io(format!("{failure}: {} ({err})", path.display())))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("USERPROFILE")
         .or_else(|| env::var_os("HOME"))
-        .map(PathBuf::from)?;
-    Some(home.join(".config").join("pmt").join("prompts.md"))
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Config directory of the test running on this thread.
+    static TEST_CONFIG_DIR: std::cell::RefCell<Option<PathBuf>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// An empty directory that stands in for the config directory on this
+/// thread, so that tests of files pmt reads and writes run side by side.
+/// While it is set `--file`, `PMT_PROMPTS` and the project's prompts file
+/// are ignored. Dropping it removes the directory.
+#[cfg(test)]
+pub(crate) struct TestConfigDir(PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for TestConfigDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestConfigDir {
+    fn drop(&mut self) {
+        TEST_CONFIG_DIR.with(|dir| dir.borrow_mut().take());
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_config_dir(name: &str) -> TestConfigDir {
+    let dir = env::temp_dir().join(format!("pmt-test-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("the temp directory is writable");
+    TEST_CONFIG_DIR.with(|test_dir| *test_dir.borrow_mut() = Some(dir.clone()));
+    TestConfigDir(dir)
+}
+
+/// Whether a test on this thread stands in its own config directory.
+fn in_test_config_dir() -> bool {
+    #[cfg(test)]
+    return TEST_CONFIG_DIR.with(|dir| dir.borrow().is_some());
+    #[cfg(not(test))]
+    false
+}
+
+/// `$XDG_CONFIG_HOME/pmt`, or `~/.config/pmt` when it is unset or not an
+/// absolute path.
+fn config_dir() -> Option<PathBuf> {
+    #[cfg(test)]
+    if let Some(dir) = TEST_CONFIG_DIR.with(|dir| dir.borrow().clone()) {
+        return Some(dir);
+    }
+    let base = match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        Some(base) if base.is_absolute() => base,
+        _ => home_dir()?.join(".config"),
+    };
+    Some(base.join("pmt"))
+}
+
+/// Uses `path` as the prompts file for this run, ahead of `PMT_PROMPTS`
+/// and the config.
+pub(crate) fn set_prompts_file(path: PathBuf) {
+    let _ = PROMPTS_FILE.set(path);
+}
+
+/// Prompts file from `--file` or `PMT_PROMPTS`, relative to the working
+/// directory. It stands in for both the prompts file and the prompts
+/// directory, so a project's own file is the whole personal library.
+fn chosen_prompts_file() -> Option<PathBuf> {
+    if in_test_config_dir() {
+        return None;
+    }
+    let cwd = env::current_dir().unwrap_or_default();
+    choose_prompts_file(PROMPTS_FILE.get(), env::var_os(PROMPTS_ENV), &cwd)
+}
+
+/// `flag`, else a non-empty `env_value`, resolved against `cwd`.
+fn choose_prompts_file(
+    flag: Option<&PathBuf>,
+    env_value: Option<OsString>,
+    cwd: &Path,
+) -> Option<PathBuf> {
+    let path = match flag {
+        Some(path) => path.clone(),
+        None => PathBuf::from(env_value.filter(|value| !value.is_empty())?),
+    };
+    Some(resolve_include(cwd, &path.to_string_lossy()))
+}
+
+/// The chosen prompts file, `[prompts] path` from the config, or
+/// `prompts.md` in the config directory. A broken config falls back to the
+/// default; `load_config` reports the error at startup.
+fn prompts_path() -> Option<PathBuf> {
+    chosen_prompts_file().or_else(personal_prompts_path)
+}
+
+/// `[prompts] path` from the config, or `prompts.md` in the config
+/// directory, whatever file was chosen for this run.
+fn personal_prompts_path() -> Option<PathBuf> {
+    let dir = config_dir()?;
+    match read_config().ok().and_then(|config| config.prompts.path) {
+        Some(path) => Some(resolve_include(&dir, &path.to_string_lossy())),
+        None => Some(dir.join("prompts.md")),
+    }
+}
+
+/// `[prompts] dir` from the config, or `prompts` in the config directory.
+/// None when a prompts file was chosen for this run.
+fn prompts_dir() -> Option<PathBuf> {
+    if chosen_prompts_file().is_some() {
+        return None;
+    }
+    let dir = config_dir()?;
+    match read_config().ok().and_then(|config| config.prompts.dir) {
+        Some(path) => Some(resolve_include(&dir, &path.to_string_lossy())),
+        None => Some(dir.join("prompts")),
+    }
+}
+
+/// The prompts directory when it exists and the prompts file does not.
+fn only_prompts_dir() -> Option<PathBuf> {
+    if prompts_path().is_some_and(|path| path.exists()) {
+        return None;
+    }
+    prompts_dir().filter(|dir| dir.is_dir())
+}
+
+fn usage_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("usage.jsonl"))
+}
+
+fn drafts_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("drafts.json"))
+}
+
+fn last_values_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("last_values.json"))
+}
+
+fn layout_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("layout.json"))
+}
+
+fn globals_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("globals.toml"))
+}
+
+fn presets_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("presets.json"))
+}
+
+fn config_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Watches template files for changes made outside pmt. Directories are
+/// watched rather than the files themselves, since many editors save by
+/// replacing the file.
+pub(crate) struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<(PathBuf, OsString)>,
+}
+
+impl FileWatcher {
+    pub(crate) fn new() -> Result<Self, PmtError> {
+        let (sender, events) = channel();
+        let watcher = notify::recommended_watcher(sender).map_err(|err| {
+            PmtError::Io(tr!(
+                "无法监听文件变更: {err}",
+                "Cannot watch for file changes: {err}"
+            ))
+        })?;
+        Ok(Self {
+            watcher,
+            events,
+            files: Vec::new(),
+        })
+    }
+
+    /// Adds `files` to the watch list; files already watched are skipped.
+    pub(crate) fn watch(&mut self, files: &[PathBuf]) {
+        for file in files {
+            let Some(key) = watch_key(file) else {
+                continue;
+            };
+            if self.files.contains(&key) {
+                continue;
+            }
+            let dir_watched = self.files.iter().any(|(dir, _)| *dir == key.0);
+            if dir_watched
+                || self
+                    .watcher
+                    .watch(&key.0, RecursiveMode::NonRecursive)
+                    .is_ok()
+            {
+                self.files.push(key);
+            }
+        }
+    }
+
+    /// Drains pending events; true when any of them touched a watched file.
+    pub(crate) fn changed(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            changed |= event
+                .paths
+                .iter()
+                .filter_map(|path| watch_key(path))
+                .any(|key| self.files.contains(&key));
+        }
+        changed
+    }
+}
+
+/// Canonical parent directory and file name, so paths reported by the
+/// watcher compare equal to the ones templates were loaded from even when
+/// the file itself was just removed.
+fn watch_key(path: &Path) -> Option<(PathBuf, OsString)> {
+    let name = path.file_name()?.to_os_string();
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some((parent.canonicalize().ok()?, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FieldKind, OptionPools};
+    use crate::parser::{collect_fields, parse_tokens, render_template};
+
+    fn lines(content: &str) -> Vec<String> {
+        content.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn sections_of_the_same_name_are_swapped_by_their_line() {
+        let library = "## 示例/问候\n一\n\n## 示例/问候\n二\n";
+        let mut templates = parse_templates(library);
+        disambiguate(&mut templates);
+        let lines = lines(library);
+        let swapped = swap_sections(&lines, &templates[0], &templates[1]).unwrap();
+        assert_eq!(swapped.join("\n"), "## 示例/问候\n二\n\n## 示例/问候\n一");
+        let mut moved = templates[1].clone();
+        moved.line = 2;
+        assert!(swap_sections(&lines, &templates[0], &moved).is_err());
+    }
+
+    #[test]
+    fn lower_layers_only_add_names_no_higher_layer_has() {
+        let layer = |content: &str, layer: Layer| -> Vec<Template> {
+            parse_templates(content)
+                .into_iter()
+                .map(|template| Template { layer, ..template })
+                .collect()
+        };
+        let mut templates = layer("## 甲\n一\n", Layer::Personal);
+        let mut shared = layer("## 甲\n二\n\n## 乙\n三\n", Layer::Team);
+        shared.extend(layer("## 乙\n四\n\n## 丙\n五\n", Layer::System));
+        merge_layers(&mut templates, shared);
+
+        let merged: Vec<(&str, Layer, Option<Layer>)> = templates
+            .iter()
+            .map(|t| (t.name.as_str(), t.layer, t.overrides))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("甲", Layer::Personal, Some(Layer::Team)),
+                ("乙", Layer::Team, Some(Layer::System)),
+                ("丙", Layer::System, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_and_remote_templates_are_refused_with_exit_code_3() {
+        let personal = parse_templates("## 甲\n一\n").remove(0);
+        let team = Template {
+            layer: Layer::Team,
+            ..personal.clone()
+        };
+        let err = replace_template_body(&team, "二", 0).unwrap_err();
+        assert!(matches!(err, PmtError::Validation(_)));
+        assert_eq!(crate::cli::CliError::from(err).exit_code(), 3);
+
+        let remote = Template {
+            layer: Layer::Remote,
+            remote: Some("https://example.com/prompts.md".to_string()),
+            ..personal
+        };
+        let err = rename_template(&remote, "乙", 0).unwrap_err();
+        assert!(matches!(err, PmtError::Validation(_)));
+    }
+
+    #[test]
+    fn nested_sections_move_with_their_children() {
+        let library = "# 评审\n\n## 代码\n检查\n\n### 安全\n列出\n\n## 文档\n改进\n";
+        let templates = parse_nested_templates(library);
+        let lines = lines(library);
+        let swapped = swap_sections(&lines, &templates[0], &templates[2]).unwrap();
+        assert_eq!(
+            swapped.join("\n"),
+            "# 评审\n\n## 文档\n改进\n\n## 代码\n检查\n\n### 安全\n列出"
+        );
+        assert!(matches!(
+            swap_sections(&lines, &templates[1], &templates[2]),
+            Err(PmtError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn the_prompts_directory_is_read_as_folders_of_templates() {
+        let dir = test_config_dir("prompts-dir");
+        let prompts = dir.join("prompts");
+        fs::create_dir_all(prompts.join("写作")).unwrap();
+        fs::write(prompts.join("写作").join("邮件.md"), "给 {name} 写邮件\n").unwrap();
+        fs::write(
+            prompts.join("代码.md"),
+            "## 评审\n检查\n\n## 测试\n补测试\n",
+        )
+        .unwrap();
+        fs::write(prompts.join(".草稿.md"), "隐藏\n").unwrap();
+        fs::write(prompts.join("说明.txt"), "不是模板\n").unwrap();
+        fs::write(prompts.join("空.md"), "\n").unwrap();
+
+        let (templates, warnings) = load_library().unwrap();
+        assert!(warnings.is_empty());
+        let names: Vec<(&str, bool)> = templates
+            .iter()
+            .map(|t| (t.name.as_str(), t.whole_file))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("评审", false), ("测试", false), ("写作/邮件", true)]
+        );
+        assert_eq!(templates[2].body, "给 {name} 写邮件");
+        assert!(!dir.join("prompts.md").exists());
+    }
+
+    #[test]
+    fn team_and_system_layers_sit_below_personal_templates() {
+        let dir = test_config_dir("layers");
+        fs::write(dir.join("prompts.md"), "## 甲\n个人\n").unwrap();
+        fs::create_dir_all(dir.join("team")).unwrap();
+        fs::write(dir.join("team").join("甲.md"), "团队\n").unwrap();
+        fs::write(dir.join("team").join("乙.md"), "团队\n").unwrap();
+        fs::create_dir_all(dir.join("system")).unwrap();
+        fs::write(
+            dir.join("system").join("prompts.md"),
+            "## 乙\n系统\n\n## 丙\n系统\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            format!(
+                "[prompts]\nteam = [\"team\"]\nsystem = \"{}\"\n",
+                dir.join("system").display()
+            ),
+        )
+        .unwrap();
+
+        let (templates, warnings) = load_library().unwrap();
+        assert!(warnings.is_empty());
+        let layers: Vec<(&str, Layer, Option<Layer>)> = templates
+            .iter()
+            .map(|t| (t.name.as_str(), t.layer, t.overrides))
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                ("甲", Layer::Personal, Some(Layer::Team)),
+                ("乙", Layer::Team, Some(Layer::System)),
+                ("丙", Layer::System, None),
+            ]
+        );
+        assert_eq!(templates[1].source, dir.join("team").join("乙.md"));
+    }
+
+    #[test]
+    fn remote_templates_come_from_a_fresh_cache_read_only() {
+        let _dir = test_config_dir("remote");
+        let remote = RemoteSource {
+            source: "https://example.com/team/prompts.md".to_string(),
+            ..RemoteSource::default()
+        };
+        let cache = remote_cache_path(&remote.source).unwrap();
+        assert!(cache.ends_with("cache/https_3a_2f_2fexample.com_2fteam_2fprompts.md.md"));
+        assert_ne!(
+            remote_cache_path("https://a.b/c-d.md"),
+            remote_cache_path("https://a.b/c_d.md")
+        );
+        write_remote_cache(&cache, "## 团队/周报\n本周\n");
+
+        // A fresh copy is used as it is, without asking the source.
+        let mut templates = Vec::new();
+        load_remote(&remote, false, &mut templates, |_| panic!("fetched"));
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].name, "团队/周报");
+        assert_eq!(templates[0].layer, Layer::Remote);
+        assert_eq!(templates[0].source, cache);
+        assert_eq!(templates[0].remote.as_deref(), Some(remote.source.as_str()));
+        assert!(matches!(
+            replace_template_body(&templates[0], "改", 0),
+            Err(PmtError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn stale_remote_copies_are_fetched_again_or_kept_when_unreachable() {
+        let _dir = test_config_dir("remote-stale");
+        let remote = RemoteSource {
+            source: "https://example.com/team/prompts.md".to_string(),
+            ..RemoteSource::default()
+        };
+        let cache = remote_cache_path(&remote.source).unwrap();
+        write_remote_cache(&cache, "## 团队/周报\n本周\n");
+        let stale = SystemTime::now() - Duration::from_secs(remote.ttl + 60);
+        fs::File::options()
+            .write(true)
+            .open(&cache)
+            .and_then(|file| file.set_modified(stale))
+            .unwrap();
+
+        let mut templates = Vec::new();
+        load_remote(&remote, false, &mut templates, |_| {
+            Err(PmtError::Io("unreachable".to_string()))
+        });
+        assert_eq!(templates[0].body, "本周");
+
+        let mut templates = Vec::new();
+        load_remote(&remote, false, &mut templates, |_| {
+            Ok("## 团队/月报\n本月\n".to_string())
+        });
+        assert_eq!(templates[0].name, "团队/月报");
+        assert_eq!(fs::read_to_string(&cache).unwrap(), "## 团队/月报\n本月\n");
+    }
+
+    #[test]
+    fn appended_sections_take_the_heading_level_they_are_given() {
+        let dir = test_config_dir("append-nested");
+        // The settings come from the caller, not from a reread config.
+        fs::write(dir.join("config.toml"), "[prompts\n").unwrap();
+        let path = dir.join("prompts.md");
+        fs::write(&path, "# 评审\n\n## 代码\n检查\n").unwrap();
+        let added = [("写作/邮件".to_string(), "你好".to_string())];
+        append_templates_to(&path, &added, true, 0).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# 评审\n\n## 代码\n检查\n\n# 写作/邮件\n你好\n"
+        );
+    }
+
+    #[test]
+    fn backups_keep_the_newest_copies_of_the_prompts_file() {
+        let dir = test_config_dir("backups");
+        let prompts = dir.join("prompts.md");
+        fs::write(&prompts, "## 甲\n现在\n").unwrap();
+        let backups = dir.join("backups");
+        fs::create_dir_all(&backups).unwrap();
+        for stamp in ["20200101-000000", "20200201-000000", "20200301-000000"] {
+            fs::write(backups.join(format!("prompts-{stamp}.md")), stamp).unwrap();
+        }
+        fs::write(backups.join("notes.txt"), "").unwrap();
+
+        let other = dir.join("other.md");
+        fs::write(&other, "").unwrap();
+        backup_prompts_file(&other, 2).unwrap();
+        backup_prompts_file(&prompts, 0).unwrap();
+        assert_eq!(list_backups().len(), 3);
+
+        backup_prompts_file(&prompts, 2).unwrap();
+        let kept = list_backups();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(fs::read_to_string(&kept[0]).unwrap(), "## 甲\n现在\n");
+        assert_eq!(kept[1], backups.join("prompts-20200301-000000.md"));
+        assert!(backups.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn restore_refuses_a_prompts_file_the_backups_are_not_of() {
+        let dir = test_config_dir("restore-chosen");
+        fs::write(dir.join("prompts.md"), "## 新\n新\n").unwrap();
+        let backup = dir.join("backups").join("prompts-20200101-080000.md");
+        fs::create_dir_all(backup.parent().unwrap()).unwrap();
+        fs::write(&backup, "## 旧\n旧\n").unwrap();
+        let chosen = dir.join("project.md");
+        fs::write(&chosen, "## 项目\n项目\n").unwrap();
+
+        let err = restore_backup_over(&backup, Some(&chosen), 5).unwrap_err();
+        assert!(matches!(err, PmtError::Validation(_)));
+        assert_eq!(fs::read_to_string(&chosen).unwrap(), "## 项目\n项目\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("prompts.md")).unwrap(),
+            "## 新\n新\n"
+        );
+
+        // The personal file chosen by its own path is restored as usual.
+        restore_backup_over(&backup, Some(&dir.join("prompts.md")), 5).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("prompts.md")).unwrap(),
+            "## 旧\n旧\n"
+        );
+    }
+
+    #[test]
+    fn the_flag_comes_before_the_environment_and_the_config() {
+        let cwd = Path::new("/work");
+        let flag = PathBuf::from("a.md");
+        assert_eq!(
+            choose_prompts_file(Some(&flag), Some("b.md".into()), cwd),
+            Some(PathBuf::from("/work/a.md"))
+        );
+        assert_eq!(
+            choose_prompts_file(None, Some("/srv/b.md".into()), cwd),
+            Some(PathBuf::from("/srv/b.md"))
+        );
+        assert_eq!(choose_prompts_file(None, Some("".into()), cwd), None);
+        assert_eq!(choose_prompts_file(None, None, cwd), None);
+
+        let dir = test_config_dir("prompts-path");
+        assert_eq!(prompts_path(), Some(dir.join("prompts.md")));
+        fs::write(
+            dir.join("config.toml"),
+            "[prompts]\npath = \"notes/模板.md\"\n",
+        )
+        .unwrap();
+        assert_eq!(prompts_path(), Some(dir.join("notes").join("模板.md")));
+    }
+
+    #[test]
+    fn project_prompts_are_found_above_the_working_directory() {
+        let repo = test_config_dir("project");
+        let deeper = repo.join("src").join("deeper");
+        fs::create_dir_all(&deeper).unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        assert_eq!(find_project_prompts(&deeper), None);
+
+        fs::write(repo.join("prompts.md"), "## 甲\n一\n").unwrap();
+        assert_eq!(find_project_prompts(&deeper), Some(repo.join("prompts.md")));
+
+        let hidden = repo.join("src").join(".pmt");
+        fs::create_dir_all(&hidden).unwrap();
+        fs::write(hidden.join("prompts.md"), "## 乙\n二\n").unwrap();
+        assert_eq!(
+            find_project_prompts(&deeper),
+            Some(hidden.join("prompts.md"))
+        );
+        assert_eq!(find_project_prompts(&repo), Some(repo.join("prompts.md")));
+
+        let mut templates = Vec::new();
+        load_project(
+            &hidden.join("prompts.md"),
+            false,
+            &mut templates,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(templates[0].name, "项目/乙");
+        assert_eq!(templates[0].layer, Layer::Personal);
+    }
+
+    #[test]
+    fn osc52_sends_the_text_base64_encoded() {
+        assert_eq!(osc52_sequence("hi"), "\x1b]52;c;aGk=\x07");
+        assert_eq!(osc52_sequence("你好"), "\x1b]52;c;5L2g5aW9\x07");
+        assert_eq!(osc52_sequence(""), "\x1b]52;c;\x07");
+    }
+
+    #[test]
+    fn a_clipboard_command_gets_the_text_on_stdin() {
+        let dir = test_config_dir("clipboard-command");
+        let target = dir.join("clipboard.txt");
+        let command = format!("cat > '{}'", target.display());
+        let backend = detect_clipboard_backend(Some(&command));
+        assert_eq!(backend, ClipboardBackend::Command);
+        let used = set_clipboard(backend, Some(&command), "你好\n世界").unwrap();
+        assert_eq!(used, ClipboardBackend::Command);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "你好\n世界");
+
+        let err = set_clipboard(backend, Some("exit 3"), "文本").unwrap_err();
+        assert!(matches!(err, PmtError::Clipboard(_)));
+        assert!(err.to_string().contains("剪贴板命令失败: exit 3"));
+    }
+
+    #[test]
+    fn file_placeholders_read_their_file_or_report_it() {
+        let dir = test_config_dir("file-tokens");
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "会议纪要").unwrap();
+        let missing = dir.join("missing.txt");
+        let body = format!(
+            "{{file|{}}} {{file|{}}}",
+            notes.display(),
+            missing.display()
+        );
+        let mut tokens = parse_tokens(&body, &OptionPools::new());
+        let errors = load_file_tokens(&mut tokens);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with(&format!("读取失败: {}", missing.display())));
+        let fields = collect_fields(&tokens);
+        assert!(matches!(fields[1].kind, FieldKind::File { .. }));
+        assert_eq!(
+            render_template(&tokens, &fields),
+            format!("会议纪要 {{file|{}}}", missing.display())
+        );
+    }
 }
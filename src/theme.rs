@@ -0,0 +1,95 @@
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// Colors used by the TUI. Each role is one color so that a theme can be
+/// swapped without touching the views.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Theme {
+    /// Selected row in lists.
+    pub(crate) selection_fg: Color,
+    pub(crate) selection_bg: Color,
+    /// Borders of the focused field.
+    pub(crate) focus: Color,
+    /// Unfocused borders, hints and secondary text.
+    pub(crate) muted: Color,
+    /// Popup borders and pinned fields.
+    pub(crate) accent: Color,
+    /// Informational popups.
+    pub(crate) info: Color,
+    pub(crate) success: Color,
+    pub(crate) error: Color,
+    /// Picks that changed on reroll.
+    pub(crate) flash_fg: Color,
+    pub(crate) flash_bg: Color,
+    /// Placeholders still waiting for a value in the preview.
+    pub(crate) unfilled_fg: Color,
+    pub(crate) unfilled_bg: Color,
+    /// The bottom bar.
+    pub(crate) status: Color,
+    /// Preview text.
+    pub(crate) text: Color,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+impl ThemeName {
+    pub(crate) fn theme(self) -> Theme {
+        match self {
+            Self::Dark => Theme {
+                selection_fg: Color::White,
+                selection_bg: Color::Blue,
+                focus: Color::Blue,
+                muted: Color::DarkGray,
+                accent: Color::Yellow,
+                info: Color::Cyan,
+                success: Color::Green,
+                error: Color::Red,
+                flash_fg: Color::Black,
+                flash_bg: Color::Yellow,
+                unfilled_fg: Color::Black,
+                unfilled_bg: Color::LightRed,
+                status: Color::DarkGray,
+                text: Color::Reset,
+            },
+            Self::Light => Theme {
+                selection_fg: Color::Black,
+                selection_bg: Color::Rgb(0xb4, 0xd5, 0xff),
+                focus: Color::Rgb(0x00, 0x50, 0xc8),
+                muted: Color::Rgb(0x60, 0x60, 0x60),
+                accent: Color::Rgb(0xa0, 0x64, 0x00),
+                info: Color::Rgb(0x00, 0x6e, 0x8c),
+                success: Color::Rgb(0x00, 0x78, 0x00),
+                error: Color::Rgb(0xb4, 0x00, 0x00),
+                flash_fg: Color::Black,
+                flash_bg: Color::Rgb(0xff, 0xdc, 0x78),
+                unfilled_fg: Color::Black,
+                unfilled_bg: Color::Rgb(0xff, 0xb4, 0xb4),
+                status: Color::Rgb(0x60, 0x60, 0x60),
+                text: Color::Reset,
+            },
+            Self::HighContrast => Theme {
+                selection_fg: Color::Black,
+                selection_bg: Color::Yellow,
+                focus: Color::Yellow,
+                muted: Color::White,
+                accent: Color::LightYellow,
+                info: Color::LightCyan,
+                success: Color::LightGreen,
+                error: Color::LightRed,
+                flash_fg: Color::Black,
+                flash_bg: Color::White,
+                unfilled_fg: Color::Black,
+                unfilled_bg: Color::LightRed,
+                status: Color::White,
+                text: Color::White,
+            },
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use serde::Deserialize;
+
+/// How the editor approximates the token count of the rendered prompt.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TokenEstimator {
+    /// One token per four characters.
+    Chars,
+    /// Splits the text roughly like a BPE tokenizer: one token per CJK
+    /// character or punctuation mark, one per four letters of a word.
+    #[default]
+    Words,
+}
+
+pub(crate) fn estimate_tokens(text: &str, estimator: TokenEstimator) -> usize {
+    match estimator {
+        TokenEstimator::Chars => text.chars().count().div_ceil(4),
+        TokenEstimator::Words => estimate_words(text),
+    }
+}
+
+fn estimate_words(text: &str) -> usize {
+    let mut tokens = 0;
+    let mut word: usize = 0;
+    for ch in text.chars() {
+        if ch.is_alphanumeric() && !is_cjk(ch) {
+            word += 1;
+            continue;
+        }
+        tokens += word.div_ceil(4);
+        word = 0;
+        if !ch.is_whitespace() {
+            tokens += 1;
+        }
+    }
+    tokens + word.div_ceil(4)
+}
+
+/// Words of `text`: runs of letters and digits, with every CJK character
+/// counted as a word of its own.
+pub(crate) fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    let mut in_word = false;
+    for ch in text.chars() {
+        if is_cjk(ch) {
+            words += 1;
+            in_word = false;
+        } else if ch.is_alphanumeric() {
+            words += usize::from(!in_word);
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+    words
+}
+
+/// Han, kana and hangul, which BPE vocabularies rarely merge.
+fn is_cjk(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{3040}'..='\u{30ff}'
+            | '\u{3400}'..='\u{4dbf}'
+            | '\u{4e00}'..='\u{9fff}'
+            | '\u{ac00}'..='\u{d7af}'
+            | '\u{f900}'..='\u{faff}'
+    )
+}
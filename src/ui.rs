@@ -1,13 +1,39 @@
+use std::time::Instant;
+
+use chrono::{DateTime, Local};
 use ratatui::Frame;
-use ratatui::layout::{Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Style};
-use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::layout::{Constraint, Direction, Layout, Margin, Rect};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{
+    Block, Clear, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
+    ScrollbarState, Wrap,
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::app::{App, EditorState, View};
-use crate::models::{FieldKind, Token, TreeItem};
-use crate::parser::render_template;
+use crate::app::{
+    Answer, App, EditorState, FormFocus, NameAction, NamePrompt, OptionPicker, PresetPicker,
+    REROLL_HIGHLIGHT_MS, RenamePrompt, StatusMessage, TagPicker, View,
+};
+use crate::config::{Config, FooterWidget, KeyAction};
+use crate::cursor::{VimMode, byte_index};
+use crate::locale::{pick, tr};
+use crate::markdown::style_markdown;
+use crate::models::{
+    Field, FieldKind, Layer, LoadWarning, Segment, SegmentKind, Template, Token, TreeItem, TreeLine,
+};
+use crate::parser::{render_segments, render_template_at};
+use crate::platform::Platform;
+use crate::quality::{QualityReport, template_description};
+use crate::sanitize::escape_control;
+use crate::stats::{format_row, stats_header};
+use crate::store::{StateStore, TemplateSource};
+use crate::system::{ClipboardBackend, PROJECT_FOLDER};
+use crate::theme::Theme;
+use crate::tokens::{count_words, estimate_tokens};
 
-const STATUS_DURATION_MS: u128 = 1500;
+const MAX_FIELD_LINES: usize = 6;
 const ICON_FOLDER: &str = "";
 const ICON_TEMPLATE: &str = "󰈙";
 const SELECTED_MARKER: &str = " ";
@@ -16,45 +42,495 @@ const TREE_BRANCH: &str = "├─ ";
 const TREE_LAST: &str = "└─ ";
 const TREE_PIPE: &str = "│  ";
 const TREE_EMPTY: &str = "   ";
+const TREE_BRANCH_NARROW: &str = "├";
+const TREE_LAST_NARROW: &str = "└";
+const TREE_PIPE_NARROW: &str = "│";
+const TREE_EMPTY_NARROW: &str = " ";
 
-pub(crate) fn render_app(frame: &mut Frame, app: &mut App) {
+/// Draws `app` as it looks at `now`, the platform's clock: status messages
+/// and the reroll highlight last a while from when they were set.
+pub(crate) fn render_app<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+    now: Instant,
+) {
     match app.view {
-        View::List => render_list(frame, app),
-        View::Editor => render_editor(frame, app),
+        View::List => render_list(frame, app, now),
+        View::Editor => render_editor(frame, app, now),
+        View::Form => render_form(frame, app, now),
         View::Error => render_error(frame, app),
     }
+    if app.show_help {
+        render_help(frame, app);
+    }
+}
+
+/// Key bindings grouped by view, with remappable actions shown as
+/// configured.
+fn help_sections(config: &Config) -> Vec<(&'static str, Vec<(String, &'static str)>)> {
+    let keys = &config.keys;
+    let key = |action| keys.label(action);
+    let fixed = |text: &str| text.to_string();
+    vec![
+        (
+            pick("模板列表", "Template list"),
+            vec![
+                (fixed("↑↓ / j k"), pick("移动", "Move")),
+                (
+                    fixed("PgUp PgDn / Ctrl+U Ctrl+D"),
+                    pick("翻一页 / 半页", "Move a page / half a page"),
+                ),
+                (
+                    fixed("Home End"),
+                    pick("第一项 / 最后一项", "First / last item"),
+                ),
+                (fixed("Enter / 双击"), pick("打开模板", "Open the template")),
+                (
+                    fixed("/"),
+                    pick(
+                        "搜索路径和正文，Esc 退出搜索",
+                        "Search paths and bodies, Esc leaves search",
+                    ),
+                ),
+                (
+                    fixed("p"),
+                    pick(
+                        "在右侧显示所选模板的正文",
+                        "Show the selected template's body on the right",
+                    ),
+                ),
+                (fixed("n"), pick("新建模板", "New template")),
+                (
+                    fixed("E"),
+                    pick("修改所选模板的正文", "Edit the selected template's body"),
+                ),
+                (
+                    fixed("d"),
+                    pick("复制为新名称", "Duplicate under a new name"),
+                ),
+                (
+                    fixed("r"),
+                    pick(
+                        "重命名，带目录时移动过去",
+                        "Rename; a name with folders moves it there",
+                    ),
+                ),
+                (fixed("t"), pick("按标签筛选", "Filter by tag")),
+                (
+                    fixed("m"),
+                    pick(
+                        "移动模板：↑↓ 换位，Enter/Esc 放下",
+                        "Move the template: ↑↓ reorder, Enter/Esc drop",
+                    ),
+                ),
+                (
+                    key(KeyAction::OpenEditor),
+                    pick(
+                        "在 $EDITOR 中打开模板文件",
+                        "Open the templates file in $EDITOR",
+                    ),
+                ),
+                (
+                    fixed("F5 / Ctrl+L"),
+                    pick("从磁盘重新载入", "Reload from disk"),
+                ),
+                (
+                    fixed("D"),
+                    pick("超出阈值的模板", "Templates over the thresholds"),
+                ),
+                (
+                    fixed("A"),
+                    pick("为所选模板评分", "Score the selected template"),
+                ),
+                (
+                    fixed("S"),
+                    pick("各模板的使用统计", "Usage stats of every template"),
+                ),
+                (
+                    fixed(pick("其他字母", "Other letters")),
+                    pick("跳到名称匹配的模板", "Jump to a template by name"),
+                ),
+                (key(KeyAction::Quit), pick("退出", "Quit")),
+            ],
+        ),
+        (
+            pick("编辑器", "Editor"),
+            vec![
+                (fixed("Tab / ↑↓"), pick("切换字段", "Switch fields")),
+                (
+                    fixed("Enter"),
+                    pick(
+                        "换行；随机和选择字段打开选项列表",
+                        "Newline; opens the option list on random and select fields",
+                    ),
+                ),
+                (fixed("◀▶"), pick("切换选项", "Cycle options")),
+                (
+                    fixed("Space"),
+                    pick("固定或取消固定随机项", "Pin or unpin a random pick"),
+                ),
+                (
+                    fixed("PageUp/PageDown"),
+                    pick("滚动预览", "Scroll the preview"),
+                ),
+                (
+                    fixed("Ctrl+◀▶"),
+                    pick(
+                        "调整字段栏宽度，也可用鼠标拖动分隔线",
+                        "Resize the field column, or drag the divider",
+                    ),
+                ),
+                (key(KeyAction::Copy), pick("复制", "Copy")),
+                (
+                    fixed("Ctrl+Shift+C"),
+                    pick(
+                        "复制并退出，输出到 stdout",
+                        "Copy, quit and print to stdout",
+                    ),
+                ),
+                (
+                    key(KeyAction::CopyRecord),
+                    pick(
+                        "复制模板、变量和输出的记录",
+                        "Copy a record of template, variables and output",
+                    ),
+                ),
+                (
+                    key(KeyAction::Send),
+                    pick("发送到 [send] command", "Send to [send] command"),
+                ),
+                (
+                    key(KeyAction::Ask),
+                    pick("向 [llm] 提问", "Ask the [llm] endpoint"),
+                ),
+                (
+                    key(KeyAction::CopyAnswer),
+                    pick("复制回答", "Copy the answer"),
+                ),
+                (key(KeyAction::Reroll), pick("重随", "Reroll")),
+                (
+                    key(KeyAction::Seed),
+                    pick("按输入的种子重随", "Reroll from a typed seed"),
+                ),
+                (
+                    key(KeyAction::Select),
+                    pick("选择预览中的行", "Select lines of the preview"),
+                ),
+                (
+                    fixed("Ctrl+S / Ctrl+O"),
+                    pick("保存 / 载入预设", "Save / load presets"),
+                ),
+                (key(KeyAction::Undo), pick("撤销", "Undo")),
+                (key(KeyAction::Redo), pick("重做", "Redo")),
+                (
+                    key(KeyAction::ClearValues),
+                    pick("清空并忘记上次的值", "Clear and forget the last values"),
+                ),
+                (
+                    fixed("F2"),
+                    pick(
+                        "在所有模板中重命名变量",
+                        "Rename the variable in every template",
+                    ),
+                ),
+                (
+                    fixed("F6"),
+                    pick("运行命令占位符", "Run command placeholders"),
+                ),
+                (
+                    fixed("Esc"),
+                    pick(
+                        "关闭回答，再按返回列表",
+                        "Close the answer, again to go back to the list",
+                    ),
+                ),
+            ],
+        ),
+        (
+            pick(
+                "vim 模式 ([editor] vim = true)",
+                "Vim mode ([editor] vim = true)",
+            ),
+            vec![
+                (
+                    fixed("i a I A o O"),
+                    pick(
+                        "进入插入模式，Esc 回到普通模式",
+                        "Insert mode, Esc back to normal mode",
+                    ),
+                ),
+                (
+                    fixed("h l / 0 ^ $"),
+                    pick(
+                        "在行内移动；选择和随机字段中切换选项",
+                        "Move within the line; cycle options on select and random fields",
+                    ),
+                ),
+                (
+                    fixed("j k"),
+                    pick(
+                        "上下移动行，到头时切换字段",
+                        "Move between lines, and fields past the edges",
+                    ),
+                ),
+                (fixed("w b e"), pick("按词移动", "Move by word")),
+                (
+                    fixed("x X D C"),
+                    pick(
+                        "删除字符或到行尾",
+                        "Delete a char or to the end of the line",
+                    ),
+                ),
+                (
+                    fixed("dd cc"),
+                    pick("删除或改写整行", "Delete or change the line"),
+                ),
+                (
+                    fixed("dw cw diw ciw daw"),
+                    pick("删除或改写词", "Delete or change a word"),
+                ),
+                (fixed("u"), pick("撤销", "Undo")),
+            ],
+        ),
+        (
+            "readline ([editor] readline = true)",
+            vec![
+                (
+                    fixed("Ctrl+A / Ctrl+E"),
+                    pick("行首 / 行尾", "Start / end of line"),
+                ),
+                (
+                    fixed("Ctrl+W"),
+                    pick("删除光标前的词", "Delete the word before the cursor"),
+                ),
+                (
+                    fixed("Ctrl+U"),
+                    pick("删除到行首", "Delete to the start of the line"),
+                ),
+                (
+                    fixed("Alt+B / Alt+F"),
+                    pick("按词后退 / 前进", "Back / forward one word"),
+                ),
+                (fixed("◀▶"), pick("移动光标", "Move the cursor")),
+            ],
+        ),
+        (
+            pick("选项列表", "Option list"),
+            vec![
+                (fixed("↑↓ / j k"), pick("预览选项", "Preview options")),
+                (
+                    fixed("Enter"),
+                    pick(
+                        "确定（随机项同时固定）",
+                        "Confirm (random picks are pinned too)",
+                    ),
+                ),
+                (
+                    fixed("a"),
+                    pick(
+                        "为随机项临时添加选项",
+                        "Add a one-off option to a random field",
+                    ),
+                ),
+                (fixed("e"), pick("编辑模板", "Edit template")),
+                (fixed("Esc"), pick("还原", "Restore")),
+            ],
+        ),
+        (
+            pick("预览选择", "Preview selection"),
+            vec![
+                (fixed("v"), pick("开始选区", "Start a selection")),
+                (
+                    fixed("↑↓ / j k / g G"),
+                    pick("移动或扩展选区", "Move or extend the selection"),
+                ),
+                (
+                    fixed("y / Enter"),
+                    pick("复制选中的行", "Copy the selected lines"),
+                ),
+                (fixed("Esc"), pick("退出选择", "Leave the selection")),
+            ],
+        ),
+        (
+            pick("模板表单", "Template form"),
+            vec![
+                (
+                    fixed("Tab"),
+                    pick(
+                        "切换名称和正文（仅新建）",
+                        "Switch between name and body (new templates only)",
+                    ),
+                ),
+                (fixed("Enter"), pick("换行", "Newline")),
+                (fixed("Ctrl+S"), pick("保存", "Save")),
+                (fixed("Esc"), pick("取消", "Cancel")),
+            ],
+        ),
+        (
+            pick("任意界面", "Any view"),
+            vec![(
+                key(KeyAction::Help),
+                pick(
+                    "显示本帮助（输入框中用 F1）",
+                    "Show this help (F1 in text inputs)",
+                ),
+            )],
+        ),
+    ]
+}
+
+fn render_help<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+) {
+    let theme = app.config.theme.theme();
+    let area = frame.area();
+    let mut lines = Vec::new();
+    for (title, entries) in help_sections(&app.config) {
+        if !lines.is_empty() {
+            lines.push(Line::raw(""));
+        }
+        lines.push(Line::styled(title, Style::new().fg(theme.accent)));
+        let width = entries
+            .iter()
+            .map(|(keys, _)| Span::raw(keys.as_str()).width())
+            .max()
+            .unwrap_or(0);
+        for (keys, description) in entries {
+            let padding = " ".repeat(width - Span::raw(keys.as_str()).width());
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {keys}{padding}  "), Style::new().fg(theme.focus)),
+                Span::raw(description),
+            ]));
+        }
+    }
+    let visible = area.height.saturating_sub(2);
+    let max_scroll = (lines.len() as u16).saturating_sub(visible);
+    app.help_scroll = app.help_scroll.min(max_scroll);
+
+    frame.render_widget(Clear, area);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(pick("快捷键", "Keys"))
+                .title_bottom(pick(
+                    " ↑↓/PageUp/PageDown 滚动  Esc 关闭 ",
+                    " ↑↓/PageUp/PageDown scroll  Esc close ",
+                ))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .scroll((app.help_scroll, 0));
+    frame.render_widget(paragraph, area);
 }
 
-fn render_error(frame: &mut Frame, app: &mut App) {
+fn render_error<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+) {
+    let theme = app.config.theme.theme();
     let area = frame.area();
-    let message = app
-        .error_message
-        .clone()
-        .unwrap_or_else(|| "未知错误".to_string());
-    let block = Block::bordered().title("错误");
-    let paragraph = Paragraph::new(message)
+    // The message says what went wrong, the muted line below it what to
+    // try next.
+    let (title, lines) = match &app.error_message {
+        Some(err) => {
+            let mut lines: Vec<Line> = err.message().lines().map(Line::raw).collect();
+            lines.push(Line::raw(""));
+            lines.push(Line::styled(err.hint(), Style::new().fg(theme.muted)));
+            (err.kind(), lines)
+        }
+        None => (
+            pick("错误", "Error"),
+            vec![Line::raw(pick("未知错误", "Unknown error"))],
+        ),
+    };
+    let block = Block::bordered().title(title);
+    let paragraph = Paragraph::new(lines)
         .block(block)
-        .style(Style::new().fg(Color::Red))
+        .style(Style::new().fg(theme.error))
         .wrap(Wrap { trim: false });
     frame.render_widget(paragraph, area);
 }
 
-fn render_list(frame: &mut Frame, app: &mut App) {
+fn render_list<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+    now: Instant,
+) {
+    let theme = app.config.theme.theme();
     let area = frame.area();
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Fill(1), Constraint::Length(1)])
         .split(area);
 
-    let list_area = layout[0];
+    let narrow = app.config.layout.is_narrow(area.width);
+    let (list_area, body_area) = if app.show_body {
+        let columns = Layout::default()
+            .direction(if narrow {
+                Direction::Vertical
+            } else {
+                Direction::Horizontal
+            })
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(layout[0]);
+        (columns[0], Some(columns[1]))
+    } else {
+        (layout[0], None)
+    };
     let help_area = layout[1];
 
-    let title = format!("模板列表 ({})", app.templates.len());
+    let mut title = match &app.search {
+        Some(query) => tr!(
+            "搜索: {query} ({})",
+            "Search: {query} ({})",
+            app.tree_items.len()
+        ),
+        None if app.moving.is_some() => tr!(
+            "模板列表 ({})  移动中",
+            "Templates ({})  moving",
+            app.templates.len()
+        ),
+        None if !app.problems.is_empty() || !app.load_warnings.is_empty() => tr!(
+            "模板列表 ({})  ✗ {} 个问题，按 D 查看",
+            "Templates ({})  ✗ {} problems, D to see them",
+            app.templates.len(),
+            app.problems.len() + app.load_warnings.len()
+        ),
+        None if app.diagnostics.is_empty() => {
+            tr!("模板列表 ({})", "Templates ({})", app.templates.len())
+        }
+        None => tr!(
+            "模板列表 ({})  ⚠ {} 个模板超出阈值",
+            "Templates ({})  ⚠ {} over the thresholds",
+            app.templates.len(),
+            app.diagnostics.len()
+        ),
+    };
+    if !app.tag_filter.is_empty() {
+        let tags: Vec<String> = app.tag_filter.iter().map(|tag| format!("#{tag}")).collect();
+        let shown = app
+            .tree_items
+            .iter()
+            .filter(|item| item.template_index.is_some())
+            .count();
+        title.push_str(&tr!(
+            "  标签: {} ({shown})",
+            "  tags: {} ({shown})",
+            tags.join(" ")
+        ));
+    }
+    if app.fetching {
+        title.push_str(pick("  正在获取远程模板…", "  fetching remote templates…"));
+    }
     let block = Block::bordered().title(title);
-    let inner = inner_rect(list_area);
-    app.tree_area = inner;
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Fill(1)])
+        .split(inner_rect(list_area));
+    let (crumb_area, tree_area) = (rows[0], rows[1]);
+    app.tree_area = tree_area;
 
-    let view_height = inner.height as usize;
+    let view_height = tree_area.height as usize;
     app.list_scroll = ensure_visible(
         app.list_scroll,
         app.list_state.selected().unwrap_or(0),
@@ -64,8 +540,17 @@ fn render_list(frame: &mut Frame, app: &mut App) {
 
     let start = app.list_scroll;
     let end = (start + view_height).min(app.tree_items.len());
-    let tree_lines = build_tree_lines(&app.tree_items);
-    let visible = &tree_lines[start..end];
+    if app
+        .tree_lines
+        .as_ref()
+        .is_none_or(|(built_narrow, _)| *built_narrow != narrow)
+    {
+        app.tree_lines = Some((narrow, build_tree_lines(&app.tree_items, narrow)));
+    }
+    let visible = match &app.tree_lines {
+        Some((_, lines)) => &lines[start..end],
+        None => &[],
+    };
     let selected = app.list_state.selected().unwrap_or(0);
 
     let items: Vec<ListItem> = visible
@@ -78,50 +563,557 @@ fn render_list(frame: &mut Frame, app: &mut App) {
             } else {
                 UNSELECTED_MARKER
             };
-            ListItem::new(format!("{marker}{line}"))
+            let mut spans = vec![Span::raw(format!("{marker}{}", line.text))];
+            if let Some(count) = line.folder_count {
+                spans.push(Span::styled(
+                    format!(" ({count})"),
+                    Style::new().fg(theme.muted),
+                ));
+            }
+            let template = app.tree_items[start + idx]
+                .template_index
+                .and_then(|index| app.templates.get(index));
+            if let Some(template) = template {
+                spans.extend(metadata_spans(&theme, template));
+            }
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
+    let highlight = if app.moving.is_some() {
+        Style::new().bg(theme.flash_bg).fg(theme.flash_fg)
+    } else {
+        Style::new().bg(theme.selection_bg).fg(theme.selection_fg)
+    };
     let list = List::new(items)
-        .block(block)
-        .highlight_style(Style::new().bg(Color::Blue).fg(Color::White))
+        .highlight_style(highlight)
         .highlight_symbol("");
 
     let mut state = ListState::default();
-    if let Some(selected) = app.list_state.selected() {
-        if selected >= start && selected < end {
-            state.select(Some(selected - start));
-        }
+    if let Some(selected) = app.list_state.selected()
+        && selected >= start
+        && selected < end
+    {
+        state.select(Some(selected - start));
     }
-    frame.render_stateful_widget(list, list_area, &mut state);
+    frame.render_widget(block, list_area);
+    frame.render_widget(breadcrumb(&theme, &app.selected_path()), crumb_area);
+    frame.render_stateful_widget(list, tree_area, &mut state);
 
-    let mut help = "↑↓/j k 选择  Enter/双击 打开  e 编辑  q 退出".to_string();
-    if let Some(message) = app
-        .list_status
-        .as_ref()
-        .filter(|msg| msg.since.elapsed().as_millis() <= STATUS_DURATION_MS)
+    if let Some(body_area) = body_area {
+        render_body(frame, &theme, app, body_area);
+    }
+
+    let keys = &app.config.keys;
+    let hints = if app.tag_picker.is_some() {
+        pick(
+            "↑↓ 选择  Space 勾选  c 全部取消  Enter 应用  Esc 取消",
+            "↑↓ select  Space check  c uncheck all  Enter apply  Esc cancel",
+        )
+        .to_string()
+    } else if app.moving.is_some() {
+        pick(
+            "↑↓ 在目录内移动 (立即写入文件)  Enter/Esc 放下",
+            "↑↓ move within the folder (written right away)  Enter/Esc drop",
+        )
+        .to_string()
+    } else if app.name_prompt.is_some() {
+        pick(
+            "输入名称，用 / 分隔目录  Enter 确定  Esc 取消",
+            "Type a name, / separates folders  Enter confirm  Esc cancel",
+        )
+        .to_string()
+    } else if let Some((typed, _)) = &app.type_ahead {
+        tr!("跳转: {typed}", "Jump: {typed}")
+    } else if app.search.is_some() {
+        pick(
+            "输入关键词搜索  ↑↓ 选择  Enter 打开  Esc 退出搜索",
+            "Type to search  ↑↓ select  Enter open  Esc leave search",
+        )
+        .to_string()
+    } else if let Some(custom) = &app.config.footer.hints.list {
+        custom.clone()
+    } else {
+        tr!(
+            "↑↓/j k 选择  Enter/双击 打开  / 搜索  p 正文  n 新建  E 修改  d 复制  r 重命名  m 移动  t 标签  {} 编辑  D 诊断  A 评分  S 统计  F5 刷新  {} 退出",
+            "↑↓/j k select  Enter/double click open  / search  p body  n new  E edit  d duplicate  r rename  m move  t tags  {} editor  D diagnostics  A score  S stats  F5 reload  {} quit",
+            keys.label(KeyAction::OpenEditor),
+            keys.label(KeyAction::Quit)
+        )
+    };
+    let footer = Footer {
+        hints: &hints,
+        status: app.list_status.as_ref().filter(|msg| msg.shown_at(now)),
+        time: app.platform.local_time(),
+        template_count: app.templates.len(),
+        clipboard: app.platform.clipboard_backend(),
+        tokens: None,
+        unfilled: 0,
+        seed: None,
+        template: app
+            .selected_template_index()
+            .and_then(|index| app.templates.get(index))
+            .map(|template| template.name.as_str()),
+    };
+    render_footer(frame, &theme, &app.config, &footer, help_area);
+
+    if app.show_diagnostics {
+        render_diagnostics(frame, app, area);
+    }
+    if let Some((index, report)) = &app.quality {
+        render_quality(frame, &theme, &app.templates[*index].name, report, area);
+    }
+    if app.stats.is_some() {
+        render_stats(frame, app, area);
+    }
+    if let Some(prompt) = &app.name_prompt {
+        render_name_prompt(frame, &theme, &app.templates, prompt, area);
+    }
+    if let Some(picker) = &app.tag_picker {
+        render_tag_picker(frame, &theme, picker, area);
+    }
+    if app.show_load_warnings {
+        render_load_warnings(frame, &theme, &app.load_warnings, area);
+    }
+}
+
+/// What was skipped while loading, with file and line, over the list.
+fn render_load_warnings(frame: &mut Frame, theme: &Theme, warnings: &[LoadWarning], area: Rect) {
+    let mut lines = vec![
+        Line::raw(pick(
+            "以下内容未能按原样载入，其余模板可以正常使用:",
+            "These parts could not be loaded as written; the other templates work as usual:",
+        )),
+        Line::raw(""),
+    ];
+    for warning in warnings {
+        lines.push(Line::from(vec![
+            Span::styled("⚠ ", Style::new().fg(theme.error)),
+            Span::raw(warning.to_string()),
+        ]));
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(pick(
+        "Esc 关闭  D 再次查看",
+        "Esc close  D to see again",
+    )));
+
+    let popup = centered_rect(
+        area,
+        area.width.saturating_mul(4) / 5,
+        // Room for the border and warnings that wrap.
+        lines.len() as u16 + 2 + warnings.len() as u16,
+    );
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(tr!("载入警告 ({})", "Load warnings ({})", warnings.len()))
+                .border_style(Style::new().fg(theme.error)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+/// Raw body of the selected template, placeholders left as written and
+/// highlighted, so similar templates can be told apart without opening them.
+fn render_body<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    theme: &Theme,
+    app: &mut App<S, T, P>,
+    area: Rect,
+) {
+    let template = match app
+        .selected_template_index()
+        .and_then(|index| app.templates.get(index))
     {
-        help.push_str("  |  ");
-        help.push_str(&message.text);
+        Some(template) => template,
+        None => {
+            let block = Block::bordered().title(pick("正文", "Body"));
+            let hint = Paragraph::new(pick(
+                "选择一个模板查看正文",
+                "Select a template to see its body",
+            ))
+            .block(block)
+            .style(Style::new().fg(theme.muted));
+            frame.render_widget(hint, area);
+            return;
+        }
+    };
+    let segments: Vec<Segment> = app
+        .token_cache
+        .tokens(&template.body, &app.pools)
+        .iter()
+        .cloned()
+        .map(|token| match token {
+            Token::Text(text) => Segment {
+                text,
+                kind: SegmentKind::Text,
+            },
+            Token::Var { raw, .. }
+            | Token::List { raw, .. }
+            | Token::Select { raw, .. }
+            | Token::Random { raw, .. }
+            | Token::Timestamp { raw, .. }
+            | Token::Command { raw, .. }
+            | Token::Clipboard { raw, .. }
+            | Token::File { raw, .. } => Segment {
+                text: raw,
+                kind: SegmentKind::Value,
+            },
+        })
+        .collect();
+    let text = segments_to_text(&segments, |kind| match kind {
+        SegmentKind::Value => Style::new().fg(theme.accent),
+        _ => Style::new(),
+    });
+    let title = tr!("正文: {}", "Body: {}", template.name);
+    render_preview(frame, theme, &title, None, text, &mut 0, area);
+}
+
+fn render_tag_picker(frame: &mut Frame, theme: &Theme, picker: &TagPicker, area: Rect) {
+    let height = (picker.tags.len() as u16).saturating_add(2);
+    let popup = centered_rect(area, 40, height);
+    frame.render_widget(Clear, popup);
+    let items: Vec<ListItem> = picker
+        .tags
+        .iter()
+        .map(|(tag, count)| {
+            let check = if picker.chosen.contains(tag) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{check} #{tag}")),
+                Span::styled(format!("  {count}"), Style::new().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::bordered()
+                .title(pick("按标签筛选", "Filter by tag"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .highlight_style(Style::new().bg(theme.selection_bg).fg(theme.selection_fg))
+        .highlight_symbol(SELECTED_MARKER);
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+    frame.render_stateful_widget(list, popup, &mut state);
+}
+
+/// Description and tags shown after a template in the list, or why its
+/// frontmatter could not be read.
+fn metadata_spans(theme: &Theme, template: &Template) -> Vec<Span<'static>> {
+    if let Some(err) = &template.metadata_error {
+        let first = err.lines().next().unwrap_or_default();
+        return vec![Span::styled(
+            format!("  ⚠ {first}"),
+            Style::new().fg(theme.error),
+        )];
+    }
+    let mut spans = Vec::new();
+    if template.layer != Layer::Personal {
+        spans.push(Span::styled(
+            format!("  [{}]", template.layer.label()),
+            Style::new().fg(theme.muted),
+        ));
     }
-    let help = Paragraph::new(help).style(Style::new().fg(Color::DarkGray));
-    frame.render_widget(help, help_area);
+    if let Some(hidden) = template.overrides {
+        spans.push(Span::styled(
+            tr!("  [覆盖{}]", "  [overrides {}]", hidden.label()),
+            Style::new().fg(theme.muted),
+        ));
+    }
+    if let Some(description) = template_description(template) {
+        spans.push(Span::styled(
+            format!("  {description}"),
+            Style::new().fg(theme.muted),
+        ));
+    }
+    if !template.metadata.tags.is_empty() {
+        let tags: Vec<String> = template
+            .metadata
+            .tags
+            .iter()
+            .map(|tag| format!("#{tag}"))
+            .collect();
+        spans.push(Span::styled(
+            format!("  {}", tags.join(" ")),
+            Style::new().fg(theme.info),
+        ));
+    }
+    spans
 }
 
-fn render_editor(frame: &mut Frame, app: &mut App) {
+fn render_name_prompt(
+    frame: &mut Frame,
+    theme: &Theme,
+    templates: &[Template],
+    prompt: &NamePrompt,
+    area: Rect,
+) {
+    let current = templates
+        .get(prompt.template_index)
+        .map_or("", |template| template.name.as_str());
+    let (title, action) = match prompt.action {
+        NameAction::Duplicate => (
+            pick("复制模板", "Duplicate template"),
+            pick("复制", "Duplicate"),
+        ),
+        NameAction::Rename => (
+            pick("重命名模板", "Rename template"),
+            pick("重命名", "Rename"),
+        ),
+    };
+    let popup = centered_rect(area, 56, 6);
+    frame.render_widget(Clear, popup);
+    let text = tr!(
+        "{action} {current}\n新名称: {}|\n\nEnter 确定  Esc 取消",
+        "{action} {current}\nNew name: {}|\n\nEnter confirm  Esc cancel",
+        prompt.name
+    );
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::bordered()
+                .title(title)
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+/// Usage counts, last use, placeholders and average prompt length per
+/// template, one row each.
+fn render_stats<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+    area: Rect,
+) {
+    let theme = app.config.theme.theme();
+    let stats = match &app.stats {
+        Some(stats) => stats,
+        None => return,
+    };
+    let mut lines = vec![Line::styled(stats_header(), Style::new().fg(theme.accent))];
+    for row in stats {
+        let style = if row.uses == 0 {
+            Style::new().fg(theme.muted)
+        } else {
+            Style::new()
+        };
+        lines.push(Line::styled(format_row(row), style));
+    }
+    let unused = stats.iter().filter(|row| row.uses == 0).count();
+    let visible = area.height.saturating_sub(2);
+    let max_scroll = (lines.len() as u16).saturating_sub(visible);
+    app.stats_scroll = app.stats_scroll.min(max_scroll);
+
+    frame.render_widget(Clear, area);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(tr!(
+                    "使用统计: {} 个模板，{unused} 个从未使用",
+                    "Usage: {} templates, {unused} never used",
+                    stats.len()
+                ))
+                .title_bottom(pick(
+                    " ↑↓/PageUp/PageDown 滚动  Esc 关闭 ",
+                    " ↑↓/PageUp/PageDown scroll  Esc close ",
+                ))
+                .border_style(Style::new().fg(theme.info)),
+        )
+        .scroll((app.stats_scroll, 0));
+    frame.render_widget(paragraph, area);
+}
+
+fn render_quality(
+    frame: &mut Frame,
+    theme: &Theme,
+    name: &str,
+    report: &QualityReport,
+    area: Rect,
+) {
+    let score_color = match report.score {
+        75.. => theme.success,
+        50.. => theme.accent,
+        _ => theme.error,
+    };
+    let mut lines = vec![
+        Line::from(vec![
+            Span::raw(pick("得分: ", "Score: ")),
+            Span::styled(report.score.to_string(), Style::new().fg(score_color)),
+            Span::raw(" / 100"),
+        ]),
+        Line::raw(""),
+    ];
+    for check in &report.checks {
+        let (mark, color) = match check.suggestion {
+            Some(_) => ("✗", theme.error),
+            None => ("✓", theme.success),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{mark} "), Style::new().fg(color)),
+            Span::raw(check.label),
+        ]));
+        if let Some(suggestion) = &check.suggestion {
+            lines.push(Line::styled(
+                format!("  {suggestion}"),
+                Style::new().fg(theme.muted),
+            ));
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(pick("Esc 关闭", "Esc close")));
+
+    let popup = centered_rect(
+        area,
+        area.width.saturating_mul(3) / 4,
+        // Room for the border and suggestions that wrap.
+        lines.len() as u16 + 2 + report.checks.len() as u16,
+    );
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(tr!("模板评分: {name}", "Template score: {name}"))
+                .border_style(Style::new().fg(theme.info)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_diagnostics<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &App<S, T, P>,
+    area: Rect,
+) {
+    let theme = app.config.theme.theme();
+    let limits = &app.thresholds;
+    let mut lines = vec![
+        Line::styled(
+            tr!(
+                "阈值: 正文 {} 字节  片段 {}  include 层级 {}  占位符 {}",
+                "Thresholds: body {} bytes  segments {}  include depth {}  placeholders {}",
+                limits.body_bytes,
+                limits.tokens,
+                limits.include_depth,
+                limits.dynamic_tokens
+            ),
+            Style::new().fg(theme.muted),
+        ),
+        Line::raw(""),
+    ];
+    for problem in &app.problems {
+        lines.push(Line::from(vec![
+            Span::styled("✗ ", Style::new().fg(theme.error)),
+            Span::styled(
+                app.templates[problem.template_index].name.clone(),
+                Style::new().fg(theme.accent),
+            ),
+            Span::raw(format!("  {}", problem.message)),
+        ]));
+    }
+    for warning in &app.load_warnings {
+        lines.push(Line::from(vec![
+            Span::styled("⚠ ", Style::new().fg(theme.error)),
+            Span::raw(warning.to_string()),
+        ]));
+    }
+    if !app.problems.is_empty() || !app.load_warnings.is_empty() {
+        lines.push(Line::raw(""));
+    }
+    if app.diagnostics.is_empty() {
+        lines.push(Line::raw(pick(
+            "没有超出阈值的模板",
+            "No templates over the thresholds",
+        )));
+    }
+    for diagnostic in &app.diagnostics {
+        let metrics = &diagnostic.metrics;
+        lines.push(Line::styled(
+            app.templates[diagnostic.template_index].name.clone(),
+            Style::new().fg(theme.accent),
+        ));
+        lines.push(Line::raw(tr!(
+            "  正文 {} 字节  片段 {}  include 层级 {}  占位符 {}",
+            "  body {} bytes  segments {}  include depth {}  placeholders {}",
+            metrics.body_bytes,
+            metrics.tokens,
+            metrics.include_depth,
+            metrics.dynamic_tokens
+        )));
+        lines.push(Line::styled(
+            tr!(
+                "  超出: {}",
+                "  over: {}",
+                diagnostic.exceeded.join(pick("、", ", "))
+            ),
+            Style::new().fg(theme.error),
+        ));
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(pick("Esc 关闭", "Esc close")));
+
+    let popup = centered_rect(
+        area,
+        area.width.saturating_mul(4) / 5,
+        area.height.saturating_mul(4) / 5,
+    );
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(pick("模板诊断", "Template diagnostics"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_editor<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+    now: Instant,
+) {
+    let theme = app.config.theme.theme();
     let title = app
         .editor
         .as_ref()
         .and_then(|editor| app.templates.get(editor.template_index))
-        .map(|template| format!("预览: {}", template.name))
-        .unwrap_or_else(|| "预览".to_string());
+        .map(|template| tr!("预览: {}", "Preview: {}", template.name))
+        .unwrap_or_else(|| pick("预览", "Preview").to_string());
+    let title = match app
+        .editor
+        .as_ref()
+        .and_then(|editor| editor.commands.as_ref())
+    {
+        Some(run) => tr!(
+            "{title}  运行命令 {}/{}…",
+            "{title}  running commands {}/{}…",
+            run.done,
+            run.total
+        ),
+        None => title,
+    };
+    let split = app.field_percent();
+    let markdown = app.config.preview.markdown;
+    let time = app.platform.local_time();
+    let area = frame.area();
+    // Too narrow for two columns: fields on top, preview below.
+    let direction = if app.config.layout.is_narrow(area.width) {
+        Direction::Vertical
+    } else {
+        Direction::Horizontal
+    };
 
     let editor = match app.editor.as_mut() {
         Some(editor) => editor,
         None => return,
     };
 
-    let area = frame.area();
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Fill(1), Constraint::Length(1)])
@@ -130,83 +1122,730 @@ fn render_editor(frame: &mut Frame, app: &mut App) {
     let content_area = layout[0];
     let status_area = layout[1];
 
-    let horizontal = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+    let panes = Layout::default()
+        .direction(direction)
+        .constraints([
+            Constraint::Percentage(split),
+            Constraint::Percentage(100 - split),
+        ])
         .split(content_area);
 
-    let form_area = horizontal[0];
-    let preview_area = horizontal[1];
+    let form_area = panes[0];
+    let mut preview_area = panes[1];
+    editor.content_area = content_area;
+    if let Some(answer) = editor.answer.as_mut() {
+        let columns = Layout::default()
+            .direction(direction)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(preview_area);
+        preview_area = columns[0];
+        editor.answer_area = columns[1];
+        render_answer(frame, &theme, answer, markdown, columns[1]);
+    }
 
-    render_fields(frame, editor, form_area);
-    let rendered = render_template(&editor.tokens, &editor.fields);
-    render_preview(frame, &title, &rendered, preview_area);
+    render_fields(frame, &theme, editor, form_area);
+    let segments = render_segments(&editor.tokens, &editor.fields, time);
+    let changed: &[usize] = match &editor.reroll_highlight {
+        Some((changed, since))
+            if now.saturating_duration_since(*since).as_millis() < REROLL_HIGHLIGHT_MS =>
+        {
+            changed
+        }
+        _ => &[],
+    };
+    let selection = editor.selection.map(|selection| selection.range());
+    let text = preview_text(&theme, &segments, changed, selection, markdown);
+    let unfilled = segments
+        .iter()
+        .filter(|segment| matches!(segment.kind, SegmentKind::Unfilled))
+        .count();
+    let stats = preview_stats(&theme, &segments, unfilled);
+    editor.preview_area = preview_area;
+    render_preview(
+        frame,
+        &theme,
+        &title,
+        Some(stats),
+        text,
+        &mut editor.preview_scroll,
+        preview_area,
+    );
 
-    let mut status = String::from("Esc 返回  Tab/↑↓ 切换");
-    if editor.active_field_is_random() {
-        status.push_str("  ◀▶ 选项  Space 固定");
-    }
-    status.push_str("  Ctrl+C 复制  F5 重随");
-    if let Some(message) = editor
-        .status
+    let mut hints = String::from(pick("Esc 返回  Tab/↑↓ 切换", "Esc back  Tab/↑↓ switch"));
+    if editor.preset_name.is_some() {
+        hints = String::from(pick(
+            "输入预设名称  Enter 保存 (同名覆盖)  Esc 取消",
+            "Type a preset name  Enter save (replaces one of the same name)  Esc cancel",
+        ));
+    } else if editor.seed_prompt.is_some() {
+        hints = String::from(pick(
+            "输入种子  Enter 重随  Esc 取消",
+            "Type a seed  Enter reroll  Esc cancel",
+        ));
+    } else if editor.preset_picker.is_some() {
+        hints = String::from(pick(
+            "↑↓ 选择  Enter 载入  d 删除  Esc 取消",
+            "↑↓ select  Enter load  d delete  Esc cancel",
+        ));
+    } else if editor
+        .selection
+        .is_some_and(|selection| selection.anchor.is_some())
+    {
+        hints = String::from(pick(
+            "↑↓/jk 扩展选区  v 取消选区  y 复制  Esc 退出",
+            "↑↓/jk extend  v cancel selection  y copy  Esc leave",
+        ));
+    } else if editor.selection.is_some() {
+        hints = String::from(pick(
+            "↑↓/jk 移动  v 开始选区  y 复制当前行  Esc 退出",
+            "↑↓/jk move  v start selection  y copy line  Esc leave",
+        ));
+    } else if editor
+        .picker
         .as_ref()
-        .filter(|msg| msg.since.elapsed().as_millis() <= STATUS_DURATION_MS)
+        .is_some_and(|picker| picker.adding.is_some())
     {
-        status.push_str("  |  ");
-        status.push_str(&message.text);
+        hints = String::from(pick(
+            "输入新选项  Enter 添加  Esc 取消",
+            "Type a new option  Enter add  Esc cancel",
+        ));
+    } else if editor.picker.is_some() && editor.active_field_is_random() {
+        hints = String::from(pick(
+            "↑↓ 预览  Enter 选定并固定  a 临时添加  e 编辑模板  Esc 取消",
+            "↑↓ preview  Enter pick and pin  a add one-off  e edit template  Esc cancel",
+        ));
+    } else if editor.picker.is_some() {
+        hints = String::from(pick(
+            "↑↓ 预览  Enter 确定  Esc 取消",
+            "↑↓ preview  Enter confirm  Esc cancel",
+        ));
+    } else if editor.active_choice_is_typed() {
+        hints.push_str(pick(
+            "  输入自定义内容  ◀▶ 回到选项",
+            "  type a custom choice  ◀▶ back to options",
+        ));
+    } else if editor.active_field_is_random() {
+        hints.push_str(pick(
+            "  ◀▶ 选项  Enter 列表  Space 固定  输入以自定义",
+            "  ◀▶ options  Enter list  Space pin  type to customize",
+        ));
+    } else if editor.active_field_is_select() {
+        hints.push_str(pick(
+            "  ◀▶/Space 选择  Enter 列表",
+            "  ◀▶/Space choose  Enter list",
+        ));
+    }
+    let keys = &app.config.keys;
+    if editor.selection.is_none()
+        && editor.preset_name.is_none()
+        && editor.seed_prompt.is_none()
+        && editor.preset_picker.is_none()
+    {
+        hints.push_str(&tr!(
+            "  {} 复制  {} 复制记录  {} 重随  F2 重命名  {} 选择预览  Ctrl+S/Ctrl+O 存/取预设  {} 清空  {}/{} 撤销/重做",
+            "  {} copy  {} copy record  {} reroll  F2 rename  {} select preview  Ctrl+S/Ctrl+O save/load preset  {} clear  {}/{} undo/redo",
+            keys.label(KeyAction::Copy),
+            keys.label(KeyAction::CopyRecord),
+            keys.label(KeyAction::Reroll),
+            keys.label(KeyAction::Select),
+            keys.label(KeyAction::ClearValues),
+            keys.label(KeyAction::Undo),
+            keys.label(KeyAction::Redo)
+        ));
+        if app.config.send.command.is_some() {
+            hints.push_str(&tr!("  {} 发送", "  {} send", keys.label(KeyAction::Send)));
+        }
+        if editor.answer.is_some() {
+            hints.push_str(&tr!(
+                "  {} 复制回答  Esc 关闭回答",
+                "  {} copy answer  Esc close answer",
+                keys.label(KeyAction::CopyAnswer)
+            ));
+        } else if app.config.llm.model.is_some() {
+            hints.push_str(&tr!("  {} 提问", "  {} ask", keys.label(KeyAction::Ask)));
+        }
+        if editor.has_random() {
+            hints.push_str(&tr!("  {} 种子", "  {} seed", keys.label(KeyAction::Seed)));
+        }
+        if editor.has_commands() {
+            hints.push_str(pick("  F6 运行命令", "  F6 run commands"));
+        }
+        if editor.picker.is_none() {
+            if let Some(custom) = &app.config.footer.hints.editor {
+                hints = custom.clone();
+            }
+            if let Some(vim) = &editor.vim {
+                let pending = if vim.pending.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {}", vim.pending)
+                };
+                hints.insert_str(0, &format!("{}{pending}  ", vim.mode.label()));
+            }
+        }
+    }
+    let footer = Footer {
+        hints: &hints,
+        status: editor.status.as_ref().filter(|msg| msg.shown_at(now)),
+        time: app.platform.local_time(),
+        template_count: app.templates.len(),
+        clipboard: app.platform.clipboard_backend(),
+        tokens: Some(estimate_tokens(
+            &render_template_at(&editor.tokens, &editor.fields, time),
+            app.config.tokens.estimator,
+        )),
+        unfilled,
+        seed: editor.has_random().then_some(editor.seed),
+        template: app
+            .templates
+            .get(editor.template_index)
+            .map(|template| template.name.as_str()),
+    };
+    render_footer(frame, &theme, &app.config, &footer, status_area);
+
+    if editor.reload_conflict.is_some() {
+        render_reload_conflict(frame, &theme, area);
+    } else if editor.pending_draft.is_some() {
+        render_draft_prompt(frame, &theme, area);
+    } else if editor.command_prompt {
+        render_command_prompt(frame, &theme, &editor.tokens, area);
+    } else if let Some(prompt) = &editor.rename {
+        render_rename(frame, &theme, &app.templates, prompt, area);
+    } else if let Some(picker) = &editor.picker {
+        render_option_picker(frame, &theme, picker, form_area);
+    } else if let Some(name) = &editor.preset_name {
+        render_preset_name(frame, &theme, name, area);
+    } else if let Some(text) = &editor.seed_prompt {
+        render_seed_prompt(frame, &theme, text, area);
+    } else if let Some(picker) = &editor.preset_picker {
+        render_preset_picker(frame, &theme, picker, area);
+    }
+}
+
+fn render_rename(
+    frame: &mut Frame,
+    theme: &Theme,
+    templates: &[Template],
+    prompt: &RenamePrompt,
+    area: Rect,
+) {
+    let plan = match &prompt.plan {
+        Some(plan) => plan,
+        None => {
+            let popup = centered_rect(area, 48, 6);
+            frame.render_widget(Clear, popup);
+            let text = tr!(
+                "在所有模板中重命名变量 {}\n新名称: {}|\n\nEnter 预览  Esc 取消",
+                "Rename variable {} in every template\nNew name: {}|\n\nEnter preview  Esc cancel",
+                prompt.from,
+                prompt.to
+            );
+            let paragraph = Paragraph::new(text)
+                .block(
+                    Block::bordered()
+                        .title(pick("重命名变量", "Rename variable"))
+                        .border_style(Style::new().fg(theme.accent)),
+                )
+                .wrap(Wrap { trim: false });
+            frame.render_widget(paragraph, popup);
+            return;
+        }
+    };
+
+    let mut lines: Vec<Line> = Vec::new();
+    for change in plan {
+        let template = &templates[change.template_index];
+        lines.push(Line::styled(
+            tr!("{} ({} 处)", "{} ({} places)", template.name, change.count),
+            Style::new().fg(theme.info),
+        ));
+        for (old, new) in template.body.lines().zip(change.body.lines()) {
+            if old != new {
+                lines.push(Line::styled(
+                    format!("- {old}"),
+                    Style::new().fg(theme.error),
+                ));
+                lines.push(Line::styled(
+                    format!("+ {new}"),
+                    Style::new().fg(theme.success),
+                ));
+            }
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(pick(
+        "y/Enter 应用  n/Esc 取消",
+        "y/Enter apply  n/Esc cancel",
+    )));
+
+    let popup = centered_rect(
+        area,
+        area.width.saturating_mul(4) / 5,
+        area.height.saturating_mul(4) / 5,
+    );
+    frame.render_widget(Clear, popup);
+    let title = tr!("重命名 {} → {}", "Rename {} → {}", prompt.from, prompt.to);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(title)
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_option_picker(frame: &mut Frame, theme: &Theme, picker: &OptionPicker, area: Rect) {
+    let extra = u16::from(picker.adding.is_some());
+    let height = (picker.options.len() as u16).saturating_add(2 + extra);
+    let popup = centered_rect(area, area.width.saturating_sub(2), height);
+    frame.render_widget(Clear, popup);
+    let mut items: Vec<ListItem> = picker
+        .options
+        .iter()
+        .map(|option| ListItem::new(option.as_str()))
+        .collect();
+    if let Some(text) = &picker.adding {
+        items.push(ListItem::new(format!("+ {text}|")).style(Style::new().fg(theme.success)));
+    }
+    let list = List::new(items)
+        .block(
+            Block::bordered()
+                .title(pick("选择", "Choose"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .highlight_style(Style::new().bg(theme.selection_bg).fg(theme.selection_fg))
+        .highlight_symbol(SELECTED_MARKER);
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+    frame.render_stateful_widget(list, popup, &mut state);
+}
+
+fn render_preset_name(frame: &mut Frame, theme: &Theme, name: &str, area: Rect) {
+    let popup = centered_rect(area, 48, 6);
+    frame.render_widget(Clear, popup);
+    let text = tr!(
+        "将当前填写的值保存为预设\n名称: {name}|\n\nEnter 保存  Esc 取消",
+        "Save the filled-in values as a preset\nName: {name}|\n\nEnter save  Esc cancel"
+    );
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::bordered()
+                .title(pick("保存预设", "Save preset"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_seed_prompt(frame: &mut Frame, theme: &Theme, text: &str, area: Rect) {
+    let popup = centered_rect(area, 48, 6);
+    frame.render_widget(Clear, popup);
+    let text = tr!(
+        "按种子重随未固定的随机项\n种子: {text}|\n\nEnter 重随  Esc 取消",
+        "Reroll the unpinned random picks from a seed\nSeed: {text}|\n\nEnter reroll  Esc cancel"
+    );
+    let paragraph = Paragraph::new(text)
+        .block(
+            Block::bordered()
+                .title(pick("随机种子", "Random seed"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_preset_picker(frame: &mut Frame, theme: &Theme, picker: &PresetPicker, area: Rect) {
+    let height = (picker.presets.len() as u16).saturating_add(2);
+    let popup = centered_rect(area, area.width.saturating_mul(3) / 5, height);
+    frame.render_widget(Clear, popup);
+    let items: Vec<ListItem> = picker
+        .presets
+        .iter()
+        .map(|preset| {
+            let values = preset
+                .values
+                .iter()
+                .map(|(name, value)| format!("{name}={}", escape_control(value)))
+                .collect::<Vec<_>>()
+                .join("  ");
+            ListItem::new(Line::from(vec![
+                Span::raw(preset.name.clone()),
+                Span::styled(format!("  {values}"), Style::new().fg(theme.muted)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::bordered()
+                .title(pick("预设", "Presets"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .highlight_style(Style::new().bg(theme.selection_bg).fg(theme.selection_fg))
+        .highlight_symbol(SELECTED_MARKER);
+    let mut state = ListState::default();
+    state.select(Some(picker.selected));
+    frame.render_stateful_widget(list, popup, &mut state);
+}
+
+fn render_reload_conflict(frame: &mut Frame, theme: &Theme, area: Rect) {
+    let popup = centered_rect(area, 48, 6);
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(
+        pick("模板已在磁盘上被修改。\n保留已填写的值并套用到新版本？\n\nk/Enter 保留  d 丢弃", "The template changed on disk.\nKeep the filled-in values for the new version?\n\nk/Enter keep  d discard"),
+    )
+    .block(
+        Block::bordered()
+            .title(pick("模板已更新", "Template updated"))
+            .border_style(Style::new().fg(theme.accent)),
+    )
+    .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_command_prompt(frame: &mut Frame, theme: &Theme, tokens: &[Token], area: Rect) {
+    let mut lines = vec![
+        Line::raw(pick(
+            "模板将执行以下命令:",
+            "The template will run these commands:",
+        )),
+        Line::raw(""),
+    ];
+    for token in tokens {
+        if let Token::Command { command, .. } = token {
+            lines.push(Line::styled(
+                format!("$ {command}"),
+                Style::new().fg(theme.info),
+            ));
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw(pick(
+        "y/Enter 执行  n/Esc 跳过",
+        "y/Enter run  n/Esc skip",
+    )));
+
+    let height = lines.len() as u16 + 2;
+    let popup = centered_rect(area, area.width.saturating_mul(3) / 5, height);
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(Text::from(lines))
+        .block(
+            Block::bordered()
+                .title(pick("运行命令", "Run commands"))
+                .border_style(Style::new().fg(theme.accent)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_draft_prompt(frame: &mut Frame, theme: &Theme, area: Rect) {
+    let popup = centered_rect(area, 40, 5);
+    frame.render_widget(Clear, popup);
+    let paragraph = Paragraph::new(pick(
+        "发现未完成的草稿，是否恢复？\n\ny/Enter 恢复  n/Esc 丢弃",
+        "Found an unfinished draft. Restore it?\n\ny/Enter restore  n/Esc discard",
+    ))
+    .block(
+        Block::bordered()
+            .title(pick("草稿", "Draft"))
+            .border_style(Style::new().fg(theme.accent)),
+    )
+    .wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, popup);
+}
+
+fn render_form<S: TemplateSource, T: StateStore, P: Platform>(
+    frame: &mut Frame,
+    app: &mut App<S, T, P>,
+    now: Instant,
+) {
+    let theme = app.config.theme.theme();
+    let form = match app.form.as_ref() {
+        Some(form) => form,
+        None => return,
+    };
+    let area = frame.area();
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let focus_style = |focus: FormFocus| {
+        if form.focus == focus {
+            Style::new().fg(theme.focus)
+        } else {
+            Style::new().fg(theme.muted)
+        }
+    };
+    let with_cursor = |value: &str, focus: FormFocus| {
+        let mut value = value.to_string();
+        if form.focus == focus {
+            value.push('|');
+        }
+        value
+    };
+
+    let name_title = if form.template_index.is_some() {
+        pick("名称 (只读)", "Name (read-only)")
+    } else {
+        pick("名称 (用 / 分隔目录)", "Name (/ separates folders)")
+    };
+    let name = Paragraph::new(with_cursor(&form.name, FormFocus::Name)).block(
+        Block::bordered()
+            .title(name_title)
+            .border_style(focus_style(FormFocus::Name)),
+    );
+    frame.render_widget(name, layout[0]);
+
+    let body_title = if form.template_index.is_some() {
+        pick("编辑模板", "Edit template")
+    } else {
+        pick("新建模板", "New template")
+    };
+    let body = Paragraph::new(with_cursor(&form.body, FormFocus::Body))
+        .block(
+            Block::bordered()
+                .title(body_title)
+                .border_style(focus_style(FormFocus::Body)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(body, layout[1]);
+
+    let mut hints = String::from(pick(
+        "Esc 取消  Ctrl+S 保存  Enter 换行",
+        "Esc cancel  Ctrl+S save  Enter newline",
+    ));
+    if form.template_index.is_none() {
+        hints.push_str(pick("  Tab 切换", "  Tab switch"));
+    }
+    let footer = Footer {
+        hints: &hints,
+        status: form.status.as_ref().filter(|msg| msg.shown_at(now)),
+        time: app.platform.local_time(),
+        template_count: app.templates.len(),
+        clipboard: app.platform.clipboard_backend(),
+        tokens: None,
+        unfilled: 0,
+        seed: None,
+        template: form
+            .template_index
+            .and_then(|index| app.templates.get(index))
+            .map(|template| template.name.as_str()),
+    };
+    render_footer(frame, &theme, &app.config, &footer, layout[2]);
+}
+
+/// What a view contributes to the bottom bar.
+struct Footer<'a> {
+    hints: &'a str,
+    /// The status message, if it is still shown.
+    status: Option<&'a StatusMessage>,
+    /// Local time for the clock widget.
+    time: DateTime<Local>,
+    template_count: usize,
+    clipboard: ClipboardBackend,
+    /// Estimated tokens of the rendered prompt; only the editor has one.
+    tokens: Option<usize>,
+    /// Placeholders of the open template left blank.
+    unfilled: usize,
+    /// Seed of the editor's random picks.
+    seed: Option<u64>,
+    template: Option<&'a str>,
+}
+
+/// The bottom bar shared by all views: the configured widgets in order,
+/// separated by `|`, skipping empty ones. `[footer] right` widgets are
+/// aligned to the right edge.
+fn render_footer(frame: &mut Frame, theme: &Theme, config: &Config, footer: &Footer, area: Rect) {
+    let style = Style::new().fg(theme.status);
+    let right = footer_line(theme, config, footer, &config.footer.right);
+    let right_width = (right.width() as u16).min(area.width);
+    let [left_area, right_area] =
+        Layout::horizontal([Constraint::Min(0), Constraint::Length(right_width)]).areas(area);
+    let left = footer_line(theme, config, footer, &config.footer.widgets);
+    frame.render_widget(Paragraph::new(left).style(style), left_area);
+    frame.render_widget(Paragraph::new(right).style(style), right_area);
+}
+
+fn footer_line(
+    theme: &Theme,
+    config: &Config,
+    footer: &Footer,
+    widgets: &[FooterWidget],
+) -> Line<'static> {
+    let parts: Vec<Span> = widgets
+        .iter()
+        .map(|widget| match widget {
+            FooterWidget::Hints => footer.hints.to_string().into(),
+            FooterWidget::Status => footer
+                .status
+                .map(|msg| msg.text.clone())
+                .unwrap_or_default()
+                .into(),
+            FooterWidget::Count => tr!("{} 个模板", "{} templates", footer.template_count).into(),
+            FooterWidget::Workspace => config.footer.workspace.clone().unwrap_or_default().into(),
+            FooterWidget::Clipboard => match footer.clipboard {
+                ClipboardBackend::System => pick("剪贴板: 系统", "clipboard: system").into(),
+                ClipboardBackend::Osc52 => pick("剪贴板: OSC 52", "clipboard: OSC 52").into(),
+                ClipboardBackend::Command => pick("剪贴板: 命令", "clipboard: command").into(),
+                ClipboardBackend::Capture => pick("剪贴板: 演练", "clipboard: dry run").into(),
+            },
+            FooterWidget::Clock => footer.time.format("%H:%M").to_string().into(),
+            // Over the limit the count is the only part in the error color.
+            FooterWidget::Tokens => match (footer.tokens, config.tokens.limit) {
+                (Some(count), Some(limit)) if count > limit => Span::styled(
+                    format!("⚠ ≈{count}/{limit} tokens"),
+                    Style::new().fg(theme.error),
+                ),
+                (Some(count), Some(limit)) => format!("≈{count}/{limit} tokens").into(),
+                (Some(count), None) => format!("≈{count} tokens").into(),
+                (None, _) => "".into(),
+            },
+            FooterWidget::Unfilled if footer.unfilled > 0 => Span::styled(
+                tr!("{} 处未填写", "{} unfilled", footer.unfilled),
+                Style::new().fg(theme.error),
+            ),
+            FooterWidget::Unfilled => "".into(),
+            FooterWidget::Seed => footer
+                .seed
+                .map(|seed| tr!("种子 {seed}", "seed {seed}"))
+                .unwrap_or_default()
+                .into(),
+            FooterWidget::Template => footer.template.unwrap_or_default().to_string().into(),
+            FooterWidget::Branch => config
+                .footer
+                .branch
+                .as_ref()
+                .map(|branch| format!("⎇ {branch}"))
+                .unwrap_or_default()
+                .into(),
+            FooterWidget::Text => config.footer.text.clone().unwrap_or_default().into(),
+        })
+        .filter(|part| !part.content.is_empty())
+        .collect();
+    let mut spans = Vec::new();
+    for (index, part) in parts.into_iter().enumerate() {
+        if index > 0 {
+            spans.push(Span::raw("  |  "));
+        }
+        spans.push(part);
     }
-    let status = Paragraph::new(status).style(Style::new().fg(Color::DarkGray));
-    frame.render_widget(status, status_area);
+    Line::from(spans)
 }
 
-fn render_fields(frame: &mut Frame, editor: &mut EditorState, area: Rect) {
-    let block = Block::bordered().title("参数");
+fn render_fields(frame: &mut Frame, theme: &Theme, editor: &mut EditorState, area: Rect) {
+    let block = Block::bordered().title(pick("参数", "Fields"));
     let inner = inner_rect(area);
     frame.render_widget(block, area);
 
-    let field_height: u16 = 3;
     editor.fields_area = inner;
-    editor.field_height = field_height;
-    let view_capacity = (inner.height / field_height) as usize;
-    editor.field_scroll = ensure_visible(
+    let heights: Vec<u16> = editor.fields.iter().map(field_height).collect();
+    editor.field_scroll = ensure_field_visible(
         editor.field_scroll,
         editor.active_field,
-        editor.fields.len(),
-        view_capacity,
+        &heights,
+        inner.height,
     );
 
     let start = editor.field_scroll;
-    let end = (start + view_capacity).min(editor.fields.len());
+    let mut y = inner.y;
+    editor.field_rects.clear();
 
-    for (idx, field) in editor.fields[start..end].iter().enumerate() {
-        let is_active = start + idx == editor.active_field;
+    for (idx, field) in editor.fields.iter().enumerate().skip(start) {
+        let is_active = idx == editor.active_field;
+        let height = heights[idx].min(inner.y + inner.height - y);
+        if height < 3 {
+            break;
+        }
 
         let (title, display_value, border_style) = match &field.kind {
-            FieldKind::Var => {
-                let border = if is_active {
-                    Style::new().fg(Color::Blue)
+            FieldKind::Var | FieldKind::List => {
+                let invalid = field.invalid();
+                let border = if invalid.is_some() {
+                    Style::new().fg(theme.error)
+                } else if is_active {
+                    Style::new().fg(theme.focus)
+                } else if field.is_missing() {
+                    Style::new().fg(theme.error)
                 } else {
-                    Style::new().fg(Color::DarkGray)
+                    Style::new().fg(theme.muted)
                 };
-                let mut v = field.value.clone();
+                let value = &field.value;
+                let mut cursor_line = value.matches('\n').count();
+                let mut v = escape_control(value);
                 if is_active {
-                    v.push('|');
+                    let (cursor, caret) = match editor.cursor {
+                        Some(_) => {
+                            let normal = editor
+                                .vim
+                                .as_ref()
+                                .is_some_and(|vim| vim.mode == VimMode::Normal);
+                            let cursor = byte_index(value, editor.cursor_index());
+                            (cursor, if normal { '▌' } else { '|' })
+                        }
+                        None => (value.len(), '|'),
+                    };
+                    cursor_line = value[..cursor].matches('\n').count();
+                    let before = escape_control(&value[..cursor]);
+                    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+                    let shown = caret_window(&before[line_start..], inner.width.saturating_sub(2));
+                    v = format!(
+                        "{}{shown}{caret}{}",
+                        &before[..line_start],
+                        escape_control(&value[cursor..])
+                    );
                 }
-                (field.label.clone(), v, border)
+                let visible = (height - 2) as usize;
+                let line_count = v.split('\n').count();
+                if line_count > visible {
+                    // Keep the cursor's line on screen, the tail otherwise.
+                    v = v
+                        .split('\n')
+                        .skip((line_count - visible).min(cursor_line))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                }
+                let mut title = if field.required {
+                    format!("{} *", field.label)
+                } else {
+                    field.label.clone()
+                };
+                if let Some(err) = invalid {
+                    title.push_str(&format!(" · {err}"));
+                }
+                (title, v, border)
+            }
+            FieldKind::Select { options } => {
+                let border = if is_active {
+                    Style::new().fg(theme.focus)
+                } else {
+                    Style::new().fg(theme.muted)
+                };
+                let pos = options.iter().position(|o| *o == field.value).unwrap_or(0);
+                let display = if is_active {
+                    format!("◀ {} ({}/{}) ▶", field.value, pos + 1, options.len())
+                } else {
+                    format!("{} ({}/{})", field.value, pos + 1, options.len())
+                };
+                (field.label.clone(), display, border)
             }
             FieldKind::Random {
                 token_index,
                 pinned,
             } => {
                 let border = if is_active {
-                    Style::new().fg(Color::Blue)
+                    Style::new().fg(theme.focus)
                 } else if *pinned {
-                    Style::new().fg(Color::Yellow)
+                    Style::new().fg(theme.accent)
                 } else {
-                    Style::new().fg(Color::DarkGray)
+                    Style::new().fg(theme.muted)
                 };
                 let title = if *pinned {
-                    format!("[固定] {}", field.label)
+                    tr!("[固定] {}", "[pinned] {}", field.label)
                 } else {
                     field.label.clone()
                 };
@@ -214,40 +1853,289 @@ fn render_fields(frame: &mut Frame, editor: &mut EditorState, area: Rect) {
                     options, choice, ..
                 } = &editor.tokens[*token_index]
                 {
-                    let pos = options.iter().position(|o| o == choice).unwrap_or(0);
-                    if is_active {
-                        format!("◀ {} ({}/{}) ▶", choice, pos + 1, options.len())
-                    } else {
-                        format!("{} ({}/{})", choice, pos + 1, options.len())
+                    let pos = options.iter().position(|o| o == choice);
+                    let choice = escape_control(choice);
+                    match pos {
+                        Some(pos) if is_active => {
+                            format!("◀ {choice} ({}/{}) ▶", pos + 1, options.len())
+                        }
+                        Some(pos) => format!("{choice} ({}/{})", pos + 1, options.len()),
+                        None if is_active => {
+                            tr!("◀ {choice}| (自定义) ▶", "◀ {choice}| (custom) ▶")
+                        }
+                        None => tr!("{choice} (自定义)", "{choice} (custom)"),
                     }
                 } else {
                     field.value.clone()
                 };
                 (title, display, border)
             }
+            FieldKind::File { token_index } => {
+                let border = if is_active {
+                    Style::new().fg(theme.focus)
+                } else {
+                    Style::new().fg(theme.muted)
+                };
+                let display = match &editor.tokens[*token_index] {
+                    Token::File {
+                        resolved: Some(resolved),
+                        content: Some(content),
+                        ..
+                    } => tr!(
+                        "{} ({} 字节)",
+                        "{} ({} bytes)",
+                        resolved.display(),
+                        content.len()
+                    ),
+                    Token::File {
+                        resolved: Some(resolved),
+                        ..
+                    } => tr!("{} (无法读取)", "{} (unreadable)", resolved.display()),
+                    _ => String::new(),
+                };
+                (tr!("文件 {}", "file {}", field.label), display, border)
+            }
         };
 
         let field_area = Rect {
             x: inner.x,
-            y: inner.y + (idx as u16) * field_height,
+            y,
             width: inner.width,
-            height: field_height,
+            height,
         };
+        y += height;
+        editor.field_rects.push((idx, field_area));
         let field_block = Block::bordered()
             .title(title.as_str())
             .border_style(border_style);
-        let paragraph = Paragraph::new(display_value)
-            .block(field_block)
-            .wrap(Wrap { trim: false });
+        // Lines are clipped rather than wrapped so each takes one row, as
+        // `field_height` counts them.
+        let paragraph = Paragraph::new(display_value).block(field_block);
         frame.render_widget(paragraph, field_area);
     }
 }
 
-fn render_preview(frame: &mut Frame, title: &str, rendered: &str, area: Rect) {
-    let paragraph = Paragraph::new(rendered)
-        .block(Block::bordered().title(title))
-        .wrap(Wrap { trim: false });
+/// Preview lines with the picks changed by the last reroll and the selected
+/// lines highlighted and, unless `[preview] markdown = false`, markdown
+/// styling.
+fn preview_text(
+    theme: &Theme,
+    segments: &[Segment],
+    changed: &[usize],
+    selection: Option<(usize, usize)>,
+    markdown: bool,
+) -> Text<'static> {
+    let text = segments_to_text(segments, |kind| match kind {
+        SegmentKind::Random { token_index } if changed.contains(token_index) => {
+            Style::new().bg(theme.flash_bg).fg(theme.flash_fg)
+        }
+        SegmentKind::Unfilled => Style::new().bg(theme.unfilled_bg).fg(theme.unfilled_fg),
+        _ => Style::new(),
+    });
+    let mut text = if markdown {
+        style_markdown(text, theme)
+    } else {
+        text
+    };
+    if let Some((first, last)) = selection {
+        let style = Style::new().bg(theme.selection_bg).fg(theme.selection_fg);
+        for line in text.lines.iter_mut().take(last + 1).skip(first) {
+            for span in &mut line.spans {
+                span.style = span.style.patch(style);
+            }
+            // Keeps empty lines visible in the selection.
+            line.style = line.style.patch(style);
+        }
+    }
+    text
+}
+
+/// Size of the rendered prompt and how many placeholders are still blank,
+/// for the bottom border of the preview.
+fn preview_stats(theme: &Theme, segments: &[Segment], unfilled: usize) -> Line<'static> {
+    let rendered: String = segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect();
+    let mut spans = vec![Span::raw(tr!(
+        " {} 字符 · {} 词 · {} 行 · ",
+        " {} chars · {} words · {} lines · ",
+        rendered.chars().count(),
+        count_words(&rendered),
+        rendered.lines().count()
+    ))];
+    spans.push(if unfilled > 0 {
+        Span::styled(
+            tr!("{unfilled} 处未填写", "{unfilled} unfilled"),
+            Style::new().fg(theme.error),
+        )
+    } else {
+        Span::styled(pick("已填完", "all filled"), Style::new().fg(theme.success))
+    });
+    spans.push(Span::raw(" "));
+    Line::from(spans).right_aligned()
+}
+
+/// The streamed answer, kept scrolled to its end while `follow` is set.
+fn render_answer(
+    frame: &mut Frame,
+    theme: &Theme,
+    answer: &mut Answer,
+    markdown: bool,
+    area: Rect,
+) {
+    let mut lines: Vec<Line<'static>> = answer
+        .text
+        .split('\n')
+        .map(|line| Line::from(escape_control(line)))
+        .collect();
+    if let Some(err) = &answer.error {
+        lines.push(Line::styled(err.clone(), Style::new().fg(theme.error)));
+    }
+    let text = Text::from(lines);
+    let text = if markdown {
+        style_markdown(text, theme)
+    } else {
+        text
+    };
+    let visible = area.height.saturating_sub(2) as usize;
+    if answer.follow {
+        answer.scroll = text.lines.len().saturating_sub(visible) as u16;
+    }
+    let title = if answer.stream.is_some() {
+        tr!(
+            "回答: {} (生成中…)",
+            "Answer: {} (generating…)",
+            answer.model
+        )
+    } else {
+        tr!("回答: {}", "Answer: {}", answer.model)
+    };
+    render_preview(frame, theme, &title, None, text, &mut answer.scroll, area);
+}
+
+fn render_preview(
+    frame: &mut Frame,
+    theme: &Theme,
+    title: &str,
+    stats: Option<Line<'static>>,
+    text: Text<'static>,
+    scroll: &mut u16,
+    area: Rect,
+) {
+    let line_count = text.lines.len();
+    *scroll = (*scroll).min(line_count.saturating_sub(1) as u16);
+    let mut block = Block::bordered().title(title);
+    if let Some(stats) = stats {
+        block = block.title_bottom(stats);
+    }
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::new().fg(theme.text))
+        .wrap(Wrap { trim: false })
+        .scroll((*scroll, 0));
     frame.render_widget(paragraph, area);
+
+    // Drawn on the right border once the text is taller than the pane.
+    if line_count > area.height.saturating_sub(2) as usize {
+        let mut state = ScrollbarState::new(line_count).position(*scroll as usize);
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None)
+            .thumb_style(Style::new().fg(theme.focus))
+            .track_style(Style::new().fg(theme.muted));
+        frame.render_stateful_widget(
+            scrollbar,
+            area.inner(Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut state,
+        );
+    }
+}
+
+/// Splits segments on newlines into styled lines, since a single segment
+/// (e.g. a template text run) may span several lines. Control characters
+/// are shown escaped.
+fn segments_to_text(segments: &[Segment], style: impl Fn(&SegmentKind) -> Style) -> Text<'static> {
+    let mut lines: Vec<Line<'static>> = Vec::new();
+    let mut current: Vec<Span<'static>> = Vec::new();
+    for segment in segments {
+        let style = style(&segment.kind);
+        let text = escape_control(&segment.text);
+        let mut parts = text.split('\n');
+        if let Some(first) = parts.next()
+            && !first.is_empty()
+        {
+            current.push(Span::styled(first.to_string(), style));
+        }
+        for part in parts {
+            lines.push(Line::from(std::mem::take(&mut current)));
+            if !part.is_empty() {
+                current.push(Span::styled(part.to_string(), style));
+            }
+        }
+    }
+    lines.push(Line::from(current));
+    Text::from(lines)
+}
+
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Outer height of a field widget: multi-line values grow the box up to
+/// `MAX_FIELD_LINES` lines of content.
+fn field_height(field: &Field) -> u16 {
+    let lines = match field.kind {
+        FieldKind::Var | FieldKind::List => {
+            field.value.split('\n').count().clamp(1, MAX_FIELD_LINES)
+        }
+        _ => 1,
+    };
+    lines as u16 + 2
+}
+
+/// End of `line` up to the caret, cut at the front by whole grapheme
+/// clusters so it and the caret fit in `width` columns.
+fn caret_window(line: &str, width: u16) -> &str {
+    let mut start = 0;
+    let mut graphemes = line.grapheme_indices(true);
+    while line[start..].width() + 1 > width as usize {
+        match graphemes.next() {
+            Some((_, grapheme)) => start += grapheme.len(),
+            None => break,
+        }
+    }
+    &line[start..]
+}
+
+/// Like `ensure_visible`, but for rows of varying height.
+fn ensure_field_visible(current_scroll: usize, active: usize, heights: &[u16], view: u16) -> usize {
+    if heights.is_empty() {
+        return 0;
+    }
+    let mut scroll = current_scroll.min(heights.len() - 1);
+    if active < scroll {
+        scroll = active;
+    }
+    while scroll < active && heights[scroll..=active].iter().sum::<u16>() > view {
+        scroll += 1;
+    }
+    // Scrolls back while the fields above still fit, e.g. after the
+    // terminal grew.
+    while scroll > 0 && heights[scroll - 1..].iter().sum::<u16>() <= view {
+        scroll -= 1;
+    }
+    scroll
 }
 
 fn inner_rect(area: Rect) -> Rect {
@@ -272,7 +2160,8 @@ fn ensure_visible(
     if total == 0 || view_height == 0 {
         return 0;
     }
-    let mut scroll = current_scroll.min(total.saturating_sub(1));
+    // No blank rows below the last item, e.g. after the terminal grew.
+    let mut scroll = current_scroll.min(total.saturating_sub(view_height));
     if selected < scroll {
         scroll = selected;
     } else if selected >= scroll + view_height {
@@ -281,7 +2170,19 @@ fn ensure_visible(
     scroll
 }
 
-fn build_tree_lines(items: &[TreeItem]) -> Vec<String> {
+/// Tree guides in front of each item. Narrow terminals get one column per
+/// level instead of three.
+fn build_tree_lines(items: &[TreeItem], narrow: bool) -> Vec<TreeLine> {
+    let (pipe, empty, branch, last) = if narrow {
+        (
+            TREE_PIPE_NARROW,
+            TREE_EMPTY_NARROW,
+            TREE_BRANCH_NARROW,
+            TREE_LAST_NARROW,
+        )
+    } else {
+        (TREE_PIPE, TREE_EMPTY, TREE_BRANCH, TREE_LAST)
+    };
     let mut lines = Vec::with_capacity(items.len());
     let mut branches: Vec<bool> = Vec::new();
     for (index, item) in items.iter().enumerate() {
@@ -297,27 +2198,81 @@ fn build_tree_lines(items: &[TreeItem]) -> Vec<String> {
         let mut line = String::new();
         for has_next in &branches {
             if *has_next {
-                line.push_str(TREE_PIPE);
+                line.push_str(pipe);
             } else {
-                line.push_str(TREE_EMPTY);
+                line.push_str(empty);
             }
         }
 
         if is_last {
-            line.push_str(TREE_LAST);
+            line.push_str(last);
         } else {
-            line.push_str(TREE_BRANCH);
+            line.push_str(branch);
         }
         line.push_str(icon);
         line.push(' ');
-        line.push_str(&item.label);
-        lines.push(line);
+        if item.depth == 0 && has_children {
+            line.push_str(top_folder_label(&item.label));
+        } else {
+            line.push_str(&item.label);
+        }
+        lines.push(TreeLine {
+            text: line,
+            folder_count: has_children.then(|| folder_count(items, index)),
+        });
 
         branches.push(!is_last);
     }
     lines
 }
 
+/// Path of the selected item above the tree, the item itself emphasized.
+fn breadcrumb(theme: &Theme, path: &[&str]) -> Paragraph<'static> {
+    let mut spans = Vec::new();
+    for (index, part) in path.iter().enumerate() {
+        let part = if index == 0 {
+            top_folder_label(part)
+        } else {
+            part
+        };
+        if index + 1 == path.len() {
+            spans.push(Span::styled(
+                part.to_string(),
+                Style::new().fg(theme.accent),
+            ));
+        } else {
+            spans.push(Span::styled(
+                format!("{part} / "),
+                Style::new().fg(theme.muted),
+            ));
+        }
+    }
+    if spans.is_empty() {
+        spans.push(Span::styled("/", Style::new().fg(theme.muted)));
+    }
+    Paragraph::new(Line::from(spans))
+}
+
+/// Label drawn for a folder at the root of the tree: the project folder in
+/// the UI language, any other folder as named.
+fn top_folder_label(label: &str) -> &str {
+    if PROJECT_FOLDER.strip_suffix('/') == Some(label) {
+        pick("项目", "Project")
+    } else {
+        label
+    }
+}
+
+/// Number of templates below the folder at `index`.
+fn folder_count(items: &[TreeItem], index: usize) -> usize {
+    let depth = items[index].depth;
+    items[index + 1..]
+        .iter()
+        .take_while(|item| item.depth > depth)
+        .filter(|item| item.template_index.is_some())
+        .count()
+}
+
 fn is_last_sibling(items: &[TreeItem], index: usize) -> bool {
     let depth = items[index].depth;
     for item in &items[index + 1..] {
@@ -334,3 +2289,124 @@ fn has_children(items: &[TreeItem], index: usize) -> bool {
         None => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::app::{press, type_text};
+    use crate::config::FooterWidget;
+    use crate::platform::Platform;
+    use crate::replay::screen;
+    use crate::store::{LIBRARY, test_app};
+
+    #[test]
+    fn status_messages_run_out_on_the_platform_clock() {
+        let mut app = test_app(LIBRARY);
+        app.config.footer.widgets = vec![FooterWidget::Status];
+        app.config.footer.right = vec![FooterWidget::Clock];
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "张三");
+        app.on_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL));
+        let copied = "已复制 (演练模式，未写入剪贴板)";
+        let before = screen(&mut app, 60, 10);
+        assert!(before.contains(copied));
+        assert!(before.ends_with("09:00\n"));
+        assert_eq!(
+            app.next_change(),
+            Some(app.platform.now() + Duration::from_millis(1500))
+        );
+
+        app.platform.advance(Duration::from_millis(1400));
+        assert!(screen(&mut app, 60, 10).contains(copied));
+        app.platform.advance(Duration::from_millis(100));
+        assert!(!screen(&mut app, 60, 10).contains(copied));
+    }
+
+    #[test]
+    fn the_list_view_draws_the_tree_and_key_hints() {
+        let mut app = test_app(LIBRARY);
+        assert_eq!(
+            screen(&mut app, 48, 8),
+            "\
+┌模板列表 (2)──────────────────────────────────┐
+│示例                                          │
+│ └ 示例 (2)                                 │
+│   ├󰈙 问候                                    │
+│   └󰈙 告别                                    │
+│                                              │
+└──────────────────────────────────────────────┘
+↑↓/j k 选择  Enter/双击 打开  / 搜索  p 正文  n
+"
+        );
+    }
+
+    #[test]
+    fn the_editor_view_draws_fields_above_the_preview_when_narrow() {
+        let mut app = test_app(LIBRARY);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        type_text(&mut app, "小明");
+        assert_eq!(
+            screen(&mut app, 60, 14),
+            "\
+┌参数──────────────────────────────────────────────────────┐
+│┌收件人──────────────────────────────────────────────────┐│
+││小明|                                                   ││
+│└────────────────────────────────────────────────────────┘│
+└──────────────────────────────────────────────────────────┘
+┌预览: 示例/问候───────────────────────────────────────────┐
+│你好 小明                                                 │
+│                                                          │
+│                                                          │
+│                                                          │
+│                                                          │
+│                                                          │
+└─────────────────────────── 6 字符 · 4 词 · 1 行 · 已填完 ┘
+≈4 tokens  |  Esc 返回  Tab/↑↓ 切换  Ctrl+C 复制  Alt+C 复制
+"
+        );
+    }
+
+    #[test]
+    fn the_error_view_says_what_to_try_next() {
+        let mut app = test_app("nothing\n");
+        assert_eq!(
+            screen(&mut app, 48, 9),
+            "\
+┌模板错误──────────────────────────────────────┐
+│未找到任何模板，请检查是否有 `## 标题` 段落。 │
+│                                              │
+│在 prompts.md 中用 `## 标题`                  │
+│开始每个模板，或运行 pmt lint 查看问题。      │
+│                                              │
+│                                              │
+│                                              │
+└──────────────────────────────────────────────┘
+"
+        );
+    }
+
+    #[test]
+    fn tree_rows_are_built_once_until_the_list_changes() {
+        let library: String = (0..50).map(|n| format!("## d/t{n}\nx\n\n")).collect();
+        let mut app = test_app(&library);
+        screen(&mut app, 120, 20);
+        let (narrow, lines) = app.tree_lines.clone().unwrap();
+        assert!(!narrow);
+        assert_eq!(lines.len(), 51);
+        assert_eq!(lines[0].folder_count, Some(50));
+
+        press(&mut app, KeyCode::Char('/'));
+        type_text(&mut app, "t7");
+        assert!(app.tree_lines.is_none());
+        screen(&mut app, 120, 20);
+        assert_eq!(
+            app.tree_lines.as_ref().unwrap().1.len(),
+            app.tree_items.len()
+        );
+    }
+}